@@ -0,0 +1,28 @@
+//! Feed arbitrary byte streams through the signaling frame decoder.
+//!
+//! Covers malformed length prefixes, truncated frames, bogus compressed
+//! bodies and binary audio opcodes. Any panic is a bug: untrusted input
+//! must only ever produce a `ProtocolError`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pqc_chat::protocol::SignalingMessage;
+
+fuzz_target!(|data: &[u8]| {
+    // Raw JSON, as read by `from_bytes`
+    let _ = SignalingMessage::from_bytes(data);
+
+    // A stream of frames, decoded the way a reader walks its buffer
+    let mut rest = data;
+    while let Ok(Some((message, used))) = SignalingMessage::decode_frame(rest) {
+        // Anything accepted must survive a round trip
+        let json = message.to_bytes().expect("decoded message re-serializes");
+        assert!(SignalingMessage::from_bytes(&json).is_ok());
+        rest = &rest[used..];
+    }
+
+    // The same bytes taken as a body behind either header flag
+    let _ = SignalingMessage::from_frame_body(data, false);
+    let _ = SignalingMessage::from_frame_body(data, true);
+});
@@ -6,6 +6,9 @@
 use opus::{Encoder, Decoder, Application, Channels};
 use thiserror::Error;
 
+use crate::audio::SampleFormat;
+use crate::protocol::AudioCodec;
+
 /// Codec errors
 #[derive(Error, Debug)]
 pub enum CodecError {
@@ -17,23 +20,105 @@ pub enum CodecError {
     BufferTooSmall,
 }
 
-/// Opus audio encoder (48kHz, mono, 20ms frames)
+/// RMS level below which DTX treats a frame as silence (about -60 dBFS)
+const DTX_SILENCE_RMS: f32 = 0.001;
+
+/// Silence still sent before DTX kicks in, as in libopus
+const DTX_HANGOVER_MS: u32 = 200;
+
+/// While in DTX, one frame per this interval is still sent so the
+/// receiver's comfort noise tracks the background, as in libopus
+const DTX_UPDATE_MS: u32 = 400;
+
+/// Samples in the longest Opus frame we produce (60ms at 48kHz)
+const MAX_FRAME_SAMPLES: usize = 2880;
+
+/// Encoded frames this small carry no audio and need not be sent
+pub const DTX_FRAME_MAX_BYTES: usize = 2;
+
+/// Whether an encoded frame is a DTX placeholder rather than audio
+pub fn is_dtx_frame(frame: &[u8]) -> bool {
+    frame.len() <= DTX_FRAME_MAX_BYTES
+}
+
+/// Length of one Opus frame. Shorter frames cut latency, longer ones cut
+/// per-packet overhead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameDuration {
+    Ms10,
+    #[default]
+    Ms20,
+    Ms40,
+    Ms60,
+}
+
+impl FrameDuration {
+    pub const ALL: [FrameDuration; 4] = [Self::Ms10, Self::Ms20, Self::Ms40, Self::Ms60];
+
+    /// The duration for a length in milliseconds, if Opus supports it
+    pub fn from_millis(ms: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.as_millis() == ms)
+    }
+
+    pub fn as_millis(self) -> u32 {
+        match self {
+            Self::Ms10 => 10,
+            Self::Ms20 => 20,
+            Self::Ms40 => 40,
+            Self::Ms60 => 60,
+        }
+    }
+
+    /// Samples per frame at 48kHz
+    pub fn samples(self) -> usize {
+        self.as_millis() as usize * 48
+    }
+
+    /// The duration of a 48kHz frame of `samples` samples, if Opus supports it
+    pub fn from_samples(samples: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.samples() == samples)
+    }
+}
+
+/// Optional encoder behaviour
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpusSettings {
+    /// Discontinuous transmission: stop emitting frames during sustained
+    /// silence, returning empty frames instead
+    pub dtx: bool,
+    /// Length of the frames `encode` takes
+    pub frame_duration: FrameDuration,
+}
+
+/// Opus audio encoder (48kHz, mono, 20ms frames unless configured otherwise)
 pub struct OpusEncoder {
     encoder: Encoder,
+    settings: OpusSettings,
+    /// Consecutive silent frames, for DTX
+    silent_frames: u32,
 }
 
 impl OpusEncoder {
     /// Create a new Opus encoder (48kHz, mono, optimized for voice)
     pub fn new() -> Result<Self, CodecError> {
+        Self::with_settings(OpusSettings::default())
+    }
+
+    /// Create an encoder with non-default settings.
+    ///
+    /// The `opus` crate doesn't expose `OPUS_SET_DTX`, so DTX is done here
+    /// the same way libopus does it: after a short hangover, silent frames
+    /// come back empty except for a periodic background update.
+    pub fn with_settings(settings: OpusSettings) -> Result<Self, CodecError> {
         let encoder = Encoder::new(48000, Channels::Mono, Application::Voip)
             .map_err(|e| CodecError::OpusError(format!("Failed to create encoder: {:?}", e)))?;
-        Ok(Self { encoder })
+        Ok(Self { encoder, settings, silent_frames: 0 })
     }
 
     /// Encode f32 audio samples to Opus bytes
-    /// Input: 960 samples @ 48kHz = 20ms frame
+    /// Input: one frame of the configured duration (960 samples @ 48kHz = 20ms)
     pub fn encode(&mut self, samples: &[f32]) -> Result<Vec<u8>, CodecError> {
-        if samples.len() != 960 {
+        if samples.len() != self.settings.frame_duration.samples() {
             return Err(CodecError::InvalidFormat);
         }
 
@@ -45,11 +130,28 @@ impl OpusEncoder {
             .map_err(|e| CodecError::OpusError(format!("Encode failed: {:?}", e)))?;
         
         encoded.truncate(encoded_len);
+
+        if self.settings.dtx {
+            if crate::audio::frame_rms(samples) >= DTX_SILENCE_RMS {
+                self.silent_frames = 0;
+            } else {
+                let frame_ms = self.settings.frame_duration.as_millis();
+                let hangover_frames = DTX_HANGOVER_MS / frame_ms;
+                let update_frames = DTX_UPDATE_MS / frame_ms;
+                self.silent_frames = self.silent_frames.saturating_add(1);
+                if self.silent_frames > hangover_frames
+                    && !(self.silent_frames - hangover_frames).is_multiple_of(update_frames)
+                {
+                    // Still encoded above so the encoder state stays continuous
+                    encoded.clear();
+                }
+            }
+        }
         Ok(encoded)
     }
 }
 
-/// Opus audio decoder (48kHz, mono, 20ms frames)
+/// Opus audio decoder (48kHz, mono, frames of any supported duration)
 pub struct OpusDecoder {
     decoder: Decoder,
 }
@@ -63,23 +165,277 @@ impl OpusDecoder {
     }
 
     /// Decode Opus bytes to f32 audio samples
-    /// Output: 960 samples @ 48kHz = 20ms frame
+    /// Output: as many samples as the sender's frame held (960 for 20ms @ 48kHz)
     pub fn decode(&mut self, encoded: &[u8]) -> Result<Vec<f32>, CodecError> {
-        // Opus produces 960 samples for 20ms @ 48kHz
-        let mut samples = vec![0f32; 960];
+        // Room for the longest frame; the packet says how long it really is
+        let mut samples = vec![0f32; MAX_FRAME_SAMPLES];
         
         let decoded_len = self.decoder.decode_float(encoded, &mut samples, false)
             .map_err(|e| CodecError::OpusError(format!("Decode failed: {:?}", e)))?;
         
-        if decoded_len != 960 {
-            eprintln!("WARNING: Decoded {} samples, expected 960", decoded_len);
-        }
-        
         samples.truncate(decoded_len);
         Ok(samples)
     }
 }
 
+/// Encodes captured frames as Opus, falling back to raw PCM for any frame
+/// the encoder rejects (e.g. a short frame during device reconfiguration)
+pub struct FrameEncoder {
+    opus: OpusEncoder,
+    /// Sample layout for frames that go out as PCM
+    pcm_format: SampleFormat,
+    /// Set while frames are going out as PCM, so the fallback is logged once
+    falling_back: bool,
+    /// Cleared when the peer didn't agree to Opus: every frame goes out as PCM
+    use_opus: bool,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Result<Self, CodecError> {
+        Self::with_settings(OpusSettings::default())
+    }
+
+    pub fn with_settings(settings: OpusSettings) -> Result<Self, CodecError> {
+        Ok(Self {
+            opus: OpusEncoder::with_settings(settings)?,
+            pcm_format: SampleFormat::default(),
+            falling_back: false,
+            use_opus: true,
+        })
+    }
+
+    /// Send PCM fallback frames in `format` (f32 by default)
+    pub fn with_pcm_format(mut self, format: SampleFormat) -> Self {
+        self.pcm_format = format;
+        self
+    }
+
+    /// Send frames as `codec` (see `protocol::preferred_codec`): a PCM
+    /// codec skips Opus and sends every frame in that format
+    pub fn with_codec(mut self, codec: AudioCodec) -> Self {
+        self.use_opus = codec == AudioCodec::Opus;
+        match codec {
+            AudioCodec::Opus => {}
+            AudioCodec::Pcm => self.pcm_format = SampleFormat::F32,
+            AudioCodec::Pcm16 => self.pcm_format = SampleFormat::I16,
+        }
+        self
+    }
+
+    /// Encode one frame, returning the codec it ended up in
+    pub fn encode(&mut self, samples: &[f32]) -> (AudioCodec, Vec<u8>) {
+        if !self.use_opus {
+            return (self.pcm_format.codec(), self.pcm_format.encode(samples));
+        }
+        match self.opus.encode(samples) {
+            Ok(encoded) => {
+                if self.falling_back {
+                    log::info!("Opus encoding recovered");
+                    self.falling_back = false;
+                }
+                (AudioCodec::Opus, encoded)
+            }
+            Err(e) => {
+                if !self.falling_back {
+                    log::warn!("Opus encode failed ({}), sending raw PCM until it recovers", e);
+                    self.falling_back = true;
+                }
+                (self.pcm_format.codec(), self.pcm_format.encode(samples))
+            }
+        }
+    }
+}
+
+/// Decodes received frames according to their codec tag
+pub struct FrameDecoder {
+    opus: OpusDecoder,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Result<Self, CodecError> {
+        Ok(Self {
+            opus: OpusDecoder::new()?,
+        })
+    }
+
+    /// Decode one frame: Opus goes through the decoder, PCM is passed through
+    pub fn decode(&mut self, codec: AudioCodec, frame: &[u8]) -> Result<Vec<f32>, CodecError> {
+        match codec {
+            AudioCodec::Opus => self.opus.decode(frame),
+            AudioCodec::Pcm => {
+                if frame.len() % 4 != 0 {
+                    return Err(CodecError::InvalidFormat);
+                }
+                Ok(crate::audio::bytes_to_samples(frame))
+            }
+            AudioCodec::Pcm16 => {
+                if frame.len() % 2 != 0 {
+                    return Err(CodecError::InvalidFormat);
+                }
+                Ok(crate::audio::i16_bytes_to_samples(frame))
+            }
+        }
+    }
+}
+
+/// Re-encodes one sender's audio into another codec, so PCM and Opus
+/// clients can hear each other when the server transcodes
+pub struct Transcoder {
+    decoder: FrameDecoder,
+    /// Opus encoder for the sender's frame duration, rebuilt if it changes
+    encoder: Option<(FrameDuration, OpusEncoder)>,
+}
+
+impl Transcoder {
+    pub fn new() -> Result<Self, CodecError> {
+        Ok(Self {
+            decoder: FrameDecoder::new()?,
+            encoder: None,
+        })
+    }
+
+    /// Opus-encode one frame, sizing the encoder from its length
+    fn encode_opus(&mut self, samples: &[f32]) -> Result<Vec<u8>, CodecError> {
+        let duration = FrameDuration::from_samples(samples.len()).ok_or(CodecError::InvalidFormat)?;
+        let encoder = match &mut self.encoder {
+            Some((current, encoder)) if *current == duration => encoder,
+            slot => {
+                let settings = OpusSettings { frame_duration: duration, ..OpusSettings::default() };
+                &mut slot.insert((duration, OpusEncoder::with_settings(settings)?)).1
+            }
+        };
+        encoder.encode(samples)
+    }
+
+    /// Convert a payload from `from` to `to`, frame by frame if `batched`
+    pub fn transcode(
+        &mut self,
+        payload: &[u8],
+        batched: bool,
+        from: AudioCodec,
+        to: AudioCodec,
+    ) -> Result<Vec<u8>, CodecError> {
+        if from == to {
+            return Ok(payload.to_vec());
+        }
+        let frames = self.decode(payload, batched, from)?;
+        self.encode(&frames, batched, to)
+    }
+
+    /// Decode a payload in `from` into its frames' samples, so one decode
+    /// can feed several [`encode`](Self::encode)s
+    pub fn decode(&mut self, payload: &[u8], batched: bool, from: AudioCodec) -> Result<Vec<Vec<f32>>, CodecError> {
+        let frames = if batched { unpack_frames(payload)? } else { vec![payload.to_vec()] };
+        frames.iter().map(|frame| self.decoder.decode(from, frame)).collect()
+    }
+
+    /// Encode decoded frames as `to`, packed into one payload if `batched`
+    pub fn encode(&mut self, frames: &[Vec<f32>], batched: bool, to: AudioCodec) -> Result<Vec<u8>, CodecError> {
+        let mut converted = Vec::with_capacity(frames.len());
+        for samples in frames {
+            converted.push(match to {
+                AudioCodec::Opus => self.encode_opus(samples)?,
+                AudioCodec::Pcm => SampleFormat::F32.encode(samples),
+                AudioCodec::Pcm16 => SampleFormat::I16.encode(samples),
+            });
+        }
+
+        if batched {
+            Ok(pack_frames(&converted))
+        } else {
+            Ok(converted.pop().unwrap_or_default())
+        }
+    }
+}
+
+/// Most frames [`FrameBatcher`] packs into one payload
+pub const MAX_FRAMES_PER_PACKET: u32 = 4;
+
+/// Largest `AudioData` payload a client sends: a full batch of 60ms f32 PCM
+/// frames, each with its length prefix
+pub const MAX_AUDIO_PAYLOAD_BYTES: usize =
+    (2 + 60 * 48 * std::mem::size_of::<f32>()) * MAX_FRAMES_PER_PACKET as usize;
+const _: () = assert!(MAX_AUDIO_PAYLOAD_BYTES < crate::protocol::MAX_FRAME_LEN);
+
+/// Collects encoded frames and emits them as one batched payload.
+///
+/// Each frame is prefixed with its length (2 bytes, big-endian) so the
+/// receiver can split the payload back into individual frames with
+/// [`unpack_frames`]. Sending several frames per `AudioData` message cuts
+/// the per-packet JSON/TLS overhead at the cost of `frames_per_packet - 1`
+/// frames of extra latency.
+pub struct FrameBatcher {
+    frames_per_packet: usize,
+    pending: Vec<Vec<u8>>,
+}
+
+impl FrameBatcher {
+    /// Create a batcher that emits a payload every `frames_per_packet` frames,
+    /// capped at [`MAX_FRAMES_PER_PACKET`]
+    pub fn new(frames_per_packet: u32) -> Self {
+        let frames_per_packet = frames_per_packet.clamp(1, MAX_FRAMES_PER_PACKET) as usize;
+        Self {
+            frames_per_packet,
+            pending: Vec::with_capacity(frames_per_packet),
+        }
+    }
+
+    /// Add an encoded frame, returning a packed payload once the batch is full
+    pub fn push(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        self.pending.push(frame);
+        if self.pending.len() >= self.frames_per_packet {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Pack whatever is pending, even if the batch is not full
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let packed = pack_frames(&self.pending);
+        self.pending.clear();
+        Some(packed)
+    }
+
+    /// Whether batches hold more than one frame (payloads must be unpacked)
+    pub fn is_batching(&self) -> bool {
+        self.frames_per_packet > 1
+    }
+}
+
+/// Pack encoded frames into a single length-prefixed payload
+pub fn pack_frames(frames: &[Vec<u8>]) -> Vec<u8> {
+    let total: usize = frames.iter().map(|f| 2 + f.len()).sum();
+    let mut packed = Vec::with_capacity(total);
+    for frame in frames {
+        packed.extend_from_slice(&(frame.len() as u16).to_be_bytes());
+        packed.extend_from_slice(frame);
+    }
+    packed
+}
+
+/// Split a payload produced by [`pack_frames`] back into individual frames
+pub fn unpack_frames(packed: &[u8]) -> Result<Vec<Vec<u8>>, CodecError> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos < packed.len() {
+        if pos + 2 > packed.len() {
+            return Err(CodecError::InvalidFormat);
+        }
+        let len = u16::from_be_bytes([packed[pos], packed[pos + 1]]) as usize;
+        pos += 2;
+        if pos + len > packed.len() {
+            return Err(CodecError::InvalidFormat);
+        }
+        frames.push(packed[pos..pos + len].to_vec());
+        pos += len;
+    }
+    Ok(frames)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -106,4 +462,168 @@ mod tests {
             assert!(sample.abs() < 0.01);
         }
     }
+
+    #[test]
+    fn test_each_frame_duration_round_trips() {
+        for duration in FrameDuration::ALL {
+            let settings = OpusSettings { frame_duration: duration, ..OpusSettings::default() };
+            let mut encoder = OpusEncoder::with_settings(settings).unwrap();
+            let mut decoder = OpusDecoder::new().unwrap();
+
+            let tone = crate::audio::sine_wave(440.0, 0.5, 48000, 0, duration.samples());
+            let encoded = encoder.encode(&tone).unwrap();
+            assert_eq!(decoder.decode(&encoded).unwrap().len(), duration.samples(), "{:?}", duration);
+
+            // Frames of any other length are rejected
+            assert!(encoder.encode(&tone[..duration.samples() / 2]).is_err());
+        }
+        assert_eq!(
+            FrameDuration::ALL.map(FrameDuration::samples),
+            [480, 960, 1920, 2880]
+        );
+        assert_eq!(FrameDuration::from_millis(40), Some(FrameDuration::Ms40));
+        assert_eq!(FrameDuration::from_millis(30), None);
+    }
+
+    #[test]
+    fn test_dtx_suppresses_sustained_silence() {
+        let silence = [0.0f32; 960];
+        let encode_all = |settings| {
+            let mut encoder = OpusEncoder::with_settings(settings).unwrap();
+            (0..100).map(|_| encoder.encode(&silence).unwrap()).collect::<Vec<_>>()
+        };
+
+        let plain = encode_all(OpusSettings::default());
+        let dtx = encode_all(OpusSettings { dtx: true, ..OpusSettings::default() });
+        let sent = |frames: &[Vec<u8>]| frames.iter().filter(|f| !f.is_empty()).count();
+        let bytes = |frames: &[Vec<u8>]| frames.iter().map(Vec::len).sum::<usize>();
+
+        assert_eq!(sent(&plain), 100);
+        // 2s of silence: the 200ms hangover plus an update every 400ms
+        assert_eq!(sent(&dtx), 10 + 4);
+        assert!(bytes(&dtx) * 5 < bytes(&plain));
+
+        // Speech ends DTX straight away
+        let mut encoder = OpusEncoder::with_settings(OpusSettings { dtx: true, ..OpusSettings::default() }).unwrap();
+        for _ in 0..30 {
+            encoder.encode(&silence).unwrap();
+        }
+        let tone = crate::audio::sine_wave(440.0, 0.5, 48000, 0, 960);
+        assert!(!encoder.encode(&tone).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pcm_frame_passes_through() {
+        let mut decoder = FrameDecoder::new().unwrap();
+        let samples = vec![0.25f32, -0.5, 1.0, 0.0];
+        let bytes = crate::audio::samples_to_bytes(&samples);
+
+        assert_eq!(decoder.decode(AudioCodec::Pcm, &bytes).unwrap(), samples);
+        assert!(decoder.decode(AudioCodec::Pcm, &bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_opus_frame_routed_to_decoder() {
+        let mut encoder = OpusEncoder::new().unwrap();
+        let mut decoder = FrameDecoder::new().unwrap();
+        let encoded = encoder.encode(&[0.0f32; 960]).unwrap();
+
+        // A full 20ms frame comes back, not the handful of "samples" the
+        // compressed bytes would make if read as PCM
+        let decoded = decoder.decode(AudioCodec::Opus, &encoded).unwrap();
+        assert_eq!(decoded.len(), 960);
+    }
+
+    #[test]
+    fn test_rejected_frame_falls_back_to_pcm() {
+        let mut encoder = FrameEncoder::new().unwrap();
+
+        // Opus only takes 20ms frames; a 10ms frame is rejected
+        let short = vec![0.5f32; 480];
+        let (codec, bytes) = encoder.encode(&short);
+        assert_eq!(codec, AudioCodec::Pcm);
+        assert_eq!(crate::audio::bytes_to_samples(&bytes), short);
+
+        let (codec, _) = encoder.encode(&[0.0f32; 960]);
+        assert_eq!(codec, AudioCodec::Opus);
+    }
+
+    #[test]
+    fn test_i16_fallback_is_tagged_and_decodes() {
+        let mut encoder = FrameEncoder::new().unwrap().with_pcm_format(SampleFormat::I16);
+        let mut decoder = FrameDecoder::new().unwrap();
+
+        let short = vec![0.5f32; 480];
+        let (codec, bytes) = encoder.encode(&short);
+        assert_eq!(codec, AudioCodec::Pcm16);
+        assert_eq!(bytes.len(), short.len() * 2);
+
+        let decoded = decoder.decode(codec, &bytes).unwrap();
+        assert!(decoded.iter().all(|s| (s - 0.5).abs() <= 1.0 / i16::MAX as f32));
+        assert!(decoder.decode(AudioCodec::Pcm16, &bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_pcm_codec_skips_opus() {
+        let mut encoder = FrameEncoder::new().unwrap().with_codec(AudioCodec::Pcm);
+        let frame = vec![0.25f32; 960];
+        let (codec, bytes) = encoder.encode(&frame);
+        assert_eq!(codec, AudioCodec::Pcm);
+        assert_eq!(crate::audio::bytes_to_samples(&bytes), frame);
+
+        let mut encoder = FrameEncoder::new()
+            .unwrap()
+            .with_pcm_format(SampleFormat::I16)
+            .with_codec(AudioCodec::Pcm);
+        assert_eq!(encoder.encode(&frame).0, AudioCodec::Pcm);
+        let mut encoder = FrameEncoder::new().unwrap().with_codec(AudioCodec::Pcm16);
+        assert_eq!(encoder.encode(&frame).0, AudioCodec::Pcm16);
+    }
+
+    #[test]
+    fn test_transcoder_follows_sender_frame_duration() {
+        let mut transcoder = Transcoder::new().unwrap();
+        let mut decoder = OpusDecoder::new().unwrap();
+        for duration in [FrameDuration::Ms40, FrameDuration::Ms20, FrameDuration::Ms60] {
+            let pcm = crate::audio::samples_to_bytes(&crate::audio::sine_wave(440.0, 0.5, 48000, 0, duration.samples()));
+            let opus = transcoder.transcode(&pcm, false, AudioCodec::Pcm, AudioCodec::Opus).unwrap();
+            assert_eq!(decoder.decode(&opus).unwrap().len(), duration.samples(), "{:?}", duration);
+        }
+
+        // A length Opus has no frame size for can't be converted
+        let odd = crate::audio::samples_to_bytes(&[0.0; 500]);
+        assert!(transcoder.transcode(&odd, false, AudioCodec::Pcm, AudioCodec::Opus).is_err());
+    }
+
+    #[test]
+    fn test_batching_produces_single_packet() {
+        let mut batcher = FrameBatcher::new(3);
+        let frames: Vec<Vec<u8>> = vec![vec![1; 120], vec![2; 95], vec![3; 140]];
+
+        assert!(batcher.push(frames[0].clone()).is_none());
+        assert!(batcher.push(frames[1].clone()).is_none());
+        let packet = batcher.push(frames[2].clone()).expect("batch should be full");
+
+        assert_eq!(packet.len(), 120 + 95 + 140 + 3 * 2);
+        assert!(batcher.flush().is_none());
+    }
+
+    #[test]
+    fn test_largest_batch_fits_payload_cap() {
+        let frame = crate::audio::samples_to_bytes(&vec![0.0; FrameDuration::Ms60.samples()]);
+        let mut batcher = FrameBatcher::new(10);
+        let packet = (0..MAX_FRAMES_PER_PACKET).find_map(|_| batcher.push(frame.clone())).unwrap();
+        assert_eq!(packet.len(), MAX_AUDIO_PAYLOAD_BYTES);
+    }
+
+    #[test]
+    fn test_unbatching_restores_frames() {
+        let frames: Vec<Vec<u8>> = vec![vec![9; 10], vec![], vec![7; 300]];
+        let packed = pack_frames(&frames);
+        let unpacked = unpack_frames(&packed).expect("unpack failed");
+        assert_eq!(unpacked, frames);
+
+        // Truncated payloads are rejected
+        assert!(unpack_frames(&packed[..packed.len() - 1]).is_err());
+    }
 }
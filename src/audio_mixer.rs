@@ -0,0 +1,326 @@
+//! Audio Mixer
+//!
+//! Sums decoded audio from several room participants into a single
+//! playback stream, with per-sender volume control. For spatial
+//! conferencing the mix can also be interleaved stereo, with each sender
+//! panned to its own position.
+
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::FRAC_PI_4;
+
+/// Positions handed out to senders in join order when no pan is set:
+/// center, then alternating left and right
+const AUTO_PAN_POSITIONS: [f32; 5] = [0.0, -0.6, 0.6, -0.3, 0.3];
+
+/// One mixed frame along with what each sender put into it, so a
+/// per-recipient mix can leave out the recipient's own voice
+#[derive(Debug, Clone)]
+pub struct MixedFrame {
+    pub total: Vec<f32>,
+    parts: HashMap<String, Vec<f32>>,
+}
+
+impl MixedFrame {
+    /// The mix minus `sender_id`'s contribution, or `None` if nobody else
+    /// contributed (the result would be silence)
+    pub fn without(&self, sender_id: &str) -> Option<Vec<f32>> {
+        if self.parts.keys().all(|id| id == sender_id) {
+            return None;
+        }
+        let mut mix = self.total.clone();
+        if let Some(own) = self.parts.get(sender_id) {
+            for (out, sample) in mix.iter_mut().zip(own) {
+                *out -= sample;
+            }
+        }
+        Some(mix)
+    }
+}
+
+/// Mixes per-sender sample queues into one output stream
+pub struct AudioMixer {
+    /// Decoded samples waiting to be mixed, per sender
+    queues: HashMap<String, VecDeque<f32>>,
+    /// Per-sender gain; kept even after a sender's queue drains
+    volumes: HashMap<String, f32>,
+    /// Per-sender stereo position, -1.0 (left) to 1.0 (right)
+    pans: HashMap<String, f32>,
+    /// Senders seen so far, for join-order auto panning
+    joined: usize,
+    /// Most samples queued per sender; older ones are dropped past this
+    max_queued: Option<usize>,
+}
+
+impl AudioMixer {
+    pub fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+            volumes: HashMap::new(),
+            pans: HashMap::new(),
+            joined: 0,
+            max_queued: None,
+        }
+    }
+
+    /// Cap each sender's queue at `samples`. A sender running ahead of the
+    /// mix loses its oldest audio instead of growing the queue.
+    pub fn set_max_queued(&mut self, samples: usize) {
+        self.max_queued = Some(samples);
+    }
+
+    /// Queue decoded samples from a sender
+    pub fn push(&mut self, sender_id: &str, samples: &[f32]) {
+        if !self.pans.contains_key(sender_id) {
+            let pan = AUTO_PAN_POSITIONS[self.joined % AUTO_PAN_POSITIONS.len()];
+            self.pans.insert(sender_id.to_string(), pan);
+            self.joined += 1;
+        }
+        let queue = self.queues.entry(sender_id.to_string()).or_default();
+        queue.extend(samples.iter().copied());
+        if let Some(max) = self.max_queued {
+            let excess = queue.len().saturating_sub(max);
+            queue.drain(..excess);
+        }
+    }
+
+    /// Set the playback gain for one sender (1.0 = unchanged, 0.0 = muted)
+    pub fn set_sender_volume(&mut self, sender_id: &str, gain: f32) {
+        self.volumes.insert(sender_id.to_string(), gain.max(0.0));
+    }
+
+    /// Current gain for a sender
+    pub fn sender_volume(&self, sender_id: &str) -> f32 {
+        self.volumes.get(sender_id).copied().unwrap_or(1.0)
+    }
+
+    /// Set a sender's stereo position (-1.0 = left, 0.0 = center, 1.0 = right).
+    /// Only affects [`mix_stereo`](Self::mix_stereo).
+    pub fn set_sender_pan(&mut self, sender_id: &str, pan: f32) {
+        self.pans.insert(sender_id.to_string(), pan.clamp(-1.0, 1.0));
+    }
+
+    /// Current stereo position for a sender
+    pub fn sender_pan(&self, sender_id: &str) -> f32 {
+        self.pans.get(sender_id).copied().unwrap_or(0.0)
+    }
+
+    /// Whether no sender has audio queued
+    pub fn is_idle(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Number of samples queued for a sender
+    pub fn queued(&self, sender_id: &str) -> usize {
+        self.queues.get(sender_id).map_or(0, |q| q.len())
+    }
+
+    /// Whether the mixer holds any state (queued audio, volume, pan) for a
+    /// sender
+    pub fn has_sender(&self, sender_id: &str) -> bool {
+        self.queues.contains_key(sender_id) || self.volumes.contains_key(sender_id) || self.pans.contains_key(sender_id)
+    }
+
+    /// Forget a sender that left: their queued audio, volume and pan
+    pub fn remove_sender(&mut self, sender_id: &str) {
+        self.queues.remove(sender_id);
+        self.volumes.remove(sender_id);
+        self.pans.remove(sender_id);
+    }
+
+    /// Mix `len` samples, taking whatever each sender has queued.
+    ///
+    /// Senders with fewer than `len` samples contribute silence for the
+    /// remainder. Gains are applied before summing; the sum is not clipped
+    /// here (the playback limiter handles that).
+    pub fn mix(&mut self, len: usize) -> Vec<f32> {
+        let mut output = vec![0.0f32; len];
+        for (sender_id, queue) in self.queues.iter_mut() {
+            let gain = self.volumes.get(sender_id).copied().unwrap_or(1.0);
+            let take = len.min(queue.len());
+            for (out, sample) in output.iter_mut().zip(queue.drain(..take)) {
+                *out += sample * gain;
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        output
+    }
+
+    /// Like [`mix`](Self::mix), also keeping each sender's gained
+    /// contribution
+    pub fn mix_parts(&mut self, len: usize) -> MixedFrame {
+        let mut total = vec![0.0f32; len];
+        let mut parts = HashMap::new();
+        for (sender_id, queue) in self.queues.iter_mut() {
+            let gain = self.volumes.get(sender_id).copied().unwrap_or(1.0);
+            let take = len.min(queue.len());
+            let part: Vec<f32> = queue.drain(..take).map(|sample| sample * gain).collect();
+            for (out, sample) in total.iter_mut().zip(&part) {
+                *out += sample;
+            }
+            parts.insert(sender_id.clone(), part);
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        MixedFrame { total, parts }
+    }
+
+    /// Like [`mix`](Self::mix), but produces `len` interleaved stereo
+    /// frames (`2 * len` samples, left first) with each sender placed at its
+    /// pan position using a constant-power pan law.
+    pub fn mix_stereo(&mut self, len: usize) -> Vec<f32> {
+        let mut output = vec![0.0f32; 2 * len];
+        for (sender_id, queue) in self.queues.iter_mut() {
+            let gain = self.volumes.get(sender_id).copied().unwrap_or(1.0);
+            let angle = (self.pans.get(sender_id).copied().unwrap_or(0.0) + 1.0) * FRAC_PI_4;
+            let (left, right) = (gain * angle.cos(), gain * angle.sin());
+            let take = len.min(queue.len());
+            for (frame, sample) in output.chunks_exact_mut(2).zip(queue.drain(..take)) {
+                frame[0] += sample * left;
+                frame[1] += sample * right;
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        output
+    }
+
+    /// Mix one frame once enough audio is queued.
+    ///
+    /// A frame is ready when every active sender has `frame_len` samples,
+    /// or when any sender has fallen two frames behind (so one stalled
+    /// sender can't hold everyone else back).
+    pub fn pop_ready(&mut self, frame_len: usize) -> Option<Vec<f32>> {
+        self.frame_ready(frame_len).then(|| self.mix(frame_len))
+    }
+
+    /// Stereo counterpart of [`pop_ready`](Self::pop_ready)
+    pub fn pop_ready_stereo(&mut self, frame_len: usize) -> Option<Vec<f32>> {
+        self.frame_ready(frame_len).then(|| self.mix_stereo(frame_len))
+    }
+
+    fn frame_ready(&self, frame_len: usize) -> bool {
+        if self.queues.is_empty() {
+            return false;
+        }
+        let all_ready = self.queues.values().all(|q| q.len() >= frame_len);
+        let any_backlogged = self.queues.values().any(|q| q.len() >= 2 * frame_len);
+        all_ready || any_backlogged
+    }
+}
+
+impl Default for AudioMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_sums_senders() {
+        let mut mixer = AudioMixer::new();
+        mixer.push("a", &[0.1, 0.2, 0.3]);
+        mixer.push("b", &[0.1, 0.1]);
+
+        let mixed = mixer.mix(3);
+        let expected = [0.2, 0.3, 0.3];
+        for (m, e) in mixed.iter().zip(expected.iter()) {
+            assert!((m - e).abs() < 1e-6);
+        }
+        assert!(mixer.pop_ready(1).is_none());
+    }
+
+    #[test]
+    fn test_zero_gain_mutes_only_that_sender() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_sender_volume("loud", 0.0);
+        mixer.push("loud", &[0.9; 4]);
+        mixer.push("quiet", &[0.2; 4]);
+
+        let mixed = mixer.mix(4);
+        for sample in mixed {
+            assert!((sample - 0.2).abs() < 1e-6);
+        }
+
+        assert_eq!(mixer.sender_volume("loud"), 0.0);
+        assert_eq!(mixer.sender_volume("quiet"), 1.0);
+    }
+
+    #[test]
+    fn test_pop_ready_waits_for_all_senders() {
+        let mut mixer = AudioMixer::new();
+        mixer.push("a", &[0.5; 4]);
+        mixer.push("b", &[0.5; 2]);
+        assert!(mixer.pop_ready(4).is_none());
+
+        mixer.push("b", &[0.5; 2]);
+        assert_eq!(mixer.pop_ready(4).unwrap(), vec![1.0; 4]);
+
+        // A backlogged sender forces a frame out even if others are empty-handed
+        mixer.push("a", &[0.5; 8]);
+        mixer.push("b", &[0.5; 1]);
+        assert!(mixer.pop_ready(4).is_some());
+    }
+
+    #[test]
+    fn test_capped_queue_keeps_newest_audio() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_max_queued(4);
+        mixer.push("a", &[0.1, 0.2, 0.3]);
+        mixer.push("a", &[0.4, 0.5, 0.6]);
+        assert_eq!(mixer.queued("a"), 4);
+        assert_eq!(mixer.mix(4), vec![0.3, 0.4, 0.5, 0.6]);
+    }
+
+    #[test]
+    fn test_left_panned_sender_only_reaches_left_channel() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_sender_pan("left", -1.0);
+        mixer.push("left", &[0.5; 4]);
+
+        let mixed = mixer.mix_stereo(4);
+        assert_eq!(mixed.len(), 8);
+        for frame in mixed.chunks_exact(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-6);
+            assert!(frame[1].abs() < 1e-6);
+        }
+
+        // Centered senders split evenly at equal power
+        mixer.set_sender_pan("center", 0.0);
+        mixer.push("center", &[1.0; 2]);
+        let mixed = mixer.mix_stereo(2);
+        assert!((mixed[0] - mixed[1]).abs() < 1e-6);
+        assert!((mixed[0] * mixed[0] + mixed[1] * mixed[1] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_senders_auto_panned_by_join_order() {
+        let mut mixer = AudioMixer::new();
+        for id in ["a", "b", "c"] {
+            mixer.push(id, &[0.1]);
+        }
+        assert_eq!(mixer.sender_pan("a"), 0.0);
+        assert!(mixer.sender_pan("b") < 0.0);
+        assert!(mixer.sender_pan("c") > 0.0);
+
+        mixer.set_sender_pan("b", 5.0);
+        assert_eq!(mixer.sender_pan("b"), 1.0);
+    }
+
+    #[test]
+    fn test_mixed_frame_without_drops_own_voice() {
+        let mut mixer = AudioMixer::new();
+        mixer.push("a", &[0.25; 4]);
+        mixer.push("b", &[0.5; 4]);
+
+        let frame = mixer.mix_parts(4);
+        assert_eq!(frame.total, vec![0.75; 4]);
+        assert_eq!(frame.without("a").unwrap(), vec![0.5; 4]);
+        assert_eq!(frame.without("b").unwrap(), vec![0.25; 4]);
+        assert_eq!(frame.without("listener").unwrap(), vec![0.75; 4]);
+        assert!(mixer.is_idle());
+
+        mixer.push("a", &[0.25; 4]);
+        assert!(mixer.mix_parts(4).without("a").is_none());
+    }
+}
@@ -0,0 +1,271 @@
+//! Call Recording
+//!
+//! Writes call audio to a 48kHz mono WAV file. Samples are handed to a
+//! background writer thread over a bounded channel, so the audio callbacks
+//! never touch the filesystem.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use thiserror::Error;
+
+use crate::audio_mixer::AudioMixer;
+
+const SAMPLE_RATE: u32 = 48000;
+const FRAME_SIZE: usize = 960; // 20ms at 48kHz
+/// Chunks buffered for the writer before new ones are dropped (~2s of 20ms frames)
+const QUEUE_CHUNKS: usize = 100;
+
+/// Recording errors
+#[derive(Error, Debug)]
+pub enum RecorderError {
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("Recorder writer thread panicked")]
+    WriterPanicked,
+}
+
+/// Which stream a chunk of samples came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSource {
+    /// Mixed playback of the other participants
+    Call,
+    /// Local microphone
+    Mic,
+}
+
+impl RecordSource {
+    fn key(self) -> &'static str {
+        match self {
+            RecordSource::Call => "call",
+            RecordSource::Mic => "mic",
+        }
+    }
+}
+
+enum WriterMessage {
+    Samples(RecordSource, Vec<f32>),
+    Stop,
+}
+
+/// Cheap, cloneable handle for feeding samples from audio callbacks
+#[derive(Clone)]
+pub struct RecorderTap {
+    tx: SyncSender<WriterMessage>,
+    include_mic: bool,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RecorderTap {
+    /// Queue samples for writing. Never blocks; if the writer has fallen
+    /// behind the chunk is dropped and counted.
+    pub fn push(&self, source: RecordSource, samples: &[f32]) {
+        if source == RecordSource::Mic && !self.include_mic {
+            return;
+        }
+        match self.tx.try_send(WriterMessage::Samples(source, samples.to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+/// Records call audio to a WAV file
+pub struct AudioRecorder {
+    tap: RecorderTap,
+    writer: Option<JoinHandle<Result<u64, RecorderError>>>,
+    path: PathBuf,
+}
+
+impl AudioRecorder {
+    /// Create the WAV file and start the writer thread.
+    ///
+    /// With `include_mic`, microphone samples are mixed into the recording
+    /// alongside the call; otherwise they are ignored.
+    pub fn start(path: impl AsRef<Path>, include_mic: bool) -> Result<Self, RecorderError> {
+        let path = path.as_ref().to_path_buf();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        // Open up front so a bad path fails here rather than on the writer thread
+        let wav = hound::WavWriter::create(&path, spec)?;
+
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CHUNKS);
+        let writer = std::thread::Builder::new()
+            .name("audio-recorder".to_string())
+            .spawn(move || write_loop(wav, rx, include_mic))
+            .map_err(|e| RecorderError::Wav(hound::Error::IoError(e)))?;
+
+        log::info!("Recording call audio to {}", path.display());
+        Ok(Self {
+            tap: RecorderTap {
+                tx,
+                include_mic,
+                dropped: Arc::new(AtomicU64::new(0)),
+            },
+            writer: Some(writer),
+            path,
+        })
+    }
+
+    /// Handle for feeding samples from other threads
+    pub fn tap(&self) -> RecorderTap {
+        self.tap.clone()
+    }
+
+    /// Queue samples for writing (see [`RecorderTap::push`])
+    pub fn push(&self, source: RecordSource, samples: &[f32]) {
+        self.tap.push(source, samples);
+    }
+
+    /// Chunks dropped because the writer couldn't keep up
+    pub fn dropped_chunks(&self) -> u64 {
+        self.tap.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush queued audio, finalize the WAV header and return the samples written
+    pub fn stop(mut self) -> Result<u64, RecorderError> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<u64, RecorderError> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(0),
+        };
+        // Blocking send is fine here: we're on the caller's thread, not a callback
+        let _ = self.tap.tx.send(WriterMessage::Stop);
+        let written = writer.join().map_err(|_| RecorderError::WriterPanicked)??;
+        log::info!("Recording saved: {} ({} samples)", self.path.display(), written);
+        Ok(written)
+    }
+}
+
+impl Drop for AudioRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to finish recording: {}", e);
+        }
+    }
+}
+
+/// Writer thread: mix sources frame by frame and append to the WAV
+fn write_loop<W: std::io::Write + std::io::Seek>(
+    mut wav: hound::WavWriter<W>,
+    rx: Receiver<WriterMessage>,
+    include_mic: bool,
+) -> Result<u64, RecorderError> {
+    let sources: &[RecordSource] = if include_mic {
+        &[RecordSource::Call, RecordSource::Mic]
+    } else {
+        &[RecordSource::Call]
+    };
+    let mut mixer = AudioMixer::new();
+    let mut written = 0u64;
+
+    let mut write = |samples: &[f32], wav: &mut hound::WavWriter<W>| -> Result<(), RecorderError> {
+        for sample in samples {
+            wav.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        written += samples.len() as u64;
+        Ok(())
+    };
+
+    // A closed channel (all taps and the recorder gone) also ends the recording
+    while let Ok(WriterMessage::Samples(source, samples)) = rx.recv() {
+        mixer.push(source.key(), &samples);
+
+        // Wait for every source so they stay aligned, unless one is running
+        // two frames ahead (e.g. nobody else is talking)
+        loop {
+            let queued: Vec<usize> = sources.iter().map(|s| mixer.queued(s.key())).collect();
+            let all_ready = queued.iter().all(|&n| n >= FRAME_SIZE);
+            let backlogged = queued.iter().any(|&n| n >= 2 * FRAME_SIZE);
+            if !(all_ready || backlogged) {
+                break;
+            }
+            write(&mixer.mix(FRAME_SIZE), &mut wav)?;
+        }
+    }
+
+    // Flush whatever is left, including a final partial frame
+    let remaining = sources.iter().map(|s| mixer.queued(s.key())).max().unwrap_or(0);
+    if remaining > 0 {
+        write(&mixer.mix(remaining), &mut wav)?;
+    }
+
+    wav.finalize()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_wav() -> PathBuf {
+        std::env::temp_dir().join(format!("pqc-recorder-{}.wav", uuid::Uuid::new_v4()))
+    }
+
+    fn read_wav(path: &Path) -> (hound::WavSpec, Vec<f32>) {
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let spec = reader.spec();
+        let samples = reader
+            .samples::<i16>()
+            .map(|s| s.unwrap() as f32 / i16::MAX as f32)
+            .collect();
+        (spec, samples)
+    }
+
+    #[test]
+    fn test_recorded_samples_match() {
+        let path = temp_wav();
+        let input: Vec<f32> = (0..2500).map(|i| ((i % 200) as f32 / 100.0) - 1.0).collect();
+
+        let recorder = AudioRecorder::start(&path, false).unwrap();
+        for chunk in input.chunks(FRAME_SIZE) {
+            recorder.push(RecordSource::Call, chunk);
+        }
+        // Mic is ignored unless requested
+        recorder.push(RecordSource::Mic, &[0.5; FRAME_SIZE]);
+        assert_eq!(recorder.stop().unwrap(), input.len() as u64);
+
+        let (spec, samples) = read_wav(&path);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(spec.channels, 1);
+        assert_eq!(samples.len(), input.len());
+        for (got, want) in samples.iter().zip(input.iter()) {
+            assert!((got - want).abs() <= 1.0 / i16::MAX as f32);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mic_mixed_into_recording() {
+        let path = temp_wav();
+
+        let recorder = AudioRecorder::start(&path, true).unwrap();
+        let tap = recorder.tap();
+        tap.push(RecordSource::Call, &[0.25; FRAME_SIZE]);
+        tap.push(RecordSource::Mic, &[0.25; FRAME_SIZE]);
+        recorder.stop().unwrap();
+
+        let (_, samples) = read_wav(&path);
+        assert_eq!(samples.len(), FRAME_SIZE);
+        assert!(samples.iter().all(|s| (s - 0.5).abs() < 0.001));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -14,6 +14,7 @@ use tokio_rustls::rustls::{self, pki_types::ServerName};
 use tokio_rustls::TlsConnector;
 
 use pqc_chat::crypto::kyber::KyberKeyExchange;
+use pqc_chat::crypto::tls::NoVerifier;
 use pqc_chat::protocol::SignalingMessage;
 
 /// Command-line arguments
@@ -230,6 +231,19 @@ async fn perform_connection_test(host: &str, port: u16, username: &str, attempt:
         }
     };
 
+    // Server banner comes first
+    match receive_message(&mut tls_stream).await {
+        Ok(SignalingMessage::ServerInfo { .. }) => {}
+        Ok(other) => {
+            metrics.error = Some(format!("Expected server info, got {:?}", other));
+            return metrics;
+        }
+        Err(e) => {
+            metrics.error = Some(format!("Failed to receive server info: {}", e));
+            return metrics;
+        }
+    }
+
     // Kyber Key Generation
     let keygen_start = Instant::now();
     let kyber = KyberKeyExchange::new();
@@ -269,6 +283,7 @@ async fn perform_connection_test(host: &str, port: u16, username: &str, attempt:
     let login_start = Instant::now();
     let login = SignalingMessage::Login {
         username: username.to_string(),
+        resume_token: None,
     };
 
     if let Err(e) = send_message(&mut tls_stream, &login).await {
@@ -412,53 +427,4 @@ async fn receive_message(
     stream.read_exact(&mut msg_buf).await?;
 
     Ok(SignalingMessage::from_bytes(&msg_buf)?)
-}
-
-#[derive(Debug)]
-struct NoVerifier;
-
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
-
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::ED25519,
-        ]
-    }
 }
\ No newline at end of file
@@ -127,6 +127,10 @@ async fn main() -> Result<()> {
 
     info!("Connected to server");
 
+    if let SignalingMessage::ServerInfo { name, version, .. } = receive_message(&mut tls_stream).await? {
+        info!("Server: {} v{}", name, version);
+    }
+
     // Perform Kyber key exchange
     let key_init = SignalingMessage::KeyExchangeInit {
         public_key: engine.get_public_key(),
@@ -144,6 +148,7 @@ async fn main() -> Result<()> {
     // Login
     let login = SignalingMessage::Login {
         username: username.clone(),
+        resume_token: None,
     };
     send_message(&mut tls_stream, &login).await?;
 
@@ -152,6 +157,7 @@ async fn main() -> Result<()> {
         success,
         participant_id,
         error,
+        ..
     } = response
     {
         if success {
@@ -164,11 +170,11 @@ async fn main() -> Result<()> {
     }
 
     // Interactive mode - list rooms
-    let list_rooms = SignalingMessage::ListRooms;
+    let list_rooms = SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false };
     send_message(&mut tls_stream, &list_rooms).await?;
 
     let response = receive_message(&mut tls_stream).await?;
-    if let SignalingMessage::RoomList { rooms } = response {
+    if let SignalingMessage::RoomList { rooms, .. } = response {
         info!("Available rooms:");
         for room in &rooms {
             info!(
@@ -182,6 +188,7 @@ async fn main() -> Result<()> {
             let create_room = SignalingMessage::CreateRoom {
                 name: "Test Room".to_string(),
                 max_participants: Some(10),
+                slug: None,
             };
             send_message(&mut tls_stream, &create_room).await?;
             
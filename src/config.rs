@@ -19,14 +19,154 @@ pub struct ServerConfig {
     pub ca_certfile: Option<PathBuf>,
     #[serde(default = "default_max_participants")]
     pub default_max_participants: u32,
+    /// Largest room size a client may ask for (0 = no limit)
+    #[serde(default)]
+    pub max_room_participants: u32,
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    /// Maximum number of rooms on the server (0 = unlimited, the default)
+    #[serde(default = "default_max_rooms")]
+    pub max_rooms: usize,
+    /// Maximum number of rooms a single username may create (0 = unlimited,
+    /// the default)
+    #[serde(default = "default_max_rooms_per_user")]
+    pub max_rooms_per_user: usize,
+    /// Seconds without signaling activity before a user is marked away (0 = never)
+    #[serde(default = "default_idle_away_secs")]
+    pub idle_away_secs: u64,
+    /// Seconds without any traffic before a client is dropped (0 = never)
+    #[serde(default)]
+    pub client_timeout_secs: u64,
+    /// Seconds a new connection gets to finish the TLS handshake and log in
+    /// before it is dropped (0 = never)
+    #[serde(default = "default_handshake_timeout_secs")]
+    pub handshake_timeout_secs: u64,
+    /// Tell room members each other's observed UDP endpoints for peer-to-peer audio
+    #[serde(default)]
+    pub udp_peer_assist: bool,
+    /// Pending-connection backlog for the signaling listener
+    #[serde(default = "default_listen_backlog")]
+    pub listen_backlog: u32,
+    /// Outbound messages queued per client before audio frames are shed
+    #[serde(default = "default_client_queue_depth")]
+    pub client_queue_depth: usize,
+    /// Longest username accepted in `Login`/`JoinRoom`, in characters
+    #[serde(default = "default_max_username_len")]
+    pub max_username_len: usize,
+    /// Create an "Echo Test" room that plays your own audio back to you
+    #[serde(default = "default_echo_test_room")]
+    pub echo_test_room: bool,
+    /// Re-encode audio to each recipient's negotiated codec, and into a
+    /// room's `required_codec` (CPU heavy)
+    #[serde(default)]
+    pub transcode_audio: bool,
+    /// Forward audio from participants who have muted themselves
+    #[serde(default)]
+    pub relay_muted_audio: bool,
+    /// Skip relaying audio packets whose frames all have an RMS below this
+    /// (0.0 = relay everything). Decodes every sender's audio.
+    #[serde(default)]
+    pub relay_silence_threshold: f32,
+    /// Mix each room's audio on the server and send every member a single
+    /// stream without their own voice (CPU heavy; saves client CPU)
+    #[serde(default)]
+    pub mix_audio: bool,
+    /// Drop audio captured longer ago than this many milliseconds instead of
+    /// relaying it, over signaling or UDP (0 = never). Compares against the
+    /// sender's clock.
+    #[serde(default)]
+    pub max_audio_age_ms: u64,
+    /// Reject `AudioData` payloads larger than this many bytes (0 = no
+    /// separate cap). The default fits the largest payload a client sends.
+    #[serde(default = "default_max_audio_payload_bytes")]
+    pub max_audio_payload_bytes: usize,
+    /// Append every signaling message, in and out, to this NDJSON file
+    #[serde(default)]
+    pub signaling_log: Option<PathBuf>,
+    /// Room-mutating commands (create, join, leave, ...) a client may send
+    /// back to back before being throttled (0 = unlimited)
+    #[serde(default = "default_room_ops_burst")]
+    pub room_ops_burst: u32,
+    /// Rate at which that allowance refills, per second; must be positive
+    /// while the burst is limited
+    #[serde(default = "default_room_ops_per_sec")]
+    pub room_ops_per_sec: f64,
+    /// Name shown to clients in the `ServerInfo` banner
+    #[serde(default = "default_server_name")]
+    pub server_name: String,
+    /// Message of the day, sent in the `ServerInfo` banner and as a system
+    /// chat message on joining a room
+    #[serde(default)]
+    pub motd: Option<String>,
+    /// File to read the message of the day from instead, re-read whenever
+    /// it changes so it can be edited without a restart
+    #[serde(default)]
+    pub motd_file: Option<PathBuf>,
+    /// Delete client-created rooms once nobody is left in them
+    #[serde(default)]
+    pub reap_empty_rooms: bool,
+    /// Seconds a room is kept after a member drops (rather than leaves), so
+    /// a quick reconnect finds it still there
+    #[serde(default = "default_reconnect_grace_secs")]
+    pub reconnect_grace_secs: u64,
 }
 
 fn default_max_participants() -> u32 {
     10
 }
 
+fn default_max_rooms() -> usize {
+    0
+}
+
+fn default_max_rooms_per_user() -> usize {
+    0
+}
+
+fn default_idle_away_secs() -> u64 {
+    300
+}
+
+fn default_listen_backlog() -> u32 {
+    128
+}
+
+fn default_handshake_timeout_secs() -> u64 {
+    10
+}
+
+fn default_client_queue_depth() -> usize {
+    256
+}
+
+fn default_max_username_len() -> usize {
+    32
+}
+
+fn default_max_audio_payload_bytes() -> usize {
+    crate::audio_codec::MAX_AUDIO_PAYLOAD_BYTES
+}
+
+fn default_echo_test_room() -> bool {
+    true
+}
+
+fn default_room_ops_burst() -> u32 {
+    10
+}
+
+fn default_room_ops_per_sec() -> f64 {
+    2.0
+}
+
+fn default_reconnect_grace_secs() -> u64 {
+    30
+}
+
+fn default_server_name() -> String {
+    "PQC Chat".to_string()
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -43,7 +183,32 @@ impl Default for ServerConfig {
             keyfile: PathBuf::from("server.key"),
             ca_certfile: None,
             default_max_participants: 10,
+            max_room_participants: 0,
             log_level: "info".to_string(),
+            max_rooms: default_max_rooms(),
+            max_rooms_per_user: default_max_rooms_per_user(),
+            idle_away_secs: 300,
+            client_timeout_secs: 0,
+            handshake_timeout_secs: default_handshake_timeout_secs(),
+            udp_peer_assist: false,
+            listen_backlog: 128,
+            client_queue_depth: default_client_queue_depth(),
+            max_username_len: default_max_username_len(),
+            echo_test_room: default_echo_test_room(),
+            transcode_audio: false,
+            relay_muted_audio: false,
+            relay_silence_threshold: 0.0,
+            mix_audio: false,
+            max_audio_age_ms: 0,
+            max_audio_payload_bytes: default_max_audio_payload_bytes(),
+            signaling_log: None,
+            room_ops_burst: default_room_ops_burst(),
+            room_ops_per_sec: default_room_ops_per_sec(),
+            server_name: default_server_name(),
+            motd: None,
+            motd_file: None,
+            reap_empty_rooms: false,
+            reconnect_grace_secs: default_reconnect_grace_secs(),
         }
     }
 }
@@ -56,6 +221,56 @@ impl ServerConfig {
         toml::from_str(&content)
             .map_err(|e| ConfigError::ParseError(e.to_string()))
     }
+
+    /// Check for values that parse but can't work, reporting every problem
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        for (name, port) in [
+            ("signaling_port", self.signaling_port),
+            ("audio_port", self.audio_port),
+            ("video_port", self.video_port),
+        ] {
+            if port == 0 {
+                problems.push(format!("{} must not be 0", name));
+            }
+        }
+        if self.audio_port == self.video_port {
+            problems.push(format!("audio_port and video_port are both {}", self.audio_port));
+        }
+        if self.default_max_participants == 0 {
+            problems.push("default_max_participants must be at least 1".to_string());
+        }
+        if self.max_room_participants > 0 && self.default_max_participants > self.max_room_participants {
+            problems.push(format!(
+                "default_max_participants {} exceeds max_room_participants {}",
+                self.default_max_participants, self.max_room_participants
+            ));
+        }
+        if self.room_ops_burst > 0 && !(self.room_ops_per_sec > 0.0 && self.room_ops_per_sec.is_finite()) {
+            problems.push(format!("room_ops_per_sec {} must be above 0", self.room_ops_per_sec));
+        }
+        if self.client_queue_depth == 0 {
+            problems.push("client_queue_depth must be at least 1".to_string());
+        }
+        if self.listen_backlog == 0 {
+            problems.push("listen_backlog must be at least 1".to_string());
+        }
+        if self.max_username_len == 0 {
+            problems.push("max_username_len must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.relay_silence_threshold) {
+            problems.push(format!("relay_silence_threshold {} must be between 0.0 and 1.0", self.relay_silence_threshold));
+        }
+        if self.log_level.parse::<log::LevelFilter>().is_err() {
+            problems.push(format!("unknown log_level {:?}", self.log_level));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
 }
 
 /// Client configuration
@@ -128,6 +343,66 @@ pub struct AudioConfig {
     pub channels: u8,
     #[serde(default)]
     pub device_index: Option<u32>,
+    /// Pick the input device whose name contains this (case-insensitive)
+    #[serde(default)]
+    pub preferred_input_name: Option<String>,
+    /// Pick the output device whose name contains this (case-insensitive)
+    #[serde(default)]
+    pub preferred_output_name: Option<String>,
+    /// Number of encoded capture frames batched into one `AudioData` packet
+    /// (at most `audio_codec::MAX_FRAMES_PER_PACKET`)
+    #[serde(default = "default_frames_per_packet")]
+    pub frames_per_packet: u32,
+    /// Soft-limit playback so loud or summed streams don't clip
+    #[serde(default = "default_limiter_enabled")]
+    pub limiter_enabled: bool,
+    /// Buffer sizes and jitter target as a latency/robustness tradeoff
+    #[serde(default)]
+    pub profile: crate::audio::AudioProfile,
+    /// Silence queued before playback starts to avoid a first-packet glitch
+    #[serde(default)]
+    pub prefill_ms: u32,
+    /// Received audio held per sender before playout first starts, separate
+    /// from `prefill_ms` (0 = the profile's jitter target)
+    #[serde(default)]
+    pub jitter_prefill_ms: u32,
+    /// Sample layout for audio sent as raw PCM (i16 halves the bandwidth)
+    #[serde(default)]
+    pub pcm_sample_format: crate::audio::SampleFormat,
+    /// Largest UDP audio packet in bytes; larger payloads are fragmented
+    #[serde(default = "default_udp_packet_size")]
+    pub udp_packet_size: usize,
+    /// End a call after this many seconds without speech either way (0 = never)
+    #[serde(default = "default_silence_timeout_secs")]
+    pub silence_timeout_secs: u64,
+    /// RMS level below which a frame counts as silence
+    #[serde(default = "default_silence_threshold")]
+    pub silence_threshold: f32,
+    /// Play calls in stereo with each participant panned to their own position
+    #[serde(default)]
+    pub stereo_panning: bool,
+    /// RMS level of the noise played during gaps in received audio, so
+    /// silence doesn't sound like a dropped call (0.0 = off)
+    #[serde(default = "default_comfort_noise_level")]
+    pub comfort_noise_level: f32,
+    /// Opus discontinuous transmission: stop sending frames during silence
+    #[serde(default)]
+    pub opus_dtx: bool,
+    /// Opus frame length in milliseconds: 10, 20, 40 or 60
+    #[serde(default = "default_frame_duration_ms")]
+    pub frame_duration_ms: u32,
+    /// Real-time (SCHED_FIFO) priority for the audio send thread, 1-99
+    /// (0 = normal scheduling)
+    #[serde(default)]
+    pub send_thread_priority: u8,
+    /// Gain of your own mic in your playback when monitoring is toggled on
+    /// (capped at 0.5 to avoid feedback over speakers)
+    #[serde(default = "default_monitor_gain")]
+    pub monitor_gain: f32,
+    /// How to convert mics that can't capture at 48kHz: "fast" (linear) or
+    /// "high" (sinc). Defaults to fast on ARM boards like the Pi.
+    #[serde(default)]
+    pub resampler_quality: crate::audio_resampler::ResamplerQuality,
 }
 
 fn default_sample_rate() -> u32 {
@@ -138,16 +413,89 @@ fn default_channels() -> u8 {
     1
 }
 
+fn default_comfort_noise_level() -> f32 {
+    0.0005
+}
+
+fn default_monitor_gain() -> f32 {
+    0.15
+}
+
+fn default_frame_duration_ms() -> u32 {
+    20
+}
+
+fn default_frames_per_packet() -> u32 {
+    1
+}
+
+fn default_limiter_enabled() -> bool {
+    true
+}
+
+fn default_udp_packet_size() -> usize {
+    crate::media::SAFE_UDP_PACKET_SIZE
+}
+
+fn default_silence_timeout_secs() -> u64 {
+    300
+}
+
+fn default_silence_threshold() -> f32 {
+    0.01
+}
+
 impl Default for AudioConfig {
     fn default() -> Self {
         Self {
             sample_rate: 48000,
             channels: 1,
             device_index: None,
+            preferred_input_name: None,
+            preferred_output_name: None,
+            frames_per_packet: 1,
+            limiter_enabled: true,
+            profile: crate::audio::AudioProfile::default(),
+            prefill_ms: 0,
+            jitter_prefill_ms: 0,
+            pcm_sample_format: crate::audio::SampleFormat::default(),
+            udp_packet_size: default_udp_packet_size(),
+            silence_timeout_secs: default_silence_timeout_secs(),
+            silence_threshold: default_silence_threshold(),
+            stereo_panning: false,
+            comfort_noise_level: default_comfort_noise_level(),
+            opus_dtx: false,
+            frame_duration_ms: default_frame_duration_ms(),
+            send_thread_priority: 0,
+            monitor_gain: default_monitor_gain(),
+            resampler_quality: crate::audio_resampler::ResamplerQuality::default(),
         }
     }
 }
 
+impl AudioConfig {
+    /// Configured Opus frame duration, or 20ms if it isn't one Opus supports
+    pub fn frame_duration(&self) -> crate::audio_codec::FrameDuration {
+        use crate::audio_codec::FrameDuration;
+
+        FrameDuration::from_millis(self.frame_duration_ms).unwrap_or_else(|| {
+            log::warn!(
+                "frame_duration_ms {} is not 10, 20, 40 or 60; using 20",
+                self.frame_duration_ms
+            );
+            FrameDuration::default()
+        })
+    }
+
+    /// A receive jitter buffer for one sender, holding the profile's jitter
+    /// target once `jitter_prefill_ms` has warmed it up
+    pub fn jitter_buffer(&self) -> crate::jitter_buffer::JitterBuffer {
+        let target_ms = self.profile.jitter_target_ms();
+        let prefill_ms = if self.jitter_prefill_ms == 0 { target_ms } else { self.jitter_prefill_ms };
+        crate::jitter_buffer::JitterBuffer::new(target_ms, prefill_ms)
+    }
+}
+
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
@@ -176,6 +524,43 @@ impl ClientConfig {
     }
 }
 
+/// Audio devices picked in the GUI, remembered between runs.
+///
+/// Stored by name rather than index, since indices shift as devices are
+/// plugged in and out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceSelection {
+    #[serde(default)]
+    pub input_name: Option<String>,
+    #[serde(default)]
+    pub output_name: Option<String>,
+}
+
+impl DeviceSelection {
+    /// Load a saved selection; a missing or unreadable file means defaults
+    pub fn load(path: &str) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ConfigError> {
+        let content = toml::to_string(self).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+        std::fs::write(path, content).map_err(|e| ConfigError::IoError(e.to_string()))
+    }
+
+    /// Override the config's preferred device names with the chosen ones
+    pub fn apply(&self, audio: &mut AudioConfig) {
+        if let Some(name) = &self.input_name {
+            audio.preferred_input_name = Some(name.clone());
+        }
+        if let Some(name) = &self.output_name {
+            audio.preferred_output_name = Some(name.clone());
+        }
+    }
+}
+
 /// Configuration errors
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -183,6 +568,8 @@ pub enum ConfigError {
     IoError(String),
     #[error("Parse error: {0}")]
     ParseError(String),
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
 }
 
 #[cfg(test)]
@@ -197,6 +584,23 @@ mod tests {
         assert_eq!(config.video_port, 10001);
     }
 
+    #[test]
+    fn test_validate_reports_every_problem() {
+        assert!(ServerConfig::default().validate().is_ok());
+
+        let config = ServerConfig {
+            video_port: 10000,
+            client_queue_depth: 0,
+            max_room_participants: 5,
+            room_ops_per_sec: 0.0,
+            ..ServerConfig::default()
+        };
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(problems.len(), 4),
+            other => panic!("expected Invalid, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_default_client_config() {
         let config = ClientConfig::default();
@@ -0,0 +1,227 @@
+//! End-to-End Encrypted Chat
+//!
+//! Chat content is encrypted client-side under a per-room key, so the server
+//! relays ciphertext it cannot read, independent of the transport TLS.
+//!
+//! The room key is agreed between two participants with a Kyber exchange
+//! relayed through the server: one side publishes a [`RoomKeyOffer`], the
+//! other answers with [`accept_offer`], and both end up with the same
+//! [`RoomKey`]. Messages are sealed with ChaCha20-Poly1305, bound to the
+//! room ID so a ciphertext can't be replayed into another room.
+//!
+//! The exchange itself is not authenticated: participants have no long-term
+//! keys, so a server that substitutes its own public key and ciphertext can
+//! agree a key with each side and read everything in between. Participants
+//! detect that by comparing [`RoomKey::safety_code`] out of band; until they
+//! have, the server must be trusted not to interfere.
+
+use hkdf::Hkdf;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::kyber::{KyberError, KyberKeyExchange};
+
+/// Length of a room key in bytes
+pub const ROOM_KEY_LEN: usize = 32;
+
+/// Transcript hash bytes shown in a safety code
+const SAFETY_CODE_LEN: usize = 16;
+
+/// Errors from end-to-end chat encryption
+#[derive(Error, Debug)]
+pub enum E2eError {
+    #[error("Key exchange failed: {0}")]
+    KeyExchange(#[from] KyberError),
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    #[error("Decryption failed")]
+    DecryptionFailed,
+    #[error("Decrypted message is not valid UTF-8")]
+    InvalidUtf8,
+}
+
+/// Symmetric key shared by the participants of one room
+#[derive(Clone)]
+pub struct RoomKey {
+    room_id: String,
+    key: [u8; ROOM_KEY_LEN],
+    /// Hash of the public key and ciphertext the key was agreed from
+    transcript: [u8; 32],
+}
+
+impl RoomKey {
+    /// Derive the room key from a pairwise Kyber exchange: the offered
+    /// public key, the answering ciphertext and the resulting shared secret
+    pub fn from_exchange(public_key: &[u8], ciphertext: &[u8], shared_secret: &[u8], room_id: &str) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(Some(room_id.as_bytes()), shared_secret);
+        let mut key = [0u8; ROOM_KEY_LEN];
+        hkdf.expand(b"pqc-chat room key", &mut key)
+            .expect("HKDF output length is valid");
+        let transcript = Sha256::new()
+            .chain_update(b"pqc-chat room key transcript")
+            .chain_update((room_id.len() as u64).to_be_bytes())
+            .chain_update(room_id.as_bytes())
+            .chain_update((public_key.len() as u64).to_be_bytes())
+            .chain_update(public_key)
+            .chain_update(ciphertext)
+            .finalize()
+            .into();
+        Self {
+            room_id: room_id.to_string(),
+            key,
+            transcript,
+        }
+    }
+
+    /// Room this key belongs to
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+
+    /// Code for both participants to compare out of band, e.g.
+    /// `3f2a 91c0 ...`. It covers the exchanged public key and ciphertext,
+    /// so a server that ran a separate exchange with each side leaves them
+    /// with different codes.
+    pub fn safety_code(&self) -> String {
+        self.transcript[..SAFETY_CODE_LEN]
+            .chunks(2)
+            .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &self.key).expect("key length is valid"))
+    }
+
+    /// Encrypt a chat message. Output is `[nonce][ciphertext + tag]`.
+    pub fn encrypt(&self, plaintext: &str) -> Result<Vec<u8>, E2eError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| E2eError::EncryptionFailed)?;
+
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.aead_key()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.room_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| E2eError::EncryptionFailed)?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        Ok(payload)
+    }
+
+    /// Decrypt a payload produced by [`RoomKey::encrypt`]
+    pub fn decrypt(&self, payload: &[u8]) -> Result<String, E2eError> {
+        if payload.len() < NONCE_LEN {
+            return Err(E2eError::DecryptionFailed);
+        }
+        let (nonce, sealed) = payload.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| E2eError::DecryptionFailed)?;
+
+        let mut buffer = sealed.to_vec();
+        let plaintext = self
+            .aead_key()
+            .open_in_place(nonce, Aad::from(self.room_id.as_bytes()), &mut buffer)
+            .map_err(|_| E2eError::DecryptionFailed)?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| E2eError::InvalidUtf8)
+    }
+}
+
+/// Initiator side of the two-party room key agreement
+pub struct RoomKeyOffer {
+    room_id: String,
+    kyber: KyberKeyExchange,
+}
+
+impl RoomKeyOffer {
+    pub fn new(room_id: &str) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            kyber: KyberKeyExchange::new(),
+        }
+    }
+
+    /// Public key to send to the other participant
+    pub fn public_key(&self) -> Vec<u8> {
+        self.kyber.public_key_bytes()
+    }
+
+    /// Finish the agreement with the responder's ciphertext
+    pub fn complete(self, ciphertext: &[u8]) -> Result<RoomKey, E2eError> {
+        let shared_secret = self.kyber.decapsulate(ciphertext)?;
+        Ok(RoomKey::from_exchange(&self.public_key(), ciphertext, &shared_secret, &self.room_id))
+    }
+}
+
+/// Responder side: answer an offer, returning the ciphertext to send back
+/// and the agreed room key. The offer is unauthenticated; see the module
+/// docs on comparing safety codes.
+pub fn accept_offer(public_key_bytes: &[u8], room_id: &str) -> Result<(Vec<u8>, RoomKey), E2eError> {
+    let public_key = KyberKeyExchange::public_key_from_bytes(public_key_bytes)?;
+    let (ciphertext, shared_secret) = KyberKeyExchange::encapsulate(&public_key);
+    let key = RoomKey::from_exchange(public_key_bytes, &ciphertext, &shared_secret, room_id);
+    Ok((ciphertext, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agreed_keys(room_id: &str) -> (RoomKey, RoomKey) {
+        let offer = RoomKeyOffer::new(room_id);
+        let (ciphertext, responder) = accept_offer(&offer.public_key(), room_id).unwrap();
+        (offer.complete(&ciphertext).unwrap(), responder)
+    }
+
+    #[test]
+    fn test_both_sides_decrypt_identically() {
+        let (alice, bob) = agreed_keys("room-1");
+
+        let payload = alice.encrypt("meet at noon").unwrap();
+        assert!(!payload.windows(4).any(|w| w == b"meet"));
+        assert_eq!(bob.decrypt(&payload).unwrap(), "meet at noon");
+        assert_eq!(alice.decrypt(&payload).unwrap(), "meet at noon");
+    }
+
+    #[test]
+    fn test_tampered_or_foreign_payload_rejected() {
+        let (alice, bob) = agreed_keys("room-1");
+        let mut payload = alice.encrypt("hello").unwrap();
+
+        // Same secret, different room
+        let mut foreign = bob.clone();
+        foreign.room_id = "room-2".to_string();
+        assert!(foreign.decrypt(&payload).is_err());
+
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+        assert!(bob.decrypt(&payload).is_err());
+        assert!(bob.decrypt(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn test_relay_substituting_keys_changes_safety_code() {
+        let (alice, bob) = agreed_keys("room-1");
+        assert_eq!(alice.safety_code(), bob.safety_code());
+
+        // The relay answers Alice itself and makes its own offer to Bob
+        let alice_offer = RoomKeyOffer::new("room-1");
+        let (to_alice, relay_alice) = accept_offer(&alice_offer.public_key(), "room-1").unwrap();
+        let alice = alice_offer.complete(&to_alice).unwrap();
+        let relay_offer = RoomKeyOffer::new("room-1");
+        let (to_relay, bob) = accept_offer(&relay_offer.public_key(), "room-1").unwrap();
+        let relay_bob = relay_offer.complete(&to_relay).unwrap();
+
+        // Each half of the relay matches its victim, but the victims differ
+        assert_eq!(alice.safety_code(), relay_alice.safety_code());
+        assert_eq!(bob.safety_code(), relay_bob.safety_code());
+        assert_ne!(alice.safety_code(), bob.safety_code());
+    }
+}
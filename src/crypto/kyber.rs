@@ -6,9 +6,14 @@
 use pqcrypto_kyber::kyber1024::{
     self, Ciphertext, PublicKey, SecretKey,
 };
+use hkdf::Hkdf;
 use pqcrypto_traits::kem::{Ciphertext as _, PublicKey as _, SharedSecret as _};
+use sha2::Sha256;
 use thiserror::Error;
 
+#[cfg(all(feature = "test-rng", not(debug_assertions)))]
+compile_error!("the `test-rng` feature is for tests only and must not be enabled in release builds");
+
 /// Errors that can occur during Kyber operations
 #[derive(Error, Debug)]
 pub enum KyberError {
@@ -42,6 +47,29 @@ impl KyberKeyExchange {
         }
     }
 
+    /// Generate a reproducible key pair from a fixed seed.
+    ///
+    /// Only available with the `test-rng` feature. Key generation runs on a
+    /// ChaCha20 CSPRNG seeded with `seed`, and the resulting keys use the
+    /// standard Kyber1024 encoding, so they interoperate with `encapsulate`
+    /// and `decapsulate` like any other key pair.
+    #[cfg(feature = "test-rng")]
+    pub fn from_seed(seed: [u8; 32]) -> Result<Self, KyberError> {
+        use pqcrypto_traits::kem::SecretKey as _;
+        use rand_chacha::rand_core::SeedableRng;
+
+        let mut rng = rand_chacha::ChaCha20Rng::from_seed(seed);
+        let keys = pqc_kyber::keypair(&mut rng).map_err(|_| KyberError::KeyGenerationFailed)?;
+        let public_key = PublicKey::from_bytes(&keys.public)
+            .map_err(|_| KyberError::InvalidPublicKeyLength)?;
+        let secret_key = SecretKey::from_bytes(&keys.secret)
+            .map_err(|_| KyberError::InvalidSecretKeyLength)?;
+        Ok(Self {
+            public_key,
+            secret_key,
+        })
+    }
+
     /// Get the public key bytes for transmission
     pub fn public_key_bytes(&self) -> Vec<u8> {
         self.public_key.as_bytes().to_vec()
@@ -74,6 +102,21 @@ impl Default for KyberKeyExchange {
     }
 }
 
+/// Length of the per-direction session keys in bytes
+pub const SESSION_KEY_LEN: usize = 32;
+
+/// HKDF info label for client-to-server traffic
+const CLIENT_TO_SERVER_INFO: &[u8] = b"c2s";
+/// HKDF info label for server-to-client traffic
+const SERVER_TO_CLIENT_INFO: &[u8] = b"s2c";
+
+/// Which end of the connection a session belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionRole {
+    Client,
+    Server,
+}
+
 /// Represents a completed key exchange session
 pub struct KyberSession {
     /// The shared secret derived from the key exchange
@@ -91,6 +134,34 @@ impl KyberSession {
         &self.shared_secret
     }
 
+    /// Key for traffic this side sends.
+    ///
+    /// Each direction gets its own HKDF-SHA256 key, so the client's send key
+    /// is the server's receive key and vice versa.
+    pub fn send_key(&self, role: SessionRole) -> [u8; SESSION_KEY_LEN] {
+        match role {
+            SessionRole::Client => self.direction_key(CLIENT_TO_SERVER_INFO),
+            SessionRole::Server => self.direction_key(SERVER_TO_CLIENT_INFO),
+        }
+    }
+
+    /// Key for traffic this side receives (the peer's send key)
+    pub fn recv_key(&self, role: SessionRole) -> [u8; SESSION_KEY_LEN] {
+        match role {
+            SessionRole::Client => self.direction_key(SERVER_TO_CLIENT_INFO),
+            SessionRole::Server => self.direction_key(CLIENT_TO_SERVER_INFO),
+        }
+    }
+
+    fn direction_key(&self, info: &[u8]) -> [u8; SESSION_KEY_LEN] {
+        let hkdf = Hkdf::<Sha256>::new(None, &self.shared_secret);
+        let mut key = [0u8; SESSION_KEY_LEN];
+        // 32 bytes is far below HKDF-SHA256's output limit, so this can't fail
+        hkdf.expand(info, &mut key)
+            .expect("HKDF output length is valid");
+        key
+    }
+
     /// Derive a symmetric key from the shared secret.
     /// 
     /// Uses SHA-256 based key derivation with counter mode.
@@ -153,4 +224,35 @@ mod tests {
         assert_eq!(key1.len(), 32);
         assert_eq!(key2.len(), 32);
     }
+
+    #[test]
+    fn test_direction_keys_match_across_roles() {
+        let alice = KyberKeyExchange::new();
+        let public = KyberKeyExchange::public_key_from_bytes(&alice.public_key_bytes()).unwrap();
+        let (ciphertext, server_secret) = KyberKeyExchange::encapsulate(&public);
+        let client = KyberSession::new(alice.decapsulate(&ciphertext).unwrap());
+        let server = KyberSession::new(server_secret);
+
+        assert_eq!(client.send_key(SessionRole::Client), server.recv_key(SessionRole::Server));
+        assert_eq!(server.send_key(SessionRole::Server), client.recv_key(SessionRole::Client));
+
+        // The two directions never share a key
+        assert_ne!(client.send_key(SessionRole::Client), client.recv_key(SessionRole::Client));
+    }
+
+    #[cfg(feature = "test-rng")]
+    #[test]
+    fn test_seeded_keypairs_are_reproducible() {
+        let a = KyberKeyExchange::from_seed([42u8; 32]).unwrap();
+        let b = KyberKeyExchange::from_seed([42u8; 32]).unwrap();
+        let c = KyberKeyExchange::from_seed([7u8; 32]).unwrap();
+
+        assert_eq!(a.public_key_bytes(), b.public_key_bytes());
+        assert_ne!(a.public_key_bytes(), c.public_key_bytes());
+
+        // Seeded keys still work for a normal exchange
+        let public = KyberKeyExchange::public_key_from_bytes(&a.public_key_bytes()).unwrap();
+        let (ciphertext, shared) = KyberKeyExchange::encapsulate(&public);
+        assert_eq!(b.decapsulate(&ciphertext).unwrap(), shared);
+    }
 }
@@ -1,5 +1,9 @@
 //! Post-Quantum Cryptography Module
 //!
-//! Provides Kyber-based key exchange for post-quantum secure communications.
+//! Provides Kyber-based key exchange for post-quantum secure communications,
+//! end-to-end encrypted chat on top of it, and helpers for the transport TLS
+//! certificates.
 
+pub mod e2e;
 pub mod kyber;
+pub mod tls;
@@ -0,0 +1,112 @@
+//! TLS Certificate Helpers
+//!
+//! Generates self-signed certificates so a fresh server can start without
+//! an external openssl recipe, and the verifier clients use to accept them.
+
+use thiserror::Error;
+
+/// Errors from certificate generation
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("No hostnames given for the certificate")]
+    NoHostnames,
+    #[error("Certificate generation failed: {0}")]
+    Generation(String),
+}
+
+/// Generate a self-signed certificate for `hostnames`.
+///
+/// Every hostname (or IP address) becomes a subject alternative name.
+/// Returns `(cert_pem, key_pem)`.
+pub fn generate_self_signed(hostnames: &[String]) -> Result<(String, String), TlsError> {
+    if hostnames.is_empty() {
+        return Err(TlsError::NoHostnames);
+    }
+
+    let cert = rcgen::generate_simple_self_signed(hostnames.to_vec())
+        .map_err(|e| TlsError::Generation(e.to_string()))?;
+    let cert_pem = cert
+        .serialize_pem()
+        .map_err(|e| TlsError::Generation(e.to_string()))?;
+    Ok((cert_pem, cert.serialize_private_key_pem()))
+}
+
+/// Accepts any server certificate, so clients can reach a server using a
+/// self-signed certificate. For development only: it gives no protection
+/// against an impersonated server.
+#[derive(Debug)]
+pub struct NoVerifier;
+
+impl rustls::client::danger::ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        vec![
+            rustls::SignatureScheme::RSA_PKCS1_SHA256,
+            rustls::SignatureScheme::RSA_PKCS1_SHA384,
+            rustls::SignatureScheme::RSA_PKCS1_SHA512,
+            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
+            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
+            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
+            rustls::SignatureScheme::RSA_PSS_SHA256,
+            rustls::SignatureScheme::RSA_PSS_SHA384,
+            rustls::SignatureScheme::RSA_PSS_SHA512,
+            rustls::SignatureScheme::ED25519,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_cert_parses_with_sans() {
+        let hostnames = vec!["pqc-chat.local".to_string(), "raspberrypi".to_string()];
+        let (cert_pem, key_pem) = generate_self_signed(&hostnames).unwrap();
+
+        let certs = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(certs.len(), 1);
+        assert!(rustls_pemfile::private_key(&mut key_pem.as_bytes()).unwrap().is_some());
+
+        // dNSName SANs are stored as raw IA5 strings in the DER
+        let der = certs[0].as_ref();
+        for hostname in &hostnames {
+            assert!(der.windows(hostname.len()).any(|w| w == hostname.as_bytes()), "missing SAN {}", hostname);
+        }
+    }
+
+    #[test]
+    fn test_no_hostnames_rejected() {
+        assert!(matches!(generate_self_signed(&[]), Err(TlsError::NoHostnames)));
+    }
+}
@@ -0,0 +1,274 @@
+//! Room Events
+//!
+//! A typed view of what happens in a room, for bots and other integrators
+//! who would rather not match on every [`SignalingMessage`]. Server
+//! broadcasts are mapped to [`RoomEvent`]s; replies to the client's own
+//! requests (room lists, acks, errors) are not events and are skipped.
+//!
+//! [`RoomEvents`] is a `Stream<Item = RoomEvent>`, fed either from a
+//! connection's read half ([`RoomEvents::from_reader`]) or from raw messages
+//! the caller already reads itself ([`RoomEvents::channel`]).
+
+use crate::protocol::{
+    AudioCodec, LeaveReason, ParticipantInfo, RoomClosedReason, SignalingMessage, UserStatus, SYSTEM_SENDER_ID,
+};
+use crate::transport::{read_frame, TransportError};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncRead;
+use tokio::sync::mpsc;
+
+/// Messages buffered ahead of a slow consumer. Once full, `from_reader`
+/// stops reading the connection until the stream is polled again.
+pub const EVENT_QUEUE_LEN: usize = 256;
+
+/// Something that happened in a room the client is in.
+///
+/// Non-exhaustive so new kinds (typing indicators, reactions) can be added
+/// as the protocol grows.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RoomEvent {
+    /// Someone joined the room
+    Joined { participant_id: String, username: String },
+    /// Someone left, with why
+    Left { participant_id: String, reason: LeaveReason },
+    /// Full membership after a change
+    Roster { room_id: String, participants: Vec<ParticipantInfo>, version: u64 },
+    /// Membership change to apply to the last roster (see
+    /// [`Roster::apply_delta`](crate::protocol::Roster::apply_delta))
+    RosterDelta {
+        room_id: String,
+        version: u64,
+        added: Vec<ParticipantInfo>,
+        removed: Vec<String>,
+        media_changes: Vec<(String, bool, bool)>,
+    },
+    TopicChanged { room_id: String, topic: Option<String> },
+    /// The room was deleted; no more events will come from it
+    Closed { room_id: String, reason: RoomClosedReason },
+    /// Chat message from a participant
+    Message {
+        message_id: String,
+        room_id: String,
+        sender_id: String,
+        username: String,
+        content: String,
+        timestamp: u64,
+    },
+    /// Server-generated chat line (join/leave announcements and the like)
+    Notice { room_id: String, content: String, timestamp: u64 },
+    MessageEdited { message_id: String, content: String },
+    MessageDeleted { message_id: String },
+    /// Message sent to a subset of the room
+    Whisper { sender_id: String, username: String, content: String, timestamp: u64 },
+    /// Encoded audio from a participant
+    AudioFrame { sender_id: String, codec: AudioCodec, batched: bool, data: Vec<u8> },
+    /// Decoded RGBA video frame from a participant
+    VideoFrame { sender_id: String, width: u32, height: u32, rgba: Vec<u8> },
+    AudioToggled { participant_id: String, enabled: bool },
+    VideoToggled { participant_id: String, enabled: bool },
+    HandRaised { participant_id: String, raised: bool },
+    StatusChanged { participant_id: String, status: UserStatus },
+}
+
+impl RoomEvent {
+    /// The event a server message represents, if any
+    pub fn from_message(message: SignalingMessage) -> Option<Self> {
+        let event = match message {
+            SignalingMessage::ParticipantJoined { participant_id, username } => Self::Joined { participant_id, username },
+            SignalingMessage::ParticipantLeft { participant_id, reason } => Self::Left { participant_id, reason },
+            SignalingMessage::RoomRoster { room_id, participants, version } => Self::Roster { room_id, participants, version },
+            SignalingMessage::RoomRosterDelta { room_id, version, added, removed, media_changes } => {
+                Self::RosterDelta { room_id, version, added, removed, media_changes }
+            }
+            SignalingMessage::RoomTopicChanged { room_id, topic } => Self::TopicChanged { room_id, topic },
+            SignalingMessage::RoomClosed { room_id, reason } => Self::Closed { room_id, reason },
+            SignalingMessage::MessageReceived { room_id, sender_id, content, timestamp, .. }
+                if sender_id == SYSTEM_SENDER_ID =>
+            {
+                Self::Notice { room_id, content, timestamp }
+            }
+            SignalingMessage::MessageReceived { message_id, room_id, sender_id, sender_username, content, timestamp, .. } => {
+                Self::Message { message_id, room_id, sender_id, username: sender_username, content, timestamp }
+            }
+            SignalingMessage::MessageEdited { message_id, new_content, .. } => {
+                Self::MessageEdited { message_id, content: new_content }
+            }
+            SignalingMessage::MessageDeleted { message_id } => Self::MessageDeleted { message_id },
+            SignalingMessage::GroupMessageReceived { sender_id, sender_username, content, timestamp, .. } => {
+                Self::Whisper { sender_id, username: sender_username, content, timestamp }
+            }
+            SignalingMessage::AudioDataReceived { sender_id, data, batched, codec, .. } => {
+                Self::AudioFrame { sender_id, codec, batched, data }
+            }
+            SignalingMessage::VideoFrameReceived { sender_id, width, height, rgba } => {
+                Self::VideoFrame { sender_id, width, height, rgba }
+            }
+            SignalingMessage::AudioToggled { participant_id, enabled } => Self::AudioToggled { participant_id, enabled },
+            SignalingMessage::VideoToggled { participant_id, enabled } => Self::VideoToggled { participant_id, enabled },
+            SignalingMessage::HandRaised { participant_id, raised, .. } => Self::HandRaised { participant_id, raised },
+            SignalingMessage::UserStatusChanged { participant_id, status } => {
+                Self::StatusChanged { participant_id, status }
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Stream of [`RoomEvent`]s; ends when the message source does
+pub struct RoomEvents {
+    messages: mpsc::Receiver<SignalingMessage>,
+}
+
+impl RoomEvents {
+    /// Events for raw messages pushed into the returned sender, which holds
+    /// up to [`EVENT_QUEUE_LEN`] unread messages
+    pub fn channel() -> (mpsc::Sender<SignalingMessage>, Self) {
+        let (tx, rx) = mpsc::channel(EVENT_QUEUE_LEN);
+        (tx, Self { messages: rx })
+    }
+
+    /// Read frames from a connection's read half on a background task
+    /// (after login) and turn them into events. The stream ends when the
+    /// connection closes or sends a frame that is oversized or doesn't parse.
+    pub fn from_reader<R>(mut reader: R) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let (tx, events) = Self::channel();
+        tokio::spawn(async move {
+            loop {
+                let message = match read_frame(&mut reader).await.and_then(|frame| Ok(frame.message()?)) {
+                    Ok(message) => message,
+                    Err(TransportError::Closed) => break,
+                    Err(e) => {
+                        log::warn!("Ending room event stream: {}", e);
+                        break;
+                    }
+                };
+                if tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        events
+    }
+
+    /// Wait for the next event, without needing a `StreamExt`
+    pub async fn next_event(&mut self) -> Option<RoomEvent> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for RoomEvents {
+    type Item = RoomEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<RoomEvent>> {
+        loop {
+            match self.messages.poll_recv(cx) {
+                Poll::Ready(Some(message)) => {
+                    if let Some(event) = RoomEvent::from_message(message) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(sender_id: &str, content: &str) -> SignalingMessage {
+        SignalingMessage::MessageReceived {
+            message_id: "m1".to_string(),
+            room_id: "r1".to_string(),
+            sender_id: sender_id.to_string(),
+            sender_username: "alice".to_string(),
+            content: content.to_string(),
+            timestamp: 42,
+            edited: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_messages_map_to_room_events() {
+        let (tx, mut events) = RoomEvents::channel();
+        let raw = vec![
+            SignalingMessage::ParticipantJoined { participant_id: "p1".to_string(), username: "alice".to_string() },
+            // Replies to our own requests are skipped
+            SignalingMessage::Error { message: "Message sent".to_string(), code: None },
+            chat("p1", "hi"),
+            chat(SYSTEM_SENDER_ID, "alice joined the room"),
+            SignalingMessage::AudioDataReceived {
+                sender_id: "p1".to_string(),
+                data: vec![1, 2, 3],
+                batched: false,
+                codec: AudioCodec::Opus,
+                captured_at: Some(7),
+            },
+            SignalingMessage::MessageDeleted { message_id: "m1".to_string() },
+            SignalingMessage::ParticipantLeft { participant_id: "p1".to_string(), reason: LeaveReason::Kicked },
+        ];
+        for message in raw {
+            tx.send(message).await.unwrap();
+        }
+        drop(tx);
+
+        let mut received = Vec::new();
+        while let Some(event) = events.next_event().await {
+            received.push(event);
+        }
+        assert_eq!(received, vec![
+            RoomEvent::Joined { participant_id: "p1".to_string(), username: "alice".to_string() },
+            RoomEvent::Message {
+                message_id: "m1".to_string(),
+                room_id: "r1".to_string(),
+                sender_id: "p1".to_string(),
+                username: "alice".to_string(),
+                content: "hi".to_string(),
+                timestamp: 42,
+            },
+            RoomEvent::Notice { room_id: "r1".to_string(), content: "alice joined the room".to_string(), timestamp: 42 },
+            RoomEvent::AudioFrame { sender_id: "p1".to_string(), codec: AudioCodec::Opus, batched: false, data: vec![1, 2, 3] },
+            RoomEvent::MessageDeleted { message_id: "m1".to_string() },
+            RoomEvent::Left { participant_id: "p1".to_string(), reason: LeaveReason::Kicked },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_events_read_from_framed_connection() {
+        let (mut server, client) = tokio::io::duplex(4096);
+        let mut events = RoomEvents::from_reader(client);
+
+        use tokio::io::AsyncWriteExt;
+        let hand = SignalingMessage::HandRaised { participant_id: "p2".to_string(), raised: true, order: 1 };
+        server.write_all(&hand.to_framed().unwrap()).await.unwrap();
+        drop(server);
+
+        assert_eq!(events.next_event().await, Some(RoomEvent::HandRaised { participant_id: "p2".to_string(), raised: true }));
+        assert_eq!(events.next_event().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_ends_stream() {
+        use crate::protocol::MAX_FRAME_LEN;
+        use tokio::io::AsyncWriteExt;
+
+        let (mut server, client) = tokio::io::duplex(4096);
+        let mut events = RoomEvents::from_reader(client);
+        let hand = SignalingMessage::HandRaised { participant_id: "p2".to_string(), raised: true, order: 1 };
+        server.write_all(&hand.to_framed().unwrap()).await.unwrap();
+        server.write_all(&(MAX_FRAME_LEN as u32 + 1).to_be_bytes()).await.unwrap();
+
+        // Ends without waiting for (or allocating) the declared body
+        assert!(events.next_event().await.is_some());
+        assert_eq!(events.next_event().await, None);
+    }
+}
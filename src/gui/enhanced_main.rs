@@ -13,12 +13,20 @@ use tokio::sync::mpsc;
 #[cfg(feature = "gui")]
 use tokio::runtime::Runtime;
 #[cfg(feature = "gui")]
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 
 #[cfg(feature = "gui")]
-use pqc_chat::crypto::kyber::KyberKeyExchange;
+use pqc_chat::crypto::kyber::{KyberKeyExchange, KyberSession, SessionRole};
 #[cfg(feature = "gui")]
-use pqc_chat::protocol::{ParticipantInfo, RoomInfo, SignalingMessage};
+use pqc_chat::crypto::tls::NoVerifier;
+#[cfg(feature = "gui")]
+use pqc_chat::protocol::{preferred_codec, AudioCodec, Capabilities, ClockOffset, ErrorCode, FrameAuthenticator, FrameOptions, LeaveReason, ParticipantInfo, RoomInfo, Roster, SignalingMessage, UserStatus, FEATURE_FRAME_MAC, PROTOCOL_VERSION, SUPPORTED_FEATURES};
+#[cfg(feature = "gui")]
+use pqc_chat::config::{AudioConfig, DeviceSelection, VideoConfig};
+#[cfg(feature = "gui")]
+use pqc_chat::video::VideoFrame;
+#[cfg(feature = "gui")]
+use pqc_chat::ClientConfig;
 
 // Helper function for formatting timestamps
 fn format_time(time: std::time::SystemTime) -> String {
@@ -69,13 +77,29 @@ fn main() {
     eprintln!("GUI feature not enabled. Build with: cargo build --features gui");
 }
 
+/// Chat messages kept per room
+#[cfg(feature = "gui")]
+const MAX_ROOM_HISTORY: usize = 100;
+
+/// How long a dropped user is shown as reconnecting before being removed;
+/// matches the server's default `reconnect_grace_secs`
+#[cfg(feature = "gui")]
+const USER_RECONNECT_GRACE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Where the devices picked in the call controls are remembered
+#[cfg(feature = "gui")]
+const DEVICE_SELECTION_PATH: &str = "config/devices.toml";
+
 #[cfg(feature = "gui")]
 #[derive(Debug, Clone)]
 struct ChatMessage {
+    /// Server-assigned ID; empty for optimistic and group messages
+    message_id: String,
     sender_id: String,
     sender_username: String,
     content: String,
     timestamp: std::time::SystemTime,
+    edited: bool,
 }
 
 #[cfg(feature = "gui")]
@@ -87,6 +111,19 @@ struct ConnectedUser {
     in_room: Option<String>,
     audio_enabled: bool,
     video_enabled: bool,
+    status: UserStatus,
+    /// When the connection dropped, while waiting to see if they come back
+    reconnecting_since: Option<std::time::Instant>,
+}
+
+/// Presence dot color for the user panel
+#[cfg(feature = "gui")]
+fn status_color(status: UserStatus) -> egui::Color32 {
+    match status {
+        UserStatus::Available => egui::Color32::from_rgb(0, 200, 80),
+        UserStatus::Away => egui::Color32::from_rgb(230, 180, 0),
+        UserStatus::Busy => egui::Color32::from_rgb(220, 50, 50),
+    }
 }
 
 #[cfg(feature = "gui")]
@@ -97,6 +134,13 @@ struct RoomData {
     participants: u32,
     max_participants: u32,
     is_locked: bool,
+    /// Not full, locked or password protected
+    joinable: bool,
+    /// Creator's participant ID, once known from `RoomInfoResponse`
+    owner_id: Option<String>,
+    topic: Option<String>,
+    /// e.g. "active 2m ago", as of when the list arrived
+    activity: Option<String>,
 }
 
 #[cfg(feature = "gui")]
@@ -111,8 +155,11 @@ struct EnhancedPqcChatApp {
     // Room state
     rooms: Vec<RoomData>,
     current_room: Option<RoomData>,
-    selected_room_idx: Option<usize>,
+    // Tracked by ID, since the list is re-sorted by activity
+    selected_room_id: Option<String>,
     new_room_name: String,
+    // What the connected server reported supporting, if it answered
+    server_capabilities: Option<Capabilities>,
     room_participants: Vec<ParticipantInfo>,
 
     // User management
@@ -125,7 +172,51 @@ struct EnhancedPqcChatApp {
     audio_call_active: bool,
     audio_manager: Option<Arc<Mutex<pqc_chat::audio::AudioManager>>>,
     audio_producer: Option<Arc<Mutex<ringbuf::HeapProducer<f32>>>>,
+    // Playback underrun/overrun counters for the active call
+    audio_stats: Option<Arc<pqc_chat::audio::PlaybackStats>>,
+    // Smoothed microphone level for the call controls' meter
+    mic_level: Option<Arc<pqc_chat::audio::InputLevel>>,
+    // Hear-yourself monitoring for the active call, and whether it's toggled on
+    monitor: Option<Arc<pqc_chat::audio::LocalMonitor>>,
+    monitor_enabled: bool,
     audio_send_handle: Option<std::thread::JoinHandle<()>>,
+    audio_config: AudioConfig,
+    // Device names for the call controls' dropdowns, enumerated at startup
+    input_devices: Vec<String>,
+    output_devices: Vec<String>,
+    /// What the chosen input device can capture, shown next to its dropdown
+    input_caps: Option<pqc_chat::audio::DeviceCaps>,
+    audio_mixer: pqc_chat::audio_mixer::AudioMixer,
+    // Opus is stateful, so every sender gets its own decoder
+    audio_decoders: HashMap<String, pqc_chat::audio_codec::FrameDecoder>,
+    // Decoded audio held per sender until its jitter prefill is met
+    jitter_buffers: HashMap<String, pqc_chat::jitter_buffer::JitterBuffer>,
+    // Result of a running loopback self-test, filled in by its worker thread
+    audio_test_result: Option<Arc<Mutex<Option<String>>>>,
+    audio_recorder: Option<pqc_chat::audio_recorder::AudioRecorder>,
+    // Read by the capture callback so the mic can be tapped mid-call
+    recorder_mic_tap: Arc<Mutex<Option<pqc_chat::audio_recorder::RecorderTap>>>,
+    record_include_mic: bool,
+    // Dead-air detection; fed by both captured and received audio
+    silence_timer: Option<Arc<Mutex<pqc_chat::audio::SilenceTimer>>>,
+    // The server refused our audio as too large during this call
+    audio_rejected: bool,
+    // Codec for outgoing audio, from the features agreed in Hello
+    send_codec: AudioCodec,
+    video_config: VideoConfig,
+    // Latest frame per participant ("local" for our own preview), waiting for upload
+    pending_video_frames: HashMap<String, VideoFrame>,
+    video_textures: std::collections::BTreeMap<String, egui::TextureHandle>,
+    // Test-pattern preview state until a real camera source exists
+    local_preview_frame: u32,
+    local_preview_last: std::time::Instant,
+    #[cfg(feature = "video")]
+    video_capture: Option<pqc_chat::video_capture::VideoCapture>,
+    // Don't retry a camera that failed to open every frame
+    #[cfg(feature = "video")]
+    video_capture_failed: bool,
+    #[cfg(feature = "video")]
+    captured_frame: Arc<Mutex<Option<VideoFrame>>>,
 
     // Chat state - per room
     room_chat_history: HashMap<String, Vec<ChatMessage>>,  // room_id -> messages
@@ -149,7 +240,8 @@ enum GuiCommand {
     Connect { host: String, port: u16, username: String },
     Disconnect,
     ListRooms,
-    CreateRoom { name: String, max_participants: u32 },
+    /// `max_participants` of `None` takes the server's default size
+    CreateRoom { name: String, max_participants: Option<u32> },
     JoinRoom { room_id: String },
     LeaveRoom,
     ToggleAudio { enabled: bool },
@@ -161,31 +253,42 @@ enum GuiCommand {
     // Audio call functionality
     StartAudioCall,
     StopAudioCall,
-    SendAudioData { data: Vec<u8> },
+    /// `captured_at` is the local Unix ms time the newest frame was captured
+    SendAudioData { data: Vec<u8>, batched: bool, codec: AudioCodec, captured_at: u64 },
 }
 
 #[cfg(feature = "gui")]
 #[derive(Debug, Clone)]
 enum GuiUpdate {
-    Connected { participant_id: String },
+    Connected { participant_id: String, codec: AudioCodec },
+    Capabilities { capabilities: Capabilities },
     Disconnected,
     ConnectionError { error: String },
     RoomList { rooms: Vec<RoomInfo> },
     RoomJoined { room: RoomInfo, participants: Vec<ParticipantInfo> },
     RoomLeft,
+    RoomInfo { room: RoomInfo, owner_id: Option<String> },
+    RoomTopicChanged { room_id: String, topic: Option<String> },
+    RoomClosed { room_id: String },
     ParticipantJoined { participant: ParticipantInfo },
-    ParticipantLeft { participant_id: String },
+    ParticipantLeft { participant_id: String, reason: LeaveReason },
+    RoomRoster { room_id: String, participants: Vec<ParticipantInfo> },
     ParticipantAudioToggled { participant_id: String, enabled: bool },
     ParticipantVideoToggled { participant_id: String, enabled: bool },
     // Server-wide user tracking
     ServerUserConnected { user: ConnectedUser },
     ServerUserDisconnected { user_id: String },
     ServerUserList { users: Vec<ConnectedUser> },
+    UserStatusChanged { participant_id: String, status: UserStatus },
     // Chat functionality
     ChatMessageReceived { message: ChatMessage },
+    ChatMessageEdited { message_id: String, new_content: String },
+    ChatMessageDeleted { message_id: String },
     StatusMessage { message: String },
     // Audio functionality
-    AudioDataReceived { sender_id: String, data: Vec<u8> },
+    AudioRejected { message: String },
+    AudioDataReceived { sender_id: String, data: Vec<u8>, batched: bool, codec: AudioCodec },
+    VideoFrameReceived { sender_id: String, frame: VideoFrame },
 }
 
 #[cfg(feature = "gui")]
@@ -203,6 +306,12 @@ impl EnhancedPqcChatApp {
     let (update_sender, update_receiver) = mpsc::unbounded_channel();
         let update_receiver = Arc::new(Mutex::new(update_receiver));
 
+        // Audio tuning comes from the client config file when present
+        let mut client_config = ClientConfig::from_file("config/client.toml").unwrap_or_default();
+        DeviceSelection::load(DEVICE_SELECTION_PATH).apply(&mut client_config.audio);
+        let (input_devices, output_devices) = list_audio_devices();
+        let input_caps = probe_input_caps(&input_devices, client_config.audio.preferred_input_name.as_deref());
+
         // Spawn the communication task
         let rt = runtime.clone();
         std::thread::spawn(move || {
@@ -219,8 +328,9 @@ impl EnhancedPqcChatApp {
             connection_status: "Disconnected".to_string(),
             rooms: Vec::new(),
             current_room: None,
-            selected_room_idx: None,
+            selected_room_id: None,
             new_room_name: String::new(),
+            server_capabilities: None,
             room_participants: Vec::new(),
             connected_users: HashMap::new(),
             user_list_scroll: 0.0,
@@ -231,7 +341,36 @@ impl EnhancedPqcChatApp {
             audio_call_active: false,
             audio_manager: None,
             audio_producer: None,
+            audio_stats: None,
+            mic_level: None,
+            monitor: None,
+            monitor_enabled: false,
             audio_send_handle: None,
+            audio_config: client_config.audio,
+            input_devices,
+            output_devices,
+            input_caps,
+            video_config: client_config.video,
+            pending_video_frames: HashMap::new(),
+            video_textures: std::collections::BTreeMap::new(),
+            local_preview_frame: 0,
+            local_preview_last: std::time::Instant::now(),
+            #[cfg(feature = "video")]
+            video_capture: None,
+            #[cfg(feature = "video")]
+            video_capture_failed: false,
+            #[cfg(feature = "video")]
+            captured_frame: Arc::new(Mutex::new(None)),
+            audio_mixer: pqc_chat::audio_mixer::AudioMixer::new(),
+            audio_decoders: HashMap::new(),
+            jitter_buffers: HashMap::new(),
+            audio_recorder: None,
+            recorder_mic_tap: Arc::new(Mutex::new(None)),
+            record_include_mic: true,
+            silence_timer: None,
+            audio_rejected: false,
+            send_codec: AudioCodec::Opus,
+            audio_test_result: None,
             show_users_panel: true,
             show_rooms_panel: true,
             users_window_open: true,
@@ -243,6 +382,10 @@ impl EnhancedPqcChatApp {
     }
 
     fn process_updates(&mut self) {
+        for user in expire_reconnecting_users(&mut self.connected_users, std::time::Instant::now()) {
+            self.add_status_message(format!("👤 {} disconnected from server", user.username));
+        }
+
         let updates = if let Some(receiver) = &self.update_receiver {
             let mut receiver = receiver.lock().unwrap();
             let mut updates = Vec::new();
@@ -256,8 +399,9 @@ impl EnhancedPqcChatApp {
         
         for update in updates {
             match update {
-                GuiUpdate::Connected { participant_id } => {
+                GuiUpdate::Connected { participant_id, codec } => {
                     self.is_connected = true;
+                    self.send_codec = codec;
                     self.connection_status = format!("Connected as {}", self.username);
                     self.add_status_message("🟢 Connected to server".to_string());
                     
@@ -269,28 +413,48 @@ impl EnhancedPqcChatApp {
                         in_room: None,
                         audio_enabled: self.audio_enabled,
                         video_enabled: self.video_enabled,
+                        status: UserStatus::Available,
+                        reconnecting_since: None,
                     });
                 },
+                GuiUpdate::Capabilities { capabilities } => {
+                    self.server_capabilities = Some(capabilities);
+                },
                 GuiUpdate::Disconnected => {
                     self.is_connected = false;
+                    self.server_capabilities = None;
                     self.connection_status = "Disconnected".to_string();
                     self.rooms.clear();
                     self.current_room = None;
                     self.connected_users.clear();
                     self.room_participants.clear();
+                    self.video_textures.clear();
+                    drop_room_audio(&mut self.audio_mixer, &mut self.audio_decoders, &mut self.jitter_buffers);
+                    // room_chat_history is kept: after reconnecting, replayed
+                    // messages merge into it by ID instead of starting over
                     self.add_status_message("🔴 Disconnected from server".to_string());
                 },
                 GuiUpdate::ConnectionError { error } => {
                     self.connection_status = format!("Connection Error: {}", error);
                     self.add_status_message(format!("❌ Connection failed: {}", error));
                 },
-                GuiUpdate::RoomList { rooms } => {
+                GuiUpdate::RoomList { mut rooms } => {
+                    // Most recently active first, so live rooms are easy to spot
+                    rooms.sort_by_key(|r| std::cmp::Reverse(r.last_activity));
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
                     self.rooms = rooms.into_iter().map(|r| RoomData {
+                        joinable: r.is_joinable(),
+                        activity: r.activity_label(now),
                         id: r.id,
                         name: r.name,
                         participants: r.participants,
                         max_participants: r.max_participants,
                         is_locked: r.is_locked,
+                        owner_id: None,
+                        topic: r.topic,
                     }).collect();
                 },
                 GuiUpdate::RoomJoined { room, participants } => {
@@ -305,16 +469,52 @@ impl EnhancedPqcChatApp {
                         participants: room.participants,
                         max_participants: room.max_participants,
                         is_locked: room.is_locked,
+                        joinable: room.is_joinable(),
+                        owner_id: None,
+                        topic: room.topic.clone(),
+                        activity: None,
                     });
                     self.room_participants = participants;
                     self.add_status_message(format!("🎉 Joined room: {} with {} participants", room.name, self.room_participants.len()));
                 },
+                GuiUpdate::RoomInfo { room, owner_id } => {
+                    // Authoritative metadata replaces what we guessed on join
+                    if let Some(current) = self.current_room.as_mut().filter(|r| r.id == room.id) {
+                        current.joinable = room.is_joinable();
+                        current.name = room.name;
+                        current.participants = room.participants;
+                        current.max_participants = room.max_participants;
+                        current.is_locked = room.is_locked;
+                        current.owner_id = owner_id;
+                        current.topic = room.topic;
+                    }
+                },
+                GuiUpdate::RoomTopicChanged { room_id, topic } => {
+                    let rooms = self.rooms.iter_mut().chain(self.current_room.as_mut());
+                    for room in rooms.filter(|r| r.id == room_id) {
+                        room.topic = topic.clone();
+                    }
+                },
+                GuiUpdate::RoomClosed { room_id } => {
+                    self.rooms.retain(|r| r.id != room_id);
+                    if self.selected_room_id.as_ref() == Some(&room_id) {
+                        self.selected_room_id = None;
+                    }
+                    if self.current_room.as_ref().is_some_and(|r| r.id == room_id) {
+                        if let Some(room) = self.current_room.take() {
+                            self.add_status_message(format!("🚪 Room closed: {}", room.name));
+                        }
+                        self.room_participants.clear();
+                        drop_room_audio(&mut self.audio_mixer, &mut self.audio_decoders, &mut self.jitter_buffers);
+                    }
+                },
                 GuiUpdate::RoomLeft => {
                     if let Some(room) = &self.current_room {
                         self.add_status_message(format!("👋 Left room: {}", room.name));
                     }
                     self.current_room = None;
                     self.room_participants.clear();
+                    drop_room_audio(&mut self.audio_mixer, &mut self.audio_decoders, &mut self.jitter_buffers);
                 },
                 GuiUpdate::ParticipantJoined { participant } => {
                     eprintln!("DEBUG: ParticipantJoined - {} ({})", participant.username, participant.id);
@@ -327,7 +527,7 @@ impl EnhancedPqcChatApp {
                     
                     self.add_status_message(format!("🟢 {} joined the room (total: {})", participant.username, self.room_participants.len()));
                 },
-                GuiUpdate::ParticipantLeft { participant_id } => {
+                GuiUpdate::ParticipantLeft { participant_id, reason } => {
                     // Find the username before removing for the status message
                     let username = self.room_participants.iter()
                         .find(|p| p.id == participant_id)
@@ -335,13 +535,35 @@ impl EnhancedPqcChatApp {
                         .unwrap_or_else(|| "User".to_string());
                     
                     self.room_participants.retain(|p| p.id != participant_id);
+                    self.forget_participant(&participant_id);
                     
                     // Update current room participant count
                     if let Some(ref mut room) = self.current_room {
                         room.participants = self.room_participants.len() as u32;
                     }
                     
-                    self.add_status_message(format!("🔴 {} left the room (total: {})", username, self.room_participants.len()));
+                    self.add_status_message(format!("🔴 {} {} (total: {})", username, reason.describe(), self.room_participants.len()));
+                    // Leaving the room and logging out look the same here;
+                    // the server's list tells whether they're still around
+                    if reason == LeaveReason::Left {
+                        self.send_command(GuiCommand::ListServerUsers);
+                    }
+                },
+                GuiUpdate::RoomRoster { room_id, participants } => {
+                    // Server's list wins over anything patched from join/leave events
+                    if self.current_room.as_ref().is_some_and(|r| r.id == room_id) {
+                        let gone: Vec<String> = self.room_participants.iter()
+                            .filter(|p| !participants.iter().any(|n| n.id == p.id))
+                            .map(|p| p.id.clone())
+                            .collect();
+                        for id in gone {
+                            self.forget_participant(&id);
+                        }
+                        if let Some(room) = self.current_room.as_mut() {
+                            room.participants = participants.len() as u32;
+                        }
+                        self.room_participants = participants;
+                    }
                 },
                 GuiUpdate::ParticipantAudioToggled { participant_id, enabled } => {
                     if let Some(participant) = self.room_participants.iter_mut().find(|p| p.id == participant_id) {
@@ -359,20 +581,26 @@ impl EnhancedPqcChatApp {
                         user.video_enabled = enabled;
                     }
                 },
+                GuiUpdate::UserStatusChanged { participant_id, status } => {
+                    if let Some(user) = self.connected_users.get_mut(&participant_id) {
+                        user.status = status;
+                    }
+                },
                 GuiUpdate::ServerUserConnected { user } => {
-                    self.connected_users.insert(user.id.clone(), user.clone());
-                    self.add_status_message(format!("👤 {} connected to server", user.username));
+                    let username = user.username.clone();
+                    if merge_connected_user(&mut self.connected_users, user, std::time::Instant::now()) {
+                        self.add_status_message(format!("👤 {} reconnected", username));
+                    } else {
+                        self.add_status_message(format!("👤 {} connected to server", username));
+                    }
                 },
                 GuiUpdate::ServerUserDisconnected { user_id } => {
-                    if let Some(user) = self.connected_users.remove(&user_id) {
-                        self.add_status_message(format!("👤 {} disconnected from server", user.username));
+                    if let Some(username) = mark_user_reconnecting(&mut self.connected_users, &user_id, std::time::Instant::now()) {
+                        self.add_status_message(format!("👤 {} disconnected, waiting for them to reconnect", username));
                     }
                 },
                 GuiUpdate::ServerUserList { users } => {
-                    self.connected_users.clear();
-                    for user in users {
-                        self.connected_users.insert(user.id.clone(), user);
-                    }
+                    sync_connected_users(&mut self.connected_users, users, std::time::Instant::now());
                 },
                 GuiUpdate::ChatMessageReceived { message } => {
                     eprintln!("DEBUG: GuiUpdate::ChatMessageReceived - from {} ({}): {}", message.sender_username, message.sender_id, message.content);
@@ -384,68 +612,133 @@ impl EnhancedPqcChatApp {
                         
                         // Check for duplicate - don't add if we already have this message
                         // (this happens when we optimistically add our own message, then get the broadcast)
-                        let is_duplicate = chat_history.iter().any(|m| {
+                        let duplicate = chat_history.iter_mut().find(|m| {
                             m.content == message.content && 
                             m.sender_username == message.sender_username &&
                             m.timestamp.duration_since(message.timestamp).unwrap_or_default().as_secs() < 2
                         });
                         
-                        if !is_duplicate {
-                            chat_history.push(message);
-                            // Keep only last 100 messages per room
-                            if chat_history.len() > 100 {
-                                chat_history.remove(0);
+                        if let Some(existing) = duplicate {
+                            // Adopt the server's ID so later edits/deletes can find it
+                            if existing.message_id.is_empty() {
+                                existing.message_id = message.message_id;
                             }
-                            eprintln!("DEBUG: Added message to room {}. Total messages: {}", room_id, chat_history.len());
-                        } else {
                             eprintln!("DEBUG: Skipped duplicate message");
+                        } else {
+                            merge_chat_history(chat_history, [message]);
+                            eprintln!("DEBUG: Added message to room {}. Total messages: {}", room_id, chat_history.len());
                         }
                     }
                 },
+                GuiUpdate::ChatMessageEdited { message_id, new_content } => {
+                    for history in self.room_chat_history.values_mut() {
+                        if let Some(msg) = history.iter_mut().find(|m| m.message_id == message_id) {
+                            msg.content = new_content.clone();
+                            msg.edited = true;
+                        }
+                    }
+                },
+                GuiUpdate::ChatMessageDeleted { message_id } => {
+                    for history in self.room_chat_history.values_mut() {
+                        history.retain(|m| m.message_id != message_id);
+                    }
+                },
                 GuiUpdate::StatusMessage { message } => {
                     self.add_status_message(message);
                 },
-                GuiUpdate::AudioDataReceived { sender_id, data } => {
-                    // Decode Opus-compressed audio
-                    use pqc_chat::audio_codec::OpusDecoder;
-                    static OPUS_DECODER: std::sync::OnceLock<std::sync::Mutex<OpusDecoder>> = std::sync::OnceLock::new();
+                GuiUpdate::AudioRejected { message } => {
+                    // Every packet gets the same refusal; say so once per call
+                    if !self.audio_rejected {
+                        self.audio_rejected = true;
+                        self.add_status_message(format!(
+                            "⚠️ Server refused our audio ({}); use Opus or lower frames_per_packet",
+                            message
+                        ));
+                    }
+                },
+                GuiUpdate::VideoFrameReceived { sender_id, frame } => {
+                    self.pending_video_frames.insert(sender_id, frame);
+                },
+                GuiUpdate::AudioDataReceived { sender_id, data, batched, codec } => {
+                    // Decode according to the sender's codec tag (Opus or raw PCM)
+                    use pqc_chat::audio_codec::{unpack_frames, FrameDecoder};
+                    
+                    // Batched packets carry several frames; split them back into playout frames
+                    let frames = if batched {
+                        match unpack_frames(&data) {
+                            Ok(frames) => frames,
+                            Err(e) => {
+                                eprintln!("ERROR: Failed to unpack audio batch: {}", e);
+                                continue;
+                            }
+                        }
+                    } else {
+                        vec![data]
+                    };
                     
                     if let Some(producer) = &self.audio_producer {
-                        if let Ok(mut decoder_guard) = OPUS_DECODER.get_or_init(|| {
-                            std::sync::Mutex::new(
-                                OpusDecoder::new().expect("Failed to create Opus decoder")
-                            )
-                        }).lock() {
-                            match decoder_guard.decode(&data) {
-                                Ok(samples) => {
-                                    let num_samples = samples.len();
-                                    let max_amplitude = samples.iter().map(|s| s.abs()).fold(0.0f32, f32::max);
-                                    
-                                    let mut producer = producer.lock().unwrap();
-                                    
-                                    // Push all samples to buffer
-                                    let mut pushed_count = 0;
-                                    for sample in samples {
-                                        if producer.push(sample).is_ok() {
-                                            pushed_count += 1;
-                                        } else {
-                                            break;
+                        let decoder = match self.audio_decoders.entry(sender_id.clone()) {
+                            std::collections::hash_map::Entry::Occupied(entry) => Some(entry.into_mut()),
+                            std::collections::hash_map::Entry::Vacant(entry) => FrameDecoder::new().ok().map(|d| entry.insert(d)),
+                        };
+                        if let Some(decoder) = decoder {
+                            for data in frames {
+                                match decoder.decode(codec, &data) {
+                                    Ok(samples) => {
+                                        let max_amplitude = samples.iter().map(|s| s.abs()).fold(0.0f32, f32::max);
+                                        eprintln!("DEBUG: Audio from {}: {} compressed bytes → {} samples, max_amp={:.4}", 
+                                                  sender_id, data.len(), samples.len(), max_amplitude);
+                                        
+                                        if let Some(timer) = &self.silence_timer {
+                                            timer.lock().unwrap().observe(&samples, std::time::Instant::now());
+                                        }
+                                        
+                                        // Queue per sender; the mixer applies per-participant volume
+                                        let jitter = self
+                                            .jitter_buffers
+                                            .entry(sender_id.clone())
+                                            .or_insert_with(|| self.audio_config.jitter_buffer());
+                                        jitter.push(samples);
+                                        for frame in jitter.drain_ready() {
+                                            self.audio_mixer.push(&sender_id, &frame);
                                         }
                                     }
-                                    
-                                    eprintln!("DEBUG: Audio from {}: {} compressed bytes → {} samples, pushed {}, max_amp={:.4}", 
-                                              sender_id, data.len(), num_samples, pushed_count, max_amplitude);
-                                    
-                                    if pushed_count < num_samples {
-                                        eprintln!("WARNING: Buffer full, dropped {} samples", num_samples - pushed_count);
+                                    Err(e) => {
+                                        eprintln!("ERROR: {:?} decode failed: {}", codec, e);
                                     }
                                 }
-                                Err(e) => {
-                                    eprintln!("ERROR: Opus decode failed: {}", e);
+                            }
+                            
+                            // Push every mixed frame that's ready to the playback buffer
+                            let mut producer = producer.lock().unwrap();
+                            let stereo = self.audio_config.stereo_panning;
+                            while let Some(mixed) = if stereo {
+                                self.audio_mixer.pop_ready_stereo(960)
+                            } else {
+                                self.audio_mixer.pop_ready(960)
+                            } {
+                                if let Some(recorder) = &self.audio_recorder {
+                                    if stereo {
+                                        // Recordings stay mono; undo the center pan's -3dB
+                                        let mono: Vec<f32> = mixed
+                                            .chunks_exact(2)
+                                            .map(|f| (f[0] + f[1]) * std::f32::consts::FRAC_1_SQRT_2)
+                                            .collect();
+                                        recorder.push(pqc_chat::audio_recorder::RecordSource::Call, &mono);
+                                    } else {
+                                        recorder.push(pqc_chat::audio_recorder::RecordSource::Call, &mixed);
+                                    }
+                                }
+                                let pushed_count = match &self.audio_stats {
+                                    Some(stats) => pqc_chat::audio::push_playback(&mut producer, &mixed, stats),
+                                    None => producer.push_slice(&mixed),
+                                };
+                                if pushed_count < mixed.len() {
+                                    eprintln!("WARNING: Buffer full, dropped {} samples", mixed.len() - pushed_count);
                                 }
                             }
                         } else {
-                            eprintln!("DEBUG: Received audio but no decoder (call not started?)");
+                            eprintln!("ERROR: Failed to create audio decoder for {}", sender_id);
                         }
                     } else {
                         eprintln!("DEBUG: Received audio but no producer (call not started?)");
@@ -455,6 +748,13 @@ impl EnhancedPqcChatApp {
         }
     }
 
+    /// Drop everything kept per participant once they've left the room
+    fn forget_participant(&mut self, participant_id: &str) {
+        drop_sender_audio(&mut self.audio_mixer, &mut self.audio_decoders, &mut self.jitter_buffers, participant_id);
+        self.video_textures.remove(participant_id);
+        self.pending_video_frames.remove(participant_id);
+    }
+
     fn add_status_message(&mut self, message: String) {
         self.status_messages.push((message, std::time::SystemTime::now()));
         // Keep only last 50 messages
@@ -472,6 +772,7 @@ impl EnhancedPqcChatApp {
 
     fn start_audio_call(&mut self) {
         log::info!("Starting audio call...");
+        self.audio_rejected = false;
         
         // Create audio manager
         let mut manager = match pqc_chat::audio::AudioManager::new() {
@@ -483,6 +784,22 @@ impl EnhancedPqcChatApp {
         };
 
         // Start playback first
+        manager.set_limiter_enabled(self.audio_config.limiter_enabled);
+        manager.set_profile(self.audio_config.profile);
+        manager.set_prefill_ms(self.audio_config.prefill_ms);
+        if self.audio_config.stereo_panning {
+            manager.set_output_channels(2);
+        }
+        manager.set_comfort_noise_level(self.audio_config.comfort_noise_level);
+        let frame_duration = self.audio_config.frame_duration();
+        manager.set_frame_duration(frame_duration);
+        manager.set_send_thread_priority(self.audio_config.send_thread_priority);
+        manager.set_resampler_quality(self.audio_config.resampler_quality);
+        manager.set_device_preferences(
+            self.audio_config.preferred_input_name.clone(),
+            self.audio_config.preferred_output_name.clone(),
+            self.audio_config.device_index,
+        );
         let producer = match manager.start_playback() {
             Ok(p) => p,
             Err(e) => {
@@ -494,30 +811,95 @@ impl EnhancedPqcChatApp {
 
         // Start capture with callback
         let command_sender = self.command_sender.clone();
+        let mut batcher = pqc_chat::audio_codec::FrameBatcher::new(self.audio_config.frames_per_packet);
+        let mic_tap = self.recorder_mic_tap.clone();
+        
+        // Dead-air timer, if enabled
+        self.silence_timer = if self.audio_config.silence_timeout_secs > 0 {
+            Some(Arc::new(Mutex::new(pqc_chat::audio::SilenceTimer::new(
+                self.audio_config.silence_threshold,
+                std::time::Duration::from_secs(self.audio_config.silence_timeout_secs),
+                std::time::Instant::now(),
+            ))))
+        } else {
+            None
+        };
+        let capture_timer = self.silence_timer.clone();
+        let pcm_format = self.audio_config.pcm_sample_format;
+        let opus_settings = pqc_chat::audio_codec::OpusSettings {
+            dtx: self.audio_config.opus_dtx,
+            frame_duration,
+        };
+        // Encode to Opus (compresses ~3.8KB to ~100-200 bytes per 20ms) unless
+        // the server didn't agree to it. Frames Opus rejects go out as raw PCM
+        // rather than being dropped.
+        let mut encoder = match pqc_chat::audio_codec::FrameEncoder::with_settings(opus_settings) {
+            Ok(encoder) => encoder.with_pcm_format(pcm_format).with_codec(self.send_codec),
+            Err(e) => {
+                self.add_status_message(format!("❌ Failed to create audio encoder: {}", e));
+                manager.stop_playback();
+                self.audio_producer = None;
+                return;
+            }
+        };
+        
         let capture_result = manager.start_capture(move |samples| {
-            // Encode to Opus (compresses ~3.8KB to ~100-200 bytes per 20ms)
-            // This reduces network overhead and improves TCP handling
-            use pqc_chat::audio_codec::OpusEncoder;
-            static OPUS_ENCODER: std::sync::OnceLock<std::sync::Mutex<OpusEncoder>> = std::sync::OnceLock::new();
+            let captured_at = pqc_chat::protocol::unix_millis();
+            if let Some(timer) = &capture_timer {
+                if let Ok(mut timer) = timer.try_lock() {
+                    timer.observe(&samples, std::time::Instant::now());
+                }
+            }
+
+            // try_lock: never wait on the UI thread from the audio callback
+            if let Ok(tap) = mic_tap.try_lock() {
+                if let Some(tap) = tap.as_ref() {
+                    tap.push(pqc_chat::audio_recorder::RecordSource::Mic, &samples);
+                }
+            }
             
-            if let Ok(mut encoder_guard) = OPUS_ENCODER.get_or_init(|| {
-                std::sync::Mutex::new(
-                    OpusEncoder::new().expect("Failed to create Opus encoder")
-                )
-            }).lock() {
-                match encoder_guard.encode(&samples) {
-                    Ok(compressed) => {
-                        eprintln!("DEBUG: Opus compressed {} samples to {} bytes", samples.len(), compressed.len());
-                        
-                        // Send compressed audio to server (non-blocking)
-                        if let Some(sender) = &command_sender {
-                            let _ = sender.try_send(GuiCommand::SendAudioData { data: compressed });
-                        }
+            let Some(sender) = &command_sender else { return };
+            match encoder.encode(&samples) {
+                (AudioCodec::Opus, compressed) if pqc_chat::audio_codec::is_dtx_frame(&compressed) => {
+                    // DTX: nothing worth sending during silence, but don't
+                    // hold the tail of the last utterance in a part-full batch
+                    if let Some(packet) = batcher.flush() {
+                        let _ = sender.try_send(GuiCommand::SendAudioData {
+                            data: packet,
+                            batched: batcher.is_batching(),
+                            codec: AudioCodec::Opus,
+                            captured_at,
+                        });
                     }
-                    Err(e) => {
-                        eprintln!("ERROR: Opus encode failed: {}", e);
+                }
+                (AudioCodec::Opus, compressed) => {
+                    // Send compressed audio to server (non-blocking) once a batch is ready
+                    if let Some(packet) = batcher.push(compressed) {
+                        let _ = sender.try_send(GuiCommand::SendAudioData {
+                            data: packet,
+                            batched: batcher.is_batching(),
+                            codec: AudioCodec::Opus,
+                            captured_at,
+                        });
                     }
                 }
+                (codec, raw) => {
+                    // A packet carries one codec: flush pending Opus frames first
+                    if let Some(packet) = batcher.flush() {
+                        let _ = sender.try_send(GuiCommand::SendAudioData {
+                            data: packet,
+                            batched: batcher.is_batching(),
+                            codec: AudioCodec::Opus,
+                            captured_at,
+                        });
+                    }
+                    let _ = sender.try_send(GuiCommand::SendAudioData {
+                        data: raw,
+                        batched: false,
+                        codec,
+                        captured_at,
+                    });
+                }
             }
         });
 
@@ -528,14 +910,223 @@ impl EnhancedPqcChatApp {
             return;
         }
 
+        self.audio_stats = Some(manager.playback_stats());
+        self.mic_level = Some(manager.input_level());
+        let monitor = manager.monitor();
+        if self.monitor_enabled {
+            monitor.set_gain(self.audio_config.monitor_gain);
+        }
+        self.monitor = Some(monitor);
         self.audio_manager = Some(Arc::new(Mutex::new(manager)));
         self.add_status_message("🎤 Audio call started - speak now!".to_string());
         log::info!("Audio call started successfully");
     }
 
+    fn save_device_selection(&mut self) {
+        let selection = DeviceSelection {
+            input_name: self.audio_config.preferred_input_name.clone(),
+            output_name: self.audio_config.preferred_output_name.clone(),
+        };
+        if let Err(e) = selection.save(DEVICE_SELECTION_PATH) {
+            self.add_status_message(format!("⚠️ Could not save device selection: {}", e));
+        }
+    }
+
+    fn start_audio_test(&mut self) {
+        let result = Arc::new(Mutex::new(None));
+        let result_slot = result.clone();
+        let audio_config = self.audio_config.clone();
+
+        // The loopback blocks for its duration, so keep it off the UI thread
+        std::thread::spawn(move || {
+            let outcome = pqc_chat::audio::AudioManager::new().and_then(|mut manager| {
+                manager.set_device_preferences(
+                    audio_config.preferred_input_name,
+                    audio_config.preferred_output_name,
+                    audio_config.device_index,
+                );
+                manager.run_loopback(std::time::Duration::from_secs(3))
+            });
+            let message = match outcome {
+                Ok(report) => format!(
+                    "🎧 Audio test OK: latency {} ms, peak level {:.0}%",
+                    report.latency.as_millis(),
+                    report.peak_level * 100.0
+                ),
+                Err(e) => format!("❌ Audio test failed: {}", e),
+            };
+            *result_slot.lock().unwrap() = Some(message);
+        });
+
+        self.audio_test_result = Some(result);
+        self.add_status_message("🎧 Testing audio - speak into your microphone...".to_string());
+    }
+
+    fn start_test_tone(&mut self) {
+        let result = Arc::new(Mutex::new(None));
+        let result_slot = result.clone();
+        let audio_config = self.audio_config.clone();
+
+        std::thread::spawn(move || {
+            let outcome = pqc_chat::audio::AudioManager::new().and_then(|mut manager| {
+                manager.set_device_preferences(
+                    audio_config.preferred_input_name,
+                    audio_config.preferred_output_name,
+                    audio_config.device_index,
+                );
+                manager.play_test_tone(440.0, std::time::Duration::from_secs(2))
+            });
+            let message = match outcome {
+                Ok(()) => "🔔 Test tone finished - if you heard it, playback works".to_string(),
+                Err(e) => format!("❌ Test tone failed: {}", e),
+            };
+            *result_slot.lock().unwrap() = Some(message);
+        });
+
+        self.audio_test_result = Some(result);
+        self.add_status_message("🔔 Playing a 440Hz test tone...".to_string());
+    }
+
+    /// Feed our own preview tile while video is on and we're in a room.
+    ///
+    /// Uses the webcam when built with the `video` feature and a camera
+    /// opens; otherwise falls back to a moving test pattern.
+    fn update_local_preview(&mut self) {
+        if !self.video_enabled || self.current_room.is_none() {
+            #[cfg(feature = "video")]
+            {
+                self.video_capture = None;
+            }
+            self.video_textures.remove("local");
+            return;
+        }
+
+        #[cfg(feature = "video")]
+        {
+            if self.video_capture.is_none() && !self.video_capture_failed {
+                let mut capture = pqc_chat::video_capture::VideoCapture::new(self.video_config.clone());
+                let latest = self.captured_frame.clone();
+                match capture.start(move |frame| {
+                    *latest.lock().unwrap() = Some(frame);
+                }) {
+                    Ok(()) => self.video_capture = Some(capture),
+                    Err(e) => {
+                        self.video_capture_failed = true;
+                        self.add_status_message(format!("📺 Camera unavailable, showing test pattern: {}", e));
+                    }
+                }
+            }
+            if self.video_capture.is_some() {
+                if let Some(frame) = self.captured_frame.lock().unwrap().take() {
+                    self.pending_video_frames.insert("local".to_string(), frame);
+                }
+                return;
+            }
+        }
+
+        let interval = std::time::Duration::from_secs_f64(1.0 / self.video_config.fps.max(1) as f64);
+        if self.local_preview_last.elapsed() < interval {
+            return;
+        }
+        self.local_preview_last = std::time::Instant::now();
+        self.local_preview_frame = self.local_preview_frame.wrapping_add(1);
+
+        // Scaled down; this is only a preview tile
+        let frame = VideoFrame::test_pattern(
+            self.video_config.width / 4,
+            self.video_config.height / 4,
+            self.local_preview_frame,
+        );
+        self.pending_video_frames.insert("local".to_string(), frame);
+    }
+
+    /// Upload any newly received frames into their per-participant textures
+    fn upload_video_frames(&mut self, ctx: &egui::Context) {
+        for (id, frame) in self.pending_video_frames.drain() {
+            let image = frame.to_color_image();
+            match self.video_textures.get_mut(&id) {
+                Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                None => {
+                    let texture = ctx.load_texture(format!("video-{}", id), image, egui::TextureOptions::LINEAR);
+                    self.video_textures.insert(id, texture);
+                }
+            }
+        }
+    }
+
+    fn poll_audio_test(&mut self) {
+        let finished = self
+            .audio_test_result
+            .as_ref()
+            .and_then(|result| result.lock().unwrap().take());
+        if let Some(message) = finished {
+            self.audio_test_result = None;
+            self.add_status_message(message);
+        }
+    }
+
+    /// End the call once nobody has spoken for the configured timeout
+    fn check_call_silence(&mut self) {
+        if !self.audio_call_active {
+            return;
+        }
+        let expired = match &self.silence_timer {
+            Some(timer) => timer.lock().unwrap().is_expired(std::time::Instant::now()),
+            None => false,
+        };
+        if expired {
+            self.audio_call_active = false;
+            self.stop_audio_call();
+            self.add_status_message(format!(
+                "🔕 Call ended after {}s of silence",
+                self.audio_config.silence_timeout_secs
+            ));
+        }
+    }
+
+    fn start_recording(&mut self) {
+        let dir = std::path::Path::new("recordings");
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            self.add_status_message(format!("❌ Can't create recordings folder: {}", e));
+            return;
+        }
+        let path = dir.join(format!("call-{}.wav", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+
+        match pqc_chat::audio_recorder::AudioRecorder::start(&path, self.record_include_mic) {
+            Ok(recorder) => {
+                *self.recorder_mic_tap.lock().unwrap() = Some(recorder.tap());
+                self.audio_recorder = Some(recorder);
+                self.add_status_message(format!("⏺ Recording to {}", path.display()));
+            }
+            Err(e) => self.add_status_message(format!("❌ Failed to start recording: {}", e)),
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        *self.recorder_mic_tap.lock().unwrap() = None;
+        if let Some(recorder) = self.audio_recorder.take() {
+            let path = recorder.path().display().to_string();
+            let dropped = recorder.dropped_chunks();
+            match recorder.stop() {
+                Ok(samples) => {
+                    let secs = samples as f64 / 48000.0;
+                    let mut message = format!("💾 Saved {:.1}s recording to {}", secs, path);
+                    if dropped > 0 {
+                        message.push_str(&format!(" ({} chunks dropped)", dropped));
+                    }
+                    self.add_status_message(message);
+                }
+                Err(e) => self.add_status_message(format!("❌ Failed to save recording: {}", e)),
+            }
+        }
+    }
+
     fn stop_audio_call(&mut self) {
         log::info!("Stopping audio call...");
         
+        self.stop_recording();
+        self.silence_timer = None;
+        
         // Clear any buffered audio first
         if let Some(producer) = &self.audio_producer {
             let mut producer = producer.lock().unwrap();
@@ -555,6 +1146,9 @@ impl EnhancedPqcChatApp {
         
         // Clear producer reference
         self.audio_producer = None;
+        self.audio_stats = None;
+        self.mic_level = None;
+        self.monitor = None;
         
         self.add_status_message("🔇 Audio call ended".to_string());
         log::info!("Audio call stopped");
@@ -567,6 +1161,10 @@ impl eframe::App for EnhancedPqcChatApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Process updates from backend
         self.process_updates();
+        self.poll_audio_test();
+        self.check_call_silence();
+        self.update_local_preview();
+        self.upload_video_frames(ctx);
 
         // Request repaint for live updates
         ctx.request_repaint();
@@ -586,6 +1184,23 @@ impl eframe::App for EnhancedPqcChatApp {
                 }
                 ui.checkbox(&mut self.show_rooms_panel, "🏠 Rooms");
                 
+                ui.separator();
+                let can_test = !self.audio_call_active && self.audio_test_result.is_none();
+                if ui
+                    .add_enabled(can_test, egui::Button::new("🎧 Test Audio"))
+                    .on_hover_text("Play your microphone back through your speakers (use headphones)")
+                    .clicked()
+                {
+                    self.start_audio_test();
+                }
+                if ui
+                    .add_enabled(can_test, egui::Button::new("🔔 Test Tone"))
+                    .on_hover_text("Play a tone through your speakers, no network involved")
+                    .clicked()
+                {
+                    self.start_test_tone();
+                }
+                
                 if self.is_connected {
                     ui.separator();
                     if ui.button("🔌 Disconnect").clicked() {
@@ -632,8 +1247,22 @@ impl eframe::App for EnhancedPqcChatApp {
                     if let Some(room) = &self.current_room {
                         ui.group(|ui| {
                             ui.label("📍 Current Room:");
-                            ui.strong(&room.name);
+                            ui.horizontal(|ui| {
+                                ui.strong(&room.name);
+                                if room.is_locked {
+                                    ui.label("🔒");
+                                }
+                            });
                             ui.label(format!("👥 {} / {} participants (GUI sees: {})", room.participants, room.max_participants, self.room_participants.len()));
+                            if let Some(owner_id) = &room.owner_id {
+                                let owner = self
+                                    .room_participants
+                                    .iter()
+                                    .find(|p| &p.id == owner_id)
+                                    .map(|p| p.username.as_str())
+                                    .unwrap_or(owner_id.as_str());
+                                ui.label(format!("👑 Owner: {}", owner));
+                            }
                             if ui.button("👋 Leave Room").clicked() {
                                 self.send_command(GuiCommand::LeaveRoom);
                             }
@@ -656,17 +1285,29 @@ impl eframe::App for EnhancedPqcChatApp {
                         .max_height(200.0)
                         .show(ui, |ui| {
                             for (idx, room) in self.rooms.iter().enumerate() {
-                                let is_selected = self.selected_room_idx == Some(idx);
-                                let response = ui.selectable_label(is_selected, format!(
+                                let is_selected = self.selected_room_id.as_ref() == Some(&room.id);
+                                // Rooms we can't enter are shown greyed out
+                                let response = ui.add_enabled(room.joinable, egui::SelectableLabel::new(is_selected, format!(
                                     "🏠 {} ({}/{}{})",
                                     room.name,
                                     room.participants,
                                     room.max_participants,
                                     if room.is_locked { " 🔒" } else { "" }
-                                ));
+                                )));
+                                
+                                if let Some(topic) = &room.topic {
+                                    ui.indent(("room_topic", idx), |ui| {
+                                        ui.label(egui::RichText::new(topic).small().weak());
+                                    });
+                                }
+                                if let Some(activity) = &room.activity {
+                                    ui.indent(("room_activity", idx), |ui| {
+                                        ui.label(egui::RichText::new(activity).small().weak());
+                                    });
+                                }
                                 
                                 if response.clicked() {
-                                    self.selected_room_idx = Some(idx);
+                                    self.selected_room_id = Some(room.id.clone());
                                 }
                                 
                                 if response.double_clicked() {
@@ -677,11 +1318,9 @@ impl eframe::App for EnhancedPqcChatApp {
                             }
                         });
                     
-                    if let Some(idx) = self.selected_room_idx {
-                        if idx < self.rooms.len() && ui.button("🚪 Join Room").clicked() {
-                            self.send_command(GuiCommand::JoinRoom {
-                                room_id: self.rooms[idx].id.clone(),
-                            });
+                    if let Some(room_id) = self.selected_room_id.clone() {
+                        if self.rooms.iter().any(|r| r.id == room_id) && ui.button("🚪 Join Room").clicked() {
+                            self.send_command(GuiCommand::JoinRoom { room_id });
                         }
                     }
                     
@@ -694,7 +1333,7 @@ impl eframe::App for EnhancedPqcChatApp {
                     if ui.button("➕ Create Room").clicked() && !self.new_room_name.is_empty() {
                         self.send_command(GuiCommand::CreateRoom {
                             name: self.new_room_name.clone(),
-                            max_participants: 10,
+                            max_participants: None,
                         });
                         self.new_room_name.clear();
                     }
@@ -735,6 +1374,8 @@ impl eframe::App for EnhancedPqcChatApp {
                                             let audio_icon = if user.audio_enabled { "🎤" } else { "🔇" };
                                             let video_icon = if user.video_enabled { "📹" } else { "📺" };
                                             
+                                            ui.colored_label(status_color(user.status), "●")
+                                                .on_hover_text(format!("{:?}", user.status));
                                             ui.label(format!("{} {}", audio_icon, video_icon));
                                             
                                             if user.username == self.username {
@@ -743,6 +1384,9 @@ impl eframe::App for EnhancedPqcChatApp {
                                             } else {
                                                 ui.label(&user.username);
                                             }
+                                            if user.reconnecting_since.is_some() {
+                                                ui.weak("reconnecting…");
+                                            }
                                         });
                                         
                                         if let Some(room) = &user.in_room {
@@ -792,10 +1436,12 @@ impl eframe::App for EnhancedPqcChatApp {
                                 let chat_history = self.room_chat_history.entry(room_id).or_insert_with(Vec::new);
                                 
                                 chat_history.push(ChatMessage {
+                                    message_id: String::new(),
                                     sender_id: "optimistic".to_string(),
                                     sender_username: self.username.clone(),
                                     content: content.clone(),
                                     timestamp: std::time::SystemTime::now(),
+                                    edited: false,
                                 });
                                 
                                 if chat_history.len() > 100 {
@@ -854,7 +1500,70 @@ impl eframe::App for EnhancedPqcChatApp {
                                 self.audio_call_active = false;
                                 self.stop_audio_call();
                             }
+                            
+                            if self.audio_recorder.is_some() {
+                                if ui.button("⏹ Stop Recording").on_hover_text("Finish and save the WAV file").clicked() {
+                                    self.stop_recording();
+                                }
+                            } else {
+                                ui.checkbox(&mut self.record_include_mic, "🎤 incl. mic");
+                                if ui.button("⏺ Record").on_hover_text("Record the call to a WAV file").clicked() {
+                                    self.start_recording();
+                                }
+                            }
+
+                            if let Some(level) = &self.mic_level {
+                                // RMS of speech sits well below full scale, so
+                                // stretch it to make the bar readable
+                                let fill = (level.level() * 4.0).min(1.0);
+                                ui.add(egui::ProgressBar::new(fill).desired_width(80.0))
+                                    .on_hover_text("Microphone level");
+                            }
+
+                            if ui
+                                .checkbox(&mut self.monitor_enabled, "🎧 Monitor")
+                                .on_hover_text("Hear your own microphone (use headphones to avoid feedback)")
+                                .changed()
+                            {
+                                if let Some(monitor) = &self.monitor {
+                                    monitor.set_gain(if self.monitor_enabled { self.audio_config.monitor_gain } else { 0.0 });
+                                }
+                            }
+
+                            if let Some(stats) = &self.audio_stats {
+                                ui.label(
+                                    egui::RichText::new(format!("{} underruns / {} overruns", stats.underruns(), stats.overruns()))
+                                        .small()
+                                        .weak(),
+                                )
+                                .on_hover_text("Playback samples filled with silence / dropped because the buffer was full");
+                            }
                         } else {
+                            egui::ComboBox::from_id_source("audio_profile")
+                                .selected_text(self.audio_config.profile.label())
+                                .show_ui(ui, |ui| {
+                                    for profile in pqc_chat::audio::AudioProfile::ALL {
+                                        ui.selectable_value(&mut self.audio_config.profile, profile, profile.label());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Audio buffering: lower latency or fewer dropouts");
+                            let mic = match &self.input_caps {
+                                Some(caps) if !caps.supports_capture() => ui.label("🎤⚠️"),
+                                _ => ui.label("🎤"),
+                            };
+                            if let Some(caps) = &self.input_caps {
+                                mic.on_hover_text(caps.describe());
+                            }
+                            let input_changed = device_combo(ui, "input_device", &self.input_devices, &mut self.audio_config.preferred_input_name);
+                            if input_changed {
+                                self.input_caps = probe_input_caps(&self.input_devices, self.audio_config.preferred_input_name.as_deref());
+                            }
+                            ui.label("🔊");
+                            let output_changed = device_combo(ui, "output_device", &self.output_devices, &mut self.audio_config.preferred_output_name);
+                            if input_changed || output_changed {
+                                self.save_device_selection();
+                            }
                             if ui.button("📞 Start Call").on_hover_text("Start audio call with room participants").clicked() {
                                 self.audio_call_active = true;
                                 self.start_audio_call();
@@ -865,6 +1574,52 @@ impl eframe::App for EnhancedPqcChatApp {
                         ui.label(format!("👥 {} participants", self.room_participants.len()));
                     });
                     
+                    // Video tiles, one per participant with a frame
+                    if !self.video_textures.is_empty() {
+                        let tile = egui::vec2(160.0, 120.0);
+                        ui.horizontal_wrapped(|ui| {
+                            for (id, texture) in &self.video_textures {
+                                let name = if id == "local" {
+                                    "You".to_string()
+                                } else {
+                                    self.room_participants
+                                        .iter()
+                                        .find(|p| &p.id == id)
+                                        .map(|p| p.username.clone())
+                                        .unwrap_or_else(|| id.clone())
+                                };
+                                ui.vertical(|ui| {
+                                    ui.add(egui::Image::new(egui::load::SizedTexture::new(texture.id(), tile)));
+                                    ui.small(name);
+                                });
+                            }
+                        });
+                        ui.separator();
+                    }
+                    
+                    // Per-participant playback volume
+                    let mixer = &mut self.audio_mixer;
+                    let participants = &self.room_participants;
+                    let own_username = &self.username;
+                    let stereo = self.audio_config.stereo_panning;
+                    ui.collapsing("🔊 Participant volume", |ui| {
+                        for p in participants.iter().filter(|p| &p.username != own_username) {
+                            ui.horizontal(|ui| {
+                                let mut gain = mixer.sender_volume(&p.id);
+                                ui.label(&p.username);
+                                if ui.add(egui::Slider::new(&mut gain, 0.0..=2.0)).changed() {
+                                    mixer.set_sender_volume(&p.id, gain);
+                                }
+                                if stereo {
+                                    let mut pan = mixer.sender_pan(&p.id);
+                                    if ui.add(egui::Slider::new(&mut pan, -1.0..=1.0).text("L/R")).changed() {
+                                        mixer.set_sender_pan(&p.id, pan);
+                                    }
+                                }
+                            });
+                        }
+                    });
+                    
                     ui.separator();
 
                     // Chat area - full width, scrollable, extends from header to input bar
@@ -900,6 +1655,9 @@ impl eframe::App for EnhancedPqcChatApp {
                                                         ui.label(&msg.sender_username);
                                                     }
                                                     ui.small(format_time(msg.timestamp));
+                                                    if msg.edited {
+                                                        ui.small("(edited)");
+                                                    }
                                                 });
                                                 ui.label(&msg.content);
                                             });
@@ -985,6 +1743,8 @@ impl eframe::App for EnhancedPqcChatApp {
                                             let audio_icon = if user.audio_enabled { "🎤" } else { "🔇" };
                                             let video_icon = if user.video_enabled { "📹" } else { "📺" };
 
+                                            ui.colored_label(status_color(user.status), "●")
+                                                .on_hover_text(format!("{:?}", user.status));
                                             ui.label(format!("{} {}", audio_icon, video_icon));
 
                                             if user.username == self.username {
@@ -993,6 +1753,9 @@ impl eframe::App for EnhancedPqcChatApp {
                                             } else {
                                                 ui.label(&user.username);
                                             }
+                                            if user.reconnecting_since.is_some() {
+                                                ui.weak("reconnecting…");
+                                            }
                                         });
 
                                         if let Some(room) = &user.in_room {
@@ -1025,6 +1788,173 @@ impl eframe::App for EnhancedPqcChatApp {
     }
 }
 
+/// Input and output device names; empty when there is no audio host
+#[cfg(feature = "gui")]
+fn list_audio_devices() -> (Vec<String>, Vec<String>) {
+    match pqc_chat::audio::AudioManager::new() {
+        Ok(manager) => (
+            manager.list_input_devices().unwrap_or_default(),
+            manager.list_output_devices().unwrap_or_default(),
+        ),
+        Err(e) => {
+            log::warn!("Could not enumerate audio devices: {}", e);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+/// Capabilities of the input device `preferred` names (or the system
+/// default), or `None` when it can't be probed
+#[cfg(feature = "gui")]
+fn probe_input_caps(names: &[String], preferred: Option<&str>) -> Option<pqc_chat::audio::DeviceCaps> {
+    let manager = pqc_chat::audio::AudioManager::new().ok()?;
+    let probed = match pqc_chat::audio::select_device(names, preferred, None) {
+        Some(index) => manager.probe_input(index),
+        None => manager.probe_default_input(),
+    };
+    match probed {
+        Ok(caps) => Some(caps),
+        Err(e) => {
+            log::warn!("Could not probe input device: {}", e);
+            None
+        }
+    }
+}
+
+/// The device name for a dropdown index; `None` is the system default
+#[cfg(feature = "gui")]
+fn device_choice(names: &[String], index: Option<usize>) -> Option<String> {
+    index.and_then(|i| names.get(i).cloned())
+}
+
+/// Dropdown over `names` plus "System default"; returns whether the choice changed
+#[cfg(feature = "gui")]
+fn device_combo(ui: &mut egui::Ui, id: &str, names: &[String], chosen: &mut Option<String>) -> bool {
+    let current = chosen
+        .as_deref()
+        .and_then(|name| pqc_chat::audio::select_device(names, Some(name), None));
+    let mut index = current;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(index.map_or("System default", |i| names[i].as_str()))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut index, None, "System default");
+            for (i, name) in names.iter().enumerate() {
+                ui.selectable_value(&mut index, Some(i), name);
+            }
+        });
+    if index == current {
+        return false;
+    }
+    *chosen = device_choice(names, index);
+    true
+}
+
+/// Show a dropped user as reconnecting instead of removing them straight
+/// away, returning their username
+#[cfg(feature = "gui")]
+fn mark_user_reconnecting(users: &mut HashMap<String, ConnectedUser>, user_id: &str, now: std::time::Instant) -> Option<String> {
+    let user = users.get_mut(user_id)?;
+    user.reconnecting_since.get_or_insert(now);
+    Some(user.username.clone())
+}
+
+/// Add or update a user. A resumed session keeps its participant ID, so
+/// someone back within their reconnect grace takes over their old entry,
+/// keeping when they first connected. Returns whether it did.
+#[cfg(feature = "gui")]
+fn merge_connected_user(users: &mut HashMap<String, ConnectedUser>, mut user: ConnectedUser, now: std::time::Instant) -> bool {
+    let merged = match users.get(&user.id) {
+        Some(previous) if previous
+            .reconnecting_since
+            .is_some_and(|since| now.saturating_duration_since(since) < USER_RECONNECT_GRACE) =>
+        {
+            user.connected_at = previous.connected_at;
+            true
+        }
+        _ => false,
+    };
+    users.insert(user.id.clone(), user);
+    merged
+}
+
+/// Bring the user list in line with the server's. Anyone missing who
+/// dropped is left to their reconnect grace; anyone else logged out.
+#[cfg(feature = "gui")]
+fn sync_connected_users(users: &mut HashMap<String, ConnectedUser>, listed: Vec<ConnectedUser>, now: std::time::Instant) {
+    users.retain(|id, user| user.reconnecting_since.is_some() || listed.iter().any(|listed| &listed.id == id));
+    for user in listed {
+        merge_connected_user(users, user, now);
+    }
+}
+
+/// Remove users whose reconnect grace has run out, returning them
+#[cfg(feature = "gui")]
+fn expire_reconnecting_users(users: &mut HashMap<String, ConnectedUser>, now: std::time::Instant) -> Vec<ConnectedUser> {
+    let expired: Vec<String> = users
+        .iter()
+        .filter(|(_, user)| {
+            user.reconnecting_since
+                .is_some_and(|since| now.saturating_duration_since(since) >= USER_RECONNECT_GRACE)
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+    expired.iter().filter_map(|id| users.remove(id)).collect()
+}
+
+/// Drop a departed sender's mixer channel, decoder and jitter buffer, so
+/// calls with a lot of coming and going don't pile up audio state for people
+/// who are gone
+#[cfg(feature = "gui")]
+fn drop_sender_audio(
+    mixer: &mut pqc_chat::audio_mixer::AudioMixer,
+    decoders: &mut HashMap<String, pqc_chat::audio_codec::FrameDecoder>,
+    jitter_buffers: &mut HashMap<String, pqc_chat::jitter_buffer::JitterBuffer>,
+    sender_id: &str,
+) {
+    mixer.remove_sender(sender_id);
+    decoders.remove(sender_id);
+    jitter_buffers.remove(sender_id);
+}
+
+/// Drop every sender's mixer channel, decoder and jitter buffer once we're
+/// out of the room, so nothing queued there plays (or decodes) in the next
+/// one
+#[cfg(feature = "gui")]
+fn drop_room_audio(
+    mixer: &mut pqc_chat::audio_mixer::AudioMixer,
+    decoders: &mut HashMap<String, pqc_chat::audio_codec::FrameDecoder>,
+    jitter_buffers: &mut HashMap<String, pqc_chat::jitter_buffer::JitterBuffer>,
+) {
+    *mixer = pqc_chat::audio_mixer::AudioMixer::new();
+    decoders.clear();
+    jitter_buffers.clear();
+}
+
+/// Merge messages into a room's history, oldest first. A message whose
+/// server ID is already present updates that entry rather than being
+/// added twice, so history replayed after a reconnect lines up with what's
+/// already shown.
+#[cfg(feature = "gui")]
+fn merge_chat_history(history: &mut Vec<ChatMessage>, incoming: impl IntoIterator<Item = ChatMessage>) {
+    for message in incoming {
+        let existing = if message.message_id.is_empty() {
+            None
+        } else {
+            history.iter_mut().find(|m| m.message_id == message.message_id)
+        };
+        match existing {
+            Some(existing) => {
+                existing.content = message.content;
+                existing.edited = message.edited;
+            }
+            None => history.push(message),
+        }
+    }
+    if history.len() > MAX_ROOM_HISTORY {
+        history.drain(..history.len() - MAX_ROOM_HISTORY);
+    }
+}
+
 #[cfg(feature = "gui")]
 async fn communication_task(
     mut command_receiver: mpsc::Receiver<GuiCommand>,
@@ -1037,6 +1967,12 @@ async fn communication_task(
     let mut connection: Option<Arc<Mutex<tokio_rustls::client::TlsStream<TcpStream>>>> = None;
     let mut _participant_id: Option<String> = None;
     let mut current_username: Option<String> = None;
+    // Kept across a dropped connection so the next login resumes the session
+    let mut resume_token: Option<String> = None;
+    let mut framing = FrameOptions::default();
+    let mut frame_auth: Option<FrameAuthenticator> = None;
+    let mut roster = Roster::default();
+    let mut clock = ClockOffset::default();
     
     loop {
         if let Some(ref conn_arc) = connection.clone() {
@@ -1050,13 +1986,17 @@ async fn communication_task(
                     let username = current_username.as_deref().unwrap_or("User");
                     match command {
                         GuiCommand::Disconnect => {
+                            // Tell the server so it can tear down right away
+                            let _ = send_message(&mut *conn, &SignalingMessage::Logout, &mut frame_auth).await;
                             connection = None;
+                            frame_auth = None;
                             _participant_id = None;
                             current_username = None;
+                            resume_token = None;
                             let _ = update_sender.send(GuiUpdate::Disconnected);
                         },
                         _ => {
-                            let _ = handle_command(&mut *conn, command, &update_sender, username).await;
+                            let _ = handle_command(&mut *conn, command, &update_sender, username, framing, clock, &mut frame_auth, &mut roster).await;
                         }
                     }
                 }
@@ -1067,12 +2007,19 @@ async fn communication_task(
                     match result {
                         Ok(msg) => {
                             eprintln!("DEBUG: Received message in main loop: {:?}", msg);
-                            process_server_message(msg, &update_sender).await;
+                            match track_roster(&mut roster, msg) {
+                                Ok(msg) => process_server_message(msg, &update_sender).await,
+                                Err(room_id) => {
+                                    let mut conn = conn_arc.lock().await;
+                                    let _ = send_message(&mut *conn, &SignalingMessage::RequestRoster { room_id }, &mut frame_auth).await;
+                                }
+                            }
                         }
                         Err(e) => {
                             eprintln!("DEBUG: Connection error in main loop: {:?}", e);
                             // Connection closed
                             connection = None;
+                            frame_auth = None;
                             let _ = update_sender.send(GuiUpdate::Disconnected);
                         }
                     }
@@ -1082,17 +2029,20 @@ async fn communication_task(
             // Not connected, just wait for connect command
             if let Some(command) = command_receiver.recv().await {
                 if let GuiCommand::Connect { host, port, username } = command {
-                    match connect_to_server(&host, port, &username, &update_sender).await {
-                        Ok((stream, pid)) => {
+                    match connect_to_server(&host, port, &username, &mut resume_token, &update_sender).await {
+                        Ok((stream, pid, agreed, auth, offset, codec)) => {
                             connection = Some(Arc::new(Mutex::new(stream)));
+                            framing = agreed;
+                            frame_auth = auth;
+                            clock = offset;
                             _participant_id = Some(pid.clone());
                             current_username = Some(username.clone());
-                            let _ = update_sender.send(GuiUpdate::Connected { participant_id: pid.clone() });
+                            let _ = update_sender.send(GuiUpdate::Connected { participant_id: pid.clone(), codec });
                             
                             // Request initial room list
                             if let Some(ref conn_arc) = connection {
                                 let mut conn = conn_arc.lock().await;
-                                let _ = send_message(&mut *conn, &SignalingMessage::ListRooms).await;
+                                let _ = send_message(&mut *conn, &SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false }, &mut frame_auth).await;
                             }
                         },
                         Err(e) => {
@@ -1112,8 +2062,9 @@ async fn connect_to_server(
     host: &str,
     port: u16,
     username: &str,
-    _update_sender: &mpsc::UnboundedSender<GuiUpdate>,
-) -> Result<(tokio_rustls::client::TlsStream<tokio::net::TcpStream>, String), Box<dyn std::error::Error + Send + Sync>> {
+    resume_token: &mut Option<String>,
+    update_sender: &mpsc::UnboundedSender<GuiUpdate>,
+) -> Result<(tokio_rustls::client::TlsStream<tokio::net::TcpStream>, String, FrameOptions, Option<FrameAuthenticator>, ClockOffset, AudioCodec), Box<dyn std::error::Error + Send + Sync>> {
     use tokio::net::TcpStream;
     use tokio_rustls::rustls::{self, pki_types::ServerName};
     use tokio_rustls::TlsConnector;
@@ -1132,51 +2083,111 @@ async fn connect_to_server(
     let stream = TcpStream::connect(&addr).await?;
     let server_name = ServerName::try_from(host.to_string())?;
     let mut tls_stream = connector.connect(server_name, stream).await?;
+
+    // The server introduces itself before anything else
+    match receive_message(&mut tls_stream).await? {
+        SignalingMessage::ServerInfo { name, version, protocol_version, motd } => {
+            if protocol_version != PROTOCOL_VERSION {
+                return Err(format!(
+                    "Server speaks protocol v{}, this client v{}",
+                    protocol_version, PROTOCOL_VERSION
+                ).into());
+            }
+            let mut message = format!("🖥️ Connecting to {} (v{})", name, version);
+            if let Some(motd) = motd {
+                message.push_str(&format!(" - {}", motd));
+            }
+            let _ = update_sender.send(GuiUpdate::StatusMessage { message });
+        }
+        _ => return Err("Expected server info".into()),
+    }
     
     // Perform Kyber key exchange
     let kyber = KyberKeyExchange::new();
     let key_init = SignalingMessage::KeyExchangeInit {
         public_key: kyber.public_key_bytes(),
     };
-    send_message(&mut tls_stream, &key_init).await?;
+    send_message(&mut tls_stream, &key_init, &mut None).await?;
     
     let response = receive_message(&mut tls_stream).await?;
-    if let SignalingMessage::KeyExchangeResponse { ciphertext } = response {
-        kyber.decapsulate(&ciphertext)?;
+    let shared_secret = if let SignalingMessage::KeyExchangeResponse { ciphertext } = response {
+        kyber.decapsulate(&ciphertext)?
     } else {
         return Err("Key exchange failed".into());
+    };
+    
+    // Negotiate optional protocol features (e.g. compressed frames)
+    let hello = SignalingMessage::Hello {
+        features: SUPPORTED_FEATURES.iter().map(|f| f.to_string()).collect(),
+    };
+    send_message(&mut tls_stream, &hello, &mut None).await?;
+    let agreed = match receive_message(&mut tls_stream).await? {
+        SignalingMessage::Hello { features } => features,
+        _ => Vec::new(),
+    };
+    let framing = FrameOptions::from_features(&agreed);
+    let codec = preferred_codec(&agreed);
+    // From here on every frame we send carries a tag under the session key
+    let mut frame_auth = agreed.iter().any(|f| f == FEATURE_FRAME_MAC).then(|| {
+        FrameAuthenticator::new(&KyberSession::new(shared_secret).send_key(SessionRole::Client))
+    });
+
+    // Servers that predate capabilities answer with an error instead
+    send_message(&mut tls_stream, &SignalingMessage::DescribeCapabilities, &mut frame_auth).await?;
+    let mut clock = ClockOffset::default();
+    if let SignalingMessage::Capabilities(capabilities) = receive_message(&mut tls_stream).await? {
+        // Stamp outgoing audio on the server's clock so its age checks hold
+        if capabilities.time_sync {
+            let sync = SignalingMessage::TimeSync { client_sent_ms: pqc_chat::protocol::unix_millis() };
+            send_message(&mut tls_stream, &sync, &mut frame_auth).await?;
+            if let SignalingMessage::TimeSyncResponse { client_sent_ms, server_time_ms, .. } = receive_message(&mut tls_stream).await? {
+                clock = ClockOffset::from_exchange(client_sent_ms, server_time_ms, pqc_chat::protocol::unix_millis());
+            }
+        }
+        let _ = update_sender.send(GuiUpdate::Capabilities { capabilities });
     }
     
     // Login
     let login = SignalingMessage::Login {
         username: username.to_string(),
+        resume_token: resume_token.take(),
     };
-    send_message(&mut tls_stream, &login).await?;
+    send_message(&mut tls_stream, &login, &mut frame_auth).await?;
     
     let response = receive_message(&mut tls_stream).await?;
-    if let SignalingMessage::LoginResponse { success, participant_id, .. } = response {
+    if let SignalingMessage::LoginResponse { success, participant_id, resume_token: issued, .. } = response {
         if success {
+            *resume_token = issued;
             if let Some(pid) = participant_id {
-                return Ok((tls_stream, pid));
+                return Ok((tls_stream, pid, framing, frame_auth, clock, codec));
             }
         }
+    } else if let SignalingMessage::Error { message, .. } = response {
+        return Err(format!("Login failed: {}", message).into());
     }
     
     Err("Login failed".into())
 }
 
 #[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
 async fn handle_command(
     stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
     command: GuiCommand,
     update_sender: &mpsc::UnboundedSender<GuiUpdate>,
     username: &str,
+    framing: FrameOptions,
+    clock: ClockOffset,
+    frame_auth: &mut Option<FrameAuthenticator>,
+    roster: &mut Roster,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let message = match command {
-        GuiCommand::ListRooms => SignalingMessage::ListRooms,
+        GuiCommand::ListRooms => SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false },
         GuiCommand::CreateRoom { name, max_participants } => SignalingMessage::CreateRoom {
             name,
-            max_participants: Some(max_participants),
+            max_participants,
+            slug: None,
+            required_codec: None,
         },
         GuiCommand::JoinRoom { room_id } => SignalingMessage::JoinRoom {
             room_id,
@@ -1185,23 +2196,26 @@ async fn handle_command(
         GuiCommand::LeaveRoom => SignalingMessage::LeaveRoom,
         GuiCommand::ToggleAudio { enabled } => SignalingMessage::ToggleAudio { enabled },
         GuiCommand::ToggleVideo { enabled } => SignalingMessage::ToggleVideo { enabled },
-        GuiCommand::ListServerUsers => SignalingMessage::ListServerUsers,
+        GuiCommand::ListServerUsers => SignalingMessage::ListServerUsers { offset: None, limit: None },
         GuiCommand::SendMessage { content } => {
             // Send chat message
             let msg = SignalingMessage::SendMessage { content: content.clone() };
             eprintln!("DEBUG: Sending message to server: {}", content);
             eprintln!("DEBUG: Message JSON: {}", serde_json::to_string(&msg).unwrap_or_else(|_| "ERROR".to_string()));
-            send_message(stream, &msg).await?;
+            send_message(stream, &msg, frame_auth).await?;
             // Read and discard the acknowledgment response
             // The actual message will come via broadcast to all participants
             let ack = receive_message(stream).await?;
             eprintln!("DEBUG: Received acknowledgment: {:?}", ack);
             return Ok(());
         },
-        GuiCommand::SendAudioData { data } => {
-            // Send audio data through signaling
-            let msg = SignalingMessage::AudioData { data };
-            send_message(stream, &msg).await?;
+        GuiCommand::SendAudioData { data, batched, codec, captured_at } => {
+            // Send audio data through signaling, as a binary frame if agreed,
+            // with the capture time on the server's clock so it can be dropped
+            // if it goes stale
+            let captured_at = Some(clock.to_server(captured_at));
+            let msg = SignalingMessage::AudioData { data, batched, codec, captured_at };
+            send_frame(stream, msg.to_framed_with(framing)?, frame_auth).await?;
             // Audio data doesn't need response
             return Ok(());
         },
@@ -1212,25 +2226,26 @@ async fn handle_command(
         _ => return Ok(()),
     };
     
-    send_message(stream, &message).await?;
-    let response = receive_message(stream).await?;
+    send_message(stream, &message, frame_auth).await?;
+    let response = match track_roster(roster, receive_message(stream).await?) {
+        Ok(response) => response,
+        Err(room_id) => {
+            send_message(stream, &SignalingMessage::RequestRoster { room_id }, frame_auth).await?;
+            return Ok(());
+        }
+    };
     
     // Process response
     match response {
-        SignalingMessage::RoomList { rooms } => {
+        SignalingMessage::RoomList { rooms, .. } => {
             let _ = update_sender.send(GuiUpdate::RoomList { rooms });
         },
-        SignalingMessage::RoomJoined { success, room_name, participants, .. } => {
+        SignalingMessage::RoomJoined { success, room_id, room_name, participants, .. } => {
             if success {
-                if let (Some(name), Some(parts)) = (room_name, participants) {
-                    let room = RoomInfo {
-                        id: "temp".to_string(), // TODO: Get actual room ID
-                        name,
-                        participants: parts.len() as u32,
-                        max_participants: 10,
-                        is_locked: false,
-                    };
+                if let (Some(id), Some(name), Some(parts)) = (room_id, room_name, participants) {
+                    let room = placeholder_room_info(id.clone(), name, parts.len());
                     let _ = update_sender.send(GuiUpdate::RoomJoined { room, participants: parts });
+                    send_message(stream, &SignalingMessage::GetRoomInfo { room_id: id }, frame_auth).await?;
                 }
             }
         },
@@ -1239,6 +2254,10 @@ async fn handle_command(
                 let _ = update_sender.send(GuiUpdate::RoomLeft);
             }
         },
+        SignalingMessage::WaitingRoom { position, .. } => {
+            let message = format!("⏳ Room is full - you are #{} in the waiting room", position);
+            let _ = update_sender.send(GuiUpdate::StatusMessage { message });
+        },
         SignalingMessage::ParticipantJoined { participant_id, username } => {
             let participant = ParticipantInfo {
                 id: participant_id.clone(),
@@ -1256,14 +2275,20 @@ async fn handle_command(
                 in_room: Some("Current Room".to_string()), // TODO: Get actual room name
                 audio_enabled: true,
                 video_enabled: false,
+                status: UserStatus::Available,
+                reconnecting_since: None,
             };
             let _ = update_sender.send(GuiUpdate::ServerUserConnected { user });
         },
-        SignalingMessage::ParticipantLeft { participant_id } => {
-            let _ = update_sender.send(GuiUpdate::ParticipantLeft { participant_id });
-            // Note: Don't remove from server users - they may still be connected to server
+        SignalingMessage::ParticipantLeft { participant_id, reason } => {
+            // Only a dropped connection takes them off the server; otherwise
+            // they're still connected, just not in this room
+            if matches!(reason, LeaveReason::Disconnected | LeaveReason::TimedOut) {
+                let _ = update_sender.send(GuiUpdate::ServerUserDisconnected { user_id: participant_id.clone() });
+            }
+            let _ = update_sender.send(GuiUpdate::ParticipantLeft { participant_id, reason });
         },
-        SignalingMessage::ServerUserList { users } => {
+        SignalingMessage::ServerUserList { users, .. } => {
             let connected_users = users.into_iter().map(|server_user| {
                 ConnectedUser {
                     id: server_user.id,
@@ -1272,20 +2297,27 @@ async fn handle_command(
                     in_room: server_user.current_room,
                     audio_enabled: server_user.audio_enabled,
                     video_enabled: server_user.video_enabled,
+                    status: server_user.status,
+                    reconnecting_since: None,
                 }
             }).collect();
             let _ = update_sender.send(GuiUpdate::ServerUserList { users: connected_users });
         },
-        SignalingMessage::MessageReceived { sender_id, sender_username, content, timestamp } => {
+        SignalingMessage::MessageReceived { message_id, sender_id, sender_username, content, timestamp, edited, .. } => {
             let chat_message = ChatMessage {
+                message_id,
                 sender_id,
                 sender_username,
                 content,
                 timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp),
+                edited,
             };
             let _ = update_sender.send(GuiUpdate::ChatMessageReceived { message: chat_message });
         },
-        SignalingMessage::Error { message } => {
+        SignalingMessage::Error { message, code: Some(ErrorCode::PayloadTooLarge) } => {
+            let _ = update_sender.send(GuiUpdate::AudioRejected { message });
+        },
+        SignalingMessage::Error { message, .. } => {
             let _ = update_sender.send(GuiUpdate::StatusMessage { message });
         },
         _ => {
@@ -1296,6 +2328,51 @@ async fn handle_command(
     Ok(())
 }
 
+/// Keep the connection's copy of the room roster current. Deltas come back
+/// as the full `RoomRoster` the UI works from; `Err` names a room whose
+/// roster missed a delta and needs a `RequestRoster`.
+#[cfg(feature = "gui")]
+fn track_roster(roster: &mut Roster, message: SignalingMessage) -> Result<SignalingMessage, String> {
+    match message {
+        SignalingMessage::RoomRoster { ref room_id, ref participants, version } => {
+            roster.reset(room_id, version, participants.clone());
+        }
+        SignalingMessage::RoomJoined { success: true, room_id: Some(ref room_id), participants: Some(ref participants), roster_version, .. } => {
+            roster.reset(room_id, roster_version, participants.clone());
+        }
+        SignalingMessage::RoomRosterDelta { room_id, version, added, removed, media_changes } => {
+            if !roster.apply_delta(&room_id, version, added, &removed, &media_changes) {
+                return Err(room_id);
+            }
+            return Ok(SignalingMessage::RoomRoster {
+                room_id,
+                participants: roster.participants.clone(),
+                version,
+            });
+        }
+        _ => {}
+    }
+    Ok(message)
+}
+
+/// Room summary to show on join until the server's `RoomInfoResponse` arrives
+#[cfg(feature = "gui")]
+fn placeholder_room_info(id: String, name: String, participants: usize) -> RoomInfo {
+    RoomInfo {
+        id,
+        name,
+        slug: None,
+        participants: participants as u32,
+        max_participants: 10,
+        is_locked: false,
+        is_full: false,
+        password_protected: false,
+        topic: None,
+        required_codec: None,
+        last_activity: None,
+    }
+}
+
 #[cfg(feature = "gui")]
 async fn process_server_message(
     message: SignalingMessage,
@@ -1304,17 +2381,37 @@ async fn process_server_message(
     eprintln!("DEBUG: process_server_message called with: {:?}", message);
     // Handle unsolicited broadcasts from the server (messages, participant joins/leaves, etc.)
     match message {
-        SignalingMessage::MessageReceived { sender_id, sender_username, content, timestamp } => {
+        SignalingMessage::MessageReceived { message_id, sender_id, sender_username, content, timestamp, edited, .. } => {
             eprintln!("DEBUG: Processing MessageReceived from {} ({}): {}", sender_username, sender_id, content);
             let chat_message = ChatMessage {
+                message_id,
                 sender_id: sender_id.clone(),
                 sender_username: sender_username.clone(),
                 content: content.clone(),
                 timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp),
+                edited,
             };
             eprintln!("DEBUG: Sending GuiUpdate::ChatMessageReceived");
             let _ = update_sender.send(GuiUpdate::ChatMessageReceived { message: chat_message });
         },
+        SignalingMessage::UserStatusChanged { participant_id, status } => {
+            let _ = update_sender.send(GuiUpdate::UserStatusChanged { participant_id, status });
+        },
+        SignalingMessage::RoomInfoResponse { success: true, room: Some(room), owner_id, .. } => {
+            let _ = update_sender.send(GuiUpdate::RoomInfo { room, owner_id });
+        },
+        SignalingMessage::RoomTopicChanged { room_id, topic } => {
+            let _ = update_sender.send(GuiUpdate::RoomTopicChanged { room_id, topic });
+        },
+        SignalingMessage::RoomClosed { room_id, .. } => {
+            let _ = update_sender.send(GuiUpdate::RoomClosed { room_id });
+        },
+        SignalingMessage::MessageEdited { message_id, new_content, .. } => {
+            let _ = update_sender.send(GuiUpdate::ChatMessageEdited { message_id, new_content });
+        },
+        SignalingMessage::MessageDeleted { message_id } => {
+            let _ = update_sender.send(GuiUpdate::ChatMessageDeleted { message_id });
+        },
         SignalingMessage::ParticipantJoined { participant_id, username } => {
             let participant = ParticipantInfo {
                 id: participant_id.clone(),
@@ -1324,11 +2421,43 @@ async fn process_server_message(
             };
             let _ = update_sender.send(GuiUpdate::ParticipantJoined { participant });
         },
-        SignalingMessage::ParticipantLeft { participant_id } => {
-            let _ = update_sender.send(GuiUpdate::ParticipantLeft { participant_id });
+        SignalingMessage::ParticipantLeft { participant_id, reason } => {
+            let _ = update_sender.send(GuiUpdate::ParticipantLeft { participant_id, reason });
         },
-        SignalingMessage::AudioDataReceived { sender_id, data } => {
-            let _ = update_sender.send(GuiUpdate::AudioDataReceived { sender_id, data });
+        SignalingMessage::RoomRoster { room_id, participants, .. } => {
+            let _ = update_sender.send(GuiUpdate::RoomRoster { room_id, participants });
+        },
+        SignalingMessage::WaitingRoom { position, .. } => {
+            let message = format!("⏳ Room is full - you are #{} in the waiting room", position);
+            let _ = update_sender.send(GuiUpdate::StatusMessage { message });
+        },
+        // Let in from a waiting room
+        SignalingMessage::RoomJoined { success: true, room_id: Some(id), room_name: Some(name), participants: Some(parts), .. } => {
+            let room = placeholder_room_info(id, name, parts.len());
+            let _ = update_sender.send(GuiUpdate::RoomJoined { room, participants: parts });
+        },
+        SignalingMessage::GroupMessageReceived { sender_id, sender_username, recipient_ids, content, timestamp } => {
+            // Shown inline with room chat, marked so it's clear not everyone saw it
+            let chat_message = ChatMessage {
+                message_id: String::new(),
+                sender_id,
+                sender_username,
+                content: format!("🔒 (to {} in group) {}", recipient_ids.len(), content),
+                timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp),
+                edited: false,
+            };
+            let _ = update_sender.send(GuiUpdate::ChatMessageReceived { message: chat_message });
+        },
+        SignalingMessage::AudioDataReceived { sender_id, data, batched, codec, .. } => {
+            let _ = update_sender.send(GuiUpdate::AudioDataReceived { sender_id, data, batched, codec });
+        },
+        SignalingMessage::VideoFrameReceived { sender_id, width, height, rgba } => {
+            match VideoFrame::new(width, height, rgba) {
+                Ok(frame) => {
+                    let _ = update_sender.send(GuiUpdate::VideoFrameReceived { sender_id, frame });
+                }
+                Err(e) => eprintln!("WARNING: Dropping video frame from {}: {}", sender_id, e),
+            }
         },
         _ => {
             // Ignore other message types in broadcasts
@@ -1340,9 +2469,23 @@ async fn process_server_message(
 async fn send_message(
     stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
     message: &SignalingMessage,
+    frame_auth: &mut Option<FrameAuthenticator>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let data = message.to_framed()?;
-    stream.write_all(&data).await?;
+    send_frame(stream, message.to_framed()?, frame_auth).await
+}
+
+/// Write an encoded frame, tagging it first if `frame_mac` was agreed
+#[cfg(feature = "gui")]
+async fn send_frame(
+    stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
+    frame: Vec<u8>,
+    frame_auth: &mut Option<FrameAuthenticator>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let frame = match frame_auth {
+        Some(auth) => auth.seal(frame),
+        None => frame,
+    };
+    stream.write_all(&frame).await?;
     Ok(())
 }
 
@@ -1350,63 +2493,156 @@ async fn send_message(
 async fn receive_message(
     stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
 ) -> Result<SignalingMessage, Box<dyn std::error::Error + Send + Sync>> {
-    let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf).await?;
-    let msg_len = u32::from_be_bytes(len_buf) as usize;
+    Ok(pqc_chat::transport::read_frame(stream).await?.message()?)
+}
 
-    let mut msg_buf = vec![0u8; msg_len];
-    stream.read_exact(&mut msg_buf).await?;
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            message_id: id.to_string(),
+            sender_id: "p-1".to_string(),
+            sender_username: "alice".to_string(),
+            content: content.to_string(),
+            timestamp: std::time::UNIX_EPOCH,
+            edited: false,
+        }
+    }
 
-    Ok(SignalingMessage::from_bytes(&msg_buf)?)
-}
+    #[test]
+    fn test_leave_drops_sender_audio_state() {
+        let mut mixer = pqc_chat::audio_mixer::AudioMixer::new();
+        let mut decoders = HashMap::new();
+        let mut jitter_buffers = HashMap::new();
+        for id in ["p-1", "p-2"] {
+            mixer.push(id, &[0.1; 4]);
+            decoders.insert(id.to_string(), pqc_chat::audio_codec::FrameDecoder::new().unwrap());
+            jitter_buffers.insert(id.to_string(), AudioConfig::default().jitter_buffer());
+        }
+        mixer.set_sender_volume("p-1", 0.5);
+
+        drop_sender_audio(&mut mixer, &mut decoders, &mut jitter_buffers, "p-1");
+        assert!(!mixer.has_sender("p-1"));
+        assert!(!decoders.contains_key("p-1"));
+        assert!(!jitter_buffers.contains_key("p-1"));
+        assert_eq!(mixer.mix(4), vec![0.1; 4]);
+
+        // Whoever is still in the room is untouched
+        assert!(mixer.has_sender("p-2"));
+        assert!(decoders.contains_key("p-2"));
+        assert!(jitter_buffers.contains_key("p-2"));
+
+        // Leaving the room drops everyone
+        drop_room_audio(&mut mixer, &mut decoders, &mut jitter_buffers);
+        assert!(!mixer.has_sender("p-2"));
+        assert!(mixer.is_idle());
+        assert!(decoders.is_empty());
+        assert!(jitter_buffers.is_empty());
+    }
 
-#[cfg(feature = "gui")]
-#[derive(Debug)]
-struct NoVerifier;
+    #[test]
+    fn test_merge_chat_history_dedupes_by_id() {
+        let mut history = vec![message("m-1", "hi"), message("m-2", "there")];
+        let mut replayed_edit = message("m-2", "there!");
+        replayed_edit.edited = true;
 
-#[cfg(feature = "gui")]
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
+        merge_chat_history(&mut history, [message("m-1", "hi"), replayed_edit, message("m-3", "new"), message("m-4", "newer")]);
+
+        let ids: Vec<&str> = history.iter().map(|m| m.message_id.as_str()).collect();
+        assert_eq!(ids, vec!["m-1", "m-2", "m-3", "m-4"]);
+        assert_eq!(history[1].content, "there!");
+        assert!(history[1].edited);
+
+        // Messages without a server ID are never treated as duplicates
+        merge_chat_history(&mut history, [message("", "group"), message("", "group")]);
+        assert_eq!(history.len(), 6);
     }
 
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    #[test]
+    fn test_chosen_device_reaches_audio_manager() {
+        let inputs: Vec<String> = ["USB Audio #2", "HDA Intel PCH", "USB Audio"].iter().map(|s| s.to_string()).collect();
+        let outputs: Vec<String> = ["HDA Intel PCH", "USB Audio #2"].iter().map(|s| s.to_string()).collect();
+        let selection = DeviceSelection {
+            input_name: device_choice(&inputs, Some(2)),
+            output_name: device_choice(&outputs, Some(1)),
+        };
+
+        // The selection survives a save and reload
+        let path = std::env::temp_dir().join(format!("pqc-devices-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        selection.save(path).unwrap();
+        let mut config = AudioConfig::default();
+        DeviceSelection::load(path).apply(&mut config);
+        std::fs::remove_file(path).unwrap();
+
+        let mut manager = match pqc_chat::audio::AudioManager::new() {
+            Ok(manager) => manager,
+            Err(_) => return,
+        };
+        manager.set_device_preferences(config.preferred_input_name, config.preferred_output_name, config.device_index);
+        assert_eq!(manager.input_device_index(&inputs), Some(2));
+        assert_eq!(manager.output_device_index(&outputs), Some(1));
+        assert_eq!(device_choice(&inputs, None), None);
+    }
+
+    fn user(id: &str, username: &str, connected_at: u64) -> ConnectedUser {
+        ConnectedUser {
+            id: id.to_string(),
+            username: username.to_string(),
+            connected_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(connected_at),
+            in_room: None,
+            audio_enabled: true,
+            video_enabled: false,
+            status: UserStatus::Available,
+            reconnecting_since: None,
+        }
     }
 
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    #[test]
+    fn test_reconnect_within_grace_reuses_user_entry() {
+        let start = std::time::Instant::now();
+        let mut users = HashMap::new();
+        merge_connected_user(&mut users, user("p-1", "alice", 100), start);
+        merge_connected_user(&mut users, user("p-2", "bob", 100), start);
+
+        mark_user_reconnecting(&mut users, "p-1", start);
+        assert!(users["p-1"].reconnecting_since.is_some());
+        assert!(expire_reconnecting_users(&mut users, start + USER_RECONNECT_GRACE / 2).is_empty());
+
+        // Resumed under the same id: same entry, original connection time
+        let back = start + USER_RECONNECT_GRACE / 2;
+        assert!(merge_connected_user(&mut users, user("p-1", "alice", 500), back));
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["p-1"].connected_at, std::time::UNIX_EPOCH + std::time::Duration::from_secs(100));
+        assert!(users["p-1"].reconnecting_since.is_none());
+
+        // Someone else taking the same name is a different user
+        assert!(!merge_connected_user(&mut users, user("p-3", "alice", 600), back));
+        assert_eq!(users.len(), 3);
+
+        // Gone for longer than the grace: removed, and a later login is new
+        mark_user_reconnecting(&mut users, "p-2", start);
+        let expired = expire_reconnecting_users(&mut users, start + USER_RECONNECT_GRACE);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].username, "bob");
+        assert!(!merge_connected_user(&mut users, user("p-2", "bob", 900), start + USER_RECONNECT_GRACE));
     }
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::ED25519,
-        ]
+    #[test]
+    fn test_user_list_drops_logged_out_users() {
+        let now = std::time::Instant::now();
+        let mut users = HashMap::new();
+        for (id, name) in [("p-1", "alice"), ("p-2", "bob"), ("p-3", "carol")] {
+            merge_connected_user(&mut users, user(id, name, 100), now);
+        }
+        mark_user_reconnecting(&mut users, "p-2", now);
+
+        // Carol logged out; bob dropped and is still within the grace
+        sync_connected_users(&mut users, vec![user("p-1", "alice", 100)], now);
+        assert_eq!(users.len(), 2);
+        assert!(users["p-2"].reconnecting_since.is_some());
+        assert!(!users.contains_key("p-3"));
     }
-}
\ No newline at end of file
+}
@@ -5,18 +5,30 @@
 use anyhow::Result;
 use clap::Parser;
 use log::{error, info};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::collections::HashMap;
+use std::future::Future;
 use std::io::{self, Write};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::client::TlsStream;
 use tokio_rustls::rustls::{self, pki_types::ServerName};
 use tokio_rustls::TlsConnector;
 
-use pqc_chat::crypto::kyber::KyberKeyExchange;
-use pqc_chat::protocol::SignalingMessage;
+use pqc_chat::crypto::kyber::{KyberKeyExchange, KyberSession, SessionRole};
+use pqc_chat::crypto::tls::NoVerifier;
+use pqc_chat::protocol::{
+    unix_millis, AudioCodec, ClockOffset, FrameAuthenticator, FrameOptions, RoomLogEvent, SignalingMessage, UserStatus, FEATURE_FRAME_MAC, PROTOCOL_VERSION,
+    SUPPORTED_FEATURES,
+};
+use pqc_chat::transport::{read_frame, SignalingTransport, StreamTransport};
 use pqc_chat::ClientConfig;
 
 /// Command-line arguments
@@ -43,6 +55,11 @@ struct Args {
     /// Log level
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    /// Print each server message as one line of JSON instead of the
+    /// human-readable format; other notices go to stderr
+    #[arg(long)]
+    json: bool,
 }
 
 #[tokio::main]
@@ -65,11 +82,14 @@ async fn main() -> Result<()> {
     let port = args.port.unwrap_or(config.signaling_port);
     let username = args.username.unwrap_or(config.default_username.clone());
 
-    println!("🚀 PQC Chat Interactive Client");
-    println!("================================");
-    println!("Username: {}", username);
-    println!("Server: {}:{}", host, port);
-    println!();
+    let json = args.json;
+    if !json {
+        println!("🚀 PQC Chat Interactive Client");
+        println!("================================");
+        println!("Username: {}", username);
+        println!("Server: {}:{}", host, port);
+        println!();
+    }
 
     // Configure TLS (accept self-signed certificates for development)
     let tls_config = rustls::ClientConfig::builder()
@@ -79,15 +99,215 @@ async fn main() -> Result<()> {
 
     let connector = TlsConnector::from(Arc::new(tls_config));
 
-    // Connect to server
+    // Line editing runs for the whole process so history survives reconnects.
+    // It gets a plain thread rather than `spawn_blocking`: the runtime waits
+    // for blocking tasks on shutdown, and `readline` may never return.
+    let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<String>();
+    std::thread::Builder::new()
+        .name("line-editor".to_string())
+        .spawn(move || {
+            if let Err(e) = handle_user_input(cmd_tx) {
+                error!("Input error: {}", e);
+            }
+        })?;
+
+    // Room to rejoin if the connection drops
+    let mut current_room: Option<String> = None;
+    let mut backoff = ReconnectBackoff::default();
+    let mut first_session = true;
+
+    loop {
+        let (tls_stream, frame_auth) = match connect_with_backoff(&mut backoff, json, || {
+            connect_and_login(&connector, &host, port, &username, json)
+        })
+        .await
+        {
+            Ok(connection) => connection,
+            Err(e) => {
+                notice(json, &format!("❌ Could not reach server: {}", e));
+                break;
+            }
+        };
+
+        // Split the stream for reading and writing
+        let (read_half, write_half) = tokio::io::split(tls_stream);
+        let write_half = Arc::new(tokio::sync::Mutex::new(SignalingWriter { inner: write_half, frame_auth }));
+
+        // Spawn task to handle server messages
+        let write_half_clone = write_half.clone();
+        let mut server_task = tokio::spawn(async move {
+            handle_server_messages(read_half, write_half_clone, json).await
+        });
+
+        // Restore the room we were in, or show what's available
+        let restore = match &current_room {
+            Some(room_id) => {
+                notice(json, &format!("🔁 Rejoining room {}", room_id));
+                SignalingMessage::JoinRoom {
+                    room_id: room_id.clone(),
+                    username: username.clone(),
+                }
+            }
+            None => SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false },
+        };
+        if let Err(e) = write_half.lock().await.send(&restore).await {
+            server_task.abort();
+            notice(json, &format!("⚠️ Connection error ({}), reconnecting...", e));
+            continue;
+        }
+
+        if first_session && !json {
+            print_help();
+            first_session = false;
+        }
+
+        let outcome = run_session(&mut cmd_rx, &write_half, &mut server_task, &username, &mut current_room, json).await;
+        server_task.abort();
+        match outcome {
+            Ok(SessionEnd::Quit) => break,
+            Ok(SessionEnd::ConnectionLost) => notice(json, "⚠️ Server connection lost, reconnecting..."),
+            Err(e) => notice(json, &format!("⚠️ Connection error ({}), reconnecting...", e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a client-side notice, on stderr in JSON mode so stdout stays parseable
+fn notice(json: bool, text: &str) {
+    if json {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+/// Write a server message as a single line of JSON. Media payloads are
+/// swapped for their length (`data_len`, `rgba_len`): scripts want to know
+/// audio or video arrived, not thousands of byte values.
+fn write_json_line(out: &mut impl Write, message: &SignalingMessage) -> Result<()> {
+    let mut value = serde_json::to_value(message)?;
+    let payload = match message {
+        SignalingMessage::AudioDataReceived { data, .. } => Some(("data", data.len())),
+        SignalingMessage::VideoFrameReceived { rgba, .. } => Some(("rgba", rgba.len())),
+        _ => None,
+    };
+    if let (Some((field, len)), Some(fields)) = (payload, value.as_object_mut()) {
+        fields.remove(field);
+        fields.insert(format!("{}_len", field), len.into());
+    }
+    serde_json::to_writer(&mut *out, &value)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Why a connected session ended
+#[derive(Debug, PartialEq, Eq)]
+enum SessionEnd {
+    Quit,
+    ConnectionLost,
+}
+
+/// Exponential backoff for reconnect attempts
+#[derive(Debug, Clone)]
+struct ReconnectBackoff {
+    base: Duration,
+    max: Duration,
+    max_attempts: u32,
+    attempt: u32,
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500), Duration::from_secs(30), 10)
+    }
+}
+
+impl ReconnectBackoff {
+    fn new(base: Duration, max: Duration, max_attempts: u32) -> Self {
+        Self {
+            base,
+            max,
+            max_attempts,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt, or `None` once attempts are exhausted
+    fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempt >= self.max_attempts {
+            return None;
+        }
+        let delay = self.base.saturating_mul(1 << self.attempt.min(16)).min(self.max);
+        self.attempt += 1;
+        Some(delay)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Keep calling `connect` until it succeeds or the backoff gives up
+async fn connect_with_backoff<T, F, Fut>(backoff: &mut ReconnectBackoff, json: bool, mut connect: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    loop {
+        match connect().await {
+            Ok(value) => {
+                backoff.reset();
+                return Ok(value);
+            }
+            Err(e) => match backoff.next_delay() {
+                Some(delay) => {
+                    notice(json, &format!("🔌 Connection failed ({}), retrying in {:.1}s", e, delay.as_secs_f32()));
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Connect, run the key exchange and feature negotiation, and log in
+///
+/// Returns the frame authenticator when the server agreed `frame_mac`.
+async fn connect_and_login(
+    connector: &TlsConnector,
+    host: &str,
+    port: u16,
+    username: &str,
+    json: bool,
+) -> Result<(TlsStream<TcpStream>, Option<FrameAuthenticator>)> {
     let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
-    println!("🔌 Connecting to server...");
+    notice(json, "🔌 Connecting to server...");
 
     let stream = TcpStream::connect(addr).await?;
-    let server_name = ServerName::try_from(host.clone())?;
-    let mut tls_stream = connector.connect(server_name, stream).await?;
+    let server_name = ServerName::try_from(host.to_string())?;
+    let mut transport = StreamTransport::new(connector.connect(server_name, stream).await?);
+
+    notice(json, "✅ Connected to server");
 
-    println!("✅ Connected to server");
+    // The server introduces itself before anything else
+    match transport.recv().await? {
+        SignalingMessage::ServerInfo { name, version, protocol_version, motd } => {
+            notice(json, &format!("🖥️  {} (v{})", name, version));
+            if let Some(motd) = motd {
+                notice(json, &format!("📢 {}", motd));
+            }
+            if protocol_version != PROTOCOL_VERSION {
+                return Err(anyhow::anyhow!(
+                    "Server speaks protocol v{}, this client v{}",
+                    protocol_version,
+                    PROTOCOL_VERSION
+                ));
+            }
+        }
+        other => return Err(anyhow::anyhow!("Expected server info, got {:?}", other)),
+    }
 
     // Perform key exchange and login
     let kyber = KyberKeyExchange::new();
@@ -96,71 +316,124 @@ async fn main() -> Result<()> {
     let key_init = SignalingMessage::KeyExchangeInit {
         public_key: kyber.public_key_bytes(),
     };
-    send_message(&mut tls_stream, &key_init).await?;
+    transport.send(&key_init, FrameOptions::default()).await?;
 
-    let response = receive_message(&mut tls_stream).await?;
-    if let SignalingMessage::KeyExchangeResponse { ciphertext } = response {
-        kyber.decapsulate(&ciphertext)?;
-        println!("🔐 Post-quantum key exchange completed");
+    let response = transport.recv().await?;
+    let shared_secret = if let SignalingMessage::KeyExchangeResponse { ciphertext } = response {
+        let secret = kyber.decapsulate(&ciphertext)?;
+        notice(json, "🔐 Post-quantum key exchange completed");
+        secret
     } else {
         return Err(anyhow::anyhow!("Key exchange failed"));
+    };
+
+    // Negotiate optional protocol features
+    let hello = SignalingMessage::Hello {
+        features: SUPPORTED_FEATURES.iter().map(|f| f.to_string()).collect(),
+    };
+    transport.send(&hello, FrameOptions::default()).await?;
+    let mut frame_auth = None;
+    if let SignalingMessage::Hello { features } = transport.recv().await? {
+        info!("Server features: {:?}", features);
+        if features.iter().any(|f| f == FEATURE_FRAME_MAC) {
+            let key = KyberSession::new(shared_secret).send_key(SessionRole::Client);
+            frame_auth = Some(FrameAuthenticator::new(&key));
+        }
     }
 
     // Login
     let login = SignalingMessage::Login {
-        username: username.clone(),
+        username: username.to_string(),
+        resume_token: None,
     };
-    send_message(&mut tls_stream, &login).await?;
-
-    let response = receive_message(&mut tls_stream).await?;
-    if let SignalingMessage::LoginResponse { success, .. } = response {
-        if success {
-            println!("👤 Logged in as {}", username);
-        } else {
-            return Err(anyhow::anyhow!("Login failed"));
-        }
+    let mut frame = login.to_framed()?;
+    if let Some(auth) = frame_auth.as_mut() {
+        frame = auth.seal(frame);
     }
+    transport.send_frame(&frame).await?;
 
-    // Create channels for communication between tasks
-    let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<String>();
-    
-    // Split the stream for reading and writing
-    let (read_half, write_half) = tokio::io::split(tls_stream);
-    let write_half = Arc::new(tokio::sync::Mutex::new(write_half));
-
-    // Spawn task to handle server messages
-    let write_half_clone = write_half.clone();
-    let mut server_task = tokio::spawn(async move {
-        handle_server_messages(read_half, write_half_clone).await
-    });
-
-    // Spawn task to handle user input
-    let cmd_tx_clone = cmd_tx.clone();
-    let input_task = tokio::spawn(async move {
-        handle_user_input(cmd_tx_clone).await
-    });
-
-    // Main loop to process commands
-    let mut _current_room: Option<String> = None;
-    
-    // Initial room list
-    {
-        let mut stream = write_half.lock().await;
-        send_message(&mut *stream, &SignalingMessage::ListRooms).await?;
+    let response = transport.recv().await?;
+    match response {
+        SignalingMessage::LoginResponse { success: true, .. } => notice(json, &format!("👤 Logged in as {}", username)),
+        SignalingMessage::LoginResponse { .. } => return Err(anyhow::anyhow!("Login failed")),
+        SignalingMessage::Error { message, .. } => return Err(anyhow::anyhow!("Login failed: {}", message)),
+        _ => {}
     }
 
+    Ok((transport.into_inner(), frame_auth))
+}
+
+fn print_help() {
     println!();
     println!("💬 Interactive Commands:");
-    println!("  rooms          - List available rooms");
-    println!("  join <room_id> - Join a room by ID");
-    println!("  create <name>  - Create a new room");
+    println!("  rooms [joinable] - List available rooms, optionally only ones you can join");
+    println!("  join <room>    - Join a room by ID or name");
+    println!("  spectate <room> - Follow a room's chat while staying in your current room");
+    println!("  unspectate <id> - Stop following a room");
+    println!("  create <name> [--slug <slug>] [--codec <opus|pcm|pcm16>] - Create a new room, optionally with a shareable short ID or a single allowed audio codec");
+    println!("  admit <room_id> <participant_id> - Let someone in from your room's waiting room");
+    println!("  move <participant_id> <room_id> [force] - Move a member of your room elsewhere");
+    println!("  events <room> [limit] - Show a room's join/leave and moderation history (owner only)");
     println!("  leave          - Leave current room");
+    println!("  whoami         - Show your participant ID, name and room");
+    println!("  time           - Show the server's clock and how far yours is off");
+    println!("  send <text>    - Send a chat message (alias: msg)");
+    println!("  edit <id> <text> - Edit one of your messages");
+    println!("  delete <id>    - Delete one of your messages");
+    println!("  status <available|away|busy> - Set your presence");
+    println!("  hand           - Raise your hand");
+    println!("  lower          - Lower your hand");
     println!("  quit           - Exit client");
     println!();
+}
+
+/// Chat message for a `send <text>` line, keeping the text's spacing intact
+fn chat_command(line: &str) -> Option<SignalingMessage> {
+    let content = line.trim().splitn(2, char::is_whitespace).nth(1)?.trim();
+    if content.is_empty() {
+        return None;
+    }
+    Some(SignalingMessage::SendMessage {
+        content: content.to_string(),
+    })
+}
 
+/// Codec named on the command line, as spelled on the wire
+fn parse_codec(name: &str) -> Option<AudioCodec> {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase())).ok()
+}
+
+/// Local wall-clock time for a server timestamp (seconds since the epoch)
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+        .unwrap_or_else(|| "--:--:--".to_string())
+}
+
+/// Process user commands until the user quits or the server goes away
+async fn run_session<W>(
+    cmd_rx: &mut mpsc::UnboundedReceiver<String>,
+    write_half: &Arc<tokio::sync::Mutex<SignalingWriter<W>>>,
+    server_task: &mut JoinHandle<Result<()>>,
+    username: &str,
+    current_room: &mut Option<String>,
+    json: bool,
+) -> Result<SessionEnd>
+where
+    W: AsyncWriteExt + Unpin,
+{
     loop {
         tokio::select! {
-            Some(command) = cmd_rx.recv() => {
+            command = cmd_rx.recv() => {
+                // Input closed (EOF / Ctrl-D) means the user is done
+                let command = match command {
+                    Some(command) => command,
+                    None => {
+                        let mut stream = write_half.lock().await;
+                        let _ = stream.send(&SignalingMessage::Logout).await;
+                        return Ok(SessionEnd::Quit);
+                    }
+                };
                 let parts: Vec<&str> = command.trim().split_whitespace().collect();
                 if parts.is_empty() {
                     continue;
@@ -169,83 +442,227 @@ async fn main() -> Result<()> {
                 match parts[0].to_lowercase().as_str() {
                     "rooms" => {
                         let mut stream = write_half.lock().await;
-                        send_message(&mut *stream, &SignalingMessage::ListRooms).await?;
+                        let joinable_only = parts.get(1).is_some_and(|a| a.eq_ignore_ascii_case("joinable"));
+                        stream.send(&SignalingMessage::ListRooms { offset: None, limit: None, joinable_only }).await?;
                     },
                     "join" => {
                         if parts.len() < 2 {
-                            println!("Usage: join <room_id>");
+                            notice(json, "Usage: join <room_id or name>");
                             continue;
                         }
-                        let room_id = parts[1].to_string();
+                        let room_id = parts[1..].join(" ");
                         let msg = SignalingMessage::JoinRoom {
                             room_id: room_id.clone(),
-                            username: username.clone(),
+                            username: username.to_string(),
                         };
                         let mut stream = write_half.lock().await;
-                        send_message(&mut *stream, &msg).await?;
-                        _current_room = Some(room_id);
+                        stream.send(&msg).await?;
+                        *current_room = Some(room_id);
                     },
-                    "create" => {
+                    "spectate" => {
                         if parts.len() < 2 {
-                            println!("Usage: create <room_name>");
+                            notice(json, "Usage: spectate <room_id or name>");
                             continue;
                         }
-                        let room_name = parts[1..].join(" ");
+                        let msg = SignalingMessage::SpectateRoom {
+                            room_id: parts[1..].join(" "),
+                            username: username.to_string(),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "unspectate" => {
+                        if parts.len() < 2 {
+                            notice(json, "Usage: unspectate <room_id>");
+                            continue;
+                        }
+                        let msg = SignalingMessage::StopSpectating { room_id: parts[1].to_string() };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "admit" => {
+                        if parts.len() < 3 {
+                            notice(json, "Usage: admit <room_id> <participant_id>");
+                            continue;
+                        }
+                        let msg = SignalingMessage::AdmitWaiter {
+                            room_id: parts[1].to_string(),
+                            participant_id: parts[2].to_string(),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "move" => {
+                        if parts.len() < 3 {
+                            notice(json, "Usage: move <participant_id> <room_id> [force]");
+                            continue;
+                        }
+                        let msg = SignalingMessage::MoveParticipant {
+                            participant_id: parts[1].to_string(),
+                            target_room_id: parts[2].to_string(),
+                            force: parts.get(3) == Some(&"force"),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "events" => {
+                        if parts.len() < 2 {
+                            notice(json, "Usage: events <room> [limit]");
+                            continue;
+                        }
+                        let msg = SignalingMessage::GetRoomEvents {
+                            room_id: parts[1].to_string(),
+                            limit: parts.get(2).and_then(|l| l.parse().ok()),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "create" => {
+                        let slug_at = parts.iter().position(|p| *p == "--slug");
+                        let codec_at = parts.iter().position(|p| *p == "--codec");
+                        let name_end = slug_at.into_iter().chain(codec_at).min().unwrap_or(parts.len());
+                        let missing_value = |at: Option<usize>| at.is_some_and(|i| i + 1 >= parts.len());
+                        let required_codec = codec_at.and_then(|i| parts.get(i + 1)).map(|name| parse_codec(name));
+                        if name_end < 2 || missing_value(slug_at) || missing_value(codec_at) || matches!(required_codec, Some(None)) {
+                            notice(json, "Usage: create <room_name> [--slug <slug>] [--codec <opus|pcm|pcm16>]");
+                            continue;
+                        }
+                        let room_name = parts[1..name_end].join(" ");
                         let msg = SignalingMessage::CreateRoom {
                             name: room_name,
                             max_participants: Some(10),
+                            slug: slug_at.map(|i| parts[i + 1].to_string()),
+                            required_codec: required_codec.flatten(),
                         };
                         let mut stream = write_half.lock().await;
-                        send_message(&mut *stream, &msg).await?;
+                        stream.send(&msg).await?;
                     },
                     "leave" => {
                         let mut stream = write_half.lock().await;
-                        send_message(&mut *stream, &SignalingMessage::LeaveRoom).await?;
-                        _current_room = None;
+                        stream.send(&SignalingMessage::LeaveRoom).await?;
+                        *current_room = None;
+                    },
+                    "whoami" => {
+                        let mut stream = write_half.lock().await;
+                        stream.send(&SignalingMessage::Whoami).await?;
+                    },
+                    "time" => {
+                        let mut stream = write_half.lock().await;
+                        stream.send(&SignalingMessage::TimeSync { client_sent_ms: unix_millis() }).await?;
+                    },
+                    "send" | "msg" | "/msg" => {
+                        match chat_command(&command) {
+                            Some(msg) => {
+                                let mut stream = write_half.lock().await;
+                                stream.send(&msg).await?;
+                            }
+                            None => notice(json, "Usage: send <message>"),
+                        }
+                    },
+                    "edit" => {
+                        if parts.len() < 3 {
+                            notice(json, "Usage: edit <message_id> <new text>");
+                            continue;
+                        }
+                        let msg = SignalingMessage::EditMessage {
+                            message_id: parts[1].to_string(),
+                            new_content: parts[2..].join(" "),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "delete" => {
+                        if parts.len() < 2 {
+                            notice(json, "Usage: delete <message_id>");
+                            continue;
+                        }
+                        let msg = SignalingMessage::DeleteMessage {
+                            message_id: parts[1].to_string(),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
+                    },
+                    "status" => {
+                        let status = match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                            Some("available") => UserStatus::Available,
+                            Some("away") => UserStatus::Away,
+                            Some("busy") => UserStatus::Busy,
+                            _ => {
+                                notice(json, "Usage: status <available|away|busy>");
+                                continue;
+                            }
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&SignalingMessage::SetStatus { status }).await?;
+                    },
+                    "hand" | "lower" => {
+                        let msg = SignalingMessage::RaiseHand {
+                            raised: parts[0].eq_ignore_ascii_case("hand"),
+                        };
+                        let mut stream = write_half.lock().await;
+                        stream.send(&msg).await?;
                     },
                     "quit" | "exit" => {
-                        println!("👋 Goodbye!");
-                        break;
+                        let mut stream = write_half.lock().await;
+                        let _ = stream.send(&SignalingMessage::Logout).await;
+                        notice(json, "👋 Goodbye!");
+                        return Ok(SessionEnd::Quit);
                     },
                     _ => {
-                        println!("Unknown command: {}. Type 'help' for available commands.", parts[0]);
+                        notice(json, &format!("Unknown command: {}. Type 'help' for available commands.", parts[0]));
                     }
                 }
             }
-            _ = &mut server_task => {
-                println!("Server connection lost");
-                break;
+            _ = &mut *server_task => {
+                return Ok(SessionEnd::ConnectionLost);
             }
         }
     }
-
-    input_task.abort();
-    Ok(())
 }
 
 async fn handle_server_messages<R, W>(
     mut reader: R,
-    _writer: Arc<tokio::sync::Mutex<W>>,
+    _writer: Arc<tokio::sync::Mutex<SignalingWriter<W>>>,
+    json: bool,
 ) -> Result<()>
 where
     R: AsyncReadExt + Unpin,
     W: AsyncWriteExt + Unpin,
 {
+    // Names of spectated rooms, to tag their chat
+    let mut spectated: HashMap<String, String> = HashMap::new();
     loop {
-        match receive_message(&mut reader).await {
+        match read_frame(&mut reader).await.and_then(|frame| Ok(frame.message()?)) {
+            Ok(message) if json => write_json_line(&mut io::stdout().lock(), &message)?,
             Ok(message) => {
                 match message {
-                    SignalingMessage::RoomList { rooms } => {
+                    SignalingMessage::RoomList { rooms, total } => {
                         println!();
                         println!("📋 Available Rooms:");
                         if rooms.is_empty() {
                             println!("  No rooms available");
                         } else {
+                            let shown = rooms.len();
                             for room in rooms {
+                                let flags = match (room.is_locked, room.password_protected, room.is_full) {
+                                    (true, _, _) => " 🔒 locked",
+                                    (_, true, _) => " 🔑 password",
+                                    (_, _, true) => " full",
+                                    _ => "",
+                                };
+                                let activity = room.activity_label(unix_millis() / 1000).map(|a| format!(", {}", a)).unwrap_or_default();
+                                let id = room.slug.unwrap_or(room.id);
+                                let codec = room.required_codec.map(|c| format!(" {:?} only", c)).unwrap_or_default();
                                 println!(
-                                    "  🏠 {} - {} ({}/{} participants)",
-                                    room.id, room.name, room.participants, room.max_participants
+                                    "  🏠 {} - {} ({}/{} participants{}){}{}",
+                                    id, room.name, room.participants, room.max_participants, activity, flags, codec
                                 );
+                                if let Some(topic) = room.topic {
+                                    println!("      {}", topic);
+                                }
+                            }
+                            if (shown as u32) < total {
+                                println!("  ... showing {} of {} rooms", shown, total);
                             }
                         }
                         print!("> ");
@@ -287,6 +704,22 @@ where
                         print!("> ");
                         io::stdout().flush().unwrap();
                     },
+                    SignalingMessage::WaitingRoom { room_id, position } => {
+                        println!("⏳ Room {} is full - you are #{} in the waiting room", room_id, position);
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::RoomSpectating { success, room_id, room_name, participants, error, .. } => {
+                        if let (true, Some(room_id), Some(room_name)) = (success, room_id, room_name) {
+                            let count = participants.map(|p| p.len()).unwrap_or(0);
+                            println!("👀 Spectating {} ({}) - {} participants", room_name, room_id, count);
+                            spectated.insert(room_id, room_name);
+                        } else {
+                            println!("❌ Failed to spectate room: {}", error.unwrap_or_default());
+                        }
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
                     SignalingMessage::RoomLeft { success, error } => {
                         if success {
                             println!("👋 Left room");
@@ -301,8 +734,8 @@ where
                         print!("> ");
                         io::stdout().flush().unwrap();
                     },
-                    SignalingMessage::ParticipantLeft { participant_id } => {
-                        println!("🔴 {} left the room", participant_id);
+                    SignalingMessage::ParticipantLeft { participant_id, reason } => {
+                        println!("🔴 {} {}", participant_id, reason.describe());
                         print!("> ");
                         io::stdout().flush().unwrap();
                     },
@@ -318,7 +751,101 @@ where
                         print!("> ");
                         io::stdout().flush().unwrap();
                     },
-                    SignalingMessage::Error { message } => {
+                    SignalingMessage::MessageReceived { message_id, room_id, sender_username, content, timestamp, edited, .. } => {
+                        let marker = if edited { " (edited)" } else { "" };
+                        let origin = spectated.get(&room_id).map(|name| format!("#{} ", name)).unwrap_or_default();
+                        println!(
+                            "💬 {}[{}] {}: {}{} [{}]",
+                            origin, format_timestamp(timestamp), sender_username, content, marker, message_id
+                        );
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::MessageEdited { message_id, new_content, .. } => {
+                        println!("✏️ [{}] edited: {}", message_id, new_content);
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::MessageDeleted { message_id } => {
+                        println!("🗑️ [{}] deleted", message_id);
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::PeerUdpEndpoint { participant_id, addr } => {
+                        info!("Peer {} reachable over UDP at {}", participant_id, addr);
+                    },
+                    SignalingMessage::RoomTopicChanged { room_id, topic } => {
+                        match topic {
+                            Some(topic) => println!("📌 Topic of {} is now: {}", room_id, topic),
+                            None => println!("📌 Topic of {} cleared", room_id),
+                        }
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::RoomClosed { room_id, reason } => {
+                        println!("🚪 Room {} was closed ({:?})", room_id, reason);
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::UserStatusChanged { participant_id, status } => {
+                        println!("🟡 {} is now {:?}", participant_id, status);
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::HandRaised { participant_id, raised, order } => {
+                        if raised {
+                            println!("✋ {} raised a hand (#{} in queue)", participant_id, order);
+                        } else {
+                            println!("👇 {} lowered their hand", participant_id);
+                        }
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::RoomEventLog { room_id, entries } => {
+                        println!("📜 Events in {}:", room_id);
+                        if entries.is_empty() {
+                            println!("  Nothing yet");
+                        }
+                        for entry in entries {
+                            let by = entry.actor_id.map(|id| format!(" (by {})", id)).unwrap_or_default();
+                            let what = match entry.event {
+                                RoomLogEvent::Joined { username, .. } => format!("{} joined", username),
+                                RoomLogEvent::Left { username, reason, .. } => format!("{} {}", username, reason.describe()),
+                                RoomLogEvent::TopicChanged { topic } => {
+                                    format!("topic set to {}", topic.as_deref().unwrap_or("(none)"))
+                                }
+                                RoomLogEvent::SettingsChanged { announce_joins, waiting_room } => format!(
+                                    "settings changed: announce joins {}, waiting room {}",
+                                    announce_joins, waiting_room
+                                ),
+                            };
+                            println!("  [{}] {}{}", entry.timestamp, what, by);
+                        }
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::WhoamiResponse { participant, room_id } => {
+                        println!(
+                            "🪪 {} ({}) - {}",
+                            participant.username,
+                            participant.id,
+                            room_id.map(|id| format!("in room {}", id)).unwrap_or_else(|| "not in a room".to_string())
+                        );
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::TimeSyncResponse { client_sent_ms, server_time_ms, .. } => {
+                        let clock = ClockOffset::from_exchange(client_sent_ms, server_time_ms, unix_millis());
+                        println!(
+                            "🕒 Server time {} (yours is off by {:+}ms, ±{}ms)",
+                            format_timestamp(server_time_ms / 1000),
+                            -clock.offset_ms,
+                            clock.round_trip_ms / 2
+                        );
+                        print!("> ");
+                        io::stdout().flush().unwrap();
+                    },
+                    SignalingMessage::Error { message, .. } => {
                         println!("❌ Server error: {}", message);
                         print!("> ");
                         io::stdout().flush().unwrap();
@@ -339,90 +866,161 @@ where
     Ok(())
 }
 
-async fn handle_user_input(cmd_tx: mpsc::UnboundedSender<String>) -> Result<()> {
-    let stdin = tokio::io::stdin();
-    let reader = BufReader::new(stdin);
-    let mut lines = reader.lines();
+/// Read lines with history (arrow-up recalls earlier commands).
+/// Runs on its own thread since rustyline owns the terminal.
+fn handle_user_input(cmd_tx: mpsc::UnboundedSender<String>) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
 
-    print!("> ");
-    io::stdout().flush().unwrap();
-
-    while let Some(line) = lines.next_line().await? {
-        if cmd_tx.send(line).is_err() {
-            break;
+    loop {
+        match editor.readline("> ") {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = editor.add_history_entry(line.as_str());
+                }
+                if cmd_tx.send(line).is_err() {
+                    break;
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
         }
     }
     Ok(())
 }
 
-async fn send_message<S>(stream: &mut S, message: &SignalingMessage) -> Result<()>
-where
-    S: AsyncWriteExt + Unpin,
-{
-    let data = message.to_framed()?;
-    stream.write_all(&data).await?;
-    Ok(())
+/// Write half of the signaling connection, tagging frames once `frame_mac` is agreed
+struct SignalingWriter<W> {
+    inner: W,
+    frame_auth: Option<FrameAuthenticator>,
 }
 
-async fn receive_message<S>(stream: &mut S) -> Result<SignalingMessage>
-where
-    S: AsyncReadExt + Unpin,
-{
-    let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf).await?;
-    let msg_len = u32::from_be_bytes(len_buf) as usize;
+impl<W: AsyncWriteExt + Unpin> SignalingWriter<W> {
+    async fn send(&mut self, message: &SignalingMessage) -> Result<()> {
+        let mut frame = message.to_framed()?;
+        if let Some(auth) = self.frame_auth.as_mut() {
+            frame = auth.seal(frame);
+        }
+        self.inner.write_all(&frame).await?;
+        Ok(())
+    }
+}
 
-    let mut msg_buf = vec![0u8; msg_len];
-    stream.read_exact(&mut msg_buf).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
 
-    Ok(SignalingMessage::from_bytes(&msg_buf)?)
-}
+    #[test]
+    fn test_send_command_builds_chat_message() {
+        match chat_command("send hello   there ") {
+            Some(SignalingMessage::SendMessage { content }) => assert_eq!(content, "hello   there"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(matches!(
+            chat_command("/msg hi"),
+            Some(SignalingMessage::SendMessage { content }) if content == "hi"
+        ));
+        assert!(chat_command("send").is_none());
+        assert!(chat_command("msg    ").is_none());
+    }
+
+    #[test]
+    fn test_json_mode_prints_room_list_as_one_json_line() {
+        use pqc_chat::protocol::RoomInfo;
+
+        let list = SignalingMessage::RoomList {
+            rooms: vec![RoomInfo {
+                id: "r1".to_string(),
+                name: "Lobby".to_string(),
+                slug: None,
+                participants: 2,
+                max_participants: 10,
+                is_locked: false,
+                is_full: false,
+                password_protected: false,
+                topic: Some("Say hi".to_string()),
+                required_codec: None,
+                last_activity: None,
+            }],
+            total: 1,
+        };
+        let mut out = Vec::new();
+        write_json_line(&mut out, &list).unwrap();
 
-#[derive(Debug)]
-struct NoVerifier;
-
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let parsed: serde_json::Value = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(parsed["rooms"][0]["name"], "Lobby");
+        assert_eq!(parsed["rooms"][0]["topic"], "Say hi");
+        assert!(matches!(
+            serde_json::from_value::<SignalingMessage>(parsed),
+            Ok(SignalingMessage::RoomList { total: 1, .. })
+        ));
+
+        // Audio shows up as its size rather than a byte array
+        let audio = SignalingMessage::AudioDataReceived {
+            sender_id: "p-1".to_string(),
+            data: vec![0; 960],
+            batched: false,
+            codec: pqc_chat::protocol::AudioCodec::Opus,
+            captured_at: None,
+        };
+        let mut out = Vec::new();
+        write_json_line(&mut out, &audio).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(std::str::from_utf8(&out).unwrap().trim_end()).unwrap();
+        assert_eq!(parsed["data_len"], 960);
+        assert!(parsed.get("data").is_none());
+        assert_eq!(parsed["sender_id"], "p-1");
     }
 
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    #[test]
+    fn test_backoff_grows_and_gives_up() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_millis(100), Duration::from_millis(350), 4);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(200)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(350)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(350)));
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
     }
 
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    #[tokio::test]
+    async fn test_reconnect_after_failures() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_millis(1), Duration::from_millis(5), 5);
+        let attempts = Cell::new(0);
+
+        let result = connect_with_backoff(&mut backoff, false, || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt < 3 {
+                    Err(anyhow::anyhow!("connection refused"))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+        // A successful connect resets the backoff for the next drop
+        assert_eq!(backoff.attempt, 0);
     }
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::ED25519,
-        ]
+    #[tokio::test]
+    async fn test_reconnect_gives_up() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_millis(1), Duration::from_millis(1), 2);
+        let attempts = Cell::new(0);
+
+        let result: Result<()> = connect_with_backoff(&mut backoff, false, || {
+            attempts.set(attempts.get() + 1);
+            async { Err(anyhow::anyhow!("connection refused")) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
     }
-}
\ No newline at end of file
+}
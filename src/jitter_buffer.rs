@@ -0,0 +1,205 @@
+//! Receive-side jitter buffer
+//!
+//! Packets arrive in bursts and gaps, but playout wants one frame per
+//! period. [`JitterBuffer`] holds a sender's decoded frames and hands them
+//! out in arrival order; it is a FIFO and does not reorder or detect
+//! missing packets. It has its own warm-up: nothing plays until
+//! `prefill_ms` is buffered, independent of the silence the device ring is
+//! prefilled with. After that it keeps about `target_ms` queued, trimming
+//! the oldest audio when a burst leaves it running far behind.
+//!
+//! The GUI has no playout clock of its own (the device ring paces
+//! playback), so it feeds each sender's decoded frames through a buffer and
+//! hands the mixer whatever [`JitterBuffer::drain_ready`] releases.
+
+use std::collections::VecDeque;
+
+/// Decoded audio is 48kHz mono everywhere past capture
+const SAMPLE_RATE: u32 = 48000;
+
+/// Per-sender FIFO of decoded frames with a prefill and a steady-state
+/// depth
+#[derive(Debug)]
+pub struct JitterBuffer {
+    target_ms: u32,
+    prefill_ms: u32,
+    frames: VecDeque<Vec<f32>>,
+    buffered_samples: usize,
+    /// Handing out frames; cleared on an underrun
+    playing: bool,
+    /// Playout has started at least once, so warm-ups after an underrun
+    /// only wait for `target_ms`
+    started: bool,
+    underruns: u64,
+    trimmed_frames: u64,
+}
+
+impl JitterBuffer {
+    /// A buffer that waits for `prefill_ms` of audio before the first frame
+    /// and then keeps about `target_ms` queued
+    pub fn new(target_ms: u32, prefill_ms: u32) -> Self {
+        Self {
+            target_ms,
+            prefill_ms,
+            frames: VecDeque::new(),
+            buffered_samples: 0,
+            playing: false,
+            started: false,
+            underruns: 0,
+            trimmed_frames: 0,
+        }
+    }
+
+    /// Queue a decoded frame. Once playing, audio beyond twice the target
+    /// (or the prefill, if larger) is trimmed from the front back to the
+    /// target, so latency doesn't creep up after a burst.
+    pub fn push(&mut self, frame: Vec<f32>) {
+        self.buffered_samples += frame.len();
+        self.frames.push_back(frame);
+        if !self.playing {
+            return;
+        }
+        let high_water = (2 * self.target_ms).max(self.prefill_ms);
+        if self.buffered_ms() > high_water {
+            while self.frames.len() > 1 && self.buffered_ms() > self.target_ms {
+                if let Some(old) = self.frames.pop_front() {
+                    self.buffered_samples -= old.len();
+                    self.trimmed_frames += 1;
+                }
+            }
+        }
+    }
+
+    /// Next frame to play, or `None` while warming up. Running dry counts
+    /// as an underrun and warms up again, to `target_ms` this time.
+    pub fn pop(&mut self) -> Option<Vec<f32>> {
+        if !self.playing {
+            let warm_up = if self.started { self.target_ms } else { self.prefill_ms };
+            if self.frames.is_empty() || self.buffered_ms() < warm_up {
+                return None;
+            }
+            self.playing = true;
+            self.started = true;
+        }
+        match self.frames.pop_front() {
+            Some(frame) => {
+                self.buffered_samples -= frame.len();
+                Some(frame)
+            }
+            None => {
+                self.playing = false;
+                self.underruns += 1;
+                None
+            }
+        }
+    }
+
+    /// Every queued frame once warmed up, oldest first, for a receive path
+    /// without its own playout clock. Emptying the buffer this way isn't an
+    /// underrun.
+    pub fn drain_ready(&mut self) -> Vec<Vec<f32>> {
+        let mut ready = Vec::new();
+        while !self.frames.is_empty() {
+            match self.pop() {
+                Some(frame) => ready.push(frame),
+                None => break,
+            }
+        }
+        ready
+    }
+
+    /// Audio queued, in milliseconds
+    pub fn buffered_ms(&self) -> u32 {
+        (self.buffered_samples as u64 * 1000 / SAMPLE_RATE as u64) as u32
+    }
+
+    /// Whether frames are being handed out (prefill met, no underrun since)
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Times playout ran dry
+    pub fn underruns(&self) -> u64 {
+        self.underruns
+    }
+
+    /// Frames dropped to get back down to the target
+    pub fn trimmed_frames(&self) -> u64 {
+        self.trimmed_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20ms frame whose samples all hold `n`, to check ordering
+    fn frame(n: usize) -> Vec<f32> {
+        vec![n as f32; 960]
+    }
+
+    #[test]
+    fn test_playout_withheld_until_prefill_then_steady() {
+        let mut buffer = JitterBuffer::new(40, 100);
+        for n in 0..4 {
+            buffer.push(frame(n));
+            assert!(buffer.pop().is_none(), "played with {}ms buffered", buffer.buffered_ms());
+        }
+        assert!(!buffer.is_playing());
+
+        // The fifth frame meets the 100ms prefill
+        buffer.push(frame(4));
+        assert_eq!(buffer.pop(), Some(frame(0)));
+        assert!(buffer.is_playing());
+
+        // One frame in, one frame out: order kept, depth held, no underruns
+        for n in 5..50 {
+            buffer.push(frame(n));
+            assert_eq!(buffer.pop(), Some(frame(n - 4)));
+            assert_eq!(buffer.buffered_ms(), 80);
+        }
+        assert_eq!(buffer.underruns(), 0);
+        assert_eq!(buffer.trimmed_frames(), 0);
+    }
+
+    #[test]
+    fn test_burst_trimmed_and_underrun_rewarms_to_target() {
+        let mut buffer = JitterBuffer::new(40, 60);
+        for n in 0..3 {
+            buffer.push(frame(n));
+        }
+        assert_eq!(buffer.pop(), Some(frame(0)));
+
+        // A burst well past twice the target is cut back to the target
+        for n in 3..9 {
+            buffer.push(frame(n));
+        }
+        assert_eq!(buffer.buffered_ms(), 40);
+        assert!(buffer.trimmed_frames() > 0);
+        assert_eq!(buffer.pop(), Some(frame(7)));
+        assert_eq!(buffer.pop(), Some(frame(8)));
+
+        // Running dry waits for the 40ms target again, not the 60ms prefill
+        assert!(buffer.pop().is_none());
+        assert_eq!(buffer.underruns(), 1);
+        buffer.push(frame(10));
+        assert!(buffer.pop().is_none());
+        buffer.push(frame(11));
+        assert_eq!(buffer.pop(), Some(frame(10)));
+    }
+
+    #[test]
+    fn test_drain_waits_for_prefill_then_passes_through() {
+        let mut buffer = JitterBuffer::new(20, 60);
+        buffer.push(frame(0));
+        buffer.push(frame(1));
+        assert!(buffer.drain_ready().is_empty());
+
+        buffer.push(frame(2));
+        assert_eq!(buffer.drain_ready(), vec![frame(0), frame(1), frame(2)]);
+        buffer.push(frame(3));
+        assert_eq!(buffer.drain_ready(), vec![frame(3)]);
+        assert!(buffer.drain_ready().is_empty());
+        assert_eq!(buffer.underruns(), 0);
+    }
+}
@@ -5,11 +5,20 @@
 
 pub mod crypto;
 pub mod protocol;
+pub mod transport;
+pub mod events;
 pub mod room;
 pub mod media;
 pub mod config;
 pub mod audio;
 pub mod audio_codec;
+pub mod audio_mixer;
+pub mod audio_recorder;
+pub mod audio_resampler;
+pub mod jitter_buffer;
+pub mod video;
+#[cfg(feature = "video")]
+pub mod video_capture;
 
 pub use crypto::kyber::KyberKeyExchange;
 pub use protocol::SignalingMessage;
@@ -2,8 +2,33 @@
 //!
 //! DTLS-SRTP media transport stubs for audio/video streaming.
 
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::protocol::{audio_is_stale, unix_millis, AudioCodec};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Largest UDP packet that avoids IP fragmentation on typical paths
+/// (IPv6 minimum MTU minus headers, with headroom for tunnels/VPNs)
+pub const SAFE_UDP_PACKET_SIZE: usize = 1200;
+
+/// Largest payload a single UDP datagram can carry over IPv4
+pub const MAX_UDP_PACKET_SIZE: usize = 65_507;
+
+/// Fragment header: sequence (u16 BE), fragment index (u8), fragment count (u8)
+pub const FRAGMENT_HEADER_LEN: usize = 4;
+
+/// Smallest usable packet size (header plus a reasonable payload)
+pub const MIN_UDP_PACKET_SIZE: usize = 64;
+
+/// Incomplete packets kept while waiting for their remaining fragments
+const MAX_PENDING_REASSEMBLY: usize = 32;
 
 /// Media-related errors
 #[derive(Error, Debug)]
@@ -16,6 +41,476 @@ pub enum MediaError {
     SrtpInitFailed,
     #[error("Not connected")]
     NotConnected,
+    #[error("Packet needs {0} fragments (max 255)")]
+    PacketTooLarge(usize),
+    #[error("Malformed packet fragment")]
+    InvalidFragment,
+    #[error("Invalid UDP session token")]
+    InvalidSessionToken,
+    #[error("Replayed UDP packet")]
+    ReplayedPacket,
+    #[error("UDP packet from an earlier sender epoch")]
+    StaleEpoch,
+    #[error("Unknown audio codec tag {0}")]
+    UnknownCodec(u8),
+    #[error("Malformed control packet")]
+    InvalidControlPacket,
+}
+
+/// Truncated HMAC-SHA256 tag closing every UDP audio datagram
+pub const UDP_TAG_LEN: usize = 16;
+
+/// Datagram counters behind the newest that are still accepted once
+const REPLAY_WINDOW: u64 = 64;
+
+/// Key that binds a participant's UDP audio stream to its TCP login.
+///
+/// The server makes a fresh random key per login and sends it, hex-encoded,
+/// as `udp_session_token` over the TLS signaling channel. Each datagram
+/// carries a counter and is tagged with HMAC-SHA256 under this key (see
+/// [`UdpAudioPacket::seal`]), so a captured datagram can't be altered,
+/// replayed or moved to another participant. The server drops the key on
+/// disconnect.
+#[derive(Clone, PartialEq, Eq)]
+pub struct UdpSessionKey([u8; 32]);
+
+impl UdpSessionKey {
+    /// Create a fresh random key
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        key[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+        Self(key)
+    }
+
+    /// Hex form sent in `LoginResponse`
+    pub fn to_token(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Parse the hex form produced by [`UdpSessionKey::to_token`]
+    pub fn from_token(token: &str) -> Result<Self, MediaError> {
+        if token.len() != 64 || !token.is_ascii() {
+            return Err(MediaError::InvalidSessionToken);
+        }
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&token[2 * i..2 * i + 2], 16).map_err(|_| MediaError::InvalidSessionToken)?;
+        }
+        Ok(Self(key))
+    }
+
+    fn mac(&self, signed: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(signed);
+        mac
+    }
+
+    /// Check the tag at the end of a datagram
+    pub fn verify(&self, datagram: &[u8]) -> Result<(), MediaError> {
+        let split = datagram.len().checked_sub(UDP_TAG_LEN).ok_or(MediaError::InvalidSessionToken)?;
+        let (signed, tag) = datagram.split_at(split);
+        self.mac(signed).verify_truncated_left(tag).map_err(|_| MediaError::InvalidSessionToken)
+    }
+}
+
+impl std::fmt::Debug for UdpSessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UdpSessionKey(..)")
+    }
+}
+
+/// Sliding window over the datagram counters accepted for one session
+#[derive(Debug, Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `n` set: counter `highest - n` has been accepted
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Accept `counter` at most once. Returns `Some(true)` when it is the
+    /// newest so far, `Some(false)` for an in-window straggler and `None`
+    /// for a replay or a counter too old to tell.
+    fn accept(&mut self, counter: u64) -> Option<bool> {
+        let Some(highest) = self.highest else {
+            self.highest = Some(counter);
+            self.seen = 1;
+            return Some(true);
+        };
+        if counter > highest {
+            let shift = counter - highest;
+            self.seen = if shift >= REPLAY_WINDOW { 0 } else { self.seen << shift } | 1;
+            self.highest = Some(counter);
+            return Some(true);
+        }
+        let age = highest - counter;
+        if age >= REPLAY_WINDOW || self.seen & (1 << age) != 0 {
+            return None;
+        }
+        self.seen |= 1 << age;
+        Some(false)
+    }
+}
+
+/// A UDP audio datagram: sender, counter and one packet fragment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpAudioPacket {
+    pub participant_id: String,
+    /// Per-session datagram counter; covered by the tag, so a datagram
+    /// can't be accepted twice
+    pub counter: u64,
+    pub codec: AudioCodec,
+    /// Sender's connection number, seeded randomly per sender; sequences
+    /// restart from 0 in each epoch (see [`SequenceTracker`])
+    pub epoch: u16,
+    /// Capture time in Unix milliseconds (see `protocol::audio_is_stale`)
+    pub captured_at: Option<u64>,
+    pub payload: Vec<u8>,
+}
+
+/// Bytes a `UdpAudioPacket` adds around its participant ID and payload
+pub const UDP_AUDIO_OVERHEAD: usize = 1 + 8 + 1 + 2 + 8 + UDP_TAG_LEN;
+
+impl UdpAudioPacket {
+    /// Encode as `[id length u8][participant_id][counter u64 BE][codec u8][epoch u16 BE]
+    /// [captured_at u64 BE][payload][tag]`, with a zero timestamp standing for
+    /// none and the tag computed over everything before it
+    pub fn seal(&self, key: &UdpSessionKey) -> Vec<u8> {
+        let id = self.participant_id.as_bytes();
+        let id = &id[..id.len().min(u8::MAX as usize)];
+        let mut bytes = Vec::with_capacity(UDP_AUDIO_OVERHEAD + id.len() + self.payload.len());
+        bytes.push(id.len() as u8);
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(&self.counter.to_be_bytes());
+        bytes.push(self.codec.to_wire());
+        bytes.extend_from_slice(&self.epoch.to_be_bytes());
+        bytes.extend_from_slice(&self.captured_at.unwrap_or(0).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        let tag = key.mac(&bytes).finalize().into_bytes();
+        bytes.extend_from_slice(&tag[..UDP_TAG_LEN]);
+        bytes
+    }
+
+    /// Decode a datagram produced by [`UdpAudioPacket::seal`]. The tag is
+    /// not checked here; see [`UdpSessionKey::verify`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MediaError> {
+        let (&len, rest) = bytes.split_first().ok_or(MediaError::InvalidSessionToken)?;
+        let len = len as usize;
+        if rest.len() < len {
+            return Err(MediaError::InvalidSessionToken);
+        }
+        let participant_id = std::str::from_utf8(&rest[..len])
+            .map_err(|_| MediaError::InvalidSessionToken)?
+            .to_string();
+        let (counter, rest) = rest[len..].split_first_chunk::<8>().ok_or(MediaError::InvalidFragment)?;
+        let (&tag, rest) = rest.split_first().ok_or(MediaError::InvalidFragment)?;
+        let codec = AudioCodec::from_wire(tag).ok_or(MediaError::UnknownCodec(tag))?;
+        let (epoch, rest) = rest.split_first_chunk::<2>().ok_or(MediaError::InvalidFragment)?;
+        let (timestamp, rest) = rest.split_first_chunk::<8>().ok_or(MediaError::InvalidFragment)?;
+        let payload_len = rest.len().checked_sub(UDP_TAG_LEN).ok_or(MediaError::InvalidFragment)?;
+        let captured_at = Some(u64::from_be_bytes(*timestamp)).filter(|&t| t != 0);
+        Ok(Self {
+            participant_id,
+            counter: u64::from_be_bytes(*counter),
+            codec,
+            epoch: u16::from_be_bytes(*epoch),
+            captured_at,
+            payload: rest[..payload_len].to_vec(),
+        })
+    }
+}
+
+/// Split a media packet into fragments no larger than `max_packet_size`.
+///
+/// Every fragment carries a 4-byte header so the receiver can put the
+/// packet back together with [`FragmentReassembler`]. A packet that already
+/// fits still goes out as a single fragment (`index 0 of 1`).
+pub fn fragment_packet(
+    sequence: u16,
+    data: &[u8],
+    max_packet_size: usize,
+) -> Result<Vec<Vec<u8>>, MediaError> {
+    let chunk_size = max_packet_size.max(MIN_UDP_PACKET_SIZE) - FRAGMENT_HEADER_LEN;
+    let count = data.len().div_ceil(chunk_size).max(1);
+    if count > u8::MAX as usize {
+        return Err(MediaError::PacketTooLarge(count));
+    }
+
+    let mut fragments = Vec::with_capacity(count);
+    for index in 0..count {
+        let start = index * chunk_size;
+        let end = (start + chunk_size).min(data.len());
+        let mut packet = Vec::with_capacity(FRAGMENT_HEADER_LEN + end - start);
+        packet.extend_from_slice(&sequence.to_be_bytes());
+        packet.push(index as u8);
+        packet.push(count as u8);
+        packet.extend_from_slice(&data[start..end]);
+        fragments.push(packet);
+    }
+    Ok(fragments)
+}
+
+/// Reassembles packets split by [`fragment_packet`]
+#[derive(Default)]
+pub struct FragmentReassembler {
+    pending: HashMap<u16, Vec<Option<Vec<u8>>>>,
+    /// Arrival order of pending sequences, so stale ones can be evicted
+    order: VecDeque<u16>,
+}
+
+impl FragmentReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop every partial packet, e.g. when the sender starts a new epoch and
+    /// its sequence numbers are reused
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.order.clear();
+    }
+
+    /// Feed one received datagram; returns `(sequence, payload)` once complete
+    pub fn push(&mut self, packet: &[u8]) -> Result<Option<(u16, Vec<u8>)>, MediaError> {
+        if packet.len() < FRAGMENT_HEADER_LEN {
+            return Err(MediaError::InvalidFragment);
+        }
+        let sequence = u16::from_be_bytes([packet[0], packet[1]]);
+        let index = packet[2] as usize;
+        let count = packet[3] as usize;
+        if count == 0 || index >= count {
+            return Err(MediaError::InvalidFragment);
+        }
+        let payload = &packet[FRAGMENT_HEADER_LEN..];
+
+        if count == 1 {
+            return Ok(Some((sequence, payload.to_vec())));
+        }
+
+        if !self.pending.contains_key(&sequence) {
+            if self.order.len() >= MAX_PENDING_REASSEMBLY {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.order.push_back(sequence);
+        }
+        let slots = self.pending.entry(sequence).or_insert_with(|| vec![None; count]);
+        if slots.len() != count {
+            return Err(MediaError::InvalidFragment);
+        }
+        slots[index] = Some(payload.to_vec());
+
+        if slots.iter().all(Option::is_some) {
+            let slots = self.pending.remove(&sequence).unwrap_or_default();
+            self.order.retain(|s| *s != sequence);
+            let data = slots.into_iter().flatten().flatten().collect();
+            return Ok(Some((sequence, data)));
+        }
+        Ok(None)
+    }
+}
+
+/// Where a received packet falls relative to the sender's stream so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStatus {
+    /// The next expected packet, or a later one after `lost` went missing
+    InOrder { lost: u16 },
+    /// At or behind the newest packet seen: a reorder or duplicate
+    Late,
+    /// First packet of a new epoch (the sender reconnected); tracking restarted
+    Reset,
+    /// From an epoch the sender has since left behind
+    Stale,
+}
+
+/// Receiver-side sequence tracking for one sender.
+///
+/// A reconnecting sender starts its sequence over at 0, which on its own
+/// looks like a huge backward jump. The sender's epoch tells the two apart:
+/// a newer epoch restarts tracking instead of marking everything late.
+#[derive(Debug, Default, Clone)]
+pub struct SequenceTracker {
+    /// Epoch and newest sequence seen in it
+    current: Option<(u16, u16)>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify a packet and update the expected sequence
+    pub fn observe(&mut self, epoch: u16, sequence: u16) -> SequenceStatus {
+        let Some((current_epoch, highest)) = self.current else {
+            self.current = Some((epoch, sequence));
+            return SequenceStatus::Reset;
+        };
+        if epoch != current_epoch {
+            // Epochs wrap too; newer is anything less than half the range ahead
+            if (epoch.wrapping_sub(current_epoch) as i16) < 0 {
+                return SequenceStatus::Stale;
+            }
+            self.current = Some((epoch, sequence));
+            return SequenceStatus::Reset;
+        }
+        let ahead = sequence.wrapping_sub(highest) as i16;
+        if ahead <= 0 {
+            return SequenceStatus::Late;
+        }
+        self.current = Some((epoch, sequence));
+        SequenceStatus::InOrder { lost: ahead as u16 - 1 }
+    }
+
+    /// Sequence expected next, if any packet has been seen
+    pub fn expected(&self) -> Option<u16> {
+        self.current.map(|(_, highest)| highest.wrapping_add(1))
+    }
+
+    /// Current sender epoch, if any packet has been seen
+    pub fn epoch(&self) -> Option<u16> {
+        self.current.map(|(epoch, _)| epoch)
+    }
+}
+
+/// Control datagram tag: payload that must arrive
+const CONTROL_DATA: u8 = 1;
+/// Control datagram tag: acknowledges a `CONTROL_DATA` sequence
+const CONTROL_ACK: u8 = 2;
+/// Control datagram header: tag (u8), sequence (u32 BE)
+pub const CONTROL_HEADER_LEN: usize = 5;
+
+/// Default wait before an unacknowledged control packet is resent
+pub const CONTROL_RETRANSMIT_AFTER: Duration = Duration::from_millis(200);
+/// Default sends (first plus retransmits) before a control packet is given up
+pub const CONTROL_MAX_ATTEMPTS: u32 = 5;
+/// Delivered control sequences remembered to drop retransmitted duplicates
+const CONTROL_DEDUP_WINDOW: usize = 256;
+
+/// A control packet waiting for its ack
+struct Unacked {
+    datagram: Vec<u8>,
+    last_sent: Instant,
+    attempts: u32,
+}
+
+/// What a received control datagram produced
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ControlReceived {
+    /// Payload to hand to the application; `None` for acks and duplicates
+    pub payload: Option<Vec<u8>>,
+    /// Ack datagram to send back to the peer
+    pub ack: Option<Vec<u8>>,
+}
+
+/// Minimal reliability layer for UDP control packets.
+///
+/// Control payloads get a sequence number and are kept until the peer acks
+/// them, being resent every `retransmit_after` up to `max_attempts` times.
+/// Audio goes through [`ReliableControlChannel::audio_datagram`], which is
+/// never queued: a late voice frame is worse than a lost one, so audio stays
+/// best-effort. The channel does no I/O itself; callers send the datagrams
+/// it returns and call [`ReliableControlChannel::retransmits`] on a timer.
+pub struct ReliableControlChannel {
+    next_sequence: u32,
+    unacked: BTreeMap<u32, Unacked>,
+    delivered: VecDeque<u32>,
+    retransmit_after: Duration,
+    max_attempts: u32,
+}
+
+impl ReliableControlChannel {
+    pub fn new(retransmit_after: Duration, max_attempts: u32) -> Self {
+        Self {
+            next_sequence: 0,
+            unacked: BTreeMap::new(),
+            delivered: VecDeque::new(),
+            retransmit_after,
+            max_attempts: max_attempts.max(1),
+        }
+    }
+
+    fn encode(tag: u8, sequence: u32, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(CONTROL_HEADER_LEN + payload.len());
+        datagram.push(tag);
+        datagram.extend_from_slice(&sequence.to_be_bytes());
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    /// Encode an audio packet for sending; it is not tracked or resent
+    pub fn audio_datagram(&self, packet: &UdpAudioPacket, key: &UdpSessionKey) -> Vec<u8> {
+        packet.seal(key)
+    }
+
+    /// Sequence a control payload and return the datagram to send now
+    pub fn send(&mut self, payload: &[u8], now: Instant) -> Vec<u8> {
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        let datagram = Self::encode(CONTROL_DATA, sequence, payload);
+        self.unacked.insert(sequence, Unacked { datagram: datagram.clone(), last_sent: now, attempts: 1 });
+        datagram
+    }
+
+    /// Handle a control datagram from the peer
+    pub fn receive(&mut self, datagram: &[u8]) -> Result<ControlReceived, MediaError> {
+        if datagram.len() < CONTROL_HEADER_LEN {
+            return Err(MediaError::InvalidControlPacket);
+        }
+        let sequence = u32::from_be_bytes([datagram[1], datagram[2], datagram[3], datagram[4]]);
+        match datagram[0] {
+            CONTROL_ACK => {
+                self.unacked.remove(&sequence);
+                Ok(ControlReceived::default())
+            }
+            CONTROL_DATA => {
+                // Always ack, even duplicates: the previous ack may have been lost
+                let ack = Some(Self::encode(CONTROL_ACK, sequence, &[]));
+                if self.delivered.contains(&sequence) {
+                    return Ok(ControlReceived { payload: None, ack });
+                }
+                if self.delivered.len() >= CONTROL_DEDUP_WINDOW {
+                    self.delivered.pop_front();
+                }
+                self.delivered.push_back(sequence);
+                Ok(ControlReceived { payload: Some(datagram[CONTROL_HEADER_LEN..].to_vec()), ack })
+            }
+            _ => Err(MediaError::InvalidControlPacket),
+        }
+    }
+
+    /// Datagrams due for a resend at `now`. Packets that used up their
+    /// attempts are dropped with a warning.
+    pub fn retransmits(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut resend = Vec::new();
+        let (retransmit_after, max_attempts) = (self.retransmit_after, self.max_attempts);
+        self.unacked.retain(|sequence, packet| {
+            if now.saturating_duration_since(packet.last_sent) < retransmit_after {
+                return true;
+            }
+            if packet.attempts >= max_attempts {
+                log::warn!("Giving up on control packet {} after {} attempts", sequence, packet.attempts);
+                return false;
+            }
+            packet.attempts += 1;
+            packet.last_sent = now;
+            resend.push(packet.datagram.clone());
+            true
+        });
+        resend
+    }
+
+    /// Control packets still waiting for an ack
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+}
+
+impl Default for ReliableControlChannel {
+    fn default() -> Self {
+        Self::new(CONTROL_RETRANSMIT_AFTER, CONTROL_MAX_ATTEMPTS)
+    }
 }
 
 /// Media types
@@ -45,6 +540,36 @@ pub struct MediaForwarder {
     audio_port: u16,
     video_port: u16,
     is_running: bool,
+    /// Key and replay window of each logged-in participant
+    sessions: HashMap<String, UdpSession>,
+    /// Public UDP source address seen for each authenticated participant
+    observed_endpoints: HashMap<String, SocketAddr>,
+    /// Audio captured longer ago than this is dropped (0 = never)
+    max_audio_age_ms: u64,
+}
+
+/// Server-side state of one participant's UDP stream
+struct UdpSession {
+    key: UdpSessionKey,
+    replay: ReplayWindow,
+    sequence: SequenceTracker,
+    fragments: FragmentReassembler,
+}
+
+/// A datagram that passed [`MediaForwarder::authenticate_datagram`]
+#[derive(Debug)]
+pub struct AuthenticatedDatagram {
+    pub packet: UdpAudioPacket,
+    /// Where the fragment's sequence falls in the sender's stream
+    pub sequence: SequenceStatus,
+    /// The whole audio payload, once this fragment completed it and unless
+    /// it was too old to play
+    pub complete: Option<Vec<u8>>,
+    /// The payload was completed but dropped for exceeding the max audio age
+    pub stale: bool,
+    /// The sender's observed endpoint was new or changed (e.g. a NAT
+    /// rebinding), which is when peers need to be told about it
+    pub endpoint_changed: bool,
 }
 
 impl MediaForwarder {
@@ -53,9 +578,74 @@ impl MediaForwarder {
             audio_port,
             video_port,
             is_running: false,
+            sessions: HashMap::new(),
+            observed_endpoints: HashMap::new(),
+            max_audio_age_ms: 0,
         }
     }
 
+    /// Drop reassembled audio captured more than `max_age_ms` ago (0 = never),
+    /// as the signaling relay does
+    pub fn set_max_audio_age_ms(&mut self, max_age_ms: u64) {
+        self.max_audio_age_ms = max_age_ms;
+    }
+
+    /// Last observed UDP endpoint for a participant
+    pub fn observed_endpoint(&self, participant_id: &str) -> Option<SocketAddr> {
+        self.observed_endpoints.get(participant_id).copied()
+    }
+
+    /// Drop a participant's key and observed endpoint (on disconnect), so
+    /// datagrams under the old key are refused from then on
+    pub fn end_session(&mut self, participant_id: &str) {
+        self.sessions.remove(participant_id);
+        self.observed_endpoints.remove(participant_id);
+    }
+
+    /// Start a UDP session for a logged-in participant, replacing any
+    /// earlier one, and return its token for the `LoginResponse`
+    pub fn issue_session_token(&mut self, participant_id: &str) -> String {
+        let key = UdpSessionKey::generate();
+        let token = key.to_token();
+        self.sessions.insert(participant_id.to_string(), UdpSession {
+            key,
+            replay: ReplayWindow::default(),
+            sequence: SequenceTracker::new(),
+            fragments: FragmentReassembler::new(),
+        });
+        token
+    }
+
+    /// Verify a received UDP datagram before routing it.
+    ///
+    /// Datagrams from unknown sessions, with a bad tag, with a counter
+    /// already seen or from an epoch the sender has left behind are
+    /// rejected. The sender's endpoint is only updated by the newest
+    /// datagram so far, so replaying an old one from another address can't
+    /// redirect its stream.
+    pub fn authenticate_datagram(&mut self, datagram: &[u8], source: SocketAddr) -> Result<AuthenticatedDatagram, MediaError> {
+        let packet = UdpAudioPacket::from_bytes(datagram)?;
+        let session = self.sessions.get_mut(&packet.participant_id).ok_or(MediaError::InvalidSessionToken)?;
+        session.key.verify(datagram)?;
+        let fragment_sequence = packet.payload.first_chunk::<2>().ok_or(MediaError::InvalidFragment)?;
+        let newest = session.replay.accept(packet.counter).ok_or(MediaError::ReplayedPacket)?;
+        let sequence = session.sequence.observe(packet.epoch, u16::from_be_bytes(*fragment_sequence));
+        match sequence {
+            SequenceStatus::Stale => return Err(MediaError::StaleEpoch),
+            // Sequence numbers start over, so old partial packets would collide
+            SequenceStatus::Reset => session.fragments.clear(),
+            _ => {}
+        }
+        let mut complete = session.fragments.push(&packet.payload)?.map(|(_, payload)| payload);
+        let stale = complete.is_some() && audio_is_stale(packet.captured_at, unix_millis(), self.max_audio_age_ms);
+        if stale {
+            complete = None;
+        }
+        let endpoint_changed =
+            newest && self.observed_endpoints.insert(packet.participant_id.clone(), source) != Some(source);
+        Ok(AuthenticatedDatagram { packet, sequence, complete, stale, endpoint_changed })
+    }
+
     /// Start the media forwarder (stub)
     pub fn start(&mut self) -> Result<(), MediaError> {
         log::info!(
@@ -104,41 +694,129 @@ impl MediaForwarder {
 pub struct MediaSender {
     server_addr: SocketAddr,
     is_connected: bool,
+    max_packet_size: usize,
+    /// Participant ID and key from the `LoginResponse`
+    session: Option<(String, UdpSessionKey)>,
+    /// Next datagram counter under the current key
+    counter: u64,
+    /// Seeded randomly and bumped on every connect, so receivers can tell
+    /// restarted sequences from old ones even across client restarts
+    epoch: u16,
     audio_sequence: u16,
     video_sequence: u16,
 }
 
 impl MediaSender {
     pub fn new(server_addr: SocketAddr) -> Self {
+        let seed = Uuid::new_v4();
+        let seed = seed.as_bytes();
         Self {
             server_addr,
             is_connected: false,
+            max_packet_size: SAFE_UDP_PACKET_SIZE,
+            session: None,
+            counter: 0,
+            epoch: u16::from_be_bytes([seed[0], seed[1]]),
             audio_sequence: 0,
             video_sequence: 0,
         }
     }
 
-    /// Connect to the media server (stub)
+    /// Sender for a client, with packets capped at the configured
+    /// `udp_packet_size`
+    pub fn with_config(server_addr: SocketAddr, config: &crate::config::AudioConfig) -> Self {
+        let mut sender = Self::new(server_addr);
+        sender.set_max_packet_size(config.udp_packet_size);
+        sender
+    }
+
+    /// Connect to the media server (stub), starting a new epoch
     pub fn connect(&mut self) -> Result<(), MediaError> {
         log::info!("Media sender connecting to {} (stub)", self.server_addr);
+        self.epoch = self.epoch.wrapping_add(1);
+        self.audio_sequence = 0;
+        self.video_sequence = 0;
         self.is_connected = true;
         Ok(())
     }
 
+    /// Epoch of the current connection
+    pub fn epoch(&self) -> u16 {
+        self.epoch
+    }
+
     /// Disconnect from the media server
     pub fn disconnect(&mut self) {
         self.is_connected = false;
         log::info!("Media sender disconnected");
     }
 
-    /// Send audio data (stub)
-    pub fn send_audio(&mut self, _data: &[u8]) -> Result<(), MediaError> {
+    /// Set the UDP session token issued to `participant_id` at login
+    pub fn set_session_token(&mut self, participant_id: String, token: &str) -> Result<(), MediaError> {
+        self.session = Some((participant_id, UdpSessionKey::from_token(token)?));
+        self.counter = 0;
+        Ok(())
+    }
+
+    /// Set the largest UDP packet to send, clamped to what a datagram can
+    /// carry. Sizes over [`SAFE_UDP_PACKET_SIZE`] are honoured but logged,
+    /// since a fragmented datagram is lost whole when any piece is.
+    pub fn set_max_packet_size(&mut self, size: usize) {
+        let clamped = size.clamp(MIN_UDP_PACKET_SIZE, MAX_UDP_PACKET_SIZE);
+        if clamped != size {
+            log::warn!(
+                "UDP packet size {} is outside {}..={} bytes; using {}",
+                size,
+                MIN_UDP_PACKET_SIZE,
+                MAX_UDP_PACKET_SIZE,
+                clamped
+            );
+        }
+        if clamped > SAFE_UDP_PACKET_SIZE {
+            log::warn!(
+                "UDP packet size {} exceeds the safe MTU of {} bytes; packets may be fragmented",
+                clamped,
+                SAFE_UDP_PACKET_SIZE
+            );
+        }
+        self.max_packet_size = clamped;
+    }
+
+    /// Largest UDP packet this sender produces
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// Seal audio encoded with `codec` into datagrams no larger than the
+    /// max packet size, fragmenting it if needed. `captured_at` is when the
+    /// audio was captured (see `protocol::ClockOffset`), so encoding and
+    /// queueing count toward its age. Sending them is up to the caller until
+    /// the DTLS-SRTP transport exists.
+    pub fn send_audio(
+        &mut self,
+        codec: AudioCodec,
+        data: &[u8],
+        captured_at: Option<u64>,
+    ) -> Result<Vec<Vec<u8>>, MediaError> {
         if !self.is_connected {
             return Err(MediaError::NotConnected);
         }
+        let (participant_id, key) = self.session.as_ref().ok_or(MediaError::InvalidSessionToken)?;
         self.audio_sequence = self.audio_sequence.wrapping_add(1);
-        // Stub: Would encrypt with SRTP and send
-        Ok(())
+
+        // Leave room for the header and tag so each datagram stays under the MTU
+        let fragment_size = self.max_packet_size.saturating_sub(UDP_AUDIO_OVERHEAD + participant_id.len());
+        let epoch = self.epoch;
+        let counter = &mut self.counter;
+        let datagrams = fragment_packet(self.audio_sequence, data, fragment_size)?
+            .into_iter()
+            .map(|payload| {
+                let packet = UdpAudioPacket { participant_id: participant_id.clone(), counter: *counter, codec, epoch, captured_at, payload };
+                *counter += 1;
+                packet.seal(key)
+            })
+            .collect();
+        Ok(datagrams)
     }
 
     /// Send video data (stub)
@@ -216,15 +894,326 @@ mod tests {
         let mut sender = MediaSender::new(addr);
         
         // Should fail when not connected
-        assert!(sender.send_audio(&[1, 2, 3]).is_err());
+        assert!(sender.send_audio(AudioCodec::Opus, &[1, 2, 3], None).is_err());
         
         sender.connect().unwrap();
         assert!(sender.is_connected());
         
+        // Should fail until the login token is set
+        assert!(sender.send_audio(AudioCodec::Opus, &[1, 2, 3], None).is_err());
+        sender.set_session_token("p1".to_string(), &UdpSessionKey::generate().to_token()).unwrap();
+
         // Should succeed when connected
-        assert!(sender.send_audio(&[1, 2, 3]).is_ok());
+        assert!(sender.send_audio(AudioCodec::Opus, &[1, 2, 3], None).is_ok());
         
         sender.disconnect();
         assert!(!sender.is_connected());
     }
+
+    /// Alice's packet number `counter`
+    fn audio(counter: u64) -> UdpAudioPacket {
+        UdpAudioPacket {
+            participant_id: "alice".to_string(),
+            counter,
+            codec: AudioCodec::Pcm,
+            epoch: 7,
+            captured_at: Some(1_700_000_000_000),
+            payload: fragment_packet(counter as u16, &[1, 2, 3], SAFE_UDP_PACKET_SIZE).unwrap().remove(0),
+        }
+    }
+
+    #[test]
+    fn test_valid_session_token_routes() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let datagram = audio(0).seal(&key);
+
+        let accepted = forwarder.authenticate_datagram(&datagram, source).unwrap();
+        assert_eq!(accepted.packet, audio(0));
+        assert!(accepted.endpoint_changed);
+
+        // Unknown codec tags are rejected rather than guessed at
+        let mut bad = datagram.clone();
+        bad[1 + "alice".len() + 8] = 42;
+        assert!(matches!(forwarder.authenticate_datagram(&bad, source), Err(MediaError::UnknownCodec(42))));
+    }
+
+    #[test]
+    fn test_forged_session_token_dropped() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        forwarder.issue_session_token("bob");
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+
+        let mut tampered = audio(0).seal(&key);
+        let last = tampered.len() - UDP_TAG_LEN - 1;
+        tampered[last] ^= 1;
+        let forged = [
+            // Alice's key used to speak for Bob
+            UdpAudioPacket { participant_id: "bob".to_string(), ..audio(0) }.seal(&key),
+            // Signed with some other key
+            audio(0).seal(&UdpSessionKey::generate()),
+            // Payload changed after signing
+            tampered,
+            // Nobody logged in under this ID
+            UdpAudioPacket { participant_id: "carol".to_string(), ..audio(0) }.seal(&key),
+        ];
+        for datagram in forged {
+            assert!(matches!(
+                forwarder.authenticate_datagram(&datagram, source),
+                Err(MediaError::InvalidSessionToken)
+            ));
+        }
+        assert!(forwarder.authenticate_datagram(&[], source).is_err());
+        assert!(forwarder.observed_endpoint("alice").is_none());
+    }
+
+    #[test]
+    fn test_replayed_datagram_cannot_rebind_endpoint() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        let home: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let rebound: SocketAddr = "203.0.113.5:40001".parse().unwrap();
+        let attacker: SocketAddr = "198.51.100.9:6666".parse().unwrap();
+
+        assert!(forwarder.authenticate_datagram(&audio(0).seal(&key), home).unwrap().endpoint_changed);
+        assert!(!forwarder.authenticate_datagram(&audio(1).seal(&key), home).unwrap().endpoint_changed);
+
+        // A captured datagram sent again from elsewhere is refused
+        assert!(matches!(
+            forwarder.authenticate_datagram(&audio(1).seal(&key), attacker),
+            Err(MediaError::ReplayedPacket)
+        ));
+
+        // A late but unseen datagram is accepted without moving the endpoint
+        assert!(!forwarder.authenticate_datagram(&audio(5).seal(&key), home).unwrap().endpoint_changed);
+        let late = forwarder.authenticate_datagram(&audio(3).seal(&key), attacker).unwrap();
+        assert!(!late.endpoint_changed);
+        assert_eq!(forwarder.observed_endpoint("alice"), Some(home));
+
+        // Only a fresh datagram moves it (a NAT rebinding)
+        assert!(forwarder.authenticate_datagram(&audio(6).seal(&key), rebound).unwrap().endpoint_changed);
+        assert_eq!(forwarder.observed_endpoint("alice"), Some(rebound));
+
+        // Too far behind the newest to tell apart from a replay
+        assert!(forwarder.authenticate_datagram(&audio(6 + REPLAY_WINDOW).seal(&key), rebound).is_ok());
+        assert!(forwarder.authenticate_datagram(&audio(4).seal(&key), rebound).is_err());
+    }
+
+    #[test]
+    fn test_session_key_expires_on_disconnect() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        forwarder.authenticate_datagram(&audio(0).seal(&key), source).unwrap();
+
+        forwarder.end_session("alice");
+        assert!(forwarder.observed_endpoint("alice").is_none());
+        assert!(forwarder.authenticate_datagram(&audio(1).seal(&key), source).is_err());
+
+        // Logging in again hands out a new key; the old one stays dead
+        let new_key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        assert_ne!(new_key, key);
+        assert!(forwarder.authenticate_datagram(&audio(2).seal(&key), source).is_err());
+        assert!(forwarder.authenticate_datagram(&audio(0).seal(&new_key), source).is_ok());
+    }
+
+    #[test]
+    fn test_fragment_at_mtu_boundary() {
+        let payload_per_packet = SAFE_UDP_PACKET_SIZE - FRAGMENT_HEADER_LEN;
+
+        // Exactly one packet's worth stays in one fragment
+        let data = vec![7u8; payload_per_packet];
+        let fragments = fragment_packet(1, &data, SAFE_UDP_PACKET_SIZE).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].len(), SAFE_UDP_PACKET_SIZE);
+
+        // One byte more spills into a second fragment
+        let data = vec![7u8; payload_per_packet + 1];
+        let fragments = fragment_packet(1, &data, SAFE_UDP_PACKET_SIZE).unwrap();
+        assert_eq!(fragments.len(), 2);
+        assert!(fragments.iter().all(|f| f.len() <= SAFE_UDP_PACKET_SIZE));
+        assert_eq!(fragments[1].len(), FRAGMENT_HEADER_LEN + 1);
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let data: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+        let mut fragments = fragment_packet(42, &data, SAFE_UDP_PACKET_SIZE).unwrap();
+        assert_eq!(fragments.len(), 3);
+        fragments.reverse();
+
+        let mut reassembler = FragmentReassembler::new();
+        assert!(reassembler.push(&fragments[0]).unwrap().is_none());
+        assert!(reassembler.push(&fragments[1]).unwrap().is_none());
+        let (sequence, rebuilt) = reassembler.push(&fragments[2]).unwrap().unwrap();
+        assert_eq!(sequence, 42);
+        assert_eq!(rebuilt, data);
+
+        assert!(reassembler.push(&[0, 1]).is_err());
+        assert!(reassembler.push(&[0, 1, 2, 2]).is_err());
+    }
+
+    #[test]
+    fn test_epoch_change_resets_expected_sequence() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.observe(1, 500), SequenceStatus::Reset);
+        assert_eq!(tracker.observe(1, 501), SequenceStatus::InOrder { lost: 0 });
+        assert_eq!(tracker.observe(1, 504), SequenceStatus::InOrder { lost: 2 });
+        assert_eq!(tracker.expected(), Some(505));
+
+        // Same epoch: going back to 0 is a reorder, not a restart
+        assert_eq!(tracker.observe(1, 0), SequenceStatus::Late);
+        assert_eq!(tracker.expected(), Some(505));
+
+        // The sender reconnected and started over
+        assert_eq!(tracker.observe(2, 0), SequenceStatus::Reset);
+        assert_eq!(tracker.epoch(), Some(2));
+        assert_eq!(tracker.expected(), Some(1));
+        assert_eq!(tracker.observe(2, 1), SequenceStatus::InOrder { lost: 0 });
+
+        // Stragglers from before the reconnect don't disturb the new epoch
+        assert_eq!(tracker.observe(1, 505), SequenceStatus::Stale);
+        assert_eq!(tracker.expected(), Some(2));
+
+        // Sequences and epochs both wrap
+        assert_eq!(tracker.observe(u16::MAX, 0), SequenceStatus::Stale);
+        let mut wrapped = SequenceTracker::new();
+        wrapped.observe(u16::MAX, u16::MAX);
+        assert_eq!(wrapped.observe(u16::MAX, 0), SequenceStatus::InOrder { lost: 0 });
+        assert_eq!(wrapped.observe(0, 0), SequenceStatus::Reset);
+    }
+
+    #[test]
+    fn test_forwarder_drops_stale_epoch() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let key = UdpSessionKey::from_token(&forwarder.issue_session_token("alice")).unwrap();
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let fragment = |sequence: u16| fragment_packet(sequence, &[1, 2, 3], SAFE_UDP_PACKET_SIZE).unwrap().remove(0);
+        let sent = |counter: u64, epoch: u16, sequence: u16| {
+            UdpAudioPacket { epoch, payload: fragment(sequence), ..audio(counter) }.seal(&key)
+        };
+
+        assert_eq!(forwarder.authenticate_datagram(&sent(0, 7, 40), source).unwrap().sequence, SequenceStatus::Reset);
+        assert_eq!(
+            forwarder.authenticate_datagram(&sent(1, 7, 42), source).unwrap().sequence,
+            SequenceStatus::InOrder { lost: 1 }
+        );
+        // The sender reconnected; its restarted sequence isn't mistaken for a reorder
+        assert_eq!(forwarder.authenticate_datagram(&sent(2, 8, 1), source).unwrap().sequence, SequenceStatus::Reset);
+        assert!(matches!(forwarder.authenticate_datagram(&sent(3, 7, 43), source), Err(MediaError::StaleEpoch)));
+        // Too short to carry a fragment header
+        let short = UdpAudioPacket { epoch: 8, payload: vec![0], ..audio(4) }.seal(&key);
+        assert!(matches!(forwarder.authenticate_datagram(&short, source), Err(MediaError::InvalidFragment)));
+    }
+
+    #[test]
+    fn test_configured_packet_size_reaches_sender() {
+        let addr: SocketAddr = "127.0.0.1:10000".parse().unwrap();
+        let config = crate::config::AudioConfig { udp_packet_size: 500, ..Default::default() };
+        assert_eq!(MediaSender::with_config(addr, &config).max_packet_size(), 500);
+        assert_eq!(MediaSender::with_config(addr, &Default::default()).max_packet_size(), SAFE_UDP_PACKET_SIZE);
+
+        let config = crate::config::AudioConfig { udp_packet_size: 10, ..Default::default() };
+        assert_eq!(MediaSender::with_config(addr, &config).max_packet_size(), MIN_UDP_PACKET_SIZE);
+    }
+
+    #[test]
+    fn test_fragmented_audio_reassembled_by_forwarder() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        let token = forwarder.issue_session_token("p1");
+        let mut sender = MediaSender::new("127.0.0.1:10000".parse().unwrap());
+        sender.connect().unwrap();
+        sender.set_session_token("p1".to_string(), &token).unwrap();
+        sender.set_max_packet_size(300);
+
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let datagrams = sender.send_audio(AudioCodec::Opus, &data, Some(unix_millis())).unwrap();
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|d| d.len() <= 300));
+
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let mut complete = Vec::new();
+        for datagram in datagrams.iter().rev() {
+            complete.extend(forwarder.authenticate_datagram(datagram, source).unwrap().complete);
+        }
+        assert_eq!(complete, vec![data]);
+    }
+
+    #[test]
+    fn test_forwarder_drops_stale_audio() {
+        let mut forwarder = MediaForwarder::new(10000, 10001);
+        forwarder.set_max_audio_age_ms(500);
+        let token = forwarder.issue_session_token("p1");
+        let mut sender = MediaSender::new("127.0.0.1:10000".parse().unwrap());
+        sender.connect().unwrap();
+        sender.set_session_token("p1".to_string(), &token).unwrap();
+        let source: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+
+        let now = unix_millis();
+        for (captured_at, fresh) in [(Some(now), true), (Some(now - 2_000), false), (None, true)] {
+            let datagram = sender.send_audio(AudioCodec::Opus, &[1, 2, 3], captured_at).unwrap().remove(0);
+            let accepted = forwarder.authenticate_datagram(&datagram, source).unwrap();
+            assert_eq!(accepted.complete.is_some(), fresh);
+            assert_eq!(accepted.stale, !fresh);
+        }
+    }
+
+    #[test]
+    fn test_reconnect_starts_new_epoch() {
+        let mut sender = MediaSender::new("127.0.0.1:10000".parse().unwrap());
+        sender.connect().unwrap();
+        let first = sender.epoch();
+        sender.set_session_token("p1".to_string(), &UdpSessionKey::generate().to_token()).unwrap();
+        sender.send_audio(AudioCodec::Opus, &[1, 2, 3], None).unwrap();
+        sender.disconnect();
+
+        sender.connect().unwrap();
+        assert_eq!(sender.epoch(), first.wrapping_add(1));
+        assert_eq!(sender.audio_sequence, 0);
+    }
+
+    #[test]
+    fn test_dropped_control_packet_is_retransmitted_until_acked() {
+        let start = Instant::now();
+        let mut alice = ReliableControlChannel::default();
+        let mut bob = ReliableControlChannel::default();
+
+        // First send is lost on the way
+        let _lost = alice.send(b"mute", start);
+        assert!(alice.retransmits(start).is_empty());
+
+        let resent = alice.retransmits(start + CONTROL_RETRANSMIT_AFTER);
+        assert_eq!(resent.len(), 1);
+        let received = bob.receive(&resent[0]).unwrap();
+        assert_eq!(received.payload.as_deref(), Some(&b"mute"[..]));
+
+        // The ack is lost too, so Alice resends; Bob acks again without redelivering
+        let resent = alice.retransmits(start + CONTROL_RETRANSMIT_AFTER * 2);
+        assert_eq!(resent.len(), 1);
+        let duplicate = bob.receive(&resent[0]).unwrap();
+        assert_eq!(duplicate.payload, None);
+
+        alice.receive(&duplicate.ack.unwrap()).unwrap();
+        assert_eq!(alice.unacked(), 0);
+        assert!(alice.retransmits(start + CONTROL_RETRANSMIT_AFTER * 10).is_empty());
+    }
+
+    #[test]
+    fn test_audio_packets_are_never_retransmitted() {
+        let start = Instant::now();
+        let mut channel = ReliableControlChannel::default();
+        let _lost_audio = channel.audio_datagram(&audio(0), &UdpSessionKey::generate());
+        let _lost_control = channel.send(b"hand", start);
+
+        let mut resent = Vec::new();
+        for step in 1..=CONTROL_MAX_ATTEMPTS * 2 {
+            resent.extend(channel.retransmits(start + CONTROL_RETRANSMIT_AFTER * step));
+        }
+        // Only the control packet comes back, and only until it is given up
+        assert_eq!(resent.len(), CONTROL_MAX_ATTEMPTS as usize - 1);
+        assert!(resent.iter().all(|d| d[0] == CONTROL_DATA && &d[CONTROL_HEADER_LEN..] == b"hand"));
+        assert_eq!(channel.unacked(), 0);
+    }
 }
@@ -2,33 +2,280 @@
 //!
 //! Defines the message format for client-server signaling.
 
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Feature name advertised in `Hello` for compressed signaling frames
+pub const FEATURE_COMPRESSION: &str = "compression";
+
+/// Feature names advertising which audio codecs a peer can decode
+pub const FEATURE_CODEC_OPUS: &str = "codec_opus";
+pub const FEATURE_CODEC_PCM: &str = "codec_pcm";
+pub const FEATURE_CODEC_PCM16: &str = "codec_pcm16";
+
+/// Feature name advertised in `Hello` for binary (non-JSON) audio frames
+pub const FEATURE_BINARY_AUDIO: &str = "binary_audio";
+
+/// Feature name advertised in `Hello` for HMAC-tagged client frames (see
+/// [`FrameAuthenticator`]). Only agreed after a Kyber key exchange.
+pub const FEATURE_FRAME_MAC: &str = "frame_mac";
+
+/// Feature name advertised in `Hello` for incremental roster updates
+/// (`RoomRosterDelta`) instead of a full `RoomRoster` on every change
+pub const FEATURE_ROSTER_DELTA: &str = "roster_delta";
+
+/// A full `RoomRoster` replaces deltas every this many roster versions, so
+/// clients that misapplied one converge without asking
+pub const ROSTER_SNAPSHOT_INTERVAL: u64 = 32;
+
+/// Protocol revision reported in `Capabilities`; bumped on breaking changes.
+/// Version 2 opened connections with the `ServerInfo` banner and added the
+/// epoch and capture time to UDP audio datagrams.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Features this build of the protocol understands
+pub const SUPPORTED_FEATURES: &[&str] = &[
+    FEATURE_COMPRESSION,
+    FEATURE_CODEC_OPUS,
+    FEATURE_CODEC_PCM,
+    FEATURE_CODEC_PCM16,
+    FEATURE_BINARY_AUDIO,
+    FEATURE_FRAME_MAC,
+    FEATURE_ROSTER_DELTA,
+];
+
+/// Bodies smaller than this are never compressed
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Largest frame body the server accepts from a client, and the most a
+/// compressed body may inflate to
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// High bit of the length prefix marks a deflate-compressed body
+const COMPRESSED_FLAG: u32 = 0x8000_0000;
+
+/// Length of the HMAC-SHA256 tag closing an authenticated frame body
+pub const FRAME_MAC_LEN: usize = 32;
+
+/// Leading byte of a binary `AudioData` frame body. JSON bodies always start
+/// with `{`, so the opcode can't be mistaken for a control message.
+const OPCODE_AUDIO_DATA: u8 = 0x01;
+/// Leading byte of a binary `AudioDataReceived` frame body
+const OPCODE_AUDIO_DATA_RECEIVED: u8 = 0x02;
+/// Binary audio flag bit: payload is batched
+const AUDIO_FLAG_BATCHED: u8 = 0x01;
+/// Binary audio flag bit: an 8-byte capture timestamp follows the codec byte
+const AUDIO_FLAG_CAPTURED_AT: u8 = 0x02;
+
+/// Protocol framing errors
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Compression error: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("Malformed binary audio frame")]
+    BinaryAudio,
+    #[error("Frame too large ({0} bytes)")]
+    FrameTooLarge(usize),
+    #[error("Frame failed authentication")]
+    FrameAuthentication,
+    #[error("Compressed frame without negotiated compression")]
+    CompressionNotNegotiated,
+}
+
+/// Why a username was rejected
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum UsernameError {
+    #[error("Username is empty")]
+    Empty,
+    #[error("Username is longer than {0} characters")]
+    TooLong(usize),
+    #[error("Username contains a disallowed character ({0:?})")]
+    InvalidCharacter(char),
+}
+
+/// Framing choices agreed with the peer in `Hello`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// Deflate large control messages
+    pub compress: bool,
+    /// Send audio as raw bytes behind an opcode instead of JSON
+    pub binary_audio: bool,
+}
+
+impl FrameOptions {
+    /// Options enabled by a negotiated feature list
+    pub fn from_features(features: &[String]) -> Self {
+        let has = |name: &str| features.iter().any(|f| f == name);
+        Self {
+            compress: has(FEATURE_COMPRESSION),
+            binary_audio: has(FEATURE_BINARY_AUDIO),
+        }
+    }
+}
 
 /// Signaling messages exchanged between client and server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SignalingMessage {
+    // Feature negotiation (sent by the client, echoed by the server with
+    // the subset it agrees to use)
+    Hello {
+        features: Vec<String>,
+    },
+    /// Ask what the server supports; answered with `Capabilities`
+    DescribeCapabilities,
+    Capabilities(Capabilities),
+    /// Ask for the server's clock; `client_sent_ms` (local Unix ms) is
+    /// echoed back so the reply can be timed
+    TimeSync {
+        client_sent_ms: u64,
+    },
+    /// Reply to `TimeSync`: the server's wall clock in Unix ms, and a
+    /// monotonic clock (ms since the server started) for measuring intervals
+    TimeSyncResponse {
+        client_sent_ms: u64,
+        server_time_ms: u64,
+        server_monotonic_ms: u64,
+    },
+    /// Sent by the server as the first frame after the TLS handshake,
+    /// before key exchange
+    ServerInfo {
+        name: String,
+        /// Server build version
+        version: String,
+        protocol_version: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motd: Option<String>,
+    },
+
     // Client -> Server
     Login {
         username: String,
+        /// Token from an earlier `LoginResponse`; within the server's
+        /// reconnect grace it gets that session's participant ID back
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_token: Option<String>,
+    },
+    ListRooms {
+        #[serde(default)]
+        offset: Option<u32>,
+        #[serde(default)]
+        limit: Option<u32>,
+        /// Omit rooms the caller can't enter because they're full
+        #[serde(default)]
+        joinable_only: bool,
+    },
+    ListServerUsers {
+        #[serde(default)]
+        offset: Option<u32>,
+        #[serde(default)]
+        limit: Option<u32>,
     },
-    ListRooms,
-    ListServerUsers,
     CreateRoom {
         name: String,
         max_participants: Option<u32>,
+        /// Shareable alias usable in place of the room ID, e.g. "standup"
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slug: Option<String>,
+        /// Only accept audio in this codec (see `RoomInfo::required_codec`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        required_codec: Option<AudioCodec>,
     },
     JoinRoom {
+        /// Room ID, slug, or an exact room name
+        room_id: String,
+        username: String,
+    },
+    /// Join a room for chat and presence only, keeping the current room
+    /// for audio
+    SpectateRoom {
+        /// Room ID, slug, or an exact room name
         room_id: String,
         username: String,
     },
+    StopSpectating {
+        room_id: String,
+    },
+    /// Request authoritative metadata for a room
+    GetRoomInfo {
+        room_id: String,
+    },
+    /// Ask for a room's full `RoomRoster`, e.g. after missing a delta
+    RequestRoster {
+        room_id: String,
+    },
+    /// Ask for a room's event log (room owner only); `limit` keeps only the
+    /// newest entries
+    GetRoomEvents {
+        room_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
+    /// Ask the server who the caller is; answered with `WhoamiResponse`
+    Whoami,
+    /// Change a room's settings (room owner only)
+    UpdateRoomSettings {
+        room_id: String,
+        /// Post a system chat message when someone joins or leaves
+        announce_joins: bool,
+        /// Queue joins to a full room instead of rejecting them; `None` leaves it unchanged
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        waiting_room: Option<bool>,
+    },
+    /// Let a queued participant into the room now, even if it is full (owner only)
+    AdmitWaiter {
+        room_id: String,
+        participant_id: String,
+    },
+    /// Move a member of your room into another room (owner only). `force`
+    /// lets them in even if the target room is full, and needs ownership of
+    /// the target room as well
+    MoveParticipant {
+        participant_id: String,
+        target_room_id: String,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Set or clear (`None` or blank) a room's topic (owner only)
+    SetRoomTopic {
+        room_id: String,
+        topic: Option<String>,
+    },
+    /// Remove everyone from a room but keep the room (owner only). Each
+    /// member gets `ParticipantLeft` with reason `Cleared` and a `RoomLeft`
+    ClearRoom {
+        room_id: String,
+    },
+    /// Delete a room outright (owner only). Everyone on the server gets
+    /// `RoomClosed` with reason `Deleted`
+    DeleteRoom {
+        room_id: String,
+    },
     LeaveRoom,
+    /// Explicit disconnect so the server can tear down immediately
+    Logout,
     ToggleAudio {
         enabled: bool,
     },
     ToggleVideo {
         enabled: bool,
     },
+    /// Set the sender's presence status
+    SetStatus {
+        status: UserStatus,
+    },
+    /// Raise or lower a hand in a moderated room
+    RaiseHand {
+        raised: bool,
+    },
     MediaOffer {
         target_id: String,
         sdp: String,
@@ -46,10 +293,47 @@ pub enum SignalingMessage {
     SendMessage {
         content: String,
     },
+    /// Replace the content of one of the sender's own messages
+    EditMessage {
+        message_id: String,
+        new_content: String,
+    },
+    /// Retract one of the sender's own messages
+    DeleteMessage {
+        message_id: String,
+    },
+    /// Message for a subset of the sender's room (breakout-style whisper)
+    GroupMessage {
+        recipient_ids: Vec<String>,
+        content: String,
+    },
+    /// Offer a Kyber public key to the sender's room to agree a room
+    /// chat key. Unauthenticated; see `crypto::e2e` on safety codes.
+    E2eKeyOffer {
+        public_key: Vec<u8>,
+    },
+    /// Answer another participant's key offer
+    E2eKeyAccept {
+        recipient_id: String,
+        ciphertext: Vec<u8>,
+    },
+    /// Chat message sealed with the room key; the server can't read it
+    SendEncryptedMessage {
+        ciphertext: Vec<u8>,
+    },
     
     // Audio streaming
     AudioData {
         data: Vec<u8>,
+        /// Payload holds several length-prefixed frames (see `audio_codec::pack_frames`)
+        #[serde(default)]
+        batched: bool,
+        /// Encoding of every frame in `data`
+        #[serde(default)]
+        codec: AudioCodec,
+        /// Capture time in Unix milliseconds, for dropping stale frames
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        captured_at: Option<u64>,
     },
     
     // Key exchange messages
@@ -65,12 +349,22 @@ pub enum SignalingMessage {
         success: bool,
         participant_id: Option<String>,
         error: Option<String>,
+        /// Per-session key for signing UDP audio packets (see `media::UdpSessionKey`)
+        #[serde(default)]
+        udp_session_token: Option<String>,
+        /// Token to log in with after a dropped connection (see `Login`)
+        #[serde(default)]
+        resume_token: Option<String>,
     },
     RoomList {
         rooms: Vec<RoomInfo>,
+        #[serde(default)]
+        total: u32,
     },
     ServerUserList {
         users: Vec<ServerUserInfo>,
+        #[serde(default)]
+        total: u32,
     },
     RoomCreated {
         success: bool,
@@ -84,17 +378,83 @@ pub enum SignalingMessage {
         room_name: Option<String>,
         participants: Option<Vec<ParticipantInfo>>,
         error: Option<String>,
+        /// Version of `participants`, for applying later `RoomRosterDelta`s
+        #[serde(default)]
+        roster_version: u64,
     },
     RoomLeft {
         success: bool,
         error: Option<String>,
     },
+    /// The room was full so the join was queued. Sent again whenever the
+    /// position changes; a `RoomJoined` follows once a slot frees up.
+    WaitingRoom {
+        room_id: String,
+        position: u32,
+    },
+    /// Reply to `SpectateRoom`
+    RoomSpectating {
+        success: bool,
+        room_id: Option<String>,
+        room_name: Option<String>,
+        participants: Option<Vec<ParticipantInfo>>,
+        error: Option<String>,
+        /// Version of `participants`, for applying later `RoomRosterDelta`s
+        #[serde(default)]
+        roster_version: u64,
+    },
+    RoomInfoResponse {
+        success: bool,
+        room: Option<RoomInfo>,
+        /// Participant that created the room; `None` for server-created rooms
+        owner_id: Option<String>,
+        /// Unix timestamp
+        created_at: Option<u64>,
+        error: Option<String>,
+    },
+    /// The caller's own identity, as the server sees it
+    WhoamiResponse {
+        /// Username is the one used in the active room, else the login name
+        participant: ParticipantInfo,
+        /// Active room, if any
+        room_id: Option<String>,
+    },
+    /// Reply to `GetRoomEvents`, oldest entry first
+    RoomEventLog {
+        room_id: String,
+        entries: Vec<RoomLogEntry>,
+    },
     ParticipantJoined {
         participant_id: String,
         username: String,
     },
     ParticipantLeft {
         participant_id: String,
+        #[serde(default)]
+        reason: LeaveReason,
+    },
+    /// Authoritative membership of a room, sent after every join or leave
+    /// (periodically and on `RequestRoster` with `roster_delta`). Clients
+    /// should replace their local list rather than patch it.
+    RoomRoster {
+        room_id: String,
+        participants: Vec<ParticipantInfo>,
+        /// Bumped on every membership or audio/video change
+        #[serde(default)]
+        version: u64,
+    },
+    /// Changes since roster `version - 1`, sent instead of `RoomRoster`
+    /// once `roster_delta` is agreed. `media_changes` holds
+    /// `(participant_id, audio_enabled, video_enabled)`.
+    RoomRosterDelta {
+        room_id: String,
+        version: u64,
+        #[serde(default)]
+        added: Vec<ParticipantInfo>,
+        #[serde(default)]
+        removed: Vec<String>,
+        #[serde(default)]
+        media_changes: Vec<(String, bool, bool)>,
     },
     AudioToggled {
         participant_id: String,
@@ -104,38 +464,178 @@ pub enum SignalingMessage {
         participant_id: String,
         enabled: bool,
     },
+    /// Observed public UDP endpoint of a co-room member, for peer-to-peer audio
+    PeerUdpEndpoint {
+        participant_id: String,
+        addr: String,
+    },
+    /// A room's topic changed, broadcast server-wide so room lists stay current
+    RoomTopicChanged {
+        room_id: String,
+        topic: Option<String>,
+    },
+    /// A room no longer exists, broadcast server-wide so members and room
+    /// lists can drop it
+    RoomClosed {
+        room_id: String,
+        reason: RoomClosedReason,
+    },
+    /// Presence change, broadcast server-wide
+    UserStatusChanged {
+        participant_id: String,
+        status: UserStatus,
+    },
+    /// Hand state change; `order` is the 1-based queue position (0 when lowered)
+    HandRaised {
+        participant_id: String,
+        raised: bool,
+        order: u32,
+    },
     
     // Chat messages
     MessageReceived {
+        #[serde(default)]
+        message_id: String,
+        /// Room the message was posted in; empty from older servers
+        #[serde(default)]
+        room_id: String,
+        sender_id: String,
+        sender_username: String,
+        content: String,
+        timestamp: u64,
+        #[serde(default)]
+        edited: bool,
+    },
+    MessageEdited {
+        message_id: String,
+        new_content: String,
+        edited: bool,
+    },
+    MessageDeleted {
+        message_id: String,
+    },
+    GroupMessageReceived {
         sender_id: String,
         sender_username: String,
+        recipient_ids: Vec<String>,
         content: String,
         timestamp: u64,
     },
+    E2eKeyOfferReceived {
+        sender_id: String,
+        room_id: String,
+        public_key: Vec<u8>,
+    },
+    E2eKeyAcceptReceived {
+        sender_id: String,
+        room_id: String,
+        ciphertext: Vec<u8>,
+    },
+    EncryptedMessageReceived {
+        sender_id: String,
+        sender_username: String,
+        ciphertext: Vec<u8>,
+        timestamp: u64,
+    },
     
     // Audio streaming
     AudioDataReceived {
         sender_id: String,
         data: Vec<u8>,
+        #[serde(default)]
+        batched: bool,
+        #[serde(default)]
+        codec: AudioCodec,
+        /// Sender's capture time in Unix milliseconds
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        captured_at: Option<u64>,
+    },
+    
+    // Video streaming
+    /// Decoded RGBA frame from a participant (see `video::VideoFrame`)
+    VideoFrameReceived {
+        sender_id: String,
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
     },
     
     Error {
         message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<ErrorCode>,
     },
 }
 
+/// Machine-readable error categories carried in `Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A server-configured limit (rooms, rooms per user, ...) was hit
+    LimitExceeded,
+    /// No room matched the given ID or name
+    RoomNotFound,
+    /// Several rooms share the given name; join by ID instead
+    Ambiguous,
+    /// Username too long or containing disallowed characters
+    InvalidUsername,
+    /// Too many room-mutating commands in a short time; retry later
+    RateLimited,
+    /// An audio payload was over the server's `max_audio_payload_bytes`
+    PayloadTooLarge,
+    /// Audio in a codec other than the room's `required_codec`
+    CodecNotAllowed,
+}
+
 /// Information about a room
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomInfo {
     pub id: String,
     pub name: String,
+    /// Shareable alias accepted by `join_room`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
     pub participants: u32,
     pub max_participants: u32,
     pub is_locked: bool,
+    #[serde(default)]
+    pub is_full: bool,
+    /// Joining requires a password
+    #[serde(default)]
+    pub password_protected: bool,
+    /// What the room is about, set by its owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// The only codec the room relays; other audio is transcoded to it or
+    /// refused with `codec_not_allowed`, depending on the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_codec: Option<AudioCodec>,
+    /// Unix seconds of the last chat message, join or leave
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<u64>,
+}
+
+impl RoomInfo {
+    /// Whether a join attempt could succeed
+    pub fn is_joinable(&self) -> bool {
+        !self.is_locked && !self.is_full && !self.password_protected
+    }
+
+    /// "active 2m ago" style summary of `last_activity`, relative to `now`
+    /// (Unix seconds)
+    pub fn activity_label(&self, now: u64) -> Option<String> {
+        let ago = now.saturating_sub(self.last_activity?);
+        Some(match ago {
+            0..=59 => "active just now".to_string(),
+            60..=3599 => format!("active {}m ago", ago / 60),
+            3600..=86399 => format!("active {}h ago", ago / 3600),
+            _ => format!("active {}d ago", ago / 86400),
+        })
+    }
 }
 
 /// Information about a participant
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ParticipantInfo {
     pub id: String,
     pub username: String,
@@ -143,6 +643,76 @@ pub struct ParticipantInfo {
     pub video_enabled: bool,
 }
 
+/// A client's copy of one room's roster, kept current from `RoomRoster`
+/// snapshots and `RoomRosterDelta`s
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Roster {
+    pub room_id: String,
+    pub version: u64,
+    pub participants: Vec<ParticipantInfo>,
+}
+
+impl Roster {
+    /// Replace the roster with a full snapshot
+    pub fn reset(&mut self, room_id: &str, version: u64, participants: Vec<ParticipantInfo>) {
+        self.room_id = room_id.to_string();
+        self.version = version;
+        self.participants = participants;
+    }
+
+    /// Apply the delta that takes the roster to `version`.
+    ///
+    /// Returns `false`, leaving the roster as it was, when the delta isn't
+    /// the next one for this room; a delta went missing and the client
+    /// should send `RequestRoster`.
+    pub fn apply_delta(
+        &mut self,
+        room_id: &str,
+        version: u64,
+        added: Vec<ParticipantInfo>,
+        removed: &[String],
+        media_changes: &[(String, bool, bool)],
+    ) -> bool {
+        if room_id != self.room_id || version != self.version + 1 {
+            return false;
+        }
+        self.participants.retain(|p| !removed.contains(&p.id));
+        for participant in added {
+            match self.participants.iter_mut().find(|p| p.id == participant.id) {
+                Some(existing) => *existing = participant,
+                None => self.participants.push(participant),
+            }
+        }
+        for (id, audio_enabled, video_enabled) in media_changes {
+            if let Some(p) = self.participants.iter_mut().find(|p| &p.id == id) {
+                p.audio_enabled = *audio_enabled;
+                p.video_enabled = *video_enabled;
+            }
+        }
+        self.version = version;
+        true
+    }
+}
+
+/// What a server supports, so clients can adapt their UI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    /// Audio codecs the server relays
+    pub codecs: Vec<AudioCodec>,
+    /// Largest signaling frame body the server accepts
+    pub max_message_bytes: u32,
+    /// Login needs credentials beyond a username
+    pub auth_required: bool,
+    /// End-to-end encrypted chat (`e2e_key_offer` and friends) is relayed
+    pub e2e_supported: bool,
+    /// Largest room `create_room` accepts (0 = no limit)
+    pub max_room_participants: u32,
+    /// `time_sync` is answered
+    #[serde(default)]
+    pub time_sync: bool,
+}
+
 /// Information about a server-wide user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerUserInfo {
@@ -152,6 +722,230 @@ pub struct ServerUserInfo {
     pub current_room: Option<String>,
     pub audio_enabled: bool,
     pub video_enabled: bool,
+    #[serde(default)]
+    pub status: UserStatus,
+}
+
+/// User presence status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    #[default]
+    Available,
+    Away,
+    Busy,
+}
+
+/// Reserved `sender_id` for server-generated chat messages
+pub const SYSTEM_SENDER_ID: &str = "system";
+
+/// Reserved `sender_id` for the single combined stream sent when the server
+/// mixes room audio (`mix_audio`)
+pub const MIXED_AUDIO_SENDER_ID: &str = "mix";
+
+/// Why a participant left a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaveReason {
+    /// Left the room or logged out
+    #[default]
+    Left,
+    /// Connection dropped
+    Disconnected,
+    /// Removed by a moderator
+    Kicked,
+    /// Moved to another room by a moderator
+    Moved,
+    /// Sent nothing within the server's client timeout
+    TimedOut,
+    /// Server is shutting down
+    ServerShutdown,
+    /// Removed along with everyone else when the owner cleared the room
+    Cleared,
+}
+
+impl LeaveReason {
+    /// Short phrase for status lines, e.g. "alice timed out"
+    pub fn describe(self) -> &'static str {
+        match self {
+            LeaveReason::Left => "left the room",
+            LeaveReason::Disconnected => "disconnected",
+            LeaveReason::Kicked => "was removed from the room",
+            LeaveReason::Moved => "was moved to another room",
+            LeaveReason::TimedOut => "timed out",
+            LeaveReason::ServerShutdown => "left (server shutting down)",
+            LeaveReason::Cleared => "was removed when the room was cleared",
+        }
+    }
+}
+
+/// One entry in a room's event log, for moderators reviewing what happened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomLogEntry {
+    /// Unix seconds
+    pub timestamp: u64,
+    /// Who did it: the member themselves for joins and leaves, the owner for
+    /// moderation, `None` for the server (dropped connections, timeouts)
+    pub actor_id: Option<String>,
+    pub event: RoomLogEvent,
+}
+
+/// What a [`RoomLogEntry`] records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RoomLogEvent {
+    Joined { participant_id: String, username: String },
+    /// Left for any reason, including being moved out or cleared by the owner
+    Left { participant_id: String, username: String, reason: LeaveReason },
+    TopicChanged { topic: Option<String> },
+    SettingsChanged { announce_joins: bool, waiting_room: bool },
+}
+
+/// Why a room was closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomClosedReason {
+    /// Deleted by its owner
+    Deleted,
+    /// Removed by the server after sitting empty (`reap_empty_rooms`)
+    Reaped,
+}
+
+/// Encoding of an audio frame. Peers that predate the tag always sent Opus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioCodec {
+    #[default]
+    Opus,
+    /// Little-endian f32 samples (see `audio::samples_to_bytes`)
+    Pcm,
+    /// Little-endian i16 samples, half the size of `Pcm` (see
+    /// `audio::samples_to_i16_bytes`)
+    Pcm16,
+}
+
+impl AudioCodec {
+    pub const ALL: [AudioCodec; 3] = [AudioCodec::Opus, AudioCodec::Pcm, AudioCodec::Pcm16];
+
+    /// One-byte tag used in UDP audio packets
+    pub fn to_wire(self) -> u8 {
+        match self {
+            AudioCodec::Opus => 0,
+            AudioCodec::Pcm => 1,
+            AudioCodec::Pcm16 => 2,
+        }
+    }
+
+    pub fn from_wire(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(AudioCodec::Opus),
+            1 => Some(AudioCodec::Pcm),
+            2 => Some(AudioCodec::Pcm16),
+            _ => None,
+        }
+    }
+}
+
+/// Check a username is non-blank, at most `max_len` characters, and made
+/// only of letters, digits, `-`, `_`, `.` and spaces
+pub fn validate_username(username: &str, max_len: usize) -> Result<(), UsernameError> {
+    if username.trim().is_empty() {
+        return Err(UsernameError::Empty);
+    }
+    if username.chars().count() > max_len {
+        return Err(UsernameError::TooLong(max_len));
+    }
+    match username
+        .chars()
+        .find(|c| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ')))
+    {
+        Some(c) => Err(UsernameError::InvalidCharacter(c)),
+        None => Ok(()),
+    }
+}
+
+/// Codec to send with, given the features agreed in `Hello`.
+///
+/// Smallest first: Opus, then i16 PCM, then f32 PCM, so PCM is only chosen
+/// when the peer didn't agree to Opus. Peers that advertise no codecs at
+/// all predate negotiation and get Opus.
+pub fn preferred_codec(agreed: &[String]) -> AudioCodec {
+    let has = |name: &str| agreed.iter().any(|f| f == name);
+    if has(FEATURE_CODEC_OPUS) {
+        AudioCodec::Opus
+    } else if has(FEATURE_CODEC_PCM16) {
+        AudioCodec::Pcm16
+    } else if has(FEATURE_CODEC_PCM) {
+        AudioCodec::Pcm
+    } else {
+        AudioCodec::Opus
+    }
+}
+
+/// Current time in Unix milliseconds, as used for `captured_at`
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// How far a client's clock is from the server's, from one `TimeSync`
+/// round trip
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClockOffset {
+    /// Add to a local Unix ms time to get the server's
+    pub offset_ms: i64,
+    /// Round trip of the exchange; the offset is good to half of this
+    pub round_trip_ms: u64,
+}
+
+impl ClockOffset {
+    /// Offset from a request sent at `sent_ms` and answered at
+    /// `server_time_ms`, with the reply received at `received_ms` (both
+    /// local). Like NTP, assumes the server stamped it halfway through.
+    pub fn from_exchange(sent_ms: u64, server_time_ms: u64, received_ms: u64) -> Self {
+        let round_trip_ms = received_ms.saturating_sub(sent_ms);
+        let midpoint = sent_ms + round_trip_ms / 2;
+        Self { offset_ms: server_time_ms as i64 - midpoint as i64, round_trip_ms }
+    }
+
+    /// A local Unix ms time on the server's clock, e.g. for `captured_at`
+    pub fn to_server(self, local_ms: u64) -> u64 {
+        local_ms.saturating_add_signed(self.offset_ms)
+    }
+
+    /// A server Unix ms time on the local clock, e.g. for display
+    pub fn to_local(self, server_ms: u64) -> u64 {
+        server_ms.saturating_add_signed(-self.offset_ms)
+    }
+}
+
+/// Whether audio captured at `captured_at` is older than `max_age_ms` at
+/// `now_ms`. Frames without a timestamp and a `max_age_ms` of 0 never are.
+pub fn audio_is_stale(captured_at: Option<u64>, now_ms: u64, max_age_ms: u64) -> bool {
+    match captured_at {
+        Some(captured_at) if max_age_ms > 0 => now_ms.saturating_sub(captured_at) > max_age_ms,
+        _ => false,
+    }
+}
+
+/// Default page size for `ListRooms`/`ListServerUsers` when no limit is given
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Upper bound on the page size a client may request
+pub const MAX_PAGE_LIMIT: u32 = 500;
+
+/// Slice one page out of a list, returning the page and the total item count.
+///
+/// `limit` falls back to `DEFAULT_PAGE_LIMIT` and is capped at `MAX_PAGE_LIMIT`.
+/// An offset past the end yields an empty page with the correct total.
+pub fn paginate<T>(items: Vec<T>, offset: Option<u32>, limit: Option<u32>) -> (Vec<T>, u32) {
+    let total = items.len() as u32;
+    let offset = offset.unwrap_or(0) as usize;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as usize;
+    let page = items.into_iter().skip(offset).take(limit).collect();
+    (page, total)
 }
 
 impl SignalingMessage {
@@ -174,6 +968,252 @@ impl SignalingMessage {
         framed.extend_from_slice(&data);
         Ok(framed)
     }
+
+    /// Create a framed message, deflating the body when `compress` is set
+    /// and the body is at least `COMPRESSION_THRESHOLD` bytes.
+    ///
+    /// Audio payloads are never compressed since Opus data is already dense.
+    /// Compressed frames set the high bit of the length prefix.
+    pub fn to_framed_compressed(&self, compress: bool) -> Result<Vec<u8>, ProtocolError> {
+        let data = self.to_bytes()?;
+        if !compress || data.len() < COMPRESSION_THRESHOLD || self.is_audio() {
+            return Ok(self.to_framed()?);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data)?;
+        let body = encoder.finish()?;
+
+        let len = (body.len() as u32 | COMPRESSED_FLAG).to_be_bytes();
+        let mut framed = Vec::with_capacity(4 + body.len());
+        framed.extend_from_slice(&len);
+        framed.extend_from_slice(&body);
+        Ok(framed)
+    }
+
+    /// Create a framed message using the negotiated `options`: audio goes
+    /// out as a binary frame when agreed, everything else as (maybe
+    /// compressed) JSON.
+    pub fn to_framed_with(&self, options: FrameOptions) -> Result<Vec<u8>, ProtocolError> {
+        if options.binary_audio {
+            if let Some(framed) = self.to_binary_audio_framed() {
+                return Ok(framed);
+            }
+        }
+        self.to_framed_compressed(options.compress)
+    }
+
+    /// Frame an audio message as `[len][opcode][flags][codec][...][payload]`,
+    /// skipping JSON entirely. `None` for non-audio messages.
+    ///
+    /// A capture timestamp adds `[captured_at u64 BE]` after the codec, and
+    /// `AudioDataReceived` adds `[id len u8][sender id]` before the payload.
+    pub fn to_binary_audio_framed(&self) -> Option<Vec<u8>> {
+        let (opcode, sender_id, data, batched, codec, captured_at) = match self {
+            SignalingMessage::AudioData { data, batched, codec, captured_at } => {
+                (OPCODE_AUDIO_DATA, None, data, *batched, *codec, *captured_at)
+            }
+            SignalingMessage::AudioDataReceived { sender_id, data, batched, codec, captured_at } => {
+                (OPCODE_AUDIO_DATA_RECEIVED, Some(sender_id), data, *batched, *codec, *captured_at)
+            }
+            _ => return None,
+        };
+
+        let mut body = Vec::with_capacity(4 + 8 + 36 + data.len());
+        body.push(opcode);
+        let mut flags = if batched { AUDIO_FLAG_BATCHED } else { 0 };
+        if captured_at.is_some() {
+            flags |= AUDIO_FLAG_CAPTURED_AT;
+        }
+        body.push(flags);
+        body.push(codec.to_wire());
+        if let Some(captured_at) = captured_at {
+            body.extend_from_slice(&captured_at.to_be_bytes());
+        }
+        if let Some(sender_id) = sender_id {
+            let id_len = u8::try_from(sender_id.len()).ok()?;
+            body.push(id_len);
+            body.extend_from_slice(sender_id.as_bytes());
+        }
+        body.extend_from_slice(data);
+
+        let mut framed = Vec::with_capacity(4 + body.len());
+        framed.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        framed.extend_from_slice(&body);
+        Some(framed)
+    }
+
+    /// Decode the body of a binary audio frame (see `to_binary_audio_framed`)
+    fn from_binary_audio_body(body: &[u8]) -> Result<Self, ProtocolError> {
+        let [opcode, flags, codec, rest @ ..] = body else {
+            return Err(ProtocolError::BinaryAudio);
+        };
+        let batched = flags & AUDIO_FLAG_BATCHED != 0;
+        let codec = AudioCodec::from_wire(*codec).ok_or(ProtocolError::BinaryAudio)?;
+        let (captured_at, rest) = if flags & AUDIO_FLAG_CAPTURED_AT != 0 {
+            let (timestamp, rest) = rest.split_first_chunk::<8>().ok_or(ProtocolError::BinaryAudio)?;
+            (Some(u64::from_be_bytes(*timestamp)), rest)
+        } else {
+            (None, rest)
+        };
+
+        match *opcode {
+            OPCODE_AUDIO_DATA => Ok(SignalingMessage::AudioData { data: rest.to_vec(), batched, codec, captured_at }),
+            OPCODE_AUDIO_DATA_RECEIVED => {
+                let (&id_len, rest) = rest.split_first().ok_or(ProtocolError::BinaryAudio)?;
+                if rest.len() < id_len as usize {
+                    return Err(ProtocolError::BinaryAudio);
+                }
+                let (sender_id, data) = rest.split_at(id_len as usize);
+                let sender_id = String::from_utf8(sender_id.to_vec()).map_err(|_| ProtocolError::BinaryAudio)?;
+                Ok(SignalingMessage::AudioDataReceived { sender_id, data: data.to_vec(), batched, codec, captured_at })
+            }
+            _ => Err(ProtocolError::BinaryAudio),
+        }
+    }
+
+    /// Decode a frame body read after a length prefix.
+    ///
+    /// `compressed` comes from [`parse_frame_header`]. Binary audio bodies
+    /// are recognised by their leading opcode.
+    pub fn from_frame_body(body: &[u8], compressed: bool) -> Result<Self, ProtocolError> {
+        if !compressed {
+            if let Some(&(OPCODE_AUDIO_DATA | OPCODE_AUDIO_DATA_RECEIVED)) = body.first() {
+                return Self::from_binary_audio_body(body);
+            }
+            return Ok(Self::from_bytes(body)?);
+        }
+        // Bound the inflated size so a tiny frame can't expand without limit
+        let mut data = Vec::new();
+        DeflateDecoder::new(body)
+            .take(MAX_FRAME_LEN as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() > MAX_FRAME_LEN {
+            return Err(ProtocolError::FrameTooLarge(data.len()));
+        }
+        Ok(Self::from_bytes(&data)?)
+    }
+
+    /// Like [`from_frame_body`](Self::from_frame_body), but refuses
+    /// compressed bodies unless compression was agreed in `options`
+    pub fn from_frame_body_with(body: &[u8], compressed: bool, options: FrameOptions) -> Result<Self, ProtocolError> {
+        if compressed && !options.compress {
+            return Err(ProtocolError::CompressionNotNegotiated);
+        }
+        Self::from_frame_body(body, compressed)
+    }
+
+    /// Decode the first frame in `buf`, returning the message and the number
+    /// of bytes consumed, or `None` if the frame is still incomplete.
+    ///
+    /// Frames declaring a body over `MAX_FRAME_LEN` are rejected before any
+    /// of the body is buffered.
+    pub fn decode_frame(buf: &[u8]) -> Result<Option<(Self, usize)>, ProtocolError> {
+        let Some(header) = buf.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let (len, compressed) = parse_frame_header(*header);
+        if len > MAX_FRAME_LEN {
+            return Err(ProtocolError::FrameTooLarge(len));
+        }
+        let Some(body) = buf[4..].get(..len) else {
+            return Ok(None);
+        };
+        Ok(Some((Self::from_frame_body(body, compressed)?, 4 + len)))
+    }
+
+    /// Whether this message carries an audio payload
+    pub fn is_audio(&self) -> bool {
+        matches!(
+            self,
+            SignalingMessage::AudioData { .. } | SignalingMessage::AudioDataReceived { .. }
+        )
+    }
+
+    /// Whether this is audio captured more than `max_age_ms` before `now_ms`
+    /// (see [`audio_is_stale`]). Always false for other messages.
+    pub fn is_stale_audio(&self, now_ms: u64, max_age_ms: u64) -> bool {
+        match self {
+            SignalingMessage::AudioData { captured_at, .. }
+            | SignalingMessage::AudioDataReceived { captured_at, .. } => audio_is_stale(*captured_at, now_ms, max_age_ms),
+            _ => false,
+        }
+    }
+}
+
+/// Split a 4-byte frame header into (body length, compressed flag)
+pub fn parse_frame_header(header: [u8; 4]) -> (usize, bool) {
+    let raw = u32::from_be_bytes(header);
+    ((raw & !COMPRESSED_FLAG) as usize, raw & COMPRESSED_FLAG != 0)
+}
+
+/// HMAC-SHA256 tags on signaling frames.
+///
+/// Each tag covers a running frame counter, the compression flag and the
+/// body, so frames can't be altered, replayed or reordered by anything that
+/// sees the stream after the key exchange. Both ends count frames
+/// independently; TCP keeps them in step. Keyed with a direction key from
+/// `KyberSession`.
+///
+/// The Kyber exchange itself is not authenticated, so this adds nothing
+/// against whoever terminates TLS: a proxy there can run its own exchange
+/// with each side or strip `frame_mac` from `Hello`.
+pub struct FrameAuthenticator {
+    mac: Hmac<Sha256>,
+    sequence: u64,
+}
+
+impl FrameAuthenticator {
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(key).expect("HMAC accepts any key length"),
+            sequence: 0,
+        }
+    }
+
+    fn next_mac(&mut self, compressed: bool, body: &[u8]) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        mac.update(&self.sequence.to_be_bytes());
+        mac.update(&[compressed as u8]);
+        mac.update(body);
+        self.sequence += 1;
+        mac
+    }
+
+    /// Append a tag to a complete frame, fixing up its length prefix
+    pub fn seal(&mut self, mut frame: Vec<u8>) -> Vec<u8> {
+        let (len, compressed) = parse_frame_header([frame[0], frame[1], frame[2], frame[3]]);
+        let tag = self.next_mac(compressed, &frame[4..]).finalize().into_bytes();
+        frame.extend_from_slice(&tag);
+
+        let mut raw = (len + FRAME_MAC_LEN) as u32;
+        if compressed {
+            raw |= COMPRESSED_FLAG;
+        }
+        frame[..4].copy_from_slice(&raw.to_be_bytes());
+        frame
+    }
+
+    /// Check the tag on a received frame body and return the body without it
+    pub fn open<'a>(&mut self, body: &'a [u8], compressed: bool) -> Result<&'a [u8], ProtocolError> {
+        if body.len() < FRAME_MAC_LEN {
+            return Err(ProtocolError::FrameAuthentication);
+        }
+        let (body, tag) = body.split_at(body.len() - FRAME_MAC_LEN);
+        self.next_mac(compressed, body)
+            .verify_slice(tag)
+            .map_err(|_| ProtocolError::FrameAuthentication)?;
+        Ok(body)
+    }
+}
+
+/// Features from `requested` that this side also supports
+pub fn negotiate_features(requested: &[String]) -> Vec<String> {
+    requested
+        .iter()
+        .filter(|f| SUPPORTED_FEATURES.contains(&f.as_str()))
+        .cloned()
+        .collect()
 }
 
 #[cfg(test)]
@@ -184,11 +1224,12 @@ mod tests {
     fn test_serialize_login() {
         let msg = SignalingMessage::Login {
             username: "test_user".to_string(),
+            resume_token: None,
         };
         let bytes = msg.to_bytes().unwrap();
         let parsed: SignalingMessage = SignalingMessage::from_bytes(&bytes).unwrap();
         
-        if let SignalingMessage::Login { username } = parsed {
+        if let SignalingMessage::Login { username, .. } = parsed {
             assert_eq!(username, "test_user");
         } else {
             panic!("Wrong message type");
@@ -197,11 +1238,370 @@ mod tests {
 
     #[test]
     fn test_framed_message() {
-        let msg = SignalingMessage::ListRooms;
+        let msg = SignalingMessage::ListRooms {
+            offset: None,
+            limit: None,
+            joinable_only: false,
+        };
         let framed = msg.to_framed().unwrap();
         
         // Check length prefix
         let len = u32::from_be_bytes([framed[0], framed[1], framed[2], framed[3]]);
         assert_eq!(len as usize, framed.len() - 4);
     }
+
+    #[test]
+    fn test_list_rooms_without_paging_fields() {
+        let parsed = SignalingMessage::from_bytes(br#"{"type":"list_rooms"}"#).unwrap();
+        assert!(matches!(
+            parsed,
+            SignalingMessage::ListRooms {
+                offset: None,
+                limit: None,
+                joinable_only: false,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_serialize_flat() {
+        let caps = Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            codecs: AudioCodec::ALL.to_vec(),
+            max_message_bytes: MAX_FRAME_LEN as u32,
+            auth_required: false,
+            e2e_supported: true,
+            max_room_participants: 10,
+            time_sync: true,
+        };
+        let bytes = SignalingMessage::Capabilities(caps.clone()).to_bytes().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["type"], "capabilities");
+        assert_eq!(json["codecs"][2], "pcm16");
+
+        match SignalingMessage::from_bytes(&bytes).unwrap() {
+            SignalingMessage::Capabilities(parsed) => assert_eq!(parsed, caps),
+            other => panic!("expected Capabilities, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_paginate_default_limit() {
+        let items: Vec<u32> = (0..120).collect();
+        let (page, total) = paginate(items, None, None);
+        assert_eq!(total, 120);
+        assert_eq!(page.len(), DEFAULT_PAGE_LIMIT as usize);
+        assert_eq!(page[0], 0);
+    }
+
+    #[test]
+    fn test_paginate_boundary_pages() {
+        let items: Vec<u32> = (0..10).collect();
+
+        let (page, total) = paginate(items.clone(), Some(8), Some(5));
+        assert_eq!(page, vec![8, 9]);
+        assert_eq!(total, 10);
+
+        let (page, total) = paginate(items.clone(), Some(10), Some(5));
+        assert!(page.is_empty());
+        assert_eq!(total, 10);
+
+        let (page, total) = paginate(items, Some(100), None);
+        assert!(page.is_empty());
+        assert_eq!(total, 10);
+    }
+
+    fn large_room_list() -> SignalingMessage {
+        let rooms = (0..100)
+            .map(|i| RoomInfo {
+                id: format!("room-id-{:04}", i),
+                name: format!("Room number {}", i),
+                slug: None,
+                participants: i % 10,
+                max_participants: 10,
+                is_locked: false,
+                is_full: false,
+                password_protected: false,
+                topic: None,
+                required_codec: None,
+                last_activity: None,
+            })
+            .collect();
+        SignalingMessage::RoomList { rooms, total: 100 }
+    }
+
+    #[test]
+    fn test_large_room_list_compresses_losslessly() {
+        let msg = large_room_list();
+        let plain = msg.to_framed().unwrap();
+        let framed = msg.to_framed_compressed(true).unwrap();
+        assert!(framed.len() < plain.len());
+
+        let (len, compressed) = parse_frame_header([framed[0], framed[1], framed[2], framed[3]]);
+        assert!(compressed);
+        assert_eq!(len, framed.len() - 4);
+
+        let parsed = SignalingMessage::from_frame_body(&framed[4..], compressed).unwrap();
+        assert_eq!(parsed.to_bytes().unwrap(), msg.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_small_message_stays_uncompressed() {
+        let msg = SignalingMessage::LeaveRoom;
+        let framed = msg.to_framed_compressed(true).unwrap();
+        assert_eq!(framed, msg.to_framed().unwrap());
+
+        let (_, compressed) = parse_frame_header([framed[0], framed[1], framed[2], framed[3]]);
+        assert!(!compressed);
+    }
+
+    #[test]
+    fn test_audio_is_never_compressed() {
+        let msg = SignalingMessage::AudioData {
+            data: vec![0u8; 4096],
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: None,
+        };
+        let framed = msg.to_framed_compressed(true).unwrap();
+        let (_, compressed) = parse_frame_header([framed[0], framed[1], framed[2], framed[3]]);
+        assert!(!compressed);
+    }
+
+    fn parse_framed(framed: &[u8]) -> SignalingMessage {
+        let (len, compressed) = parse_frame_header([framed[0], framed[1], framed[2], framed[3]]);
+        assert_eq!(len, framed.len() - 4);
+        SignalingMessage::from_frame_body(&framed[4..], compressed).unwrap()
+    }
+
+    #[test]
+    fn test_binary_audio_roundtrip_without_inflation() {
+        let binary = FrameOptions { compress: false, binary_audio: true };
+        let data: Vec<u8> = (0..=255).collect();
+
+        let msg = SignalingMessage::AudioData { data: data.clone(), batched: true, codec: AudioCodec::Pcm, captured_at: None };
+        let framed = msg.to_framed_with(binary).unwrap();
+        // Length prefix + opcode, flags and codec bytes, then the raw payload
+        assert_eq!(framed.len(), 4 + 3 + data.len());
+        assert!(framed.len() * 3 < msg.to_framed().unwrap().len());
+        match parse_framed(&framed) {
+            SignalingMessage::AudioData { data: parsed, batched, codec, captured_at } => {
+                assert_eq!(parsed, data);
+                assert!(batched);
+                assert_eq!(codec, AudioCodec::Pcm);
+                assert_eq!(captured_at, None);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let msg = SignalingMessage::AudioDataReceived {
+            sender_id: "p-2".to_string(),
+            data: data.clone(),
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: Some(1_700_000_000_123),
+        };
+        let framed = msg.to_framed_with(binary).unwrap();
+        assert_eq!(framed.len(), 4 + 3 + 8 + 1 + 3 + data.len());
+        match parse_framed(&framed) {
+            SignalingMessage::AudioDataReceived { sender_id, data: parsed, batched, codec, captured_at } => {
+                assert_eq!(sender_id, "p-2");
+                assert_eq!(parsed, data);
+                assert!(!batched);
+                assert_eq!(codec, AudioCodec::Opus);
+                assert_eq!(captured_at, Some(1_700_000_000_123));
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_control_frames_stay_json_with_binary_audio() {
+        let options = FrameOptions { compress: false, binary_audio: true };
+        let msg = SignalingMessage::SendMessage { content: "hi".to_string() };
+        let framed = msg.to_framed_with(options).unwrap();
+        assert_eq!(framed, msg.to_framed().unwrap());
+        assert!(matches!(parse_framed(&framed), SignalingMessage::SendMessage { content } if content == "hi"));
+
+        // Without the feature, audio stays JSON too
+        let audio = SignalingMessage::AudioData { data: vec![1, 2], batched: false, codec: AudioCodec::Opus, captured_at: None };
+        assert_eq!(audio.to_framed_with(FrameOptions::default()).unwrap(), audio.to_framed().unwrap());
+
+        assert!(SignalingMessage::from_frame_body(&[OPCODE_AUDIO_DATA, 0], false).is_err());
+        assert!(SignalingMessage::from_frame_body(&[OPCODE_AUDIO_DATA_RECEIVED, 0, 0, 9, b'x'], false).is_err());
+        // Timestamp flag set but too few bytes for it
+        assert!(SignalingMessage::from_frame_body(&[OPCODE_AUDIO_DATA, AUDIO_FLAG_CAPTURED_AT, 0, 1, 2], false).is_err());
+    }
+
+    #[test]
+    fn test_audio_staleness() {
+        let audio = |captured_at| SignalingMessage::AudioData { data: vec![1], batched: false, codec: AudioCodec::Opus, captured_at };
+        let now = 1_000_000;
+        assert!(audio(Some(now - 201)).is_stale_audio(now, 200));
+        assert!(!audio(Some(now - 200)).is_stale_audio(now, 200));
+        // Clock skew putting the capture in the future isn't stale
+        assert!(!audio(Some(now + 50)).is_stale_audio(now, 200));
+        // Untimestamped frames and a disabled limit always pass
+        assert!(!audio(None).is_stale_audio(now, 200));
+        assert!(!audio(Some(0)).is_stale_audio(now, 0));
+        assert!(!SignalingMessage::LeaveRoom.is_stale_audio(now, 200));
+    }
+
+    #[test]
+    fn test_decode_frame_rejects_truncated_and_oversized() {
+        let framed = SignalingMessage::LeaveRoom.to_framed().unwrap();
+        for cut in 0..framed.len() {
+            assert!(SignalingMessage::decode_frame(&framed[..cut]).unwrap().is_none());
+        }
+        let (msg, used) = SignalingMessage::decode_frame(&framed).unwrap().unwrap();
+        assert!(matches!(msg, SignalingMessage::LeaveRoom));
+        assert_eq!(used, framed.len());
+
+        let oversized = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        assert!(matches!(
+            SignalingMessage::decode_frame(&oversized),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+
+        // A small compressed frame that inflates past the limit
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b' '; MAX_FRAME_LEN + 1]).unwrap();
+        let body = encoder.finish().unwrap();
+        assert!(body.len() < MAX_FRAME_LEN);
+        assert!(matches!(
+            SignalingMessage::from_frame_body(&body, true),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+
+        // Compressed frames are refused until compression is agreed
+        let framed = large_room_list().to_framed_compressed(true).unwrap();
+        let (len, compressed) = parse_frame_header(*framed.first_chunk::<4>().unwrap());
+        assert!(compressed);
+        let body = &framed[4..4 + len];
+        assert!(matches!(
+            SignalingMessage::from_frame_body_with(body, compressed, FrameOptions::default()),
+            Err(ProtocolError::CompressionNotNegotiated)
+        ));
+        let agreed = FrameOptions { compress: true, binary_audio: false };
+        assert!(SignalingMessage::from_frame_body_with(body, compressed, agreed).is_ok());
+    }
+
+    #[test]
+    fn test_validate_username() {
+        assert_eq!(validate_username("alice_b.2 -x", 32), Ok(()));
+        assert_eq!(validate_username(&"a".repeat(33), 32), Err(UsernameError::TooLong(32)));
+        assert_eq!(validate_username("bob\x1b[2J", 32), Err(UsernameError::InvalidCharacter('\x1b')));
+        assert_eq!(validate_username("  ", 32), Err(UsernameError::Empty));
+    }
+
+    #[test]
+    fn test_frame_mac_accepts_sealed_and_rejects_tampered() {
+        let key = [7u8; 32];
+        let mut sender = FrameAuthenticator::new(&key);
+        let mut receiver = FrameAuthenticator::new(&key);
+        let read = |frame: &[u8]| {
+            let (len, compressed) = parse_frame_header([frame[0], frame[1], frame[2], frame[3]]);
+            assert_eq!(len, frame.len() - 4);
+            (frame[4..].to_vec(), compressed)
+        };
+
+        // A sealed frame opens to the original body, compressed or not
+        let login = SignalingMessage::Login { username: "alice".to_string(), resume_token: None };
+        for frame in [login.to_framed().unwrap(), large_room_list().to_framed_compressed(true).unwrap()] {
+            let sealed = sender.seal(frame.clone());
+            let (body, compressed) = read(&sealed);
+            assert_eq!(receiver.open(&body, compressed).unwrap(), &frame[4..]);
+        }
+
+        // Flipping a body bit fails, as does a valid frame replayed later
+        let sealed = sender.seal(login.to_framed().unwrap());
+        let (mut body, compressed) = read(&sealed);
+        body[5] ^= 0x01;
+        assert!(matches!(receiver.open(&body, compressed), Err(ProtocolError::FrameAuthentication)));
+
+        let mut sender = FrameAuthenticator::new(&key);
+        let mut receiver = FrameAuthenticator::new(&key);
+        let (body, compressed) = read(&sender.seal(login.to_framed().unwrap()));
+        receiver.open(&body, compressed).unwrap();
+        assert!(receiver.open(&body, compressed).is_err());
+
+        // A different key never verifies
+        let mut stranger = FrameAuthenticator::new(&[8u8; 32]);
+        let (body, compressed) = read(&stranger.seal(login.to_framed().unwrap()));
+        assert!(FrameAuthenticator::new(&key).open(&body, compressed).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_features() {
+        let requested = vec!["compression".to_string(), "telepathy".to_string()];
+        assert_eq!(negotiate_features(&requested), vec!["compression".to_string()]);
+    }
+
+    #[test]
+    fn test_roster_deltas_match_full_resend() {
+        let info = |id: &str| ParticipantInfo {
+            id: id.to_string(),
+            username: format!("user-{}", id),
+            audio_enabled: true,
+            video_enabled: false,
+        };
+        let mut roster = Roster::default();
+        roster.reset("r1", 4, vec![info("a"), info("b")]);
+
+        let deltas = [
+            SignalingMessage::RoomRosterDelta { room_id: "r1".into(), version: 5, added: vec![info("c")], removed: vec![], media_changes: vec![] },
+            SignalingMessage::RoomRosterDelta { room_id: "r1".into(), version: 6, added: vec![], removed: vec!["a".into()], media_changes: vec![] },
+            SignalingMessage::RoomRosterDelta { room_id: "r1".into(), version: 7, added: vec![], removed: vec![], media_changes: vec![("c".into(), false, true)] },
+            SignalingMessage::RoomRosterDelta { room_id: "r1".into(), version: 8, added: vec![info("d")], removed: vec!["b".into()], media_changes: vec![] },
+        ];
+        for delta in deltas {
+            // Through the wire format, as a client would see them
+            let delta = SignalingMessage::from_bytes(&delta.to_bytes().unwrap()).unwrap();
+            let SignalingMessage::RoomRosterDelta { room_id, version, added, removed, media_changes } = delta else {
+                panic!("not a delta");
+            };
+            assert!(roster.apply_delta(&room_id, version, added, &removed, &media_changes));
+        }
+
+        let mut c = info("c");
+        (c.audio_enabled, c.video_enabled) = (false, true);
+        let mut full = Roster::default();
+        full.reset("r1", 8, vec![c, info("d")]);
+        assert_eq!(roster, full);
+
+        // A gap or another room's delta asks for a resync and changes nothing
+        let skipped = vec![info("e")];
+        assert!(!roster.apply_delta("r1", 10, skipped.clone(), &[], &[]));
+        assert!(!roster.apply_delta("r2", 9, skipped, &[], &[]));
+        assert_eq!(roster, full);
+    }
+
+    #[test]
+    fn test_clock_offset_from_exchange() {
+        // Server 5s ahead, 40ms each way
+        let offset = ClockOffset::from_exchange(1_000_000, 1_005_040, 1_000_080);
+        assert_eq!(offset, ClockOffset { offset_ms: 5_000, round_trip_ms: 80 });
+        assert_eq!(offset.to_server(1_000_100), 1_005_100);
+        assert_eq!(offset.to_local(1_005_100), 1_000_100);
+
+        // Server behind; an asymmetric path only skews it by up to half the round trip
+        let offset = ClockOffset::from_exchange(2_000, 1_010, 2_100);
+        assert!((offset.offset_ms - -1_000).abs() <= 50);
+    }
+
+    #[test]
+    fn test_preferred_codec() {
+        let features = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(preferred_codec(&features(&["codec_opus", "codec_pcm"])), AudioCodec::Opus);
+        assert_eq!(preferred_codec(&features(&["codec_pcm"])), AudioCodec::Pcm);
+        assert_eq!(preferred_codec(&features(&["codec_pcm16"])), AudioCodec::Pcm16);
+        assert_eq!(preferred_codec(&features(&["codec_pcm", "codec_pcm16"])), AudioCodec::Pcm16);
+        assert_eq!(preferred_codec(&features(&["codec_opus", "codec_pcm16"])), AudioCodec::Opus);
+        assert_eq!(preferred_codec(&features(&["compression"])), AudioCodec::Opus);
+
+        for codec in [AudioCodec::Opus, AudioCodec::Pcm, AudioCodec::Pcm16] {
+            assert_eq!(AudioCodec::from_wire(codec.to_wire()), Some(codec));
+        }
+        assert_eq!(AudioCodec::from_wire(9), None);
+    }
 }
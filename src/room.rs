@@ -1,11 +1,17 @@
 //! Room Management
 //!
 //! Handles chat room creation, joining, and participant management.
+//!
+//! A participant can be in several rooms at once, but only one is their
+//! *active* room: the one their audio goes to and that room-scoped
+//! commands act on. The others are spectated, receiving chat and presence.
 
+use crate::protocol::{AudioCodec, RoomLogEntry, RoomLogEvent};
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use uuid::Uuid;
 
 /// Represents a participant in a room
@@ -30,15 +36,87 @@ impl Participant {
     }
 }
 
+/// Number of chat messages kept per room for edits and deletes
+const MAX_CHAT_HISTORY: usize = 200;
+
+/// Number of entries kept in each room's event log
+const MAX_EVENT_LOG: usize = 200;
+
+/// A chat message stored in a room's history
+#[derive(Debug, Clone)]
+pub struct ChatRecord {
+    pub message_id: String,
+    pub sender_id: String,
+    pub sender_username: String,
+    pub content: String,
+    pub timestamp: u64,
+    pub edited: bool,
+}
+
+/// Name of the built-in audio loopback room
+pub const ECHO_TEST_ROOM_NAME: &str = "Echo Test";
+
+/// Longest room slug accepted, in characters
+pub const MAX_SLUG_LEN: usize = 32;
+
+/// Longest room topic, in characters
+pub const MAX_TOPIC_LEN: usize = 200;
+
+/// Check a room slug is 1-`MAX_SLUG_LEN` lowercase letters, digits and
+/// hyphens, not starting or ending with a hyphen
+pub fn validate_slug(slug: &str) -> Result<(), RoomError> {
+    let valid = !slug.is_empty()
+        && slug.len() <= MAX_SLUG_LEN
+        && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(RoomError::InvalidSlug)
+    }
+}
+
 /// Represents a chat room
 #[derive(Debug)]
 pub struct Room {
     pub id: String,
     pub name: String,
+    /// Short shareable alias for `id`, unique across the server
+    pub slug: Option<String>,
     pub created_at: SystemTime,
     pub max_participants: u32,
     pub is_locked: bool,
+    /// Participant that created the room, if created by a client
+    pub creator_id: Option<String>,
+    /// Username of the creator. The per-user room limit counts by this
+    /// rather than `creator_id`, which changes on every reconnect.
+    pub creator_name: Option<String>,
+    /// Only audio in this codec is relayed, when set at creation
+    pub required_codec: Option<AudioCodec>,
+    /// Short description shown in room lists
+    topic: RwLock<Option<String>>,
     participants: RwLock<HashMap<String, Participant>>,
+    /// Participants with a raised hand, in the order they raised it
+    hand_queue: RwLock<Vec<String>>,
+    /// Recent chat messages, oldest first
+    chat_history: RwLock<Vec<ChatRecord>>,
+    /// Post system chat messages on join/leave
+    announce_joins: AtomicBool,
+    /// Loop each participant's audio back to them instead of forwarding it
+    echo_mode: AtomicBool,
+    /// Queue joins to a full room instead of rejecting them
+    waiting_room: AtomicBool,
+    /// Participants waiting for a slot, first come first served
+    waitlist: RwLock<VecDeque<Participant>>,
+    /// Kept from being reaped until then, so a dropped member can rejoin
+    reconnect_hold: RwLock<Option<Instant>>,
+    /// Bumped on every membership or audio/video change, for roster deltas
+    roster_version: AtomicU64,
+    /// Unix seconds of the last chat message, join or leave; 0 for none
+    last_activity: AtomicU64,
+    /// Membership and moderation history, oldest first
+    event_log: RwLock<VecDeque<RoomLogEntry>>,
 }
 
 impl Room {
@@ -46,31 +124,77 @@ impl Room {
         Self {
             id: Uuid::new_v4().to_string(),
             name,
+            slug: None,
             created_at: SystemTime::now(),
             max_participants,
             is_locked: false,
+            creator_id: None,
+            creator_name: None,
+            required_codec: None,
+            topic: RwLock::new(None),
             participants: RwLock::new(HashMap::new()),
+            hand_queue: RwLock::new(Vec::new()),
+            chat_history: RwLock::new(Vec::new()),
+            announce_joins: AtomicBool::new(true),
+            echo_mode: AtomicBool::new(false),
+            waiting_room: AtomicBool::new(false),
+            waitlist: RwLock::new(VecDeque::new()),
+            reconnect_hold: RwLock::new(None),
+            roster_version: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+            event_log: RwLock::new(VecDeque::new()),
         }
     }
 
     /// Add a participant to the room
     pub fn add_participant(&self, participant: Participant) -> Result<(), RoomError> {
+        self.insert_participant(participant, true)
+    }
+
+    fn insert_participant(&self, participant: Participant, enforce_capacity: bool) -> Result<(), RoomError> {
         if self.is_locked {
             return Err(RoomError::RoomLocked);
         }
 
         let mut participants = self.participants.write();
-        if participants.len() >= self.max_participants as usize {
+        if enforce_capacity && participants.len() >= self.max_participants as usize {
             return Err(RoomError::RoomFull);
         }
 
         participants.insert(participant.id.clone(), participant);
+        self.roster_version.fetch_add(1, Ordering::SeqCst);
+        self.mark_activity(unix_secs());
         Ok(())
     }
 
+    /// Whether the room has reached `max_participants`
+    pub fn is_full(&self) -> bool {
+        self.participants.read().len() >= self.max_participants as usize
+    }
+
     /// Remove a participant from the room
     pub fn remove_participant(&self, participant_id: &str) -> Option<Participant> {
-        self.participants.write().remove(participant_id)
+        self.hand_queue.write().retain(|id| id != participant_id);
+        let removed = self.participants.write().remove(participant_id);
+        if removed.is_some() {
+            self.roster_version.fetch_add(1, Ordering::SeqCst);
+            self.mark_activity(unix_secs());
+        }
+        removed
+    }
+
+    /// Current roster version; changes with membership and audio/video state
+    pub fn roster_version(&self) -> u64 {
+        self.roster_version.load(Ordering::SeqCst)
+    }
+
+    /// When someone last chatted, joined or left (Unix seconds)
+    pub fn last_activity(&self) -> Option<u64> {
+        Some(self.last_activity.load(Ordering::SeqCst)).filter(|&t| t > 0)
+    }
+
+    fn mark_activity(&self, at: u64) {
+        self.last_activity.fetch_max(at, Ordering::SeqCst);
     }
 
     /// Get a participant by ID
@@ -83,6 +207,11 @@ impl Room {
         self.participants.read().keys().cloned().collect()
     }
 
+    /// Check whether a participant is in this room
+    pub fn has_participant(&self, participant_id: &str) -> bool {
+        self.participants.read().contains_key(participant_id)
+    }
+
     /// Get participant count
     pub fn participant_count(&self) -> usize {
         self.participants.read().len()
@@ -93,20 +222,254 @@ impl Room {
         self.participants.read().values().cloned().collect()
     }
 
+    /// Get all participants in join order (ties broken by id), for rosters
+    /// that should not reshuffle between refreshes
+    pub fn get_participants_ordered(&self) -> Vec<Participant> {
+        self.roster_snapshot().0
+    }
+
+    /// Participants in join order along with the roster version they make
+    /// up. Both are read under the roster lock, so a change can't land
+    /// between them.
+    pub fn roster_snapshot(&self) -> (Vec<Participant>, u64) {
+        let participants = self.participants.read();
+        let version = self.roster_version.load(Ordering::SeqCst);
+        let mut ordered: Vec<Participant> = participants.values().cloned().collect();
+        drop(participants);
+        ordered.sort_by(|a, b| a.joined_at.cmp(&b.joined_at).then_with(|| a.id.cmp(&b.id)));
+        (ordered, version)
+    }
+
     /// Update participant audio state
     pub fn set_participant_audio(&self, participant_id: &str, enabled: bool) -> bool {
         if let Some(p) = self.participants.write().get_mut(participant_id) {
             p.audio_enabled = enabled;
+            self.roster_version.fetch_add(1, Ordering::SeqCst);
             true
         } else {
             false
         }
     }
 
+    /// Raise a participant's hand, returning their 1-based queue position.
+    ///
+    /// Raising an already-raised hand keeps its place in the queue.
+    pub fn raise_hand(&self, participant_id: &str) -> Option<u32> {
+        if !self.has_participant(participant_id) {
+            return None;
+        }
+        let mut queue = self.hand_queue.write();
+        if let Some(index) = queue.iter().position(|id| id == participant_id) {
+            return Some(index as u32 + 1);
+        }
+        queue.push(participant_id.to_string());
+        Some(queue.len() as u32)
+    }
+
+    /// Lower a participant's hand, returning the position it held
+    pub fn lower_hand(&self, participant_id: &str) -> Option<u32> {
+        let mut queue = self.hand_queue.write();
+        let index = queue.iter().position(|id| id == participant_id)?;
+        queue.remove(index);
+        Some(index as u32 + 1)
+    }
+
+    /// Participants with raised hands, first raised first
+    pub fn raised_hands(&self) -> Vec<String> {
+        self.hand_queue.read().clone()
+    }
+
+    /// Store a chat message, assigning it a stable message ID
+    pub fn record_message(
+        &self,
+        sender_id: &str,
+        sender_username: &str,
+        content: String,
+        timestamp: u64,
+    ) -> ChatRecord {
+        let record = ChatRecord {
+            message_id: Uuid::new_v4().to_string(),
+            sender_id: sender_id.to_string(),
+            sender_username: sender_username.to_string(),
+            content,
+            timestamp,
+            edited: false,
+        };
+
+        self.mark_activity(timestamp);
+        let mut history = self.chat_history.write();
+        history.push(record.clone());
+        if history.len() > MAX_CHAT_HISTORY {
+            history.remove(0);
+        }
+        record
+    }
+
+    /// Replace a message's content; only its original sender may do this
+    pub fn edit_message(
+        &self,
+        message_id: &str,
+        sender_id: &str,
+        new_content: String,
+    ) -> Result<ChatRecord, RoomError> {
+        let mut history = self.chat_history.write();
+        let record = history
+            .iter_mut()
+            .find(|m| m.message_id == message_id)
+            .ok_or(RoomError::MessageNotFound)?;
+        if record.sender_id != sender_id {
+            return Err(RoomError::NotMessageAuthor);
+        }
+        record.content = new_content;
+        record.edited = true;
+        Ok(record.clone())
+    }
+
+    /// Remove a message; only its original sender may do this
+    pub fn delete_message(&self, message_id: &str, sender_id: &str) -> Result<(), RoomError> {
+        let mut history = self.chat_history.write();
+        let index = history
+            .iter()
+            .position(|m| m.message_id == message_id)
+            .ok_or(RoomError::MessageNotFound)?;
+        if history[index].sender_id != sender_id {
+            return Err(RoomError::NotMessageAuthor);
+        }
+        history.remove(index);
+        Ok(())
+    }
+
+    /// Recent chat messages, oldest first
+    pub fn chat_history(&self) -> Vec<ChatRecord> {
+        self.chat_history.read().clone()
+    }
+
+    /// Append to the event log, dropping the oldest entry once it's full
+    pub fn log_event(&self, actor_id: Option<&str>, event: RoomLogEvent) {
+        let mut log = self.event_log.write();
+        if log.len() >= MAX_EVENT_LOG {
+            log.pop_front();
+        }
+        log.push_back(RoomLogEntry { timestamp: unix_secs(), actor_id: actor_id.map(str::to_string), event });
+    }
+
+    /// The newest `limit` event log entries (all of them if `None`), oldest
+    /// first
+    pub fn event_log(&self, limit: Option<usize>) -> Vec<RoomLogEntry> {
+        let log = self.event_log.read();
+        let skip = limit.map_or(0, |limit| log.len().saturating_sub(limit));
+        log.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn topic(&self) -> Option<String> {
+        self.topic.read().clone()
+    }
+
+    /// Set or clear the topic; a blank topic clears it
+    pub fn set_topic(&self, topic: Option<String>) -> Result<(), RoomError> {
+        let topic = topic.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        if topic.as_ref().is_some_and(|t| t.chars().count() > MAX_TOPIC_LEN) {
+            return Err(RoomError::TopicTooLong);
+        }
+        *self.topic.write() = topic;
+        Ok(())
+    }
+
+    /// Whether joins and leaves are announced in chat
+    pub fn announces_joins(&self) -> bool {
+        self.announce_joins.load(Ordering::Relaxed)
+    }
+
+    pub fn set_announce_joins(&self, enabled: bool) {
+        self.announce_joins.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether audio is echoed back to its sender rather than forwarded
+    pub fn is_echo_mode(&self) -> bool {
+        self.echo_mode.load(Ordering::Relaxed)
+    }
+
+    pub fn set_echo_mode(&self, enabled: bool) {
+        self.echo_mode.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether joins to a full room are queued rather than rejected
+    pub fn has_waiting_room(&self) -> bool {
+        self.waiting_room.load(Ordering::Relaxed)
+    }
+
+    /// Turning the waiting room off stops new queueing; anyone already
+    /// waiting keeps their place.
+    pub fn set_waiting_room(&self, enabled: bool) {
+        self.waiting_room.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Queue a participant for a slot, returning their 1-based position.
+    ///
+    /// Queueing again keeps the original place.
+    pub fn enqueue_waiter(&self, participant: Participant) -> u32 {
+        let mut waitlist = self.waitlist.write();
+        if let Some(index) = waitlist.iter().position(|p| p.id == participant.id) {
+            return index as u32 + 1;
+        }
+        waitlist.push_back(participant);
+        waitlist.len() as u32
+    }
+
+    /// A waiter's 1-based position in the queue
+    pub fn waiting_position(&self, participant_id: &str) -> Option<u32> {
+        self.waitlist
+            .read()
+            .iter()
+            .position(|p| p.id == participant_id)
+            .map(|index| index as u32 + 1)
+    }
+
+    /// Take a participant out of the queue
+    pub fn remove_waiter(&self, participant_id: &str) -> Option<Participant> {
+        let mut waitlist = self.waitlist.write();
+        let index = waitlist.iter().position(|p| p.id == participant_id)?;
+        waitlist.remove(index)
+    }
+
+    /// Take the longest-waiting participant out of the queue
+    pub fn pop_waiter(&self) -> Option<Participant> {
+        self.waitlist.write().pop_front()
+    }
+
+    /// Empty the queue, returning who was in it, first in line first
+    pub fn drain_waiters(&self) -> Vec<String> {
+        self.waitlist.write().drain(..).map(|p| p.id).collect()
+    }
+
+    /// IDs of waiting participants, first in line first
+    pub fn waiting_ids(&self) -> Vec<String> {
+        self.waitlist.read().iter().map(|p| p.id.clone()).collect()
+    }
+
+    /// Keep the room alive until at least `until` for a member who dropped
+    pub fn hold_for_reconnect(&self, until: Instant) {
+        let mut hold = self.reconnect_hold.write();
+        if hold.map_or(true, |current| current < until) {
+            *hold = Some(until);
+        }
+    }
+
+    /// Whether a dropped member's reconnect grace is still running
+    pub fn is_held(&self, now: Instant) -> bool {
+        self.reconnect_hold.read().is_some_and(|until| now < until)
+    }
+
+    /// Whether `participant_id` may change this room's settings
+    pub fn is_owner(&self, participant_id: &str) -> bool {
+        self.creator_id.as_deref() == Some(participant_id)
+    }
+
     /// Update participant video state
     pub fn set_participant_video(&self, participant_id: &str, enabled: bool) -> bool {
         if let Some(p) = self.participants.write().get_mut(participant_id) {
             p.video_enabled = enabled;
+            self.roster_version.fetch_add(1, Ordering::SeqCst);
             true
         } else {
             false
@@ -127,13 +490,40 @@ pub enum RoomError {
     ParticipantNotFound,
     #[error("Already in a room")]
     AlreadyInRoom,
+    #[error("Server room limit reached")]
+    ServerRoomLimitReached,
+    #[error("Per-user room limit reached")]
+    UserRoomLimitReached,
+    #[error("Message not found")]
+    MessageNotFound,
+    #[error("Only the original sender can change this message")]
+    NotMessageAuthor,
+    #[error("Only the room owner can do this")]
+    NotRoomOwner,
+    #[error("Several rooms share that name")]
+    AmbiguousRoomName,
+    #[error("Room slugs must be 1-32 lowercase letters, digits or inner hyphens")]
+    InvalidSlug,
+    #[error("That room slug is already taken")]
+    SlugTaken,
+    #[error("Room topics are limited to {} characters", MAX_TOPIC_LEN)]
+    TopicTooLong,
+}
+
+/// Caps on room creation; `0` means unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoomLimits {
+    pub max_rooms: usize,
+    pub max_rooms_per_user: usize,
 }
 
 /// Manages all chat rooms
 pub struct RoomManager {
     rooms: RwLock<HashMap<String, Arc<Room>>>,
-    /// Maps participant ID to room ID
-    participant_rooms: RwLock<HashMap<String, String>>,
+    /// Maps participant ID to every room they're in
+    participant_rooms: RwLock<HashMap<String, HashSet<String>>>,
+    /// Maps participant ID to their active room
+    active_rooms: RwLock<HashMap<String, String>>,
 }
 
 impl RoomManager {
@@ -141,9 +531,18 @@ impl RoomManager {
         Self {
             rooms: RwLock::new(HashMap::new()),
             participant_rooms: RwLock::new(HashMap::new()),
+            active_rooms: RwLock::new(HashMap::new()),
         }
     }
 
+    /// Create the diagnostic room that plays each participant's audio back
+    /// to them
+    pub fn create_echo_test_room(&self, max_participants: u32) -> Arc<Room> {
+        let room = self.create_room(ECHO_TEST_ROOM_NAME.to_string(), max_participants);
+        room.set_echo_mode(true);
+        room
+    }
+
     /// Create a new room
     pub fn create_room(&self, name: String, max_participants: u32) -> Arc<Room> {
         let room = Arc::new(Room::new(name, max_participants));
@@ -152,6 +551,67 @@ impl RoomManager {
         room
     }
 
+    /// Create a room on behalf of a participant, enforcing `limits`
+    pub fn create_room_for(
+        &self,
+        creator_id: &str,
+        name: String,
+        max_participants: u32,
+        limits: RoomLimits,
+    ) -> Result<Arc<Room>, RoomError> {
+        self.create_room_with_slug(creator_id, creator_id, name, max_participants, None, None, limits)
+    }
+
+    /// Like `create_room_for`, also reserving `slug` as a joinable alias and
+    /// counting the room against `creator_name`'s limit
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_room_with_slug(
+        &self,
+        creator_id: &str,
+        creator_name: &str,
+        name: String,
+        max_participants: u32,
+        slug: Option<String>,
+        required_codec: Option<AudioCodec>,
+        limits: RoomLimits,
+    ) -> Result<Arc<Room>, RoomError> {
+        if let Some(slug) = &slug {
+            validate_slug(slug)?;
+        }
+        let mut rooms = self.rooms.write();
+
+        // Slugs are too short to ever equal a UUID, so only other slugs clash
+        if let Some(slug) = &slug {
+            if rooms.values().any(|r| r.slug.as_ref() == Some(slug)) {
+                return Err(RoomError::SlugTaken);
+            }
+        }
+
+        if limits.max_rooms > 0 && rooms.len() >= limits.max_rooms {
+            return Err(RoomError::ServerRoomLimitReached);
+        }
+
+        if limits.max_rooms_per_user > 0 {
+            let owned = rooms
+                .values()
+                .filter(|r| r.creator_name.as_deref() == Some(creator_name))
+                .count();
+            if owned >= limits.max_rooms_per_user {
+                return Err(RoomError::UserRoomLimitReached);
+            }
+        }
+
+        let mut room = Room::new(name, max_participants);
+        room.creator_id = Some(creator_id.to_string());
+        room.creator_name = Some(creator_name.to_string());
+        room.slug = slug;
+        room.required_codec = required_codec;
+        let room = Arc::new(room);
+        rooms.insert(room.id.clone(), room.clone());
+        log::info!("Created room: {} ({}) for {}", room.name, room.id, creator_id);
+        Ok(room)
+    }
+
     /// Get a room by ID
     pub fn get_room(&self, room_id: &str) -> Option<Arc<Room>> {
         self.rooms.read().get(room_id).cloned()
@@ -162,62 +622,287 @@ impl RoomManager {
         self.rooms.read().values().find(|r| r.name == name).cloned()
     }
 
-    /// List all rooms
+    /// Get a room by slug
+    pub fn get_room_by_slug(&self, slug: &str) -> Option<Arc<Room>> {
+        self.rooms.read().values().find(|r| r.slug.as_deref() == Some(slug)).cloned()
+    }
+
+    /// Find a room by ID, then exact name, then slug. Names come before
+    /// slugs so a slug can't hijack references to a room that was already
+    /// known by that name.
+    pub fn resolve_room(&self, id_or_name: &str) -> Result<Arc<Room>, RoomError> {
+        if let Some(room) = self.get_room(id_or_name) {
+            return Ok(room);
+        }
+        {
+            let rooms = self.rooms.read();
+            let mut matches = rooms.values().filter(|r| r.name == id_or_name);
+            match (matches.next(), matches.next()) {
+                (Some(room), None) => return Ok(room.clone()),
+                (Some(_), Some(_)) => return Err(RoomError::AmbiguousRoomName),
+                (None, _) => {}
+            }
+        }
+        self.get_room_by_slug(id_or_name).ok_or(RoomError::RoomNotFound)
+    }
+
+    /// List all rooms, oldest first so paged listings stay stable
     pub fn list_rooms(&self) -> Vec<Arc<Room>> {
-        self.rooms.read().values().cloned().collect()
+        let mut rooms: Vec<Arc<Room>> = self.rooms.read().values().cloned().collect();
+        rooms.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        rooms
     }
 
-    /// Join a room
+    /// Join a room and make it the participant's active room.
+    ///
+    /// The previous active room is left; spectated rooms are kept, and a
+    /// spectated room being joined is simply promoted to active.
     pub fn join_room(
         &self,
         room_id: &str,
         participant: Participant,
     ) -> Result<Arc<Room>, RoomError> {
-        // Check if already in a room
-        if self.participant_rooms.read().contains_key(&participant.id) {
+        self.join_room_with(room_id, participant, true)
+    }
+
+    /// Let a waiter into the room on the owner's say-so, even if it is full
+    pub fn admit_waiter(&self, room_id: &str, participant_id: &str) -> Result<(Arc<Room>, Participant), RoomError> {
+        let room = self.get_room(room_id).ok_or(RoomError::RoomNotFound)?;
+        let waiter = room.remove_waiter(participant_id).ok_or(RoomError::ParticipantNotFound)?;
+        let room = self.join_room_with(room_id, waiter.clone(), false)?;
+        Ok((room, waiter))
+    }
+
+    /// Move a participant from their active room into another, returning
+    /// the room they left. `enforce_capacity` is off for a forced move.
+    pub fn move_participant(
+        &self,
+        participant_id: &str,
+        target_room_id: &str,
+        enforce_capacity: bool,
+    ) -> Result<(Arc<Room>, Arc<Room>), RoomError> {
+        let from = self.get_participant_room(participant_id).ok_or(RoomError::ParticipantNotFound)?;
+        if from.id == target_room_id {
+            return Err(RoomError::AlreadyInRoom);
+        }
+        let participant = from.get_participant(participant_id).ok_or(RoomError::ParticipantNotFound)?;
+        let to = self.join_room_with(target_room_id, participant, enforce_capacity)?;
+        Ok((from, to))
+    }
+
+    /// Drop a participant from every waiting room, returning the rooms whose
+    /// queue changed
+    pub fn cancel_waiting(&self, participant_id: &str) -> Vec<Arc<Room>> {
+        self.list_rooms()
+            .into_iter()
+            .filter(|room| room.remove_waiter(participant_id).is_some())
+            .collect()
+    }
+
+    fn join_room_with(
+        &self,
+        room_id: &str,
+        participant: Participant,
+        enforce_capacity: bool,
+    ) -> Result<Arc<Room>, RoomError> {
+        let room = self.get_room(room_id).ok_or(RoomError::RoomNotFound)?;
+        let previous = self.active_rooms.read().get(&participant.id).cloned();
+        if previous.as_deref() == Some(room_id) {
+            return Ok(room);
+        }
+
+        if !room.has_participant(&participant.id) {
+            room.insert_participant(participant.clone(), enforce_capacity)?;
+            self.participant_rooms
+                .write()
+                .entry(participant.id.clone())
+                .or_default()
+                .insert(room_id.to_string());
+        }
+        if previous.is_some() {
             self.leave_room(&participant.id)?;
         }
+        self.active_rooms
+            .write()
+            .insert(participant.id.clone(), room_id.to_string());
+
+        log::info!("Participant {} joined room {}", participant.username, room.name);
+        Ok(room)
+    }
 
+    /// Join a room as a spectator, keeping the current active room
+    pub fn spectate_room(
+        &self,
+        room_id: &str,
+        participant: Participant,
+    ) -> Result<Arc<Room>, RoomError> {
         let room = self.get_room(room_id).ok_or(RoomError::RoomNotFound)?;
+        if room.has_participant(&participant.id) {
+            return Err(RoomError::AlreadyInRoom);
+        }
+
         room.add_participant(participant.clone())?;
         self.participant_rooms
             .write()
-            .insert(participant.id.clone(), room_id.to_string());
-        
-        log::info!("Participant {} joined room {}", participant.username, room.name);
+            .entry(participant.id.clone())
+            .or_default()
+            .insert(room_id.to_string());
+
+        log::info!("Participant {} is spectating room {}", participant.username, room.name);
         Ok(room)
     }
 
-    /// Leave current room
+    /// Leave the active room; spectated rooms are kept
     pub fn leave_room(&self, participant_id: &str) -> Result<(), RoomError> {
         let room_id = self
-            .participant_rooms
+            .active_rooms
             .write()
             .remove(participant_id)
             .ok_or(RoomError::ParticipantNotFound)?;
+        self.remove_membership(participant_id, &room_id);
+        Ok(())
+    }
+
+    /// Stop spectating a room. The active room can only be left with
+    /// `leave_room`.
+    pub fn stop_spectating(&self, participant_id: &str, room_id: &str) -> Result<(), RoomError> {
+        if !self.is_spectating(participant_id, room_id) {
+            return Err(RoomError::ParticipantNotFound);
+        }
+        self.remove_membership(participant_id, room_id);
+        Ok(())
+    }
 
-        if let Some(room) = self.get_room(&room_id) {
+    /// Leave every room, returning the rooms that were left
+    pub fn leave_all_rooms(&self, participant_id: &str) -> Vec<Arc<Room>> {
+        let rooms = self.get_participant_rooms(participant_id);
+        self.active_rooms.write().remove(participant_id);
+        for room in &rooms {
+            self.remove_membership(participant_id, &room.id);
+        }
+        rooms
+    }
+
+    /// Remove everyone from a room, spectators included, keeping the room
+    /// itself. Returns who was removed.
+    pub fn clear_room(&self, room_id: &str) -> Result<Vec<Participant>, RoomError> {
+        let room = self.get_room(room_id).ok_or(RoomError::RoomNotFound)?;
+        let removed = room.get_participants();
+        for participant in &removed {
+            if self.is_active_in(&participant.id, room_id) {
+                self.active_rooms.write().remove(&participant.id);
+            }
+            self.remove_membership(&participant.id, room_id);
+        }
+        Ok(removed)
+    }
+
+    fn remove_membership(&self, participant_id: &str, room_id: &str) {
+        {
+            let mut memberships = self.participant_rooms.write();
+            if let Some(room_ids) = memberships.get_mut(participant_id) {
+                room_ids.remove(room_id);
+                if room_ids.is_empty() {
+                    memberships.remove(participant_id);
+                }
+            }
+        }
+        if let Some(room) = self.get_room(room_id) {
             room.remove_participant(participant_id);
             log::info!("Participant {} left room {}", participant_id, room.name);
         }
-
-        Ok(())
     }
 
-    /// Get the room a participant is in
+    /// Get the participant's active room
     pub fn get_participant_room(&self, participant_id: &str) -> Option<Arc<Room>> {
-        let room_id = self.participant_rooms.read().get(participant_id).cloned()?;
+        let room_id = self.active_rooms.read().get(participant_id).cloned()?;
         self.get_room(&room_id)
     }
 
+    /// Every room the participant is in, active room first
+    pub fn get_participant_rooms(&self, participant_id: &str) -> Vec<Arc<Room>> {
+        let active = self.active_rooms.read().get(participant_id).cloned();
+        let room_ids: Vec<String> = self
+            .participant_rooms
+            .read()
+            .get(participant_id)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default();
+        let mut rooms: Vec<Arc<Room>> = room_ids.iter().filter_map(|id| self.get_room(id)).collect();
+        rooms.sort_by_key(|room| (Some(&room.id) != active.as_ref(), room.created_at, room.id.clone()));
+        rooms
+    }
+
+    /// Whether `room_id` is the participant's active room
+    pub fn is_active_in(&self, participant_id: &str, room_id: &str) -> bool {
+        self.active_rooms.read().get(participant_id).map(String::as_str) == Some(room_id)
+    }
+
+    /// Whether the participant is in `room_id` without it being active
+    pub fn is_spectating(&self, participant_id: &str, room_id: &str) -> bool {
+        let member = self
+            .participant_rooms
+            .read()
+            .get(participant_id)
+            .is_some_and(|ids| ids.contains(room_id));
+        member && !self.is_active_in(participant_id, room_id)
+    }
+
+    /// Filter `candidate_ids` down to members of the sender's room.
+    ///
+    /// The sender and duplicates are dropped, as is anyone in a different
+    /// room. Returns an empty list if the sender is not in a room.
+    pub fn co_members(&self, sender_id: &str, candidate_ids: &[String]) -> Vec<String> {
+        let room = match self.get_participant_room(sender_id) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+
+        let mut members: Vec<String> = Vec::new();
+        for id in candidate_ids {
+            if id != sender_id && room.has_participant(id) && !members.contains(id) {
+                members.push(id.clone());
+            }
+        }
+        members
+    }
+
+    /// Delete client-created rooms left with nobody in or waiting for them,
+    /// unless a dropped member's reconnect grace is still running. Returns
+    /// the deleted rooms.
+    pub fn reap_empty_rooms(&self, now: Instant) -> Vec<Arc<Room>> {
+        let empty: Vec<Arc<Room>> = self
+            .rooms
+            .read()
+            .values()
+            .filter(|room| {
+                room.creator_id.is_some()
+                    && room.participant_count() == 0
+                    && room.waitlist.read().is_empty()
+                    && !room.is_held(now)
+            })
+            .cloned()
+            .collect();
+        empty.into_iter().filter(|room| self.delete_room(&room.id)).collect()
+    }
+
     /// Delete a room
     pub fn delete_room(&self, room_id: &str) -> bool {
         if let Some(room) = self.rooms.write().remove(room_id) {
             // Remove all participants from mapping
             let participant_ids = room.get_participant_ids();
             let mut pr = self.participant_rooms.write();
+            let mut active = self.active_rooms.write();
             for pid in participant_ids {
-                pr.remove(&pid);
+                if let Some(room_ids) = pr.get_mut(&pid) {
+                    room_ids.remove(room_id);
+                    if room_ids.is_empty() {
+                        pr.remove(&pid);
+                    }
+                }
+                if active.get(&pid).map(String::as_str) == Some(room_id) {
+                    active.remove(&pid);
+                }
             }
             log::info!("Deleted room: {} ({})", room.name, room.id);
             true
@@ -233,6 +918,14 @@ impl Default for RoomManager {
     }
 }
 
+/// Current time in Unix seconds, as used for chat timestamps
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +962,45 @@ mod tests {
         assert!(matches!(result, Err(RoomError::RoomFull)));
     }
 
+    #[test]
+    fn test_chat_moves_last_activity_past_join() {
+        let room = Room::new("Test Room".to_string(), 10);
+        room.add_participant(Participant::new("p1".to_string(), "User1".to_string())).unwrap();
+        // Backdate the join so only the chat can account for a recent time
+        let joined_at = unix_secs() - 600;
+        room.last_activity.store(joined_at, Ordering::SeqCst);
+
+        room.record_message("p1", "User1", "anyone here?".to_string(), joined_at + 300);
+        assert_eq!(room.last_activity(), Some(joined_at + 300));
+
+        // An older message doesn't move it back
+        room.record_message("p1", "User1", "late".to_string(), joined_at + 100);
+        assert_eq!(room.last_activity(), Some(joined_at + 300));
+    }
+
+    #[test]
+    fn test_waitlist_order_and_admission() {
+        let manager = RoomManager::new();
+        let room = manager.create_room("Test Room".to_string(), 1);
+        let p = |id: &str| Participant::new(id.to_string(), id.to_string());
+        manager.join_room(&room.id, p("p1")).unwrap();
+
+        assert_eq!(room.enqueue_waiter(p("p2")), 1);
+        assert_eq!(room.enqueue_waiter(p("p3")), 2);
+        assert_eq!(room.enqueue_waiter(p("p2")), 1);
+        assert_eq!(room.waiting_position("p3"), Some(2));
+
+        // The owner can let p3 in past both the queue and the capacity
+        manager.admit_waiter(&room.id, "p3").unwrap();
+        assert_eq!(room.participant_count(), 2);
+        assert!(manager.is_active_in("p3", &room.id));
+        assert_eq!(room.waiting_ids(), vec!["p2".to_string()]);
+        assert!(matches!(manager.admit_waiter(&room.id, "p3"), Err(RoomError::ParticipantNotFound)));
+
+        assert_eq!(manager.cancel_waiting("p2").len(), 1);
+        assert!(room.pop_waiter().is_none());
+    }
+
     #[test]
     fn test_room_manager() {
         let manager = RoomManager::new();
@@ -286,4 +1018,216 @@ mod tests {
         manager.leave_room("p1").unwrap();
         assert!(manager.get_participant_room("p1").is_none());
     }
+
+    #[test]
+    fn test_spectating_keeps_active_room() {
+        let manager = RoomManager::new();
+        let work = manager.create_room("Work".to_string(), 10);
+        let lobby = manager.create_room("Lobby".to_string(), 10);
+        let p1 = || Participant::new("p1".to_string(), "User1".to_string());
+
+        manager.join_room(&work.id, p1()).unwrap();
+        manager.spectate_room(&lobby.id, p1()).unwrap();
+        assert!(matches!(manager.spectate_room(&lobby.id, p1()), Err(RoomError::AlreadyInRoom)));
+        assert!(work.has_participant("p1") && lobby.has_participant("p1"));
+        assert_eq!(manager.get_participant_room("p1").unwrap().id, work.id);
+        assert!(manager.is_spectating("p1", &lobby.id));
+        let rooms: Vec<String> = manager.get_participant_rooms("p1").iter().map(|r| r.id.clone()).collect();
+        assert_eq!(rooms, vec![work.id.clone(), lobby.id.clone()]);
+
+        // Joining the spectated room promotes it and leaves the old active room
+        manager.join_room(&lobby.id, p1()).unwrap();
+        assert!(manager.is_active_in("p1", &lobby.id));
+        assert!(!work.has_participant("p1"));
+        assert!(manager.stop_spectating("p1", &lobby.id).is_err());
+
+        manager.spectate_room(&work.id, p1()).unwrap();
+        let left = manager.leave_all_rooms("p1");
+        assert_eq!(left.len(), 2);
+        assert!(manager.get_participant_rooms("p1").is_empty());
+        assert_eq!(work.participant_count() + lobby.participant_count(), 0);
+    }
+
+    #[test]
+    fn test_clear_room_removes_everyone_but_keeps_room() {
+        let manager = RoomManager::new();
+        let room = manager.create_room("Test".to_string(), 10);
+        let lobby = manager.create_room("Lobby".to_string(), 10);
+
+        manager.join_room(&room.id, Participant::new("p1".to_string(), "alice".to_string())).unwrap();
+        manager.join_room(&lobby.id, Participant::new("p2".to_string(), "bob".to_string())).unwrap();
+        manager.spectate_room(&room.id, Participant::new("p2".to_string(), "bob".to_string())).unwrap();
+
+        let removed = manager.clear_room(&room.id).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(room.participant_count(), 0);
+        assert!(manager.get_room(&room.id).is_some());
+        assert!(manager.get_participant_room("p1").is_none());
+        // Bob only lost the room he was spectating
+        assert!(manager.is_active_in("p2", &lobby.id));
+        assert!(!manager.is_spectating("p2", &room.id));
+
+        assert!(matches!(manager.clear_room("missing"), Err(RoomError::RoomNotFound)));
+    }
+
+    #[test]
+    fn test_co_members_filters_to_senders_room() {
+        let manager = RoomManager::new();
+        let room_a = manager.create_room("A".to_string(), 10);
+        let room_b = manager.create_room("B".to_string(), 10);
+
+        for (id, room) in [("p1", &room_a), ("p2", &room_a), ("p3", &room_a), ("p4", &room_b)] {
+            let participant = Participant::new(id.to_string(), id.to_string());
+            manager.join_room(&room.id, participant).unwrap();
+        }
+
+        let requested = vec![
+            "p2".to_string(),
+            "p4".to_string(),
+            "p2".to_string(),
+            "p1".to_string(),
+            "ghost".to_string(),
+        ];
+        // Only p2 is a listed co-member; p3 is not listed, p4 is in another room
+        assert_eq!(manager.co_members("p1", &requested), vec!["p2".to_string()]);
+
+        // A sender outside any room reaches no one
+        assert!(manager.co_members("nobody", &requested).is_empty());
+    }
+
+    #[test]
+    fn test_room_creation_limits() {
+        let manager = RoomManager::new();
+        let limits = RoomLimits {
+            max_rooms: 3,
+            max_rooms_per_user: 2,
+        };
+
+        manager.create_room_for("alice", "A1".to_string(), 10, limits).unwrap();
+        manager.create_room_for("alice", "A2".to_string(), 10, limits).unwrap();
+        let result = manager.create_room_for("alice", "A3".to_string(), 10, limits);
+        assert!(matches!(result, Err(RoomError::UserRoomLimitReached)));
+
+        manager.create_room_for("bob", "B1".to_string(), 10, limits).unwrap();
+        let result = manager.create_room_for("carol", "C1".to_string(), 10, limits);
+        assert!(matches!(result, Err(RoomError::ServerRoomLimitReached)));
+    }
+
+    #[test]
+    fn test_validate_slug() {
+        for slug in ["standup", "team-42", "a"] {
+            assert!(validate_slug(slug).is_ok(), "{} should be valid", slug);
+        }
+        let too_long = "a".repeat(MAX_SLUG_LEN + 1);
+        for slug in ["", "Standup", "stand up", "-standup", "standup-", "stand_up", "café", too_long.as_str()] {
+            assert!(matches!(validate_slug(slug), Err(RoomError::InvalidSlug)), "{:?} should be invalid", slug);
+        }
+    }
+
+    #[test]
+    fn test_slugs_are_unique_and_resolve() {
+        let manager = RoomManager::new();
+        let limits = RoomLimits::default();
+        let room = manager
+            .create_room_with_slug("alice", "alice", "Daily Standup".to_string(), 10, Some("standup".to_string()), None, limits)
+            .unwrap();
+        assert_eq!(room.slug.as_deref(), Some("standup"));
+
+        let taken = manager.create_room_with_slug("bob", "bob", "Other".to_string(), 10, Some("standup".to_string()), None, limits);
+        assert!(matches!(taken, Err(RoomError::SlugTaken)));
+        // Room IDs are never valid slugs, so they can't be shadowed
+        assert!(validate_slug(&room.id).is_err());
+
+        assert_eq!(manager.resolve_room("standup").unwrap().id, room.id);
+        assert_eq!(manager.resolve_room(&room.id).unwrap().id, room.id);
+
+        // A room named like another room's slug keeps that name
+        let named = manager.create_room("standup".to_string(), 10);
+        assert_eq!(manager.resolve_room("standup").unwrap().id, named.id);
+        assert_eq!(manager.resolve_room("Daily Standup").unwrap().id, room.id);
+    }
+
+    #[test]
+    fn test_participants_ordered_by_join_time() {
+        let room = Room::new("Test Room".to_string(), 50);
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+        // Insert latest-joined first so map order can't accidentally match
+        for i in (0..20u64).rev() {
+            let mut participant = Participant::new(format!("user{:02}", i), format!("User {}", i));
+            participant.joined_at = start + std::time::Duration::from_secs(i / 2);
+            room.add_participant(participant).unwrap();
+        }
+
+        let ids: Vec<String> = room.get_participants_ordered().into_iter().map(|p| p.id).collect();
+        let expected: Vec<String> = (0..20).map(|i| format!("user{:02}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_raise_hand_ordering() {
+        let room = Room::new("Test Room".to_string(), 10);
+        for id in ["p1", "p2", "p3"] {
+            room.add_participant(Participant::new(id.to_string(), id.to_string())).unwrap();
+        }
+
+        assert_eq!(room.raise_hand("p2"), Some(1));
+        assert_eq!(room.raise_hand("p1"), Some(2));
+        // Raising again keeps the original place
+        assert_eq!(room.raise_hand("p2"), Some(1));
+        // Non-members can't queue
+        assert_eq!(room.raise_hand("ghost"), None);
+    }
+
+    #[test]
+    fn test_lower_hand_reorders_queue() {
+        let room = Room::new("Test Room".to_string(), 10);
+        for id in ["p1", "p2", "p3"] {
+            room.add_participant(Participant::new(id.to_string(), id.to_string())).unwrap();
+            room.raise_hand(id);
+        }
+
+        assert_eq!(room.lower_hand("p1"), Some(1));
+        assert_eq!(room.lower_hand("p1"), None);
+        assert_eq!(room.raised_hands(), vec!["p2".to_string(), "p3".to_string()]);
+
+        // Leaving the room also drops the hand
+        room.remove_participant("p2");
+        assert_eq!(room.raised_hands(), vec!["p3".to_string()]);
+        assert_eq!(room.raise_hand("p3"), Some(1));
+    }
+
+    #[test]
+    fn test_delete_message_requires_author() {
+        let room = Room::new("Test Room".to_string(), 10);
+        let record = room.record_message("p1", "User1", "oops".to_string(), 0);
+
+        let result = room.delete_message(&record.message_id, "p2");
+        assert!(matches!(result, Err(RoomError::NotMessageAuthor)));
+        assert_eq!(room.chat_history().len(), 1);
+
+        room.delete_message(&record.message_id, "p1").unwrap();
+        assert!(room.chat_history().is_empty());
+        let result = room.delete_message(&record.message_id, "p1");
+        assert!(matches!(result, Err(RoomError::MessageNotFound)));
+    }
+
+    #[test]
+    fn test_reap_keeps_occupied_held_and_server_rooms() {
+        let manager = RoomManager::new();
+        let fixed = manager.create_room("Lobby".to_string(), 10);
+        let occupied = manager.create_room_for("p1", "Busy".to_string(), 10, RoomLimits::default()).unwrap();
+        manager.join_room(&occupied.id, Participant::new("p1".to_string(), "alice".to_string())).unwrap();
+        let held = manager.create_room_for("p2", "Held".to_string(), 10, RoomLimits::default()).unwrap();
+        let now = Instant::now();
+        held.hold_for_reconnect(now + std::time::Duration::from_secs(5));
+        let empty = manager.create_room_for("p3", "Empty".to_string(), 10, RoomLimits::default()).unwrap();
+
+        let reaped = manager.reap_empty_rooms(now);
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].id, empty.id);
+        for room in [&fixed, &occupied, &held] {
+            assert!(manager.get_room(&room.id).is_some());
+        }
+    }
 }
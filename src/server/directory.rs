@@ -0,0 +1,57 @@
+//! Reverse index from participant to their active room and media flags
+//!
+//! `ListServerUsers` used to look up every user's room twice through the
+//! `RoomManager`. The directory keeps the answer per participant instead,
+//! refreshed wherever membership or media flags change, so a listing reads
+//! one map under one lock.
+
+use parking_lot::{RwLock, RwLockReadGuard};
+use pqc_chat::room::RoomManager;
+use std::collections::HashMap;
+
+/// Where a participant is and what they're sending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub room_name: String,
+    pub audio_enabled: bool,
+    pub video_enabled: bool,
+}
+
+impl DirectoryEntry {
+    /// Look the entry up in the rooms directly; `None` for lobby users
+    pub fn scan(rooms: &RoomManager, participant_id: &str) -> Option<Self> {
+        let room = rooms.get_participant_room(participant_id)?;
+        let participant = room.get_participant(participant_id)?;
+        Some(Self {
+            room_name: room.name.clone(),
+            audio_enabled: participant.audio_enabled,
+            video_enabled: participant.video_enabled,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct ParticipantDirectory {
+    entries: RwLock<HashMap<String, DirectoryEntry>>,
+}
+
+impl ParticipantDirectory {
+    /// Re-read a participant's entry after their room or media changed
+    pub fn refresh(&self, rooms: &RoomManager, participant_id: &str) {
+        match DirectoryEntry::scan(rooms, participant_id) {
+            Some(entry) => {
+                self.entries.write().insert(participant_id.to_string(), entry);
+            }
+            None => self.forget(participant_id),
+        }
+    }
+
+    pub fn forget(&self, participant_id: &str) {
+        self.entries.write().remove(participant_id);
+    }
+
+    /// Every entry, under a single read lock
+    pub fn entries(&self) -> RwLockReadGuard<'_, HashMap<String, DirectoryEntry>> {
+        self.entries.read()
+    }
+}
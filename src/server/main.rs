@@ -4,23 +4,47 @@
 
 use anyhow::Result;
 use clap::Parser;
-use log::{error, info};
-use parking_lot::RwLock;
-use std::collections::HashMap;
+use log::{debug, error, info, warn};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio_rustls::rustls::{self, pki_types::PrivateKeyDer};
 use tokio_rustls::TlsAcceptor;
 use uuid::Uuid;
 
-use pqc_chat::crypto::kyber::KyberKeyExchange;
-use pqc_chat::media::MediaForwarder;
-use pqc_chat::protocol::{ParticipantInfo, RoomInfo, ServerUserInfo, SignalingMessage};
-use pqc_chat::room::{Participant, RoomManager};
+mod directory;
+mod mixing;
+mod motd;
+mod outbound;
+mod rate_limit;
+mod signaling_log;
+mod silence;
+
+use directory::ParticipantDirectory;
+use mixing::{RoomMix, RoomMixHandle};
+use outbound::OutboundSender;
+use rate_limit::TokenBucket;
+use signaling_log::{Direction, SignalingLog};
+use silence::SilenceGate;
+use motd::Motd;
+
+use pqc_chat::audio_codec::Transcoder;
+use pqc_chat::crypto::kyber::{KyberKeyExchange, KyberSession, SessionRole};
+use pqc_chat::crypto::tls::generate_self_signed;
+use pqc_chat::media::{MediaError, MediaForwarder, SequenceStatus, MAX_UDP_PACKET_SIZE};
+use pqc_chat::protocol::{
+    audio_is_stale, negotiate_features, paginate, preferred_codec, unix_millis,
+    validate_username, AudioCodec, Capabilities, ErrorCode, FrameAuthenticator, FrameOptions, LeaveReason,
+    ParticipantInfo, RoomClosedReason, RoomInfo, RoomLogEvent, ServerUserInfo, SignalingMessage, UserStatus, UsernameError, FEATURE_FRAME_MAC,
+    FEATURE_ROSTER_DELTA, MAX_FRAME_LEN, MIXED_AUDIO_SENDER_ID, PROTOCOL_VERSION, ROSTER_SNAPSHOT_INTERVAL, SYSTEM_SENDER_ID,
+};
+use pqc_chat::room::{ChatRecord, Participant, Room, RoomError, RoomLimits, RoomManager};
+use pqc_chat::transport::{read_frame, TransportError};
+use pqc_chat::config::ConfigError;
 use pqc_chat::ServerConfig;
 
 /// Command-line arguments
@@ -43,6 +67,16 @@ struct Args {
     /// Log level
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    /// Write a self-signed certificate and key to the configured paths if
+    /// they don't exist yet
+    #[arg(long)]
+    generate_cert: bool,
+
+    /// Validate the config file and TLS certificate/key, then exit without
+    /// starting the server
+    #[arg(long)]
+    check: bool,
 }
 
 /// Client connection state
@@ -50,15 +84,56 @@ struct ClientState {
     participant_id: String,
     username: Option<String>,
     shared_secret: Option<Vec<u8>>,
-    message_tx: mpsc::UnboundedSender<SignalingMessage>,
+    /// Framing negotiated via `Hello` (compression, binary audio)
+    framing: FrameOptions,
+    /// Verifies tags on inbound frames once `frame_mac` is agreed
+    frame_auth: Option<FrameAuthenticator>,
+    /// `Hello` has been answered; features are fixed for the connection
+    negotiated: bool,
+    /// Audio codec this client asked to receive, from `Hello`
+    codec: AudioCodec,
+    /// Agreed `roster_delta`: send `RoomRosterDelta` instead of full rosters
+    roster_deltas: bool,
+    /// Presence shown to other users
+    status: UserStatus,
+    /// Set when `status` was switched to away by the idle check, not the user
+    auto_away: bool,
+    /// Last non-audio signaling message from this client
+    last_activity: Instant,
+    /// Last frame of any kind, for the client timeout
+    last_seen: Instant,
+    /// Tells the connection task to hang up after a server-side removal
+    hangup: Arc<tokio::sync::Notify>,
+    /// Allowance for room-mutating commands
+    room_ops: TokenBucket,
+    /// Audio payloads refused for size, so a misconfigured client doesn't
+    /// log a warning per packet
+    oversized_audio: u64,
+    /// Issued at login; lets a dropped connection's replacement take over
+    /// this participant ID within the reconnect grace
+    resume_token: Option<String>,
+    message_tx: OutboundSender,
 }
 
 impl ClientState {
-    fn new(message_tx: mpsc::UnboundedSender<SignalingMessage>) -> Self {
+    fn new(message_tx: OutboundSender, config: &ServerConfig) -> Self {
         Self {
             participant_id: Uuid::new_v4().to_string(),
             username: None,
             shared_secret: None,
+            framing: FrameOptions::default(),
+            frame_auth: None,
+            negotiated: false,
+            codec: AudioCodec::default(),
+            roster_deltas: false,
+            status: UserStatus::Available,
+            auto_away: false,
+            last_activity: Instant::now(),
+            last_seen: Instant::now(),
+            hangup: Arc::new(tokio::sync::Notify::new()),
+            room_ops: TokenBucket::new(config.room_ops_burst, config.room_ops_per_sec),
+            oversized_audio: 0,
+            resume_token: None,
             message_tx,
         }
     }
@@ -66,17 +141,64 @@ impl ClientState {
 
 /// Server state
 struct ServerState {
+    config: ServerConfig,
     room_manager: RoomManager,
     media_forwarder: RwLock<MediaForwarder>,
     clients: RwLock<HashMap<String, Arc<RwLock<ClientState>>>>,
+    /// Each participant's active room and media flags, for `ListServerUsers`
+    directory: ParticipantDirectory,
+    /// Per-sender codec state, when `transcode_audio` is on
+    transcoders: Mutex<HashMap<String, Arc<Mutex<Transcoder>>>>,
+    /// Per-room mixing threads, when `mix_audio` is on
+    room_mixes: Mutex<HashMap<String, RoomMixHandle>>,
+    /// Drops quiet audio before relaying, when `relay_silence_threshold` is set
+    silence_gate: Option<Mutex<SilenceGate>>,
+    /// Message of the day, with `motd_file` cached until it changes
+    motd: Motd,
+    /// Record of all signaling traffic, when `signaling_log` is set
+    signaling_log: Option<SignalingLog>,
+    /// Resume tokens by token, see [`ResumeEntry`]
+    resume_tokens: Mutex<HashMap<String, ResumeEntry>>,
+    /// Origin of the monotonic clock reported in `TimeSyncResponse`
+    started: Instant,
+}
+
+/// Who a resume token belongs to, and until when it can be used
+struct ResumeEntry {
+    participant_id: String,
+    /// `None` while the connection is up; set to the end of the reconnect
+    /// grace when it drops
+    expires: Option<Instant>,
 }
 
 impl ServerState {
-    fn new(audio_port: u16, video_port: u16) -> Self {
+    fn new(config: ServerConfig) -> Self {
         Self {
             room_manager: RoomManager::new(),
-            media_forwarder: RwLock::new(MediaForwarder::new(audio_port, video_port)),
+            media_forwarder: RwLock::new({
+                let mut forwarder = MediaForwarder::new(config.audio_port, config.video_port);
+                forwarder.set_max_audio_age_ms(config.max_audio_age_ms);
+                forwarder
+            }),
             clients: RwLock::new(HashMap::new()),
+            directory: ParticipantDirectory::default(),
+            transcoders: Mutex::new(HashMap::new()),
+            room_mixes: Mutex::new(HashMap::new()),
+            silence_gate: (config.relay_silence_threshold > 0.0)
+                .then(|| Mutex::new(SilenceGate::new(config.relay_silence_threshold))),
+            motd: Motd::new(config.motd.clone(), config.motd_file.clone()),
+            signaling_log: None,
+            resume_tokens: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+            config,
+        }
+    }
+
+    /// Room creation caps from the server config
+    fn room_limits(&self) -> RoomLimits {
+        RoomLimits {
+            max_rooms: self.config.max_rooms,
+            max_rooms_per_user: self.config.max_rooms_per_user,
         }
     }
 }
@@ -89,6 +211,19 @@ async fn main() -> Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(&args.log_level))
         .init();
 
+    if args.check {
+        let problems = check_config(&args.config);
+        if problems.is_empty() {
+            println!("{}: OK", args.config.display());
+            return Ok(());
+        }
+        eprintln!("{}: {} problem(s)", args.config.display(), problems.len());
+        for problem in &problems {
+            eprintln!("  - {}", problem);
+        }
+        std::process::exit(1);
+    }
+
     // Load configuration
     let config = if args.config.exists() {
         ServerConfig::from_file(args.config.to_str().unwrap())?
@@ -96,10 +231,15 @@ async fn main() -> Result<()> {
         info!("Config file not found, using defaults");
         ServerConfig::default()
     };
+    config.validate()?;
 
     let host = args.host.unwrap_or(config.signaling_host.clone());
     let port = args.port.unwrap_or(config.signaling_port);
 
+    if args.generate_cert {
+        ensure_self_signed_cert(&config, &host)?;
+    }
+
     // Load TLS certificates
     let certs = load_certs(&config.certfile)?;
     let key = load_key(&config.keyfile)?;
@@ -112,27 +252,95 @@ async fn main() -> Result<()> {
     let acceptor = TlsAcceptor::from(Arc::new(tls_config));
 
     // Create server state
-    let state = Arc::new(ServerState::new(config.audio_port, config.video_port));
+    let mut state = ServerState::new(config);
+    if let Some(path) = &state.config.signaling_log {
+        state.signaling_log = Some(SignalingLog::open(path)?);
+        info!("Logging signaling traffic to {}", path.display());
+    }
+    let state = Arc::new(state);
 
     // Start media forwarder
     state.media_forwarder.write().start()?;
 
+    if state.config.echo_test_room {
+        state.room_manager.create_echo_test_room(state.config.default_max_participants);
+    }
+
+    // Learn clients' public UDP endpoints for peer-to-peer audio
+    if state.config.udp_peer_assist {
+        let udp_addr: SocketAddr = format!("{}:{}", state.config.media_host, state.config.audio_port).parse()?;
+        let socket = UdpSocket::bind(udp_addr).await?;
+        info!("UDP peer assist listening on {}", udp_addr);
+        let udp_state = state.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            loop {
+                match socket.recv_from(&mut buf).await {
+                    Ok((len, source)) => {
+                        if let Err(e) = on_udp_datagram(&udp_state, &buf[..len], source).await {
+                            debug!("Dropped UDP datagram from {}: {}", source, e);
+                        }
+                    }
+                    Err(e) => error!("UDP receive error: {}", e),
+                }
+            }
+        });
+    }
+
+    // Periodically mark idle users as away, drop silent connections and
+    // reap empty rooms
+    if state.config.idle_away_secs > 0 || state.config.client_timeout_secs > 0 || state.config.reap_empty_rooms {
+        let idle_state = state.clone();
+        let idle = Duration::from_secs(idle_state.config.idle_away_secs);
+        let timeout = Duration::from_secs(idle_state.config.client_timeout_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(15));
+            loop {
+                interval.tick().await;
+                if !idle.is_zero() {
+                    apply_idle_away(&idle_state, Instant::now(), idle).await;
+                }
+                if !timeout.is_zero() {
+                    expire_silent_clients(&idle_state, Instant::now(), timeout).await;
+                }
+                if idle_state.config.reap_empty_rooms {
+                    for room in idle_state.room_manager.reap_empty_rooms(Instant::now()) {
+                        announce_room_closed(&idle_state, &room, RoomClosedReason::Reaped);
+                    }
+                }
+            }
+        });
+    }
+
     // Bind TCP listener
     let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_signaling_listener(addr, state.config.listen_backlog)?;
     info!("PQC Chat Server listening on {}", addr);
 
     // Accept connections
     loop {
-        let (stream, peer_addr) = listener.accept().await?;
+        let (stream, peer_addr) = accept_signaling(&listener).await?;
         let acceptor = acceptor.clone();
         let state = state.clone();
 
         tokio::spawn(async move {
-            match acceptor.accept(stream).await {
+            // One deadline covers both the TLS handshake and logging in
+            let deadline = handshake_deadline(&state.config);
+            let accept = acceptor.accept(stream);
+            let accepted = match deadline {
+                None => accept.await,
+                Some(deadline) => match tokio::time::timeout_at(deadline, accept).await {
+                    Ok(accepted) => accepted,
+                    Err(_) => {
+                        warn!("TLS handshake with {} timed out", peer_addr);
+                        return;
+                    }
+                },
+            };
+            match accepted {
                 Ok(tls_stream) => {
                     info!("New TLS connection from {}", peer_addr);
-                    if let Err(e) = handle_client(tls_stream, peer_addr, state).await {
+                    if let Err(e) = handle_client(tls_stream, peer_addr, state, deadline).await {
                         error!("Client {} error: {}", peer_addr, e);
                     }
                 }
@@ -144,19 +352,51 @@ async fn main() -> Result<()> {
     }
 }
 
-/// Handle a connected client
+/// Bind the signaling listener with an explicit accept backlog
+fn bind_signaling_listener(addr: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Accept a signaling connection with Nagle disabled, so small control
+/// messages go out immediately instead of waiting to coalesce. Failing to
+/// disable it only costs latency, so the connection is kept either way.
+async fn accept_signaling(listener: &TcpListener) -> std::io::Result<(TcpStream, SocketAddr)> {
+    let (stream, peer_addr) = listener.accept().await?;
+    if let Err(e) = stream.set_nodelay(true) {
+        warn!("Could not set TCP_NODELAY for {}: {}", peer_addr, e);
+    }
+    Ok((stream, peer_addr))
+}
+
+/// When a connection accepted now must have logged in by, if ever
+fn handshake_deadline(config: &ServerConfig) -> Option<tokio::time::Instant> {
+    match config.handshake_timeout_secs {
+        0 => None,
+        secs => Some(tokio::time::Instant::now() + Duration::from_secs(secs)),
+    }
+}
+
+/// Handle a connected client; `login_deadline` is when it must have logged in by
 async fn handle_client<S>(
     stream: tokio_rustls::server::TlsStream<S>,
     peer_addr: SocketAddr,
     state: Arc<ServerState>,
+    login_deadline: Option<tokio::time::Instant>,
 ) -> Result<()>
 where
     S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
 {
     // Create message channel for broadcasting to this client
-    let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+    let (message_tx, mut message_rx) = outbound::channel(state.config.client_queue_depth);
     
-    let client_state = Arc::new(RwLock::new(ClientState::new(message_tx)));
+    let client_state = Arc::new(RwLock::new(ClientState::new(message_tx, &state.config)));
     let participant_id = client_state.read().participant_id.clone();
 
     // Register client
@@ -165,14 +405,39 @@ where
         .write()
         .insert(participant_id.clone(), client_state.clone());
 
+    // Drop connections that never get as far as logging in
+    if let Some(deadline) = login_deadline {
+        spawn_handshake_watchdog(state.clone(), participant_id.clone(), deadline);
+    }
+
+    // Queued before anything else, so it's the first frame the client reads
+    let _ = client_state.read().message_tx.send(server_info(&state));
+
     // Split stream for concurrent reading and writing
     let (read_half, mut write_half) = tokio::io::split(stream);
     
     // Spawn task to handle outgoing messages (broadcasts from server)
+    let writer_state = client_state.clone();
+    let writer_id = participant_id.clone();
+    let max_audio_age_ms = state.config.max_audio_age_ms;
+    let writer_server = state.clone();
     let broadcast_task = tokio::spawn(async move {
         while let Some(message) = message_rx.recv().await {
-            if let Ok(data) = message.to_framed() {
-                if write_half.write_all(&data).await.is_err() {
+            // Audio that sat in the queue too long would only add latency
+            if message.is_stale_audio(unix_millis(), max_audio_age_ms) {
+                debug!("Dropping stale audio queued for {}", writer_id);
+                continue;
+            }
+            if let Some(log) = &writer_server.signaling_log {
+                let id = writer_state.read().participant_id.clone();
+                log.record(&id, Direction::Outbound, &message);
+            }
+            let framing = writer_state.read().framing;
+            if let Ok(data) = message.to_framed_with(framing) {
+                if let Err(e) = outbound::write_frame(&mut write_half, &data).await {
+                    // Hang up so the read loop cleans up just this client
+                    warn!("Write to {} ({}) failed: {}", writer_id, peer_addr, e);
+                    writer_state.read().hangup.notify_one();
                     break;
                 }
             }
@@ -181,43 +446,74 @@ where
 
     // Handle incoming messages
     let mut read_stream = read_half;
+    let hangup = client_state.read().hangup.clone();
+    let mut reason = LeaveReason::Disconnected;
 
     let result = async {
         loop {
-            // Read message length (4 bytes)
-            let mut len_buf = [0u8; 4];
-            if read_stream.read_exact(&mut len_buf).await.is_err() {
-                break;
-            }
+            // Read the next frame, unless the server drops us first; a peer
+            // stalling mid-frame can still be dropped
+            let frame = tokio::select! {
+                read = read_frame(&mut read_stream) => match read {
+                    Ok(frame) => frame,
+                    Err(TransportError::FrameTooLarge(len)) => {
+                        error!("Message too large from {} ({} bytes)", peer_addr, len);
+                        break;
+                    }
+                    Err(_) => break,
+                },
+                _ = hangup.notified() => {
+                    info!("Closing connection to {} after server-side removal", peer_addr);
+                    break;
+                }
+            };
+            client_state.write().last_seen = Instant::now();
+            let compressed = frame.compressed;
 
-            let msg_len = u32::from_be_bytes(len_buf) as usize;
-            // Limit signaling messages to 64KB (reasonable for JSON)
-            if msg_len > 64 * 1024 {
-                error!("Message too large from {} ({} bytes)", peer_addr, msg_len);
-                break;
-            }
+            // Once agreed, every frame must carry a valid tag
+            let body = match client_state.write().frame_auth.as_mut() {
+                Some(auth) => auth.open(&frame.body, compressed),
+                None => Ok(&frame.body[..]),
+            };
+            let body = match body {
+                Ok(body) => body,
+                Err(e) => {
+                    error!("Dropping {}: {}", peer_addr, e);
+                    break;
+                }
+            };
 
-            // Read message
-            let mut msg_buf = vec![0u8; msg_len];
-            if read_stream.read_exact(&mut msg_buf).await.is_err() {
-                break;
+            // Parse and handle message; compressed frames only once agreed.
+            // The ID is re-read since a resumed login changes it.
+            let (participant_id, framing) = {
+                let client = client_state.read();
+                (client.participant_id.clone(), client.framing)
+            };
+            let parsed = SignalingMessage::from_frame_body_with(body, compressed, framing);
+            if let (Some(log), Ok(message)) = (&state.signaling_log, &parsed) {
+                log.record(&participant_id, Direction::Inbound, message);
             }
-
-            // Parse and handle message
-            match SignalingMessage::from_bytes(&msg_buf) {
+            match parsed {
+                Ok(SignalingMessage::Logout) => {
+                    info!("Client {} logged out", peer_addr);
+                    reason = LeaveReason::Left;
+                    break;
+                }
                 Ok(message) => {
+                    // Clients resync their clock on a timer; that isn't the user
+                    if !message.is_audio() && !matches!(message, SignalingMessage::TimeSync { .. }) {
+                        mark_active(&state, &participant_id).await;
+                    }
                     let response =
                         handle_message(message, &participant_id, &client_state, &state).await;
-                    
-                    // Send response through the client's message channel
-                    if let Some(client) = state.clients.read().get(&participant_id) {
-                        let _ = client.read().message_tx.send(response);
-                    }
+                    let participant_id = client_state.read().participant_id.clone();
+                    send_response(&state, &participant_id, response);
                 }
                 Err(e) => {
                     error!("Invalid message from {}: {}", peer_addr, e);
                     let error_msg = SignalingMessage::Error {
                         message: "Invalid message format".to_string(),
+                        code: None,
                     };
                     if let Some(client) = state.clients.read().get(&participant_id) {
                         let _ = client.read().message_tx.send(error_msg);
@@ -229,24 +525,570 @@ where
     }
     .await;
 
-    // Cleanup
-    state.clients.write().remove(&participant_id);
-    
-    // Notify other room participants that this user left
-    if let Some(room) = state.room_manager.get_participant_room(&participant_id) {
-        let _username = client_state.read().username.clone().unwrap_or_default();
-        broadcast_to_room(&state, &room.id, &participant_id, SignalingMessage::ParticipantLeft {
-            participant_id: participant_id.clone(),
-        }).await;
+    // Cleanup (a no-op if the server already removed this client, or another
+    // connection has resumed its ID)
+    let participant_id = client_state.read().participant_id.clone();
+    let registered = state.clients.read().get(&participant_id).is_some_and(|c| Arc::ptr_eq(c, &client_state));
+    if registered {
+        disconnect_client(&state, &participant_id, reason).await;
     }
-    
-    let _ = state.room_manager.leave_room(&participant_id);
     broadcast_task.abort();
     info!("Client {} disconnected", peer_addr);
 
     result
 }
 
+/// Tear down a client's server-side state.
+///
+/// Drops the client from the registry, notifies the rest of its room and
+/// removes it from the room. Used for explicit `Logout`, dropped
+/// connections and server-side removals; `reason` is passed on to the room.
+async fn disconnect_client(state: &Arc<ServerState>, participant_id: &str, reason: LeaveReason) {
+    let Some(left) = release_client(state, participant_id, reason) else { return };
+    for (room, username) in &left {
+        announce_leave(state, room, participant_id, username, reason).await;
+        promote_waiters(state, room).await;
+    }
+}
+
+/// Drop everything the server holds for a client: its registry entry (and
+/// connection, via `hangup`), media and codec state, room memberships and
+/// waiting-room places. Rooms left by a dropped connection are held for
+/// `reconnect_grace_secs`.
+///
+/// Returns the rooms it left, each with the name to announce, or `None` if
+/// it was already gone. Every teardown path goes through here; announcing
+/// is up to the caller.
+fn release_client(state: &Arc<ServerState>, participant_id: &str, reason: LeaveReason) -> Option<Vec<(Arc<Room>, String)>> {
+    let client = state.clients.write().remove(participant_id);
+    let rooms = state.room_manager.get_participant_rooms(participant_id);
+    if client.is_none() && rooms.is_empty() {
+        return None;
+    }
+    // A dropped connection may come straight back; keep its rooms and
+    // resume token around until the grace runs out
+    let dropped = matches!(reason, LeaveReason::Disconnected | LeaveReason::TimedOut);
+    let until = Instant::now() + Duration::from_secs(state.config.reconnect_grace_secs);
+    if let Some(client) = client {
+        let client = client.read();
+        client.hangup.notify_one();
+        if let Some(token) = &client.resume_token {
+            let mut tokens = state.resume_tokens.lock();
+            match tokens.get_mut(token) {
+                Some(entry) if dropped => entry.expires = Some(until),
+                _ => {
+                    tokens.remove(token);
+                }
+            }
+        }
+    }
+    state.media_forwarder.write().end_session(participant_id);
+    state.transcoders.lock().remove(participant_id);
+    if let Some(gate) = &state.silence_gate {
+        gate.lock().forget(participant_id);
+    }
+    for mix in state.room_mixes.lock().values() {
+        mix.forget(participant_id);
+    }
+
+    let usernames: Vec<String> = rooms.iter().map(|room| leaving_username(room, participant_id)).collect();
+    state.room_manager.leave_all_rooms(participant_id);
+    state.directory.forget(participant_id);
+    if dropped {
+        for room in &rooms {
+            room.hold_for_reconnect(until);
+        }
+    }
+    for room in state.room_manager.cancel_waiting(participant_id) {
+        notify_waiting_positions(state, &room);
+    }
+    Some(rooms.into_iter().zip(usernames).collect())
+}
+
+/// Tell a room's other members that someone joined
+async fn announce_join(state: &Arc<ServerState>, room: &Room, participant_id: &str, username: &str) {
+    room.log_event(Some(participant_id), RoomLogEvent::Joined {
+        participant_id: participant_id.to_string(),
+        username: username.to_string(),
+    });
+    broadcast_to_room(state, &room.id, participant_id, SignalingMessage::ParticipantJoined {
+        participant_id: participant_id.to_string(),
+        username: username.to_string(),
+    }).await;
+    if let Some(message) = system_message(room, format!("{} joined the room", username)) {
+        broadcast_to_room(state, &room.id, participant_id, message).await;
+    }
+    // The joiner already has the same list in its join reply
+    let added = participant_infos(room).into_iter().filter(|p| p.id == participant_id).collect();
+    send_roster_change(state, room, Some(participant_id), roster_delta(room, added, Vec::new(), Vec::new()), true);
+}
+
+/// Tell a room's remaining members that someone left
+async fn announce_leave(
+    state: &Arc<ServerState>,
+    room: &Room,
+    participant_id: &str,
+    username: &str,
+    reason: LeaveReason,
+) {
+    room.log_event(leave_actor(room, participant_id, reason), RoomLogEvent::Left {
+        participant_id: participant_id.to_string(),
+        username: username.to_string(),
+        reason,
+    });
+    broadcast_to_room(state, &room.id, participant_id, SignalingMessage::ParticipantLeft {
+        participant_id: participant_id.to_string(),
+        reason,
+    }).await;
+    if let Some(message) = system_message(room, format!("{} {}", username, reason.describe())) {
+        broadcast_to_room_all(state, &room.id, message).await;
+    }
+    let delta = roster_delta(room, Vec::new(), vec![participant_id.to_string()], Vec::new());
+    send_roster_change(state, room, None, delta, true);
+}
+
+/// Who to log as the cause of a leave: the leaver, the owner for
+/// moderation (only owners can move or clear), or nobody for the server
+fn leave_actor<'a>(room: &'a Room, participant_id: &'a str, reason: LeaveReason) -> Option<&'a str> {
+    match reason {
+        LeaveReason::Left => Some(participant_id),
+        LeaveReason::Kicked | LeaveReason::Moved | LeaveReason::Cleared => room.creator_id.as_deref(),
+        LeaveReason::Disconnected | LeaveReason::TimedOut | LeaveReason::ServerShutdown => None,
+    }
+}
+
+/// Snapshot of a room's current membership
+fn roster(room: &Room) -> SignalingMessage {
+    let (participants, version) = versioned_roster(room);
+    SignalingMessage::RoomRoster {
+        room_id: room.id.clone(),
+        participants,
+        version,
+    }
+}
+
+/// The change that brought `room` to its current roster version
+fn roster_delta(
+    room: &Room,
+    added: Vec<ParticipantInfo>,
+    removed: Vec<String>,
+    media_changes: Vec<(String, bool, bool)>,
+) -> SignalingMessage {
+    SignalingMessage::RoomRosterDelta {
+        room_id: room.id.clone(),
+        version: room.roster_version(),
+        added,
+        removed,
+        media_changes,
+    }
+}
+
+/// What a client hears about a roster change: the delta if it agreed to
+/// `roster_delta` (or the full roster every `ROSTER_SNAPSHOT_INTERVAL`
+/// versions), otherwise the full roster after membership changes only
+fn roster_update_for(client: &ClientState, room: &Room, delta: &SignalingMessage, membership: bool) -> Option<SignalingMessage> {
+    if client.roster_deltas {
+        if room.roster_version().is_multiple_of(ROSTER_SNAPSHOT_INTERVAL) {
+            Some(roster(room))
+        } else {
+            Some(delta.clone())
+        }
+    } else if membership {
+        Some(roster(room))
+    } else {
+        None
+    }
+}
+
+/// Delta for a participant's new audio/video state
+fn media_delta(room: &Room, participant_id: &str) -> SignalingMessage {
+    let media_changes = room
+        .get_participant(participant_id)
+        .map(|p| (p.id, p.audio_enabled, p.video_enabled))
+        .into_iter()
+        .collect();
+    roster_delta(room, Vec::new(), Vec::new(), media_changes)
+}
+
+/// Send a roster change to a room's members, except `skip`
+fn send_roster_change(state: &Arc<ServerState>, room: &Room, skip: Option<&str>, delta: SignalingMessage, membership: bool) {
+    let mut dead = Vec::new();
+    {
+        let clients = state.clients.read();
+        for member_id in room.get_participant_ids() {
+            if skip == Some(member_id.as_str()) {
+                continue;
+            }
+            let Some(client_state) = clients.get(&member_id) else { continue };
+            let client = client_state.read();
+            if let Some(message) = roster_update_for(&client, room, &delta, membership) {
+                if client.message_tx.send(message).is_err() {
+                    dead.push(member_id);
+                }
+            }
+        }
+    }
+    remove_dead_clients(state, dead);
+}
+
+/// Fill free slots in `room` from its waiting room, first in line first
+async fn promote_waiters(state: &Arc<ServerState>, room: &Arc<Room>) {
+    let mut promoted = false;
+    while !room.is_full() {
+        let Some(waiter) = room.pop_waiter() else { break };
+        // Waiters that have since disconnected or settled into another
+        // room just lose their place
+        if !state.clients.read().contains_key(&waiter.id)
+            || state.room_manager.get_participant_room(&waiter.id).is_some()
+        {
+            continue;
+        }
+        match state.room_manager.join_room(&room.id, waiter.clone()) {
+            Ok(room) => {
+                info!("Promoted {} from the waiting room of {}", waiter.username, room.name);
+                welcome_waiter(state, &room, &waiter).await;
+                promoted = true;
+            }
+            Err(e) => warn!("Could not promote {} into {}: {}", waiter.id, room.name, e),
+        }
+    }
+    if promoted {
+        notify_waiting_positions(state, room);
+    }
+}
+
+/// Tell a promoted waiter they're in and announce them to the room
+async fn welcome_waiter(state: &Arc<ServerState>, room: &Room, waiter: &Participant) {
+    state.directory.refresh(&state.room_manager, &waiter.id);
+    send_room_joined(state, room, &waiter.id);
+    announce_join(state, room, &waiter.id, &waiter.username).await;
+}
+
+/// Send everyone still waiting for `room` their current position
+fn notify_waiting_positions(state: &Arc<ServerState>, room: &Room) {
+    for (index, waiter_id) in room.waiting_ids().iter().enumerate() {
+        let message = SignalingMessage::WaitingRoom {
+            room_id: room.id.clone(),
+            position: index as u32 + 1,
+        };
+        send_to_participants(state, std::slice::from_ref(waiter_id), message);
+    }
+}
+
+/// Roster of a room as sent to clients, in join order
+fn participant_infos(room: &Room) -> Vec<ParticipantInfo> {
+    versioned_roster(room).0
+}
+
+/// Roster of a room with the version it corresponds to, read together so
+/// deltas applied on top of it line up
+fn versioned_roster(room: &Room) -> (Vec<ParticipantInfo>, u64) {
+    let (participants, version) = room.roster_snapshot();
+    let infos = participants
+        .iter()
+        .map(|p| ParticipantInfo {
+            id: p.id.clone(),
+            username: p.username.clone(),
+            audio_enabled: p.audio_enabled,
+            video_enabled: p.video_enabled,
+        })
+        .collect();
+    (infos, version)
+}
+
+/// Record a system chat message in `room`, if the room announces joins
+fn system_message(room: &Room, content: String) -> Option<SignalingMessage> {
+    if !room.announces_joins() {
+        return None;
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let record = room.record_message(SYSTEM_SENDER_ID, "System", content, timestamp);
+    Some(chat_message(&room.id, record))
+}
+
+/// A stored chat message as sent to clients
+fn chat_message(room_id: &str, record: ChatRecord) -> SignalingMessage {
+    SignalingMessage::MessageReceived {
+        message_id: record.message_id,
+        room_id: room_id.to_string(),
+        sender_id: record.sender_id,
+        sender_username: record.sender_username,
+        content: record.content,
+        timestamp: record.timestamp,
+        edited: record.edited,
+    }
+}
+
+/// A system chat line for one client, kept out of the room's history
+fn system_notice(room_id: &str, content: String) -> SignalingMessage {
+    SignalingMessage::MessageReceived {
+        message_id: Uuid::new_v4().to_string(),
+        room_id: room_id.to_string(),
+        sender_id: SYSTEM_SENDER_ID.to_string(),
+        sender_username: "System".to_string(),
+        content,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        edited: false,
+    }
+}
+
+/// Chat lines for someone who just joined `room`: its recent history, so a
+/// client rejoining after a reconnect catches up on what it missed, then the
+/// MOTD and the room's topic. System join/leave lines aren't replayed; the
+/// roster already reflects them.
+fn welcome_messages(state: &ServerState, room: &Room) -> Vec<SignalingMessage> {
+    let history = room
+        .chat_history()
+        .into_iter()
+        .filter(|record| record.sender_id != SYSTEM_SENDER_ID)
+        .map(|record| chat_message(&room.id, record));
+    let motd = state.motd.current().map(|motd| system_notice(&room.id, motd));
+    let topic = room.topic().map(|topic| system_notice(&room.id, format!("Topic: {}", topic)));
+    history.chain(motd).chain(topic).collect()
+}
+
+/// Successful `RoomJoined` reply for `room`
+fn room_joined(room: &Room) -> SignalingMessage {
+    let (participants, roster_version) = versioned_roster(room);
+    SignalingMessage::RoomJoined {
+        success: true,
+        room_id: Some(room.id.clone()),
+        room_name: Some(room.name.clone()),
+        participants: Some(participants),
+        error: None,
+        roster_version,
+    }
+}
+
+/// Tell someone put into `room` without asking (promoted from the waiting
+/// room, moved by the owner) that they're in, then welcome them
+fn send_room_joined(state: &Arc<ServerState>, room: &Room, participant_id: &str) {
+    let recipient = [participant_id.to_string()];
+    send_to_participants(state, &recipient, room_joined(room));
+    for message in welcome_messages(state, room) {
+        send_to_participants(state, &recipient, message);
+    }
+}
+
+/// Queue a reply for a client, followed by the welcome for a room it joined
+fn send_response(state: &ServerState, participant_id: &str, response: SignalingMessage) {
+    let welcome = match &response {
+        SignalingMessage::RoomJoined { success: true, room_id: Some(room_id), .. } => state
+            .room_manager
+            .get_room(room_id)
+            .map(|room| welcome_messages(state, &room))
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    if let Some(client) = state.clients.read().get(participant_id) {
+        let client = client.read();
+        let _ = client.message_tx.send(response);
+        for message in welcome {
+            let _ = client.message_tx.send(message);
+        }
+    }
+}
+
+/// Display name of a participant about to leave `room`, for announcements
+fn leaving_username(room: &Room, participant_id: &str) -> String {
+    room.get_participant(participant_id)
+        .map(|p| p.username)
+        .unwrap_or_else(|| participant_id.to_string())
+}
+
+/// Drop `participant_id` if it still hasn't logged in by `deadline`
+fn spawn_handshake_watchdog(state: Arc<ServerState>, participant_id: String, deadline: tokio::time::Instant) {
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline).await;
+        let stalled = state
+            .clients
+            .read()
+            .get(&participant_id)
+            .is_some_and(|client| client.read().username.is_none());
+        if stalled {
+            info!("Client {} did not log in within {}s of connecting", participant_id, state.config.handshake_timeout_secs);
+            disconnect_client(&state, &participant_id, LeaveReason::TimedOut).await;
+        }
+    });
+}
+
+/// Hand out a resume token for a freshly logged-in participant, dropping
+/// tokens whose grace has run out
+fn issue_resume_token(state: &Arc<ServerState>, participant_id: &str) -> String {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = Instant::now();
+    let mut tokens = state.resume_tokens.lock();
+    tokens.retain(|_, entry| entry.expires.is_none_or(|until| now < until));
+    tokens.insert(token.clone(), ResumeEntry { participant_id: participant_id.to_string(), expires: None });
+    token
+}
+
+/// Redeem a resume token, returning the participant ID it was issued to.
+/// Tokens are single use, and only good until their grace runs out.
+fn take_resume_token(state: &Arc<ServerState>, token: &str) -> Option<String> {
+    let entry = state.resume_tokens.lock().remove(token)?;
+    entry.expires.is_none_or(|until| Instant::now() < until).then_some(entry.participant_id)
+}
+
+/// Move a connection onto the participant ID it resumed. Anything still
+/// registered under that ID (a connection the server hasn't noticed is
+/// dead yet) is torn down first.
+async fn resume_participant(state: &Arc<ServerState>, client_state: &Arc<RwLock<ClientState>>, from: &str, to: &str) {
+    disconnect_client(state, to, LeaveReason::Disconnected).await;
+    let mut clients = state.clients.write();
+    if let Some(client) = clients.remove(from) {
+        clients.insert(to.to_string(), client);
+    }
+    client_state.write().participant_id = to.to_string();
+}
+
+/// Drop clients that have sent nothing for `timeout`
+async fn expire_silent_clients(state: &Arc<ServerState>, now: Instant, timeout: Duration) {
+    let expired: Vec<String> = state
+        .clients
+        .read()
+        .iter()
+        .filter(|(_, client)| now.saturating_duration_since(client.read().last_seen) >= timeout)
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    for participant_id in expired {
+        info!("Client {} timed out", participant_id);
+        disconnect_client(state, &participant_id, LeaveReason::TimedOut).await;
+    }
+}
+
+/// Handle a UDP datagram: authenticate it and learn the sender's public endpoint
+async fn on_udp_datagram(
+    state: &Arc<ServerState>,
+    datagram: &[u8],
+    source: SocketAddr,
+) -> Result<(), MediaError> {
+    let accepted = state.media_forwarder.write().authenticate_datagram(datagram, source)?;
+    let participant_id = accepted.packet.participant_id;
+    if let SequenceStatus::InOrder { lost: lost @ 1.. } = accepted.sequence {
+        debug!("{} UDP packets from {} lost", lost, participant_id);
+    }
+    if accepted.stale {
+        debug!("Dropped stale UDP audio from {}", participant_id);
+    }
+
+    if accepted.endpoint_changed && state.config.udp_peer_assist {
+        info!("Observed UDP endpoint {} for {}", source, participant_id);
+        share_udp_endpoints(state, &participant_id).await;
+    }
+    Ok(())
+}
+
+/// Exchange observed UDP endpoints between a participant and its room.
+///
+/// Co-members learn the participant's endpoint, and the participant learns
+/// every co-member endpoint seen so far.
+async fn share_udp_endpoints(state: &Arc<ServerState>, participant_id: &str) {
+    let room = match state.room_manager.get_participant_room(participant_id) {
+        Some(room) => room,
+        None => return,
+    };
+
+    let (own, peers): (Option<SocketAddr>, Vec<(String, SocketAddr)>) = {
+        let forwarder = state.media_forwarder.read();
+        let peers = room
+            .get_participant_ids()
+            .into_iter()
+            .filter(|id| id != participant_id)
+            .filter_map(|id| forwarder.observed_endpoint(&id).map(|addr| (id, addr)))
+            .collect();
+        (forwarder.observed_endpoint(participant_id), peers)
+    };
+
+    if let Some(addr) = own {
+        broadcast_to_room(state, &room.id, participant_id, SignalingMessage::PeerUdpEndpoint {
+            participant_id: participant_id.to_string(),
+            addr: addr.to_string(),
+        }).await;
+    }
+
+    let target = [participant_id.to_string()];
+    for (peer_id, addr) in peers {
+        send_to_participants(state, &target, SignalingMessage::PeerUdpEndpoint {
+            participant_id: peer_id,
+            addr: addr.to_string(),
+        });
+    }
+}
+
+/// Record signaling activity, undoing an automatic away status
+async fn mark_active(state: &Arc<ServerState>, participant_id: &str) {
+    let client_state = match state.clients.read().get(participant_id) {
+        Some(client_state) => client_state.clone(),
+        None => return,
+    };
+
+    let restored = {
+        let mut client = client_state.write();
+        client.last_activity = Instant::now();
+        if client.auto_away {
+            client.auto_away = false;
+            client.status = UserStatus::Available;
+            true
+        } else {
+            false
+        }
+    };
+
+    if restored {
+        broadcast_to_server(state, None, SignalingMessage::UserStatusChanged {
+            participant_id: participant_id.to_string(),
+            status: UserStatus::Available,
+        });
+    }
+}
+
+/// Mark available users with no activity for `idle` as away
+async fn apply_idle_away(state: &Arc<ServerState>, now: Instant, idle: Duration) {
+    let mut went_away = Vec::new();
+    for (client_id, client_state) in state.clients.read().iter() {
+        let mut client = client_state.write();
+        if client.status == UserStatus::Available
+            && now.saturating_duration_since(client.last_activity) >= idle
+        {
+            client.status = UserStatus::Away;
+            client.auto_away = true;
+            went_away.push(client_id.clone());
+        }
+    }
+
+    for participant_id in went_away {
+        info!("User {} is idle, marking away", participant_id);
+        broadcast_to_server(state, None, SignalingMessage::UserStatusChanged {
+            participant_id,
+            status: UserStatus::Away,
+        });
+    }
+}
+
+/// Commands that take `RoomManager` locks to change rooms or broadcast the
+/// change server-wide, subject to the per-client `room_ops` bucket. Leaving
+/// is exempt so a throttled client is never stuck in a room.
+fn mutates_rooms(message: &SignalingMessage) -> bool {
+    matches!(
+        message,
+        SignalingMessage::CreateRoom { .. }
+            | SignalingMessage::JoinRoom { .. }
+            | SignalingMessage::SpectateRoom { .. }
+            | SignalingMessage::MoveParticipant { .. }
+            | SignalingMessage::SetRoomTopic { .. }
+            | SignalingMessage::ClearRoom { .. }
+            | SignalingMessage::DeleteRoom { .. }
+    )
+}
+
 /// Handle a signaling message
 async fn handle_message(
     message: SignalingMessage,
@@ -254,14 +1096,81 @@ async fn handle_message(
     client_state: &Arc<RwLock<ClientState>>,
     state: &Arc<ServerState>,
 ) -> SignalingMessage {
+    if mutates_rooms(&message) && !client_state.write().room_ops.try_acquire() {
+        debug!("Rate limited room command from {}", participant_id);
+        return SignalingMessage::Error {
+            message: "Too many room operations, slow down".to_string(),
+            code: Some(ErrorCode::RateLimited),
+        };
+    }
+
     match message {
-        SignalingMessage::Login { username } => {
-            client_state.write().username = Some(username.clone());
+        SignalingMessage::Hello { features } => {
+            let mut features = negotiate_features(&features);
+            {
+                let mut client = client_state.write();
+                // Renegotiating could drop `frame_mac` or restart its counter
+                if client.negotiated {
+                    return SignalingMessage::Error { message: "Features already negotiated".to_string(), code: None };
+                }
+                client.negotiated = true;
+                client.framing = FrameOptions::from_features(&features);
+                client.codec = preferred_codec(&features);
+                client.roster_deltas = features.iter().any(|f| f == FEATURE_ROSTER_DELTA);
+                // Frame tags need the Kyber session key, so only after the exchange
+                if features.iter().any(|f| f == FEATURE_FRAME_MAC) {
+                    match &client.shared_secret {
+                        Some(secret) => {
+                            let key = KyberSession::new(secret.clone()).recv_key(SessionRole::Server);
+                            client.frame_auth = Some(FrameAuthenticator::new(&key));
+                        }
+                        None => features.retain(|f| f != FEATURE_FRAME_MAC),
+                    }
+                }
+            }
+            info!("Negotiated features for {}: {:?}", participant_id, features);
+            SignalingMessage::Hello { features }
+        }
+
+        SignalingMessage::DescribeCapabilities => SignalingMessage::Capabilities(capabilities(&state.config)),
+
+        SignalingMessage::TimeSync { client_sent_ms } => SignalingMessage::TimeSyncResponse {
+            client_sent_ms,
+            server_time_ms: unix_millis(),
+            server_monotonic_ms: state.started.elapsed().as_millis() as u64,
+        },
+
+        SignalingMessage::Login { username, resume_token } => {
+            if let Err(e) = validate_username(&username, state.config.max_username_len) {
+                return invalid_username(e);
+            }
+            // Resuming only makes sense for a fresh connection
+            let resume_token = resume_token.filter(|_| client_state.read().username.is_none());
+            let participant_id = match resume_token.and_then(|token| take_resume_token(state, &token)) {
+                Some(previous) => {
+                    resume_participant(state, client_state, participant_id, &previous).await;
+                    info!("User {} resumed as {}", participant_id, previous);
+                    previous
+                }
+                None => participant_id.to_string(),
+            };
+            let resume_token = issue_resume_token(state, &participant_id);
+            let replaced = {
+                let mut client = client_state.write();
+                client.username = Some(username.clone());
+                client.resume_token.replace(resume_token.clone())
+            };
+            if let Some(replaced) = replaced {
+                state.resume_tokens.lock().remove(&replaced);
+            }
             info!("User {} logged in as {}", participant_id, username);
+            let udp_session_token = state.media_forwarder.write().issue_session_token(&participant_id);
             SignalingMessage::LoginResponse {
                 success: true,
-                participant_id: Some(participant_id.to_string()),
+                participant_id: Some(participant_id),
                 error: None,
+                udp_session_token: Some(udp_session_token),
+                resume_token: Some(resume_token),
             }
         }
 
@@ -276,132 +1185,439 @@ async fn handle_message(
                 }
                 Err(e) => SignalingMessage::Error {
                     message: format!("Key exchange failed: {}", e),
+                    code: None,
                 },
             }
         }
 
-        SignalingMessage::ListRooms => {
+        SignalingMessage::ListRooms { offset, limit, joinable_only } => {
             let rooms: Vec<RoomInfo> = state
                 .room_manager
                 .list_rooms()
                 .iter()
-                .map(|r| RoomInfo {
-                    id: r.id.clone(),
-                    name: r.name.clone(),
-                    participants: r.participant_count() as u32,
-                    max_participants: r.max_participants,
-                    is_locked: r.is_locked,
-                })
+                .map(|r| room_info(r))
+                .filter(|r| !joinable_only || !r.is_full)
                 .collect();
-            SignalingMessage::RoomList { rooms }
+            let (rooms, total) = paginate(rooms, offset, limit);
+            SignalingMessage::RoomList { rooms, total }
         }
 
-        SignalingMessage::ListServerUsers => {
-            let clients = state.clients.read();
-            let mut users = Vec::new();
-            
-            for (client_id, client_state) in clients.iter() {
-                let client = client_state.read();
-                if let Some(username) = &client.username {
-                    // Get current room for this user
-                    let current_room = state.room_manager.get_participant_room(client_id)
-                        .map(|room| room.name.clone());
-                    
-                    // Get audio/video status from room if they're in one
-                    let (audio_enabled, video_enabled) = if let Some(room) = state.room_manager.get_participant_room(client_id) {
-                        if let Some(participant) = room.get_participant(client_id) {
-                            (participant.audio_enabled, participant.video_enabled)
-                        } else {
-                            (true, false) // Default values
-                        }
-                    } else {
-                        (true, false) // Default values for lobby users
-                    };
-                    
-                    users.push(ServerUserInfo {
-                        id: client_id.clone(),
-                        username: username.clone(),
-                        connected_at: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_secs(),
-                        current_room,
-                        audio_enabled,
-                        video_enabled,
+        SignalingMessage::GetRoomInfo { room_id } => match state.room_manager.get_room(&room_id) {
+            Some(room) => SignalingMessage::RoomInfoResponse {
+                success: true,
+                room: Some(room_info(&room)),
+                owner_id: room.creator_id.clone(),
+                created_at: Some(
+                    room.created_at
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                ),
+                error: None,
+            },
+            None => SignalingMessage::RoomInfoResponse {
+                success: false,
+                room: None,
+                owner_id: None,
+                created_at: None,
+                error: Some(RoomError::RoomNotFound.to_string()),
+            },
+        },
+
+        SignalingMessage::GetRoomEvents { room_id, limit } => {
+            let room = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room,
+                Err(e) => return unresolved_room(e),
+            };
+            if !room.is_owner(participant_id) {
+                return SignalingMessage::Error { message: RoomError::NotRoomOwner.to_string(), code: None };
+            }
+            let entries = room.event_log(limit.map(|limit| limit as usize));
+            SignalingMessage::RoomEventLog { room_id: room.id.clone(), entries }
+        }
+
+        // Only members get the list, so it can't be used to snoop on a room
+        SignalingMessage::RequestRoster { room_id } => match state.room_manager.get_room(&room_id) {
+            Some(room) if room.has_participant(participant_id) => roster(&room),
+            _ => SignalingMessage::Error {
+                message: RoomError::RoomNotFound.to_string(),
+                code: Some(ErrorCode::RoomNotFound),
+            },
+        },
+
+        SignalingMessage::Whoami => {
+            let room = state.room_manager.get_participant_room(participant_id);
+            let member = room.as_ref().and_then(|room| room.get_participant(participant_id));
+            let participant = match member {
+                Some(p) => ParticipantInfo {
+                    id: p.id,
+                    username: p.username,
+                    audio_enabled: p.audio_enabled,
+                    video_enabled: p.video_enabled,
+                },
+                // Lobby users get the same defaults as in `ListServerUsers`
+                None => ParticipantInfo {
+                    id: participant_id.to_string(),
+                    username: client_state.read().username.clone().unwrap_or_default(),
+                    audio_enabled: true,
+                    video_enabled: false,
+                },
+            };
+            SignalingMessage::WhoamiResponse { participant, room_id: room.map(|room| room.id.clone()) }
+        }
+
+        SignalingMessage::UpdateRoomSettings { room_id, announce_joins, waiting_room } => {
+            match state.room_manager.get_room(&room_id) {
+                Some(room) if room.is_owner(participant_id) => {
+                    room.set_announce_joins(announce_joins);
+                    if let Some(enabled) = waiting_room {
+                        room.set_waiting_room(enabled);
+                    }
+                    room.log_event(Some(participant_id), RoomLogEvent::SettingsChanged {
+                        announce_joins,
+                        waiting_room: room.has_waiting_room(),
+                    });
+                    SignalingMessage::Error { message: "Room settings updated".to_string(), code: None }
+                }
+                Some(_) => SignalingMessage::Error { message: RoomError::NotRoomOwner.to_string(), code: None },
+                None => SignalingMessage::Error { message: RoomError::RoomNotFound.to_string(), code: None },
+            }
+        }
+
+        SignalingMessage::SetRoomTopic { room_id, topic } => {
+            let error = |e: RoomError| SignalingMessage::Error { message: e.to_string(), code: None };
+            let room = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room,
+                Err(e) => return error(e),
+            };
+            if !room.is_owner(participant_id) {
+                return error(RoomError::NotRoomOwner);
+            }
+            if let Err(e) = room.set_topic(topic) {
+                return error(e);
+            }
+            room.log_event(Some(participant_id), RoomLogEvent::TopicChanged { topic: room.topic() });
+            let changed = SignalingMessage::RoomTopicChanged { room_id: room.id.clone(), topic: room.topic() };
+            broadcast_to_server(&state, Some(participant_id), changed.clone());
+            changed
+        }
+
+        SignalingMessage::AdmitWaiter { room_id, participant_id: waiter_id } => {
+            match state.room_manager.get_room(&room_id) {
+                Some(room) if room.is_owner(participant_id) => {
+                    match state.room_manager.admit_waiter(&room_id, &waiter_id) {
+                        Ok((room, waiter)) => {
+                            welcome_waiter(&state, &room, &waiter).await;
+                            notify_waiting_positions(&state, &room);
+                            SignalingMessage::Error { message: "Waiter admitted".to_string(), code: None }
+                        }
+                        Err(e) => SignalingMessage::Error { message: e.to_string(), code: None },
+                    }
+                }
+                Some(_) => SignalingMessage::Error { message: RoomError::NotRoomOwner.to_string(), code: None },
+                None => SignalingMessage::Error { message: RoomError::RoomNotFound.to_string(), code: None },
+            }
+        }
+
+        SignalingMessage::MoveParticipant { participant_id: moved_id, target_room_id, force } => {
+            let error = |e: RoomError| SignalingMessage::Error { message: e.to_string(), code: None };
+            let Some(from) = state.room_manager.get_participant_room(&moved_id) else {
+                return error(RoomError::ParticipantNotFound);
+            };
+            if !from.is_owner(participant_id) {
+                return error(RoomError::NotRoomOwner);
+            }
+            let target = match state.room_manager.resolve_room(&target_room_id) {
+                Ok(room) => room,
+                Err(e) => return error(e),
+            };
+            // Only the target's owner may override its capacity
+            if force && !target.is_owner(participant_id) {
+                return error(RoomError::NotRoomOwner);
+            }
+
+            let was_spectating = state.room_manager.is_spectating(&moved_id, &target.id);
+            let username = leaving_username(&from, &moved_id);
+            match state.room_manager.move_participant(&moved_id, &target.id, !force) {
+                Ok((from, to)) => {
+                    info!("{} moved {} from {} to {}", participant_id, username, from.name, to.name);
+                    state.directory.refresh(&state.room_manager, &moved_id);
+                    announce_leave(&state, &from, &moved_id, &username, LeaveReason::Moved).await;
+                    send_room_joined(&state, &to, &moved_id);
+                    if !was_spectating {
+                        announce_join(&state, &to, &moved_id, &username).await;
+                    }
+                    promote_waiters(&state, &from).await;
+                    SignalingMessage::Error { message: "Participant moved".to_string(), code: None }
+                }
+                Err(e) => error(e),
+            }
+        }
+
+        SignalingMessage::ClearRoom { room_id } => {
+            let error = |e: RoomError| SignalingMessage::Error { message: e.to_string(), code: None };
+            let room = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room,
+                Err(e) => return error(e),
+            };
+            if !room.is_owner(participant_id) {
+                return error(RoomError::NotRoomOwner);
+            }
+            let removed = match state.room_manager.clear_room(&room.id) {
+                Ok(removed) => removed,
+                Err(e) => return error(e),
+            };
+            state.room_mixes.lock().remove(&room.id);
+            info!("{} cleared room {} ({} removed)", participant_id, room.name, removed.len());
+
+            // Everyone who was in the room hears about everyone who left it
+            let former: Vec<String> = removed.iter().map(|p| p.id.clone()).collect();
+            for id in &former {
+                state.directory.refresh(&state.room_manager, id);
+            }
+            for participant in &removed {
+                room.log_event(Some(participant_id), RoomLogEvent::Left {
+                    participant_id: participant.id.clone(),
+                    username: participant.username.clone(),
+                    reason: LeaveReason::Cleared,
+                });
+                let left = SignalingMessage::ParticipantLeft {
+                    participant_id: participant.id.clone(),
+                    reason: LeaveReason::Cleared,
+                };
+                send_to_participants(&state, &former, left);
+            }
+            send_to_participants(&state, &former, SignalingMessage::RoomLeft { success: true, error: None });
+            promote_waiters(&state, &room).await;
+            SignalingMessage::Error { message: "Room cleared".to_string(), code: None }
+        }
+
+        SignalingMessage::DeleteRoom { room_id } => {
+            let error = |e: RoomError| SignalingMessage::Error { message: e.to_string(), code: None };
+            let room = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room,
+                Err(e) => return error(e),
+            };
+            if !room.is_owner(participant_id) {
+                return error(RoomError::NotRoomOwner);
+            }
+            if !state.room_manager.delete_room(&room.id) {
+                return error(RoomError::RoomNotFound);
+            }
+            info!("{} deleted room {}", participant_id, room.name);
+            announce_room_closed(&state, &room, RoomClosedReason::Deleted);
+            SignalingMessage::Error { message: "Room deleted".to_string(), code: None }
+        }
+
+        SignalingMessage::ListServerUsers { offset, limit } => {
+            let clients = state.clients.read();
+            let directory = state.directory.entries();
+            let mut users = Vec::new();
+            
+            for (client_id, client_state) in clients.iter() {
+                let client = client_state.read();
+                if let Some(username) = &client.username {
+                    // Room and media flags, with defaults for lobby users
+                    let (current_room, audio_enabled, video_enabled) = match directory.get(client_id) {
+                        Some(entry) => (Some(entry.room_name.clone()), entry.audio_enabled, entry.video_enabled),
+                        None => (None, true, false),
+                    };
+                    
+                    users.push(ServerUserInfo {
+                        id: client_id.clone(),
+                        username: username.clone(),
+                        connected_at: std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs(),
+                        current_room,
+                        audio_enabled,
+                        video_enabled,
+                        status: client.status,
                     });
                 }
             }
             
-            info!("Returning {} connected users", users.len());
-            SignalingMessage::ServerUserList { users }
+            // Sort so pages are stable across requests
+            users.sort_by(|a, b| a.username.cmp(&b.username).then_with(|| a.id.cmp(&b.id)));
+            let (users, total) = paginate(users, offset, limit);
+
+            info!("Returning {} of {} connected users", users.len(), total);
+            SignalingMessage::ServerUserList { users, total }
         }
 
         SignalingMessage::CreateRoom {
             name,
             max_participants,
+            slug,
+            required_codec,
         } => {
-            let room = state
-                .room_manager
-                .create_room(name.clone(), max_participants.unwrap_or(10));
-            SignalingMessage::RoomCreated {
-                success: true,
-                room_id: Some(room.id.clone()),
-                room_name: Some(room.name.clone()),
-                error: None,
+            let max_participants = max_participants.unwrap_or(state.config.default_max_participants);
+            let cap = state.config.max_room_participants;
+            if cap > 0 && max_participants > cap {
+                return SignalingMessage::Error {
+                    message: format!("Rooms are limited to {} participants", cap),
+                    code: Some(ErrorCode::LimitExceeded),
+                };
+            }
+            // Limits follow the username, which survives reconnects
+            let creator_name = client_state.read().username.clone().unwrap_or_else(|| participant_id.to_string());
+            match state.room_manager.create_room_with_slug(
+                participant_id,
+                &creator_name,
+                name.clone(),
+                max_participants,
+                slug,
+                required_codec,
+                state.room_limits(),
+            ) {
+                Ok(room) => SignalingMessage::RoomCreated {
+                    success: true,
+                    room_id: Some(room.id.clone()),
+                    room_name: Some(room.name.clone()),
+                    error: None,
+                },
+                Err(e @ (RoomError::ServerRoomLimitReached | RoomError::UserRoomLimitReached)) => {
+                    info!("Room creation by {} rejected: {}", participant_id, e);
+                    SignalingMessage::Error {
+                        message: e.to_string(),
+                        code: Some(ErrorCode::LimitExceeded),
+                    }
+                }
+                Err(e) => SignalingMessage::RoomCreated {
+                    success: false,
+                    room_id: None,
+                    room_name: None,
+                    error: Some(e.to_string()),
+                },
             }
         }
 
         SignalingMessage::JoinRoom { room_id, username } => {
+            if let Err(e) = validate_username(&username, state.config.max_username_len) {
+                return invalid_username(e);
+            }
             let participant = Participant::new(participant_id.to_string(), username.clone());
 
-            match state.room_manager.join_room(&room_id, participant) {
+            // Accept a room name as well as an ID
+            let room_id = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room.id.clone(),
+                Err(e) => return unresolved_room(e),
+            };
+
+            // Promoting a spectated room: the others already know we're here
+            let was_spectating = state.room_manager.is_spectating(participant_id, &room_id);
+            let previous = state
+                .room_manager
+                .get_participant_room(participant_id)
+                .filter(|room| room.id != room_id)
+                .map(|room| {
+                    let username = leaving_username(&room, participant_id);
+                    (room, username)
+                });
+            match state.room_manager.join_room(&room_id, participant.clone()) {
                 Ok(room) => {
-                    // Broadcast to other participants that someone joined
-                    broadcast_to_room(&state, &room_id, participant_id, SignalingMessage::ParticipantJoined {
-                        participant_id: participant_id.to_string(),
-                        username: username.clone(),
-                    }).await;
-
-                    let participants: Vec<ParticipantInfo> = room
-                        .get_participants()
-                        .iter()
-                        .map(|p| ParticipantInfo {
-                            id: p.id.clone(),
-                            username: p.username.clone(),
-                            audio_enabled: p.audio_enabled,
-                            video_enabled: p.video_enabled,
-                        })
-                        .collect();
-
-                    SignalingMessage::RoomJoined {
+                    state.directory.refresh(&state.room_manager, participant_id);
+                    // Switching rooms leaves the previous one
+                    if let Some((previous, previous_username)) = previous {
+                        announce_leave(state, &previous, participant_id, &previous_username, LeaveReason::Left).await;
+                        promote_waiters(&state, &previous).await;
+                    }
+                    if !was_spectating {
+                        announce_join(&state, &room, participant_id, &username).await;
+                    }
+
+                    if state.config.udp_peer_assist {
+                        share_udp_endpoints(&state, participant_id).await;
+                    }
+
+                    room_joined(&room)
+                }
+                Err(e) => match state.room_manager.get_room(&room_id) {
+                    Some(room) if matches!(e, RoomError::RoomFull) && room.has_waiting_room() => {
+                        let position = room.enqueue_waiter(participant);
+                        info!("{} is waiting for a slot in {} (position {})", username, room.name, position);
+                        SignalingMessage::WaitingRoom { room_id, position }
+                    }
+                    _ => SignalingMessage::RoomJoined {
+                        success: false,
+                        room_id: None,
+                        room_name: None,
+                        participants: None,
+                        error: Some(e.to_string()),
+                        roster_version: 0,
+                    },
+                },
+            }
+        }
+
+        SignalingMessage::SpectateRoom { room_id, username } => {
+            if let Err(e) = validate_username(&username, state.config.max_username_len) {
+                return invalid_username(e);
+            }
+            let failed = |e: RoomError| SignalingMessage::RoomSpectating {
+                success: false,
+                room_id: None,
+                room_name: None,
+                participants: None,
+                error: Some(e.to_string()),
+                roster_version: 0,
+            };
+            let room = match state.room_manager.resolve_room(&room_id) {
+                Ok(room) => room,
+                Err(e) => return failed(e),
+            };
+            let participant = Participant::new(participant_id.to_string(), username.clone());
+            match state.room_manager.spectate_room(&room.id, participant) {
+                Ok(room) => {
+                    announce_join(&state, &room, participant_id, &username).await;
+                    let (participants, roster_version) = versioned_roster(&room);
+                    SignalingMessage::RoomSpectating {
                         success: true,
                         room_id: Some(room.id.clone()),
                         room_name: Some(room.name.clone()),
                         participants: Some(participants),
                         error: None,
+                        roster_version,
                     }
                 }
-                Err(e) => SignalingMessage::RoomJoined {
-                    success: false,
-                    room_id: None,
-                    room_name: None,
-                    participants: None,
-                    error: Some(e.to_string()),
-                },
+                Err(e) => failed(e),
+            }
+        }
+
+        SignalingMessage::StopSpectating { room_id } => {
+            let room = state.room_manager.get_room(&room_id);
+            let username = room.as_ref().map(|room| leaving_username(room, participant_id));
+            match state.room_manager.stop_spectating(participant_id, &room_id) {
+                Ok(()) => {
+                    if let (Some(room), Some(username)) = (room, username) {
+                        announce_leave(&state, &room, participant_id, &username, LeaveReason::Left).await;
+                        promote_waiters(&state, &room).await;
+                    }
+                    SignalingMessage::RoomLeft { success: true, error: None }
+                }
+                Err(e) => SignalingMessage::RoomLeft { success: false, error: Some(e.to_string()) },
             }
         }
 
         SignalingMessage::LeaveRoom => {
             // Get room info before leaving
             let room_info = state.room_manager.get_participant_room(participant_id);
+            let username = room_info.as_ref().map(|room| leaving_username(room, participant_id));
+
+            // Leaving also gives up any place in a waiting room
+            let stopped_waiting = state.room_manager.cancel_waiting(participant_id);
+            for room in &stopped_waiting {
+                notify_waiting_positions(&state, room);
+            }
             
             match state.room_manager.leave_room(participant_id) {
                 Ok(()) => {
+                    state.directory.forget(participant_id);
                     // Broadcast to other participants that someone left
-                    if let Some(room) = room_info {
-                        broadcast_to_room(&state, &room.id, participant_id, SignalingMessage::ParticipantLeft {
-                            participant_id: participant_id.to_string(),
-                        }).await;
+                    if let (Some(room), Some(username)) = (room_info, username) {
+                        announce_leave(&state, &room, participant_id, &username, LeaveReason::Left).await;
+                        promote_waiters(&state, &room).await;
                     }
                     
                     SignalingMessage::RoomLeft {
@@ -409,6 +1625,7 @@ async fn handle_message(
                         error: None,
                     }
                 },
+                Err(_) if !stopped_waiting.is_empty() => SignalingMessage::RoomLeft { success: true, error: None },
                 Err(e) => SignalingMessage::RoomLeft {
                     success: false,
                     error: Some(e.to_string()),
@@ -418,7 +1635,10 @@ async fn handle_message(
 
         SignalingMessage::ToggleAudio { enabled } => {
             if let Some(room) = state.room_manager.get_participant_room(participant_id) {
-                room.set_participant_audio(participant_id, enabled);
+                if room.set_participant_audio(participant_id, enabled) {
+                    send_roster_change(state, &room, None, media_delta(&room, participant_id), false);
+                }
+                state.directory.refresh(&state.room_manager, participant_id);
             }
             SignalingMessage::AudioToggled {
                 participant_id: participant_id.to_string(),
@@ -428,7 +1648,10 @@ async fn handle_message(
 
         SignalingMessage::ToggleVideo { enabled } => {
             if let Some(room) = state.room_manager.get_participant_room(participant_id) {
-                room.set_participant_video(participant_id, enabled);
+                if room.set_participant_video(participant_id, enabled) {
+                    send_roster_change(state, &room, None, media_delta(&room, participant_id), false);
+                }
+                state.directory.refresh(&state.room_manager, participant_id);
             }
             SignalingMessage::VideoToggled {
                 participant_id: participant_id.to_string(),
@@ -436,6 +1659,55 @@ async fn handle_message(
             }
         }
 
+        SignalingMessage::SetStatus { status } => {
+            {
+                let mut client = client_state.write();
+                client.status = status;
+                client.auto_away = false;
+            }
+            let update = SignalingMessage::UserStatusChanged {
+                participant_id: participant_id.to_string(),
+                status,
+            };
+            broadcast_to_server(&state, Some(participant_id), update.clone());
+            update
+        }
+
+        SignalingMessage::RaiseHand { raised } => {
+            if let Some(room) = state.room_manager.get_participant_room(participant_id) {
+                let order = if raised {
+                    room.raise_hand(participant_id).unwrap_or(0)
+                } else {
+                    0
+                };
+                let previous = if raised { None } else { room.lower_hand(participant_id) };
+
+                let update = SignalingMessage::HandRaised {
+                    participant_id: participant_id.to_string(),
+                    raised,
+                    order,
+                };
+                broadcast_to_room(&state, &room.id, participant_id, update.clone()).await;
+
+                // Everyone queued behind a lowered hand moves up one place
+                if let Some(previous) = previous {
+                    let queue = room.raised_hands();
+                    for (index, id) in queue.into_iter().enumerate().skip(previous as usize - 1) {
+                        let moved = SignalingMessage::HandRaised {
+                            participant_id: id,
+                            raised: true,
+                            order: index as u32 + 1,
+                        };
+                        broadcast_to_room_all(&state, &room.id, moved).await;
+                    }
+                }
+
+                update
+            } else {
+                SignalingMessage::Error { message: "Not in a room".to_string(), code: None }
+            }
+        }
+
         SignalingMessage::SendMessage { content } => {
             // Get sender username
             let sender_username = client_state.read().username.clone().unwrap_or_else(|| "Unknown".to_string());
@@ -444,52 +1716,326 @@ async fn handle_message(
             if let Some(room) = state.room_manager.get_participant_room(participant_id) {
                 let room_id = room.id.clone();
                 
-                // Create chat message
-                let chat_message = SignalingMessage::MessageReceived {
+                // Store in room history so it can be edited or deleted later
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let record = room.record_message(participant_id, &sender_username, content.clone(), timestamp);
+
+                // Broadcast to all participants in the room (including sender)
+                broadcast_to_room_all(&state, &room_id, chat_message(&room_id, record)).await;
+                
+                info!("Chat message from {} in room {}: {}", sender_username, room.name, content);
+            }
+            
+            // Return success response
+            SignalingMessage::Error { message: "Message sent".to_string(), code: None }
+        }
+
+        SignalingMessage::EditMessage { message_id, new_content } => {
+            if let Some(room) = state.room_manager.get_participant_room(participant_id) {
+                match room.edit_message(&message_id, participant_id, new_content) {
+                    Ok(record) => {
+                        let edited = SignalingMessage::MessageEdited {
+                            message_id: record.message_id,
+                            new_content: record.content,
+                            edited: record.edited,
+                        };
+                        broadcast_to_room_all(&state, &room.id, edited).await;
+                        SignalingMessage::Error { message: "Message edited".to_string(), code: None }
+                    }
+                    Err(e) => SignalingMessage::Error { message: e.to_string(), code: None },
+                }
+            } else {
+                SignalingMessage::Error { message: "Not in a room".to_string(), code: None }
+            }
+        }
+
+        SignalingMessage::DeleteMessage { message_id } => {
+            if let Some(room) = state.room_manager.get_participant_room(participant_id) {
+                match room.delete_message(&message_id, participant_id) {
+                    Ok(()) => {
+                        broadcast_to_room_all(&state, &room.id, SignalingMessage::MessageDeleted { message_id }).await;
+                        SignalingMessage::Error { message: "Message deleted".to_string(), code: None }
+                    }
+                    Err(e) => SignalingMessage::Error { message: e.to_string(), code: None },
+                }
+            } else {
+                SignalingMessage::Error { message: "Not in a room".to_string(), code: None }
+            }
+        }
+
+        SignalingMessage::GroupMessage { recipient_ids, content } => {
+            let sender_username = client_state.read().username.clone().unwrap_or_else(|| "Unknown".to_string());
+
+            // Only co-members of the sender's room are eligible; others are silently dropped
+            let recipients = state.room_manager.co_members(participant_id, &recipient_ids);
+            if !recipients.is_empty() {
+                let group_message = SignalingMessage::GroupMessageReceived {
                     sender_id: participant_id.to_string(),
                     sender_username: sender_username.clone(),
-                    content: content.clone(),
+                    recipient_ids: recipients.clone(),
+                    content,
                     timestamp: std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
                         .unwrap_or_default()
                         .as_secs(),
                 };
-                
-                // Broadcast to all participants in the room (including sender)
-                broadcast_to_room_all(&state, &room_id, chat_message).await;
-                
-                info!("Chat message from {} in room {}: {}", sender_username, room.name, content);
+
+                // Deliver to the recipients and echo back to the sender
+                let mut targets = recipients;
+                targets.push(participant_id.to_string());
+                send_to_participants(&state, &targets, group_message);
+
+                info!("Group message from {} to {} recipients", sender_username, targets.len() - 1);
             }
-            
-            // Return success response
-            SignalingMessage::Error { message: "Message sent".to_string() }
+
+            SignalingMessage::Error { message: "Message sent".to_string(), code: None }
         }
 
-        SignalingMessage::AudioData { data } => {
-            // Find which room the sender is in and forward audio to all participants
+        // End-to-end chat: the server only relays key material and ciphertext
+        SignalingMessage::E2eKeyOffer { public_key } => match state.room_manager.get_participant_room(participant_id) {
+            Some(room) => {
+                broadcast_to_room(&state, &room.id, participant_id, SignalingMessage::E2eKeyOfferReceived {
+                    sender_id: participant_id.to_string(),
+                    room_id: room.id.clone(),
+                    public_key,
+                }).await;
+                SignalingMessage::Error { message: "Key offer sent".to_string(), code: None }
+            }
+            None => SignalingMessage::Error { message: RoomError::ParticipantNotFound.to_string(), code: None },
+        },
+
+        SignalingMessage::E2eKeyAccept { recipient_id, ciphertext } => {
+            let recipients = state.room_manager.co_members(participant_id, std::slice::from_ref(&recipient_id));
+            match state.room_manager.get_participant_room(participant_id) {
+                Some(room) if !recipients.is_empty() => {
+                    send_to_participants(&state, &recipients, SignalingMessage::E2eKeyAcceptReceived {
+                        sender_id: participant_id.to_string(),
+                        room_id: room.id.clone(),
+                        ciphertext,
+                    });
+                    SignalingMessage::Error { message: "Key accept sent".to_string(), code: None }
+                }
+                _ => SignalingMessage::Error { message: RoomError::ParticipantNotFound.to_string(), code: None },
+            }
+        }
+
+        SignalingMessage::SendEncryptedMessage { ciphertext } => {
+            let sender_username = client_state.read().username.clone().unwrap_or_else(|| "Unknown".to_string());
             if let Some(room) = state.room_manager.get_participant_room(participant_id) {
-                let room_id = room.id.clone();
-                
-                // Create audio message
-                let audio_message = SignalingMessage::AudioDataReceived {
+                let message = SignalingMessage::EncryptedMessageReceived {
                     sender_id: participant_id.to_string(),
-                    data,
+                    sender_username,
+                    ciphertext,
+                    timestamp: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
                 };
+                // Not kept in room history: the server can't read or edit it
+                broadcast_to_room_all(&state, &room.id, message).await;
+            }
+            SignalingMessage::Error { message: "Message sent".to_string(), code: None }
+        }
+
+        SignalingMessage::AudioData { data, batched, codec, captured_at } => {
+            let max_payload = state.config.max_audio_payload_bytes;
+            if max_payload > 0 && data.len() > max_payload {
+                let rejected = {
+                    let mut client = client_state.write();
+                    client.oversized_audio += 1;
+                    client.oversized_audio
+                };
+                if rejected.is_power_of_two() {
+                    warn!("Rejected {} oversized audio payloads from {} (latest {} bytes)", rejected, participant_id, data.len());
+                }
+                return SignalingMessage::Error {
+                    message: format!("Audio payload over {} bytes", max_payload),
+                    code: Some(ErrorCode::PayloadTooLarge),
+                };
+            }
+
+            // Rooms locked to a codec get only that codec, converted if we can.
+            // The decoded frames are kept so fan-out doesn't decode again.
+            let required_codec = state.room_manager.get_participant_room(participant_id).and_then(|room| room.required_codec);
+            let mut decoded = None;
+            let (data, codec) = match required_codec {
+                Some(required) if required != codec => {
+                    let converted = if state.config.transcode_audio {
+                        transcode_from(state, participant_id, data, batched, codec, required).await
+                    } else {
+                        None
+                    };
+                    match converted {
+                        Some((data, frames)) => {
+                            decoded = Some(frames);
+                            (data, required)
+                        }
+                        None => {
+                            debug!("Rejecting {:?} audio from {} in a {:?} room", codec, participant_id, required);
+                            return SignalingMessage::Error {
+                                message: format!("This room only accepts {:?} audio", required),
+                                code: Some(ErrorCode::CodecNotAllowed),
+                            };
+                        }
+                    }
+                }
+                _ => (data, codec),
+            };
+
+            // Find which room the sender is in and forward audio to all participants
+            let max_age_ms = state.config.max_audio_age_ms;
+            if audio_is_stale(captured_at, unix_millis(), max_age_ms) {
+                debug!("Dropping audio from {} older than {}ms", participant_id, max_age_ms);
+            } else if let Some(room) = state.room_manager.get_participant_room(participant_id) {
+                let room_id = room.id.clone();
+                // Stray frames from a muted participant aren't relayed
+                let muted = room.get_participant(participant_id).is_some_and(|p| !p.audio_enabled);
                 
-                // Broadcast to all other participants in the room (excluding sender)
-                broadcast_to_room(&state, &room_id, participant_id, audio_message).await;
+                if muted && !state.config.relay_muted_audio {
+                    debug!("Dropping audio from muted participant {}", participant_id);
+                } else if room.is_echo_mode() {
+                    // Echo test: loop the audio back to the sender only,
+                    // quiet or not, so they hear exactly what they send
+                    let audio_message = SignalingMessage::AudioDataReceived {
+                        sender_id: participant_id.to_string(),
+                        data,
+                        batched,
+                        codec,
+                        captured_at,
+                    };
+                    send_to_participants(&state, &[participant_id.to_string()], audio_message);
+                } else if is_gated_silence(state, participant_id, &data, batched, codec, &mut decoded).await {
+                    debug!("Dropping silent audio from {}", participant_id);
+                } else if state.config.mix_audio {
+                    let mut mixes = state.room_mixes.lock();
+                    let mix = match mixes.entry(room_id) {
+                        std::collections::hash_map::Entry::Occupied(entry) => Some(entry.into_mut()),
+                        std::collections::hash_map::Entry::Vacant(entry) => match spawn_room_mix(state, entry.key()) {
+                            Ok(mix) => Some(entry.insert(mix)),
+                            Err(e) => {
+                                error!("Cannot start mixing for room {}: {}", entry.key(), e);
+                                None
+                            }
+                        },
+                    };
+                    if mix.is_some_and(|mix| !mix.push(participant_id, data, batched, codec)) {
+                        debug!("Room mix is behind, dropping audio from {}", participant_id);
+                    }
+                } else if state.config.transcode_audio {
+                    let audio = RelayedAudio { data, batched, codec, decoded, captured_at };
+                    forward_transcoded_audio(state, &room, participant_id, audio).await;
+                } else {
+                    // Broadcast to all other participants in the room (excluding sender)
+                    let audio_message = SignalingMessage::AudioDataReceived {
+                        sender_id: participant_id.to_string(),
+                        data,
+                        batched,
+                        codec,
+                        captured_at,
+                    };
+                    broadcast_to_room(&state, &room_id, participant_id, audio_message).await;
+                }
             }
             
             // No response needed for audio data
-            SignalingMessage::Error { message: "Audio forwarded".to_string() }
+            SignalingMessage::Error { message: "Audio forwarded".to_string(), code: None }
         }
 
         _ => SignalingMessage::Error {
             message: "Unsupported message type".to_string(),
+            code: None,
         },
     }
 }
 
+/// Error reply for a room reference that `resolve_room` couldn't match
+fn unresolved_room(e: RoomError) -> SignalingMessage {
+    let code = match e {
+        RoomError::AmbiguousRoomName => ErrorCode::Ambiguous,
+        _ => ErrorCode::RoomNotFound,
+    };
+    SignalingMessage::Error { message: e.to_string(), code: Some(code) }
+}
+
+/// Rejection for a name failing `validate_username`
+fn invalid_username(e: UsernameError) -> SignalingMessage {
+    SignalingMessage::Error {
+        message: e.to_string(),
+        code: Some(ErrorCode::InvalidUsername),
+    }
+}
+
+/// What this server supports, as reported to `DescribeCapabilities`
+fn capabilities(config: &ServerConfig) -> Capabilities {
+    Capabilities {
+        protocol_version: PROTOCOL_VERSION,
+        codecs: AudioCodec::ALL.to_vec(),
+        max_message_bytes: MAX_FRAME_LEN as u32,
+        // Usernames aren't authenticated yet
+        auth_required: false,
+        e2e_supported: true,
+        max_room_participants: config.max_room_participants,
+        time_sync: true,
+    }
+}
+
+/// Banner sent before key exchange
+fn server_info(state: &ServerState) -> SignalingMessage {
+    SignalingMessage::ServerInfo {
+        name: state.config.server_name.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: PROTOCOL_VERSION,
+        motd: state.motd.current(),
+    }
+}
+
+/// Summary of a room as sent to clients
+fn room_info(room: &Room) -> RoomInfo {
+    RoomInfo {
+        id: room.id.clone(),
+        name: room.name.clone(),
+        slug: room.slug.clone(),
+        participants: room.participant_count() as u32,
+        max_participants: room.max_participants,
+        is_locked: room.is_locked,
+        is_full: room.is_full(),
+        // Rooms can't set a password yet
+        password_protected: false,
+        topic: room.topic(),
+        required_codec: room.required_codec,
+        last_activity: room.last_activity(),
+    }
+}
+
+/// Validate a config file and the certificate/key it points at without
+/// binding any sockets. Returns every problem found; empty means OK.
+fn check_config(path: &std::path::Path) -> Vec<String> {
+    let config = match ServerConfig::from_file(&path.to_string_lossy()) {
+        Ok(config) => config,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut problems = match config.validate() {
+        Ok(()) => Vec::new(),
+        Err(ConfigError::Invalid(problems)) => problems,
+        Err(e) => vec![e.to_string()],
+    };
+    match load_certs(&config.certfile) {
+        Ok(certs) if certs.is_empty() => {
+            problems.push(format!("certfile {}: no certificates found", config.certfile.display()))
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("certfile {}: {}", config.certfile.display(), e)),
+    }
+    if let Err(e) = load_key(&config.keyfile) {
+        problems.push(format!("keyfile {}: {}", config.keyfile.display(), e));
+    }
+    problems
+}
+
 /// Load TLS certificates
 fn load_certs(path: &PathBuf) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>> {
     let file = std::fs::File::open(path)?;
@@ -498,6 +2044,57 @@ fn load_certs(path: &PathBuf) -> Result<Vec<rustls::pki_types::CertificateDer<'s
     Ok(certs)
 }
 
+/// Generate a self-signed cert/key at the configured paths unless both exist
+fn ensure_self_signed_cert(config: &ServerConfig, host: &str) -> Result<()> {
+    match (config.certfile.exists(), config.keyfile.exists()) {
+        (true, true) => {
+            info!("Using existing certificate {}", config.certfile.display());
+            return Ok(());
+        }
+        (false, false) => {}
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Only one of {} and {} exists; restore the missing file or remove the other to generate a new pair",
+                config.certfile.display(),
+                config.keyfile.display()
+            ));
+        }
+    }
+
+    let mut hostnames = vec!["localhost".to_string()];
+    if host != "0.0.0.0" && host != "::" && host != "localhost" {
+        hostnames.push(host.to_string());
+    }
+    let (cert_pem, key_pem) = generate_self_signed(&hostnames)?;
+
+    for path in [&config.certfile, &config.keyfile] {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    std::fs::write(&config.certfile, cert_pem)?;
+    write_private_key(&config.keyfile, key_pem.as_bytes())?;
+    info!(
+        "Generated self-signed certificate for {:?} at {}",
+        hostnames,
+        config.certfile.display()
+    );
+    Ok(())
+}
+
+/// Write a new private key readable by the owner only, never over an
+/// existing file
+fn write_private_key(path: &Path, key_pem: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(key_pem)?;
+    Ok(())
+}
+
 /// Load TLS private key
 fn load_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>> {
     let file = std::fs::File::open(path)?;
@@ -506,6 +2103,39 @@ fn load_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>> {
     keys.ok_or_else(|| anyhow::anyhow!("No private key found"))
 }
 
+/// Send a message to every logged-in client on the server, optionally skipping one
+fn broadcast_to_server(state: &Arc<ServerState>, except: Option<&str>, message: SignalingMessage) {
+    for (client_id, client_state) in state.clients.read().iter() {
+        if Some(client_id.as_str()) == except {
+            continue;
+        }
+        let client = client_state.read();
+        if client.username.is_some() {
+            let _ = client.message_tx.send(message.clone());
+        }
+    }
+}
+
+/// Clean up after a deleted room and tell everyone on the server it's gone,
+/// so former members, its waiting room and anyone showing it in a room list
+/// can drop it
+fn announce_room_closed(state: &Arc<ServerState>, room: &Room, reason: RoomClosedReason) {
+    state.room_mixes.lock().remove(&room.id);
+    let members = room.get_participant_ids();
+    for id in &members {
+        state.directory.refresh(&state.room_manager, id);
+    }
+    // Joining doesn't need a login, so those involved are told directly
+    let involved: HashSet<String> = members.into_iter().chain(room.drain_waiters()).collect();
+    let closed = SignalingMessage::RoomClosed { room_id: room.id.clone(), reason };
+    for (client_id, client_state) in state.clients.read().iter() {
+        let client = client_state.read();
+        if client.username.is_some() || involved.contains(client_id) {
+            let _ = client.message_tx.send(closed.clone());
+        }
+    }
+}
+
 /// Broadcast a message to all participants in a room except the sender
 async fn broadcast_to_room(
     state: &Arc<ServerState>, 
@@ -515,24 +2145,33 @@ async fn broadcast_to_room(
 ) {
     if let Some(room) = state.room_manager.get_room(room_id) {
         let participant_ids = room.get_participant_ids();
-        let clients = state.clients.read();
-        
-        info!("Broadcasting {:?} to room {} (except sender {})", message, room_id, sender_id);
-        info!("Participants in room: {:?}", participant_ids);
-        
-        for participant_id in participant_ids {
-            // Don't send to the sender
-            if participant_id != sender_id {
-                if let Some(client_state) = clients.get(&participant_id) {
-                    info!("Sending broadcast to participant {}", participant_id);
-                    if let Err(e) = client_state.read().message_tx.send(message.clone()) {
-                        error!("Failed to send broadcast to {}: {}", participant_id, e);
+        let mut dead = Vec::new();
+        {
+            let clients = state.clients.read();
+            
+            info!("Broadcasting {:?} to room {} (except sender {})", message, room_id, sender_id);
+            info!("Participants in room: {:?}", participant_ids);
+            
+            for participant_id in participant_ids {
+                // Spectators get chat and presence but not audio
+                if message.is_audio() && !state.room_manager.is_active_in(&participant_id, room_id) {
+                    continue;
+                }
+                // Don't send to the sender
+                if participant_id != sender_id {
+                    if let Some(client_state) = clients.get(&participant_id) {
+                        info!("Sending broadcast to participant {}", participant_id);
+                        if let Err(e) = client_state.read().message_tx.send(message.clone()) {
+                            error!("Failed to send broadcast to {}: {}", participant_id, e);
+                            dead.push(participant_id);
+                        }
+                    } else {
+                        info!("Client {} not found in clients map", participant_id);
                     }
-                } else {
-                    info!("Client {} not found in clients map", participant_id);
                 }
             }
         }
+        remove_dead_clients(state, dead);
     } else {
         info!("Room {} not found for broadcast", room_id);
     }
@@ -546,22 +2185,2439 @@ async fn broadcast_to_room_all(
 ) {
     if let Some(room) = state.room_manager.get_room(room_id) {
         let participant_ids = room.get_participant_ids();
+        let mut dead = Vec::new();
+        {
+            let clients = state.clients.read();
+            
+            info!("Broadcasting {:?} to all in room {}", message, room_id);
+            info!("Participants in room: {:?}", participant_ids);
+            
+            for participant_id in participant_ids {
+                if let Some(client_state) = clients.get(&participant_id) {
+                    info!("Sending broadcast to participant {}", participant_id);
+                    if let Err(e) = client_state.read().message_tx.send(message.clone()) {
+                        error!("Failed to send broadcast to {}: {}", participant_id, e);
+                        dead.push(participant_id);
+                    }
+                } else {
+                    info!("Client {} not found in clients map", participant_id);
+                }
+            }
+        }
+        remove_dead_clients(state, dead);
+    } else {
+        info!("Room {} not found for broadcast", room_id);
+    }
+}
+
+/// Drop clients whose connection has gone away and tell their rooms.
+///
+/// Recipients that turn out to be dead while sending `ParticipantLeft` are
+/// queued here too, so membership heals in one pass.
+fn remove_dead_clients(state: &Arc<ServerState>, mut dead: Vec<String>) {
+    while let Some(participant_id) = dead.pop() {
+        let Some(left) = release_client(state, &participant_id, LeaveReason::Disconnected) else { continue };
+        warn!("Removed unreachable client {}", participant_id);
+
+        for (room, username) in &left {
+            if !room.waiting_ids().is_empty() {
+                let (state, room) = (state.clone(), room.clone());
+                tokio::spawn(async move { promote_waiters(&state, &room).await });
+            }
+            let mut messages = vec![SignalingMessage::ParticipantLeft {
+                participant_id: participant_id.clone(),
+                reason: LeaveReason::Disconnected,
+            }];
+            room.log_event(None, RoomLogEvent::Left {
+                participant_id: participant_id.clone(),
+                username: username.clone(),
+                reason: LeaveReason::Disconnected,
+            });
+            let content = format!("{} {}", username, LeaveReason::Disconnected.describe());
+            messages.extend(system_message(room, content));
+            let delta = roster_delta(room, Vec::new(), vec![participant_id.clone()], Vec::new());
+
+            let clients = state.clients.read();
+            for member_id in room.get_participant_ids() {
+                if let Some(client_state) = clients.get(&member_id) {
+                    let client = client_state.read();
+                    let update = roster_update_for(&client, room, &delta, true);
+                    if messages.iter().chain(&update).any(|m| client.message_tx.send(m.clone()).is_err()) {
+                        dead.push(member_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An `AudioData` payload on its way to the room
+struct RelayedAudio {
+    data: Vec<u8>,
+    batched: bool,
+    codec: AudioCodec,
+    /// The payload's frames, if already decoded on the way in
+    decoded: Option<Vec<Vec<f32>>>,
+    captured_at: Option<u64>,
+}
+
+/// Forward audio to the room's active members, re-encoding it for those
+/// that negotiated a different codec than the sender used
+async fn forward_transcoded_audio(state: &Arc<ServerState>, room: &Room, sender_id: &str, audio: RelayedAudio) {
+    let RelayedAudio { data, batched, codec, decoded, captured_at } = audio;
+    // Recipients grouped by codec, so each conversion happens once
+    let mut groups: Vec<(AudioCodec, Vec<String>)> = Vec::new();
+    {
+        let clients = state.clients.read();
+        for member_id in room.get_participant_ids() {
+            if member_id == sender_id || !state.room_manager.is_active_in(&member_id, &room.id) {
+                continue;
+            }
+            let Some(client) = clients.get(&member_id) else { continue };
+            let target = client.read().codec;
+            match groups.iter_mut().find(|(c, _)| *c == target) {
+                Some((_, ids)) => ids.push(member_id),
+                None => groups.push((target, vec![member_id])),
+            }
+        }
+    }
+
+    // Decode once (unless that's been done), then encode once per codec
+    // the recipients want
+    let targets: Vec<AudioCodec> = groups.iter().map(|(target, _)| *target).filter(|&target| target != codec).collect();
+    let mut converted = if targets.is_empty() {
+        Vec::new()
+    } else {
+        let (sender, payload) = (sender_id.to_string(), data.clone());
+        with_transcoder(state, sender_id, move |transcoder| {
+            let frames = match decoded.map_or_else(|| transcoder.decode(&payload, batched, codec), Ok) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    warn!("Dropping {:?} audio from {}: {}", codec, sender, e);
+                    return Vec::new();
+                }
+            };
+            targets
+                .into_iter()
+                .filter_map(|target| match transcoder.encode(&frames, batched, target) {
+                    Ok(payload) => Some((target, payload)),
+                    Err(e) => {
+                        warn!("Dropping audio from {} ({:?} -> {:?}): {}", sender, codec, target, e);
+                        None
+                    }
+                })
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+    };
+
+    for (target, recipients) in groups {
+        let payload = if target == codec {
+            data.clone()
+        } else {
+            match converted.iter().position(|(c, _)| *c == target) {
+                Some(i) => converted.swap_remove(i).1,
+                None => continue,
+            }
+        };
+        let message = SignalingMessage::AudioDataReceived {
+            sender_id: sender_id.to_string(),
+            data: payload,
+            batched,
+            codec: target,
+            captured_at,
+        };
+        send_to_participants(state, &recipients, message);
+    }
+}
+
+/// Convert a sender's audio payload with their transcoder, logging
+/// failures. The decoded frames come back too, so the sender's stream is
+/// only decoded once however many codecs it goes out in.
+async fn transcode_from(
+    state: &Arc<ServerState>,
+    sender_id: &str,
+    data: Vec<u8>,
+    batched: bool,
+    from: AudioCodec,
+    to: AudioCodec,
+) -> Option<(Vec<u8>, Vec<Vec<f32>>)> {
+    let sender = sender_id.to_string();
+    with_transcoder(state, sender_id, move |transcoder| {
+        let converted = transcoder
+            .decode(&data, batched, from)
+            .and_then(|frames| Ok((transcoder.encode(&frames, batched, to)?, frames)));
+        match converted {
+            Ok(converted) => Some(converted),
+            Err(e) => {
+                warn!("Dropping audio from {} ({:?} -> {:?}): {}", sender, from, to, e);
+                None
+            }
+        }
+    })
+    .await
+    .flatten()
+}
+
+/// Whether the silence gate (if any) drops this packet. The payload is
+/// decoded with the sender's transcoder unless that's been done already,
+/// and the frames are left in `decoded` for fan-out. Packets that can't be
+/// decoded are let through; judging them is the recipients' job.
+async fn is_gated_silence(
+    state: &Arc<ServerState>,
+    sender_id: &str,
+    data: &[u8],
+    batched: bool,
+    codec: AudioCodec,
+    decoded: &mut Option<Vec<Vec<f32>>>,
+) -> bool {
+    let Some(gate) = &state.silence_gate else { return false };
+    if decoded.is_none() {
+        let payload = data.to_vec();
+        *decoded = with_transcoder(state, sender_id, move |transcoder| transcoder.decode(&payload, batched, codec).ok())
+            .await
+            .flatten();
+    }
+    match decoded {
+        Some(frames) => !gate.lock().should_relay(sender_id, frames, Instant::now()),
+        None => false,
+    }
+}
+
+/// Run `convert` on the sender's transcoder. Codec work goes to the
+/// blocking pool so it doesn't stall the runtime, and the shared map is
+/// only locked to look the transcoder up.
+async fn with_transcoder<T, F>(state: &Arc<ServerState>, sender_id: &str, convert: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Transcoder) -> T + Send + 'static,
+{
+    let transcoder = {
+        let mut transcoders = state.transcoders.lock();
+        match transcoders.entry(sender_id.to_string()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.get().clone(),
+            std::collections::hash_map::Entry::Vacant(entry) => match Transcoder::new() {
+                Ok(transcoder) => entry.insert(Arc::new(Mutex::new(transcoder))).clone(),
+                Err(e) => {
+                    warn!("Cannot transcode audio from {}: {}", sender_id, e);
+                    return None;
+                }
+            },
+        }
+    };
+    match tokio::task::spawn_blocking(move || convert(&mut transcoder.lock())).await {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("Transcoding task for {} failed: {}", sender_id, e);
+            None
+        }
+    }
+}
+
+/// Start the mixing thread for a room. It holds only a weak reference to
+/// the server, and stops once the room is gone.
+fn spawn_room_mix(state: &Arc<ServerState>, room_id: &str) -> std::io::Result<RoomMixHandle> {
+    let server = Arc::downgrade(state);
+    let mix_room_id = room_id.to_string();
+    // Tearing down an unreachable client may spawn tasks
+    let runtime = tokio::runtime::Handle::current();
+    RoomMixHandle::spawn(room_id, move |mix| match server.upgrade() {
+        Some(state) => {
+            let _runtime = runtime.enter();
+            send_room_mix(&state, &mix_room_id, mix)
+        }
+        None => false,
+    })
+}
+
+/// Send every active room member their share of the room's next mixed
+/// frame: everyone else's audio, in the member's negotiated codec. Returns
+/// `false` once the room no longer exists.
+fn send_room_mix(state: &Arc<ServerState>, room_id: &str, mix: &mut RoomMix) -> bool {
+    let Some(room) = state.room_manager.get_room(room_id) else { return false };
+    let Some(frame) = mix.next_frame() else { return true };
+    let recipients: Vec<(String, AudioCodec)> = {
+        let clients = state.clients.read();
+        room.get_participant_ids()
+            .into_iter()
+            .filter(|member_id| state.room_manager.is_active_in(member_id, room_id))
+            .filter_map(|member_id| clients.get(&member_id).map(|client| (member_id, client.read().codec)))
+            .collect()
+    };
+    for (member_id, codec) in recipients {
+        let Some(samples) = frame.without(&member_id) else { continue };
+        match mix.encode_for(&member_id, &samples, codec) {
+            Ok(data) => send_to_participants(state, &[member_id], SignalingMessage::AudioDataReceived {
+                sender_id: MIXED_AUDIO_SENDER_ID.to_string(),
+                data,
+                batched: false,
+                codec,
+                captured_at: None,
+            }),
+            Err(e) => warn!("Cannot encode room mix for {}: {}", member_id, e),
+        }
+    }
+    true
+}
+
+/// Send a message to a specific set of participants
+fn send_to_participants(state: &Arc<ServerState>, participant_ids: &[String], message: SignalingMessage) {
+    let mut dead = Vec::new();
+    {
         let clients = state.clients.read();
-        
-        info!("Broadcasting {:?} to all in room {}", message, room_id);
-        info!("Participants in room: {:?}", participant_ids);
-        
         for participant_id in participant_ids {
-            if let Some(client_state) = clients.get(&participant_id) {
-                info!("Sending broadcast to participant {}", participant_id);
+            if let Some(client_state) = clients.get(participant_id) {
                 if let Err(e) = client_state.read().message_tx.send(message.clone()) {
-                    error!("Failed to send broadcast to {}: {}", participant_id, e);
+                    error!("Failed to send message to {}: {}", participant_id, e);
+                    dead.push(participant_id.clone());
                 }
-            } else {
-                info!("Client {} not found in clients map", participant_id);
             }
         }
-    } else {
-        info!("Room {} not found for broadcast", room_id);
+    }
+    remove_dead_clients(state, dead);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pqc_chat::protocol::{ClockOffset, Roster};
+
+    fn register_client(state: &Arc<ServerState>) -> (String, outbound::OutboundReceiver) {
+        let (tx, rx) = outbound::channel(state.config.client_queue_depth);
+        let client = ClientState::new(tx, &state.config);
+        let id = client.participant_id.clone();
+        state.clients.write().insert(id.clone(), Arc::new(RwLock::new(client)));
+        (id, rx)
+    }
+
+    #[tokio::test]
+    async fn test_logout_leaves_room_and_notifies() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for id in [&alice, &bob] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+
+        disconnect_client(&state, &alice, LeaveReason::Left).await;
+
+        assert!(!room.has_participant(&alice));
+        assert!(state.room_manager.get_participant_room(&alice).is_none());
+        assert!(!state.clients.read().contains_key(&alice));
+
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::ParticipantLeft { participant_id, reason: LeaveReason::Left }) => {
+                assert_eq!(participant_id, alice)
+            }
+            other => panic!("expected ParticipantLeft, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_owner_sets_room_topic() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, _owner_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        log_in(&state, &bob);
+        let room = state
+            .room_manager
+            .create_room_for(&owner, "Study".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let set_topic = |topic: &str| SignalingMessage::SetRoomTopic {
+            room_id: room.id.clone(),
+            topic: Some(topic.to_string()),
+        };
+
+        // Only the owner may set it
+        match handle_message(set_topic("Bob's room now"), &bob, &client(&bob), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert_eq!(room.topic(), None);
+
+        match handle_message(set_topic("  Exam prep, quiet please "), &owner, &client(&owner), &state).await {
+            SignalingMessage::RoomTopicChanged { topic, .. } => assert_eq!(topic.as_deref(), Some("Exam prep, quiet please")),
+            other => panic!("expected RoomTopicChanged, got {:?}", other),
+        }
+        // Everyone on the server hears about it, not only members
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::RoomTopicChanged { room_id, topic }) => {
+                assert_eq!(room_id, room.id);
+                assert_eq!(topic.as_deref(), Some("Exam prep, quiet please"));
+            }
+            other => panic!("expected RoomTopicChanged, got {:?}", other),
+        }
+
+        let list = SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false };
+        match handle_message(list, &bob, &client(&bob), &state).await {
+            SignalingMessage::RoomList { rooms, .. } => {
+                let info = rooms.iter().find(|r| r.id == room.id).unwrap();
+                assert_eq!(info.topic.as_deref(), Some("Exam prep, quiet please"));
+            }
+            other => panic!("expected RoomList, got {:?}", other),
+        }
+
+        let too_long = set_topic(&"x".repeat(pqc_chat::room::MAX_TOPIC_LEN + 1));
+        match handle_message(too_long, &owner, &client(&owner), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::TopicTooLong.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        let clear = SignalingMessage::SetRoomTopic { room_id: room.id.clone(), topic: None };
+        handle_message(clear, &owner, &client(&owner), &state).await;
+        assert_eq!(room.topic(), None);
+    }
+
+    #[tokio::test]
+    async fn test_join_announced_in_chat() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&alice, "Test".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        state.room_manager.join_room(&room.id, Participant::new(alice.clone(), "alice".to_string())).unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let join = |username: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: username.to_string(),
+        };
+
+        handle_message(join("bob"), &bob, &client(&bob), &state).await;
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::ParticipantJoined { .. })));
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::MessageReceived { sender_id, content, .. }) => {
+                assert_eq!(sender_id, SYSTEM_SENDER_ID);
+                assert_eq!(content, "bob joined the room");
+            }
+            other => panic!("expected system MessageReceived, got {:?}", other),
+        }
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::RoomRoster { .. })));
+        assert_eq!(room.chat_history().len(), 1);
+
+        // Only the owner can turn announcements off
+        let settings = SignalingMessage::UpdateRoomSettings { room_id: room.id.clone(), announce_joins: false, waiting_room: None };
+        handle_message(settings.clone(), &bob, &client(&bob), &state).await;
+        assert!(room.announces_joins());
+        handle_message(settings, &alice, &client(&alice), &state).await;
+        assert!(!room.announces_joins());
+
+        handle_message(join("carol"), &carol, &client(&carol), &state).await;
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::ParticipantJoined { .. })));
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::RoomRoster { .. })));
+        assert!(alice_rx.try_recv().is_err());
+        assert_eq!(room.chat_history().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_roster_pushed_on_membership_change() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        room.set_announce_joins(false);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+        state.room_manager.join_room(&room.id, Participant::new(alice.clone(), "alice".to_string())).unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let join = |username: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: username.to_string(),
+        };
+
+        handle_message(join("bob"), &bob, &client(&bob), &state).await;
+        handle_message(join("carol"), &carol, &client(&carol), &state).await;
+        handle_message(SignalingMessage::LeaveRoom, &bob, &client(&bob), &state).await;
+
+        let mut last = None;
+        while let Ok(message) = alice_rx.try_recv() {
+            if let SignalingMessage::RoomRoster { room_id, participants, .. } = message {
+                assert_eq!(room_id, room.id);
+                last = Some(participants);
+            }
+        }
+        let roster: Vec<String> = last.expect("no roster pushed").into_iter().map(|p| p.id).collect();
+        let members: Vec<String> = room.get_participants_ordered().into_iter().map(|p| p.id).collect();
+        assert_eq!(roster, members);
+        assert_eq!(roster, vec![alice, carol]);
+    }
+
+    #[tokio::test]
+    async fn test_roster_deltas_track_full_roster() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        room.set_announce_joins(false);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let join = |username: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: username.to_string(),
+        };
+        let hello = SignalingMessage::Hello { features: vec![FEATURE_ROSTER_DELTA.to_string()] };
+        handle_message(hello, &alice, &client(&alice), &state).await;
+
+        let mut copy = Roster::default();
+        match handle_message(join("alice"), &alice, &client(&alice), &state).await {
+            SignalingMessage::RoomJoined { success: true, participants: Some(participants), roster_version, .. } => {
+                copy.reset(&room.id, roster_version, participants);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        handle_message(join("bob"), &bob, &client(&bob), &state).await;
+        handle_message(join("carol"), &carol, &client(&carol), &state).await;
+        handle_message(SignalingMessage::ToggleAudio { enabled: false }, &bob, &client(&bob), &state).await;
+        handle_message(SignalingMessage::ToggleVideo { enabled: false }, &carol, &client(&carol), &state).await;
+        handle_message(SignalingMessage::LeaveRoom, &bob, &client(&bob), &state).await;
+
+        let mut deltas = 0;
+        while let Ok(message) = alice_rx.try_recv() {
+            match message {
+                SignalingMessage::RoomRosterDelta { room_id, version, added, removed, media_changes } => {
+                    assert!(copy.apply_delta(&room_id, version, added, &removed, &media_changes));
+                    deltas += 1;
+                }
+                SignalingMessage::RoomRoster { .. } => panic!("full roster sent to a delta client"),
+                _ => {}
+            }
+        }
+        assert_eq!(deltas, 5);
+
+        // Same result as a full resend, which is what a resync returns
+        let SignalingMessage::RoomRoster { participants, version, .. } = roster(&room) else { unreachable!() };
+        assert_eq!(copy.version, version);
+        assert_eq!(copy.participants, participants);
+        let ids: Vec<&str> = copy.participants.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec![alice.as_str(), carol.as_str()]);
+        assert!(!copy.participants[1].video_enabled);
+        let resync = SignalingMessage::RequestRoster { room_id: room.id.clone() };
+        match handle_message(resync.clone(), &alice, &client(&alice), &state).await {
+            SignalingMessage::RoomRoster { participants: resent, version: resent_version, .. } => {
+                assert_eq!((resent, resent_version), (participants, version));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // Non-members can't read the list
+        assert!(matches!(
+            handle_message(resync, &bob, &client(&bob), &state).await,
+            SignalingMessage::Error { code: Some(ErrorCode::RoomNotFound), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_waiting_room_queues_and_promotes() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, _owner_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&owner, "Small".to_string(), 1, RoomLimits::default())
+            .unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let join = |username: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: username.to_string(),
+        };
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        handle_message(join("alice"), &alice, &client(&alice), &state).await;
+
+        // Without a waiting room a full room still rejects the join
+        match handle_message(join("bob"), &bob, &client(&bob), &state).await {
+            SignalingMessage::RoomJoined { success, .. } => assert!(!success),
+            other => panic!("expected failed RoomJoined, got {:?}", other),
+        }
+
+        let settings = SignalingMessage::UpdateRoomSettings {
+            room_id: room.id.clone(),
+            announce_joins: true,
+            waiting_room: Some(true),
+        };
+        handle_message(settings, &owner, &client(&owner), &state).await;
+        assert!(room.has_waiting_room());
+
+        for (id, name, expected) in [(&bob, "bob", 1), (&carol, "carol", 2)] {
+            match handle_message(join(name), id, &client(id), &state).await {
+                SignalingMessage::WaitingRoom { room_id, position } => {
+                    assert_eq!(room_id, room.id);
+                    assert_eq!(position, expected);
+                }
+                other => panic!("expected WaitingRoom, got {:?}", other),
+            }
+        }
+        assert_eq!(room.participant_count(), 1);
+
+        // Alice leaving frees the slot for Bob; Carol moves up
+        handle_message(SignalingMessage::LeaveRoom, &alice, &client(&alice), &state).await;
+        assert!(state.room_manager.is_active_in(&bob, &room.id));
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::RoomJoined { success: true, room_id, .. }) => assert_eq!(room_id, Some(room.id.clone())),
+            other => panic!("expected RoomJoined, got {:?}", other),
+        }
+        match carol_rx.try_recv() {
+            Ok(SignalingMessage::WaitingRoom { position, .. }) => assert_eq!(position, 1),
+            other => panic!("expected WaitingRoom, got {:?}", other),
+        }
+
+        // Only the owner may admit, and admission ignores capacity
+        let admit = SignalingMessage::AdmitWaiter { room_id: room.id.clone(), participant_id: carol.clone() };
+        handle_message(admit.clone(), &bob, &client(&bob), &state).await;
+        assert_eq!(room.waiting_position(&carol), Some(1));
+        handle_message(admit, &owner, &client(&owner), &state).await;
+        assert!(state.room_manager.is_active_in(&carol, &room.id));
+        assert_eq!(room.participant_count(), 2);
+        assert!(room.waiting_ids().is_empty());
+
+        // A waiter who settles elsewhere isn't pulled back when a slot frees
+        let (dave, _dave_rx) = register_client(&state);
+        assert!(matches!(handle_message(join("dave"), &dave, &client(&dave), &state).await, SignalingMessage::WaitingRoom { .. }));
+        let other = state.room_manager.create_room("Other".to_string(), 10);
+        let join_other = SignalingMessage::JoinRoom { room_id: other.id.clone(), username: "dave".to_string() };
+        handle_message(join_other, &dave, &client(&dave), &state).await;
+        handle_message(SignalingMessage::LeaveRoom, &bob, &client(&bob), &state).await;
+        handle_message(SignalingMessage::LeaveRoom, &carol, &client(&carol), &state).await;
+        assert!(state.room_manager.is_active_in(&dave, &other.id));
+        assert_eq!(room.participant_count(), 0);
+        assert!(room.waiting_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_owner_moves_participant() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, _owner_rx) = register_client(&state);
+        let lobby = state
+            .room_manager
+            .create_room_for(&owner, "Lobby".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        let help = state
+            .room_manager
+            .create_room_for(&owner, "Help".to_string(), 1, RoomLimits::default())
+            .unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let join = |room: &Room, username: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: username.to_string(),
+        };
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        handle_message(join(&lobby, "alice"), &alice, &client(&alice), &state).await;
+        handle_message(join(&lobby, "bob"), &bob, &client(&bob), &state).await;
+        handle_message(join(&help, "carol"), &carol, &client(&carol), &state).await;
+        for rx in [&mut alice_rx, &mut bob_rx, &mut carol_rx] {
+            while rx.try_recv().is_ok() {}
+        }
+        let move_alice = |force: bool| SignalingMessage::MoveParticipant {
+            participant_id: alice.clone(),
+            target_room_id: help.id.clone(),
+            force,
+        };
+
+        // Only the owner of Alice's room may move her
+        match handle_message(move_alice(true), &bob, &client(&bob), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert!(state.room_manager.is_active_in(&alice, &lobby.id));
+
+        // A full target room rejects the move unless forced
+        match handle_message(move_alice(false), &owner, &client(&owner), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::RoomFull.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert!(state.room_manager.is_active_in(&alice, &lobby.id));
+        assert!(alice_rx.try_recv().is_err());
+
+        // Forcing needs ownership of the target room too
+        let annex = state.room_manager.create_room("Annex".to_string(), 1);
+        let force_into_annex = SignalingMessage::MoveParticipant {
+            participant_id: alice.clone(),
+            target_room_id: annex.id.clone(),
+            force: true,
+        };
+        match handle_message(force_into_annex, &owner, &client(&owner), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert!(state.room_manager.is_active_in(&alice, &lobby.id));
+
+        handle_message(move_alice(true), &owner, &client(&owner), &state).await;
+        assert!(state.room_manager.is_active_in(&alice, &help.id));
+        assert!(!lobby.has_participant(&alice));
+        assert_eq!(help.participant_count(), 2);
+
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::RoomJoined { success: true, room_id, .. }) => assert_eq!(room_id, Some(help.id.clone())),
+            other => panic!("expected RoomJoined, got {:?}", other),
+        }
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::ParticipantLeft { participant_id, reason }) => {
+                assert_eq!(participant_id, alice);
+                assert_eq!(reason, LeaveReason::Moved);
+            }
+            other => panic!("expected ParticipantLeft, got {:?}", other),
+        }
+        match carol_rx.try_recv() {
+            Ok(SignalingMessage::ParticipantJoined { participant_id, .. }) => assert_eq!(participant_id, alice),
+            other => panic!("expected ParticipantJoined, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_room_event_log_records_membership_in_order() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, _owner_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&owner, "Class".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let send = |id: &String, message: SignalingMessage| {
+            let (id, client) = (id.clone(), client(id));
+            let state = state.clone();
+            async move { handle_message(message, &id, &client, &state).await }
+        };
+
+        for (id, name) in [(&owner, "owner"), (&alice, "alice"), (&bob, "bob")] {
+            send(id, SignalingMessage::JoinRoom { room_id: room.id.clone(), username: name.to_string() }).await;
+        }
+        send(&alice, SignalingMessage::LeaveRoom).await;
+        // Joining another room counts as leaving this one
+        let elsewhere = state.room_manager.create_room("Elsewhere".to_string(), 10);
+        send(&alice, SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "alice".to_string() }).await;
+        send(&alice, SignalingMessage::JoinRoom { room_id: elsewhere.id.clone(), username: "alice".to_string() }).await;
+        send(&owner, SignalingMessage::SetRoomTopic { room_id: room.id.clone(), topic: Some("Exams".to_string()) }).await;
+        disconnect_client(&state, &bob, LeaveReason::Disconnected).await;
+        send(&owner, SignalingMessage::ClearRoom { room_id: room.id.clone() }).await;
+
+        let joined = |id: &String, name: &str| RoomLogEvent::Joined { participant_id: id.clone(), username: name.to_string() };
+        let left = |id: &String, name: &str, reason| RoomLogEvent::Left { participant_id: id.clone(), username: name.to_string(), reason };
+        let expected = vec![
+            (Some(owner.clone()), joined(&owner, "owner")),
+            (Some(alice.clone()), joined(&alice, "alice")),
+            (Some(bob.clone()), joined(&bob, "bob")),
+            (Some(alice.clone()), left(&alice, "alice", LeaveReason::Left)),
+            (Some(alice.clone()), joined(&alice, "alice")),
+            (Some(alice.clone()), left(&alice, "alice", LeaveReason::Left)),
+            (Some(owner.clone()), RoomLogEvent::TopicChanged { topic: Some("Exams".to_string()) }),
+            (None, left(&bob, "bob", LeaveReason::Disconnected)),
+            (Some(owner.clone()), left(&owner, "owner", LeaveReason::Cleared)),
+        ];
+        let get_events = |limit| SignalingMessage::GetRoomEvents { room_id: room.id.clone(), limit };
+        match send(&owner, get_events(None)).await {
+            SignalingMessage::RoomEventLog { room_id, entries } => {
+                assert_eq!(room_id, room.id);
+                let logged: Vec<_> = entries.into_iter().map(|e| (e.actor_id, e.event)).collect();
+                assert_eq!(logged, expected);
+            }
+            other => panic!("expected RoomEventLog, got {:?}", other),
+        }
+
+        // A limit keeps the newest entries
+        match send(&owner, get_events(Some(2))).await {
+            SignalingMessage::RoomEventLog { entries, .. } => {
+                let logged: Vec<_> = entries.into_iter().map(|e| (e.actor_id, e.event)).collect();
+                assert_eq!(logged, expected[7..]);
+            }
+            other => panic!("expected RoomEventLog, got {:?}", other),
+        }
+
+        // Only the owner may read the log
+        match send(&alice, get_events(None)).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        let unknown = SignalingMessage::GetRoomEvents { room_id: "no-such-room".to_string(), limit: None };
+        assert!(matches!(
+            send(&owner, unknown).await,
+            SignalingMessage::Error { code: Some(ErrorCode::RoomNotFound), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_owner_clears_room() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, mut owner_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&owner, "Class".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let members = [owner.clone(), alice.clone(), bob.clone()];
+        for id in &members {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let clear = SignalingMessage::ClearRoom { room_id: room.id.clone() };
+
+        // Only the owner may clear it
+        match handle_message(clear.clone(), &alice, &client(&alice), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert_eq!(room.participant_count(), 3);
+
+        handle_message(clear, &owner, &client(&owner), &state).await;
+        assert_eq!(room.participant_count(), 0);
+        assert!(state.room_manager.get_room(&room.id).is_some());
+        assert!(members.iter().all(|id| state.room_manager.get_participant_room(id).is_none()));
+
+        for rx in [&mut owner_rx, &mut alice_rx, &mut bob_rx] {
+            let mut left = std::collections::HashSet::new();
+            loop {
+                match rx.try_recv() {
+                    Ok(SignalingMessage::ParticipantLeft { participant_id, reason }) => {
+                        assert_eq!(reason, LeaveReason::Cleared);
+                        left.insert(participant_id);
+                    }
+                    Ok(SignalingMessage::RoomLeft { success: true, .. }) => break,
+                    Ok(_) => {}
+                    other => panic!("expected RoomLeft, got {:?}", other),
+                }
+            }
+            assert_eq!(left, members.iter().cloned().collect::<std::collections::HashSet<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deleted_room_announced_and_unlisted() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, mut owner_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        for id in [&owner, &bob, &carol] {
+            log_in(&state, id);
+        }
+        let room = state
+            .room_manager
+            .create_room_for(&owner, "Doomed".to_string(), 2, RoomLimits::default())
+            .unwrap();
+        for id in [&owner, &bob] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), id.clone())).unwrap();
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let delete = SignalingMessage::DeleteRoom { room_id: room.id.clone() };
+
+        // Dave never logged in, but is queued for a slot
+        room.set_waiting_room(true);
+        let (dave, mut dave_rx) = register_client(&state);
+        let join = SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "dave".to_string() };
+        assert!(matches!(
+            handle_message(join, &dave, &client(&dave), &state).await,
+            SignalingMessage::WaitingRoom { position: 1, .. }
+        ));
+
+        match handle_message(delete.clone(), &bob, &client(&bob), &state).await {
+            SignalingMessage::Error { message, .. } => assert_eq!(message, RoomError::NotRoomOwner.to_string()),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert!(state.room_manager.get_room(&room.id).is_some());
+
+        handle_message(delete, &owner, &client(&owner), &state).await;
+        assert!(state.room_manager.get_participant_room(&bob).is_none());
+
+        // Former members, waiters and lobby users alike hear it's gone
+        assert!(room.waiting_ids().is_empty());
+        for rx in [&mut owner_rx, &mut bob_rx, &mut carol_rx, &mut dave_rx] {
+            let closed = std::iter::from_fn(|| rx.try_recv().ok())
+                .find_map(|message| match message {
+                    SignalingMessage::RoomClosed { room_id, reason } => Some((room_id, reason)),
+                    _ => None,
+                });
+            assert_eq!(closed, Some((room.id.clone(), RoomClosedReason::Deleted)));
+        }
+
+        let list = SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false };
+        match handle_message(list, &carol, &client(&carol), &state).await {
+            SignalingMessage::RoomList { rooms, .. } => assert!(rooms.iter().all(|r| r.id != room.id)),
+            other => panic!("expected RoomList, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_chat_relays_only_ciphertext() {
+        use pqc_chat::crypto::e2e::{accept_offer, RoomKeyOffer};
+
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for id in [&alice, &bob] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+
+        // Alice offers, Bob answers through the server
+        let offer = RoomKeyOffer::new(&room.id);
+        let key_offer = SignalingMessage::E2eKeyOffer { public_key: offer.public_key() };
+        handle_message(key_offer, &alice, &client(&alice), &state).await;
+        let bob_key = match bob_rx.try_recv() {
+            Ok(SignalingMessage::E2eKeyOfferReceived { sender_id, room_id, public_key }) => {
+                assert_eq!(sender_id, alice);
+                let (ciphertext, key) = accept_offer(&public_key, &room_id).unwrap();
+                let accept = SignalingMessage::E2eKeyAccept { recipient_id: alice.clone(), ciphertext };
+                handle_message(accept, &bob, &client(&bob), &state).await;
+                key
+            }
+            other => panic!("expected E2eKeyOfferReceived, got {:?}", other),
+        };
+        let alice_key = match alice_rx.try_recv() {
+            Ok(SignalingMessage::E2eKeyAcceptReceived { ciphertext, .. }) => offer.complete(&ciphertext).unwrap(),
+            other => panic!("expected E2eKeyAcceptReceived, got {:?}", other),
+        };
+
+        let plaintext = "the password is swordfish";
+        let send = SignalingMessage::SendEncryptedMessage { ciphertext: alice_key.encrypt(plaintext).unwrap() };
+        handle_message(send, &alice, &client(&alice), &state).await;
+
+        let relayed = |rx: &mut outbound::OutboundReceiver| match rx.try_recv() {
+            Ok(SignalingMessage::EncryptedMessageReceived { ciphertext, .. }) => ciphertext,
+            other => panic!("expected EncryptedMessageReceived, got {:?}", other),
+        };
+        let to_bob = relayed(&mut bob_rx);
+        let to_alice = relayed(&mut alice_rx);
+        assert!(!to_bob.windows(9).any(|w| w == b"swordfish"));
+        assert_eq!(bob_key.decrypt(&to_bob).unwrap(), plaintext);
+        assert_eq!(alice_key.decrypt(&to_alice).unwrap(), plaintext);
+        assert!(room.chat_history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_room_by_name() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let lobby = state.room_manager.create_room("Lobby".to_string(), 10);
+        state.room_manager.create_room("Dupe".to_string(), 10);
+        state.room_manager.create_room("Dupe".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let join = |room: &str| SignalingMessage::JoinRoom {
+            room_id: room.to_string(),
+            username: "alice".to_string(),
+        };
+
+        match handle_message(join("Lobby"), &alice, &client, &state).await {
+            SignalingMessage::RoomJoined { success: true, room_id, .. } => {
+                assert_eq!(room_id.as_deref(), Some(lobby.id.as_str()))
+            }
+            other => panic!("expected RoomJoined, got {:?}", other),
+        }
+        assert!(lobby.has_participant(&alice));
+
+        match handle_message(join("Nowhere"), &alice, &client, &state).await {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, Some(ErrorCode::RoomNotFound)),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        match handle_message(join("Dupe"), &alice, &client, &state).await {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, Some(ErrorCode::Ambiguous)),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        // A failed lookup leaves the current room alone
+        assert!(lobby.has_participant(&alice));
+    }
+
+    #[tokio::test]
+    async fn test_create_and_join_room_by_slug() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let create = |slug: &str| SignalingMessage::CreateRoom {
+            name: "Daily Standup".to_string(),
+            max_participants: None,
+            slug: Some(slug.to_string()),
+            required_codec: None,
+        };
+
+        let room_id = match handle_message(create("standup"), &alice, &client(&alice), &state).await {
+            SignalingMessage::RoomCreated { success: true, room_id: Some(id), .. } => id,
+            other => panic!("expected RoomCreated, got {:?}", other),
+        };
+        for (slug, error) in [("standup", RoomError::SlugTaken), ("Stand Up", RoomError::InvalidSlug)] {
+            match handle_message(create(slug), &bob, &client(&bob), &state).await {
+                SignalingMessage::RoomCreated { success: false, error: Some(message), .. } => {
+                    assert_eq!(message, error.to_string())
+                }
+                other => panic!("expected failed RoomCreated, got {:?}", other),
+            }
+        }
+
+        let join = SignalingMessage::JoinRoom { room_id: "standup".to_string(), username: "bob".to_string() };
+        match handle_message(join, &bob, &client(&bob), &state).await {
+            SignalingMessage::RoomJoined { success: true, room_id: joined, .. } => assert_eq!(joined, Some(room_id.clone())),
+            other => panic!("expected RoomJoined, got {:?}", other),
+        }
+        let info = room_info(&state.room_manager.get_room(&room_id).unwrap());
+        assert_eq!(info.slug.as_deref(), Some("standup"));
+    }
+
+    #[tokio::test]
+    async fn test_list_rooms_joinable_only_hides_full_rooms() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let full = state.room_manager.create_room("Full".to_string(), 1);
+        state.room_manager.create_room("Open".to_string(), 5);
+        state.room_manager.join_room(&full.id, Participant::new("p-x".to_string(), "x".to_string())).unwrap();
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+
+        let list = |joinable_only| SignalingMessage::ListRooms { offset: None, limit: None, joinable_only };
+        match handle_message(list(false), &alice, &client, &state).await {
+            SignalingMessage::RoomList { rooms, total } => {
+                assert_eq!(total, 2);
+                let listed = rooms.iter().find(|r| r.name == "Full").unwrap();
+                assert!(listed.is_full);
+                assert!(!listed.is_joinable());
+            }
+            other => panic!("expected RoomList, got {:?}", other),
+        }
+        match handle_message(list(true), &alice, &client, &state).await {
+            SignalingMessage::RoomList { rooms, total } => {
+                assert_eq!(total, 1);
+                assert_eq!(rooms[0].name, "Open");
+                assert!(rooms[0].is_joinable());
+            }
+            other => panic!("expected RoomList, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_room_list_reports_last_activity() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let busy = state.room_manager.create_room("Busy".to_string(), 5);
+        state.room_manager.create_room("Quiet".to_string(), 5);
+        let (bob, _bob_rx) = register_client(&state);
+        let client = state.clients.read().get(&bob).unwrap().clone();
+        state.room_manager.join_room(&busy.id, Participant::new(bob.clone(), "bob".to_string())).unwrap();
+
+        let send = SignalingMessage::SendMessage { content: "anyone here?".to_string() };
+        handle_message(send, &bob, &client, &state).await;
+        let sent_at = busy.chat_history().last().unwrap().timestamp;
+
+        let list = SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false };
+        match handle_message(list, &bob, &client, &state).await {
+            SignalingMessage::RoomList { rooms, .. } => {
+                let listed = |name: &str| rooms.iter().find(|r| r.name == name).unwrap().clone();
+                assert_eq!(listed("Busy").last_activity, Some(sent_at));
+                assert_eq!(listed("Busy").activity_label(sent_at + 150).as_deref(), Some("active 2m ago"));
+                assert_eq!(listed("Quiet").last_activity, None);
+                assert_eq!(listed("Quiet").activity_label(sent_at), None);
+            }
+            other => panic!("expected RoomList, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_usernames_rejected() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Lobby".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let login = |name: &str| SignalingMessage::Login { username: name.to_string(), resume_token: None };
+        let join = |name: &str| SignalingMessage::JoinRoom { room_id: room.id.clone(), username: name.to_string() };
+
+        for bad in ["x".repeat(33), "evil\u{1b}[2J".to_string()] {
+            for request in [login(&bad), join(&bad)] {
+                match handle_message(request, &alice, &client, &state).await {
+                    SignalingMessage::Error { code, .. } => assert_eq!(code, Some(ErrorCode::InvalidUsername)),
+                    other => panic!("expected Error, got {:?}", other),
+                }
+            }
+        }
+        assert!(client.read().username.is_none());
+        assert_eq!(room.participant_count(), 0);
+
+        assert!(matches!(
+            handle_message(login("alice.b"), &alice, &client, &state).await,
+            SignalingMessage::LoginResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            handle_message(join("alice.b"), &alice, &client, &state).await,
+            SignalingMessage::RoomJoined { success: true, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_echo_room_returns_audio_to_sender_only() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_echo_test_room(10);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+        }
+        let client = state.clients.read().get(&alice).unwrap().clone();
+
+        let audio = SignalingMessage::AudioData {
+            data: vec![7, 8, 9],
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: None,
+        };
+        handle_message(audio, &alice, &client, &state).await;
+
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { sender_id, data, .. }) => {
+                assert_eq!(sender_id, alice);
+                assert_eq!(data, vec![7, 8, 9]);
+            }
+            other => panic!("expected echoed audio, got {:?}", other),
+        }
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_audio_payload_rejected() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Call".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+        }
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let audio = |len: usize| SignalingMessage::AudioData {
+            data: vec![1; len],
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: None,
+        };
+
+        let limit = state.config.max_audio_payload_bytes;
+        match handle_message(audio(limit + 1), &alice, &client, &state).await {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, Some(ErrorCode::PayloadTooLarge)),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert!(bob_rx.try_recv().is_err());
+
+        match handle_message(audio(limit), &alice, &client, &state).await {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, None),
+            other => panic!("expected ack, got {:?}", other),
+        }
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { data, .. }) => assert_eq!(data.len(), limit),
+            other => panic!("expected relayed audio, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spectator_gets_chat_from_both_rooms_but_audio_from_active_only() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let work = state.room_manager.create_room("Work".to_string(), 10);
+        let lobby = state.room_manager.create_room("Lobby".to_string(), 10);
+        work.set_announce_joins(false);
+        lobby.set_announce_joins(false);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+        state.room_manager.join_room(&work.id, Participant::new(bob.clone(), "bob".to_string())).unwrap();
+        state.room_manager.join_room(&lobby.id, Participant::new(carol.clone(), "carol".to_string())).unwrap();
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+
+        let join = SignalingMessage::JoinRoom { room_id: "Work".to_string(), username: "alice".to_string() };
+        handle_message(join, &alice, &client(&alice), &state).await;
+        let spectate = SignalingMessage::SpectateRoom { room_id: "Lobby".to_string(), username: "alice".to_string() };
+        match handle_message(spectate, &alice, &client(&alice), &state).await {
+            SignalingMessage::RoomSpectating { success: true, room_id, roster_version, .. } => {
+                assert_eq!(room_id.as_deref(), Some(lobby.id.as_str()));
+                // Deltas for the spectated room start from here
+                assert_eq!(roster_version, lobby.roster_version());
+            }
+            other => panic!("expected RoomSpectating, got {:?}", other),
+        }
+        assert_eq!(state.room_manager.get_participant_room(&alice).unwrap().id, work.id);
+
+        // Chat from both rooms reaches alice, tagged with its room
+        for (sender, content) in [(&bob, "from work"), (&carol, "from lobby")] {
+            let send = SignalingMessage::SendMessage { content: content.to_string() };
+            handle_message(send, sender, &client(sender), &state).await;
+        }
+        let mut chat = Vec::new();
+        while let Ok(message) = alice_rx.try_recv() {
+            if let SignalingMessage::MessageReceived { room_id, content, .. } = message {
+                chat.push((room_id, content));
+            }
+        }
+        assert_eq!(
+            chat,
+            vec![(work.id.clone(), "from work".to_string()), (lobby.id.clone(), "from lobby".to_string())]
+        );
+
+        // Audio only arrives from the active room
+        for sender in [&bob, &carol] {
+            let audio = SignalingMessage::AudioData {
+                data: vec![1],
+                batched: false,
+                codec: AudioCodec::Opus,
+                captured_at: None,
+            };
+            handle_message(audio, sender, &client(sender), &state).await;
+        }
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { sender_id, .. }) => assert_eq!(sender_id, bob),
+            other => panic!("expected audio from bob, got {:?}", other),
+        }
+        assert!(alice_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transcoding_delivers_each_recipients_codec() {
+        let config = ServerConfig { transcode_audio: true, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Mixed".to_string(), 10);
+        let (pcm_user, mut pcm_rx) = register_client(&state);
+        let (opus_user, mut opus_rx) = register_client(&state);
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        for (id, codec) in [(&pcm_user, "codec_pcm"), (&opus_user, "codec_opus")] {
+            let hello = SignalingMessage::Hello { features: vec![codec.to_string()] };
+            handle_message(hello, id, &client(id), &state).await;
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), "user".to_string())).unwrap();
+        }
+
+        // PCM in, Opus out
+        let pcm = pqc_chat::audio::samples_to_bytes(&[0.25f32; 960]);
+        let audio = SignalingMessage::AudioData { data: pcm.clone(), batched: false, codec: AudioCodec::Pcm, captured_at: None };
+        handle_message(audio, &pcm_user, &client(&pcm_user), &state).await;
+        match opus_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { codec, data, .. }) => {
+                assert_eq!(codec, AudioCodec::Opus);
+                assert!(!data.is_empty() && data.len() < pcm.len());
+            }
+            other => panic!("expected Opus audio, got {:?}", other),
+        }
+
+        // Opus in, PCM out
+        let opus = pqc_chat::audio_codec::OpusEncoder::new().unwrap().encode(&[0.25f32; 960]).unwrap();
+        let audio = SignalingMessage::AudioData { data: opus, batched: false, codec: AudioCodec::Opus, captured_at: None };
+        handle_message(audio, &opus_user, &client(&opus_user), &state).await;
+        match pcm_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { codec, data, .. }) => {
+                assert_eq!(codec, AudioCodec::Pcm);
+                assert_eq!(data.len(), 960 * 4);
+            }
+            other => panic!("expected PCM audio, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transcoded_audio_drops_unreachable_recipient() {
+        let config = ServerConfig { transcode_audio: true, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Mixed".to_string(), 10);
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        let mut members = Vec::new();
+        for codec in ["codec_opus", "codec_pcm", "codec_pcm"] {
+            let (id, rx) = register_client(&state);
+            let hello = SignalingMessage::Hello { features: vec![codec.to_string()] };
+            handle_message(hello, &id, &client(&id), &state).await;
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), codec.to_string())).unwrap();
+            members.push((id, rx));
+        }
+        let (carol, mut carol_rx) = members.pop().unwrap();
+        let (bob, bob_rx) = members.pop().unwrap();
+        let (alice, _alice_rx) = members.pop().unwrap();
+        drop(bob_rx);
+
+        let opus = pqc_chat::audio_codec::OpusEncoder::new().unwrap().encode(&[0.25f32; 960]).unwrap();
+        let audio = SignalingMessage::AudioData { data: opus, batched: false, codec: AudioCodec::Opus, captured_at: None };
+        handle_message(audio, &alice, &client(&alice), &state).await;
+
+        assert!(matches!(carol_rx.try_recv(), Ok(SignalingMessage::AudioDataReceived { codec: AudioCodec::Pcm, .. })));
+        assert!(!state.clients.read().contains_key(&bob));
+        assert!(!room.has_participant(&bob));
+    }
+
+    #[tokio::test]
+    async fn test_room_codec_lock_rejects_or_transcodes() {
+        for transcode_audio in [false, true] {
+            let state = Arc::new(ServerState::new(ServerConfig { transcode_audio, ..ServerConfig::default() }));
+            let (alice, _alice_rx) = register_client(&state);
+            let (bob, mut bob_rx) = register_client(&state);
+            let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+            let create = SignalingMessage::CreateRoom {
+                name: "Opus only".to_string(),
+                max_participants: None,
+                slug: None,
+                required_codec: Some(AudioCodec::Opus),
+            };
+            handle_message(create, &alice, &client(&alice), &state).await;
+            let room = state.room_manager.get_room_by_name("Opus only").unwrap();
+            assert_eq!(room_info(&room).required_codec, Some(AudioCodec::Opus));
+            for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+                state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+            }
+
+            let pcm = pqc_chat::audio::samples_to_bytes(&[0.25f32; 960]);
+            let audio = SignalingMessage::AudioData { data: pcm, batched: false, codec: AudioCodec::Pcm, captured_at: None };
+            let response = handle_message(audio, &alice, &client(&alice), &state).await;
+            if transcode_audio {
+                assert!(matches!(response, SignalingMessage::Error { code: None, .. }));
+                assert!(matches!(bob_rx.try_recv(), Ok(SignalingMessage::AudioDataReceived { codec: AudioCodec::Opus, .. })));
+            } else {
+                assert!(matches!(response, SignalingMessage::Error { code: Some(ErrorCode::CodecNotAllowed), .. }));
+                assert!(bob_rx.try_recv().is_err());
+            }
+
+            let opus = pqc_chat::audio_codec::OpusEncoder::new().unwrap().encode(&[0.25f32; 960]).unwrap();
+            let audio = SignalingMessage::AudioData { data: opus.clone(), batched: false, codec: AudioCodec::Opus, captured_at: None };
+            let response = handle_message(audio, &alice, &client(&alice), &state).await;
+            assert!(matches!(response, SignalingMessage::Error { code: None, .. }));
+            match bob_rx.try_recv() {
+                Ok(SignalingMessage::AudioDataReceived { codec, data, .. }) => {
+                    assert_eq!(codec, AudioCodec::Opus);
+                    assert_eq!(data, opus);
+                }
+                other => panic!("expected Opus audio, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_codec_locked_room_fans_out_from_original_frames() {
+        let config = ServerConfig { transcode_audio: true, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        let room = state
+            .room_manager
+            .create_room_with_slug("owner", "owner", "Opus only".to_string(), 10, None, Some(AudioCodec::Opus), RoomLimits::default())
+            .unwrap();
+        let mut members = Vec::new();
+        for codec in ["codec_pcm", "codec_opus", "codec_pcm"] {
+            let (id, rx) = register_client(&state);
+            let hello = SignalingMessage::Hello { features: vec![codec.to_string()] };
+            handle_message(hello, &id, &client(&id), &state).await;
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), codec.to_string())).unwrap();
+            members.push((id, rx));
+        }
+
+        // PCM into an Opus room: Opus members get the conversion, while
+        // PCM members get the sender's own samples, not an Opus round trip
+        let pcm = pqc_chat::audio::samples_to_bytes(&pqc_chat::audio::sine_wave(440.0, 0.5, 48000, 0, 960));
+        let audio = SignalingMessage::AudioData { data: pcm.clone(), batched: false, codec: AudioCodec::Pcm, captured_at: None };
+        handle_message(audio, &members[0].0, &client(&members[0].0), &state).await;
+        assert!(matches!(members[1].1.try_recv(), Ok(SignalingMessage::AudioDataReceived { codec: AudioCodec::Opus, .. })));
+        match members[2].1.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { codec, data, .. }) => {
+                assert_eq!(codec, AudioCodec::Pcm);
+                assert_eq!(data, pcm);
+            }
+            other => panic!("expected PCM audio, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_muted_participant_audio_not_relayed() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+        }
+        room.set_participant_audio(&alice, false);
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        let audio = || SignalingMessage::AudioData { data: vec![1], batched: false, codec: AudioCodec::Opus, captured_at: None };
+
+        handle_message(audio(), &alice, &client(&alice), &state).await;
+        assert!(bob_rx.try_recv().is_err());
+
+        handle_message(audio(), &bob, &client(&bob), &state).await;
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { sender_id, .. }) => assert_eq!(sender_id, bob),
+            other => panic!("expected audio from bob, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_silent_audio_not_relayed() {
+        let config = ServerConfig { relay_silence_threshold: 0.01, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+        }
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let audio = |level: f32| SignalingMessage::AudioData {
+            data: pqc_chat::audio::samples_to_bytes(&[level; 960]),
+            batched: false,
+            codec: AudioCodec::Pcm,
+            captured_at: None,
+        };
+
+        // Background hiss below the threshold isn't fanned out
+        handle_message(audio(0.005), &alice, &client, &state).await;
+        assert!(bob_rx.try_recv().is_err());
+
+        handle_message(audio(0.2), &alice, &client, &state).await;
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { sender_id, .. }) => assert_eq!(sender_id, alice),
+            other => panic!("expected audible audio, got {:?}", other),
+        }
+
+        // The same quiet frame passes once the threshold is below it
+        let mut gate = SilenceGate::new(0.001);
+        assert!(gate.should_relay("carol", &[vec![0.005; 960]], Instant::now()));
+
+        // The echo test plays back everything, quiet or not
+        let echo = state.room_manager.create_echo_test_room(10);
+        state.room_manager.join_room(&echo.id, Participant::new(bob.clone(), "bob".to_string())).unwrap();
+        let client = state.clients.read().get(&bob).unwrap().clone();
+        handle_message(audio(0.005), &bob, &client, &state).await;
+        assert!(matches!(bob_rx.try_recv(), Ok(SignalingMessage::AudioDataReceived { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_stale_audio_dropped() {
+        let config = ServerConfig { max_audio_age_ms: 200, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for (id, name) in [(&alice, "alice"), (&bob, "bob")] {
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+        }
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let audio = |captured_at| SignalingMessage::AudioData { data: vec![1], batched: false, codec: AudioCodec::Opus, captured_at };
+
+        handle_message(audio(Some(unix_millis() - 1_000)), &alice, &client, &state).await;
+        assert!(bob_rx.try_recv().is_err());
+
+        let fresh = unix_millis();
+        handle_message(audio(Some(fresh)), &alice, &client, &state).await;
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::AudioDataReceived { sender_id, captured_at, .. }) => {
+                assert_eq!(sender_id, alice);
+                assert_eq!(captured_at, Some(fresh));
+            }
+            other => panic!("expected fresh audio, got {:?}", other),
+        }
+
+        // Clients that don't stamp their audio are never held to the limit
+        handle_message(audio(None), &alice, &client, &state).await;
+        assert!(bob_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_check_config_reports_problems() {
+        let dir = std::env::temp_dir().join(format!("pqc-check-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = generate_self_signed(&["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("server.crt"), cert).unwrap();
+        std::fs::write(dir.join("server.key"), key).unwrap();
+
+        let valid = ServerConfig {
+            certfile: dir.join("server.crt"),
+            keyfile: dir.join("server.key"),
+            ..ServerConfig::default()
+        };
+        let valid_path = dir.join("valid.toml");
+        std::fs::write(&valid_path, toml::to_string(&valid).unwrap()).unwrap();
+        assert!(check_config(&valid_path).is_empty());
+
+        let invalid = ServerConfig {
+            video_port: valid.audio_port,
+            keyfile: dir.join("missing.key"),
+            ..valid
+        };
+        let invalid_path = dir.join("invalid.toml");
+        std::fs::write(&invalid_path, toml::to_string(&invalid).unwrap()).unwrap();
+        let problems = check_config(&invalid_path);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("missing.key")));
+
+        assert_eq!(check_config(&dir.join("absent.toml")).len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accepted_connections_have_nodelay() {
+        let listener = bind_signaling_listener("127.0.0.1:0".parse().unwrap(), 16).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = accept_signaling(&listener).await.unwrap();
+        assert!(stream.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_silent_client_times_out() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for id in [&alice, &bob] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+        let hangup = state.clients.read().get(&alice).unwrap().read().hangup.clone();
+
+        // Bob keeps talking; Alice goes quiet
+        let timeout = Duration::from_secs(60);
+        let later = Instant::now() + Duration::from_secs(90);
+        state.clients.read().get(&bob).unwrap().write().last_seen = later;
+        expire_silent_clients(&state, later, timeout).await;
+
+        assert!(!state.clients.read().contains_key(&alice));
+        assert!(!room.has_participant(&alice));
+        assert!(room.has_participant(&bob));
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::ParticipantLeft { participant_id, reason }) => {
+                assert_eq!(participant_id, alice);
+                assert_eq!(reason, LeaveReason::TimedOut);
+            }
+            other => panic!("expected ParticipantLeft, got {:?}", other),
+        }
+
+        // The connection task is told to hang up
+        tokio::time::timeout(Duration::from_secs(1), hangup.notified()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_stalled_before_login_is_dropped() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (stalled, _stalled_rx) = register_client(&state);
+        let (alice, _alice_rx) = register_client(&state);
+        let hangup = state.clients.read().get(&stalled).unwrap().read().hangup.clone();
+
+        let alice_state = state.clients.read().get(&alice).unwrap().clone();
+        handle_message(SignalingMessage::Login { username: "alice".to_string(), resume_token: None }, &alice, &alice_state, &state).await;
+
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+        spawn_handshake_watchdog(state.clone(), stalled.clone(), deadline);
+        spawn_handshake_watchdog(state.clone(), alice.clone(), deadline);
+
+        // The connection task is told to hang up once the timeout passes
+        tokio::time::timeout(Duration::from_secs(1), hangup.notified()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!state.clients.read().contains_key(&stalled));
+        assert!(state.clients.read().contains_key(&alice));
+    }
+
+    #[tokio::test]
+    async fn test_failed_broadcast_removes_dead_client() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        for id in [&alice, &bob, &carol] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+
+        // Bob's connection task is gone
+        let bob_hangup = state.clients.read()[&bob].read().hangup.clone();
+        drop(bob_rx);
+
+        let chat = || SignalingMessage::MessageDeleted { message_id: "m-1".to_string() };
+        broadcast_to_room(&state, &room.id, &alice, chat()).await;
+
+        // Torn down like any other dropped connection
+        assert!(!room.has_participant(&bob));
+        assert!(!state.clients.read().contains_key(&bob));
+        assert!(room.is_held(Instant::now()));
+        tokio::time::timeout(Duration::from_secs(1), bob_hangup.notified()).await.unwrap();
+        assert!(matches!(carol_rx.try_recv(), Ok(SignalingMessage::MessageDeleted { .. })));
+        match carol_rx.try_recv() {
+            Ok(SignalingMessage::ParticipantLeft { participant_id, reason: LeaveReason::Disconnected }) => {
+                assert_eq!(participant_id, bob)
+            }
+            other => panic!("expected ParticipantLeft, got {:?}", other),
+        }
+        assert!(matches!(
+            carol_rx.try_recv(),
+            Ok(SignalingMessage::MessageReceived { sender_id, .. }) if sender_id == SYSTEM_SENDER_ID
+        ));
+        assert!(matches!(carol_rx.try_recv(), Ok(SignalingMessage::RoomRoster { participants, .. }) if participants.len() == 2));
+
+        // The next broadcast only reaches the live members
+        broadcast_to_room_all(&state, &room.id, chat()).await;
+        assert_eq!(room.participant_count(), 2);
+        assert!(matches!(carol_rx.try_recv(), Ok(SignalingMessage::MessageDeleted { .. })));
+        assert!(carol_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_whoami_reports_identity_and_room() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Lobby".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        handle_message(SignalingMessage::Login { username: "alice".to_string(), resume_token: None }, &alice, &client, &state).await;
+
+        match handle_message(SignalingMessage::Whoami, &alice, &client, &state).await {
+            SignalingMessage::WhoamiResponse { participant, room_id } => {
+                assert_eq!(participant.id, alice);
+                assert_eq!(participant.username, "alice");
+                assert_eq!(room_id, None);
+            }
+            other => panic!("expected WhoamiResponse, got {:?}", other),
+        }
+
+        // In a room, the room's view of the participant wins
+        let join = SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "Alice B".to_string() };
+        handle_message(join, &alice, &client, &state).await;
+        handle_message(SignalingMessage::ToggleAudio { enabled: false }, &alice, &client, &state).await;
+        match handle_message(SignalingMessage::Whoami, &alice, &client, &state).await {
+            SignalingMessage::WhoamiResponse { participant, room_id } => {
+                assert_eq!(participant.id, alice);
+                assert_eq!(participant.username, "Alice B");
+                assert!(!participant.audio_enabled);
+                assert_eq!(room_id, Some(room.id.clone()));
+            }
+            other => panic!("expected WhoamiResponse, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_time_sync_gives_bounded_offset() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (id, _rx) = register_client(&state);
+        let client = state.clients.read().get(&id).unwrap().clone();
+
+        let mut last_monotonic = 0;
+        for _ in 0..2 {
+            let sent = unix_millis();
+            let response = handle_message(SignalingMessage::TimeSync { client_sent_ms: sent }, &id, &client, &state).await;
+            let received = unix_millis();
+            match response {
+                SignalingMessage::TimeSyncResponse { client_sent_ms, server_time_ms, server_monotonic_ms } => {
+                    assert_eq!(client_sent_ms, sent);
+                    // Same machine: a current Unix time, within the round trip
+                    assert!((sent..=received).contains(&server_time_ms));
+                    let offset = ClockOffset::from_exchange(client_sent_ms, server_time_ms, received);
+                    assert!(offset.offset_ms.unsigned_abs() <= offset.round_trip_ms);
+                    assert!(server_monotonic_ms >= last_monotonic);
+                    last_monotonic = server_monotonic_ms;
+                }
+                other => panic!("expected TimeSyncResponse, got {:?}", other),
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(last_monotonic >= 5);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_reflect_config() {
+        let config = ServerConfig { default_max_participants: 4, max_room_participants: 8, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let (id, _rx) = register_client(&state);
+        let client = state.clients.read().get(&id).unwrap().clone();
+
+        match handle_message(SignalingMessage::DescribeCapabilities, &id, &client, &state).await {
+            SignalingMessage::Capabilities(caps) => {
+                assert_eq!(caps.protocol_version, PROTOCOL_VERSION);
+                assert_eq!(caps.max_room_participants, 8);
+                assert_eq!(caps.max_message_bytes as usize, MAX_FRAME_LEN);
+                assert!(caps.codecs.contains(&AudioCodec::Opus));
+                assert!(!caps.auth_required);
+                assert!(caps.time_sync);
+            }
+            other => panic!("expected Capabilities, got {:?}", other),
+        }
+
+        // Rooms created without a size get the default; larger than the cap is refused
+        let create = SignalingMessage::CreateRoom { name: "Sized".to_string(), max_participants: None, slug: None, required_codec: None };
+        handle_message(create, &id, &client, &state).await;
+        let room = state.room_manager.get_room_by_name("Sized").unwrap();
+        assert_eq!(room.max_participants, 4);
+        let too_big = SignalingMessage::CreateRoom { name: "Huge".to_string(), max_participants: Some(9), slug: None, required_codec: None };
+        assert!(matches!(
+            handle_message(too_big, &id, &client, &state).await,
+            SignalingMessage::Error { code: Some(ErrorCode::LimitExceeded), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_room_info_matches_room() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+
+        let create = SignalingMessage::CreateRoom {
+            name: "Standup".to_string(),
+            max_participants: Some(4),
+            slug: None,
+            required_codec: None,
+        };
+        handle_message(create, &alice, &client, &state).await;
+        let room = state.room_manager.get_room_by_name("Standup").unwrap();
+        state.room_manager.join_room(&room.id, Participant::new(alice.clone(), "alice".to_string())).unwrap();
+
+        let request = SignalingMessage::GetRoomInfo { room_id: room.id.clone() };
+        match handle_message(request, &alice, &client, &state).await {
+            SignalingMessage::RoomInfoResponse { success: true, room: Some(info), owner_id, created_at, .. } => {
+                assert_eq!(info.id, room.id);
+                assert_eq!(info.name, "Standup");
+                assert_eq!(info.participants, 1);
+                assert_eq!(info.max_participants, 4);
+                assert!(!info.is_locked);
+                assert_eq!(owner_id.as_deref(), Some(alice.as_str()));
+                let expected = room.created_at.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+                assert_eq!(created_at, Some(expected));
+            }
+            other => panic!("expected RoomInfoResponse, got {:?}", other),
+        }
+
+        let request = SignalingMessage::GetRoomInfo { room_id: "missing".to_string() };
+        assert!(matches!(
+            handle_message(request, &alice, &client, &state).await,
+            SignalingMessage::RoomInfoResponse { success: false, room: None, .. }
+        ));
+
+        // Lock state comes straight from the room
+        let mut locked = Room::new("Private".to_string(), 2);
+        locked.is_locked = true;
+        assert!(room_info(&locked).is_locked);
+    }
+
+    #[tokio::test]
+    async fn test_lowering_hand_reorders_queue() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        for id in [&alice, &bob, &carol] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let raise = |raised| SignalingMessage::RaiseHand { raised };
+
+        let response = handle_message(raise(true), &alice, &client(&alice), &state).await;
+        assert!(matches!(response, SignalingMessage::HandRaised { order: 1, .. }));
+        let response = handle_message(raise(true), &bob, &client(&bob), &state).await;
+        assert!(matches!(response, SignalingMessage::HandRaised { order: 2, .. }));
+        while carol_rx.try_recv().is_ok() {}
+
+        handle_message(raise(false), &alice, &client(&alice), &state).await;
+
+        match carol_rx.try_recv() {
+            Ok(SignalingMessage::HandRaised { participant_id, raised: false, order: 0 }) => {
+                assert_eq!(participant_id, alice)
+            }
+            other => panic!("expected lowered hand, got {:?}", other),
+        }
+        match carol_rx.try_recv() {
+            Ok(SignalingMessage::HandRaised { participant_id, raised: true, order: 1 }) => {
+                assert_eq!(participant_id, bob)
+            }
+            other => panic!("expected reordered hand, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_author_can_edit_message() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        for id in [&alice, &bob] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(&room.id, participant).unwrap();
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+
+        let send = SignalingMessage::SendMessage { content: "helo".to_string() };
+        handle_message(send, &alice, &client(&alice), &state).await;
+        let message_id = match bob_rx.try_recv() {
+            Ok(SignalingMessage::MessageReceived { message_id, edited: false, .. }) => message_id,
+            other => panic!("expected MessageReceived, got {:?}", other),
+        };
+        while alice_rx.try_recv().is_ok() {}
+
+        // Bob can't touch Alice's message
+        let edit = |content: &str| SignalingMessage::EditMessage {
+            message_id: message_id.clone(),
+            new_content: content.to_string(),
+        };
+        let response = handle_message(edit("hijacked"), &bob, &client(&bob), &state).await;
+        match response {
+            SignalingMessage::Error { message, .. } => {
+                assert_eq!(message, RoomError::NotMessageAuthor.to_string())
+            }
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert_eq!(room.chat_history()[0].content, "helo");
+        assert!(bob_rx.try_recv().is_err());
+
+        // Alice can
+        handle_message(edit("hello"), &alice, &client(&alice), &state).await;
+        let record = &room.chat_history()[0];
+        assert_eq!(record.content, "hello");
+        assert!(record.edited);
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::MessageEdited { message_id: id, new_content, edited: true }) => {
+                assert_eq!(id, message_id);
+                assert_eq!(new_content, "hello");
+            }
+            other => panic!("expected MessageEdited, got {:?}", other),
+        }
+    }
+
+    fn log_in(state: &Arc<ServerState>, id: &str) {
+        let client = state.clients.read().get(id).unwrap().clone();
+        client.write().username = Some(id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_set_status_propagates() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        log_in(&state, &alice);
+        log_in(&state, &bob);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+
+        let set_busy = SignalingMessage::SetStatus { status: UserStatus::Busy };
+        let response = handle_message(set_busy, &alice, &client(&alice), &state).await;
+        assert!(matches!(
+            response,
+            SignalingMessage::UserStatusChanged { status: UserStatus::Busy, .. }
+        ));
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::UserStatusChanged { participant_id, status }) => {
+                assert_eq!(participant_id, alice);
+                assert_eq!(status, UserStatus::Busy);
+            }
+            other => panic!("expected UserStatusChanged, got {:?}", other),
+        }
+
+        let list = SignalingMessage::ListServerUsers { offset: None, limit: None };
+        match handle_message(list, &bob, &client(&bob), &state).await {
+            SignalingMessage::ServerUserList { users, .. } => {
+                let alice_info = users.iter().find(|u| u.id == alice).unwrap();
+                assert_eq!(alice_info.status, UserStatus::Busy);
+            }
+            other => panic!("expected ServerUserList, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_matches_room_scan() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (ids, _rxs): (Vec<String>, Vec<_>) = (0..3).map(|_| register_client(&state)).unzip();
+        for id in &ids {
+            log_in(&state, id);
+        }
+        let (alice, bob, carol) = (&ids[0], &ids[1], &ids[2]);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let send = |id: &String, message: SignalingMessage| {
+            let (state, client) = (state.clone(), client(id));
+            let id = id.clone();
+            async move { handle_message(message, &id, &client, &state).await }
+        };
+        let check = |step: &str| {
+            for id in &ids {
+                let cached = state.directory.entries().get(id).cloned();
+                assert_eq!(cached, directory::DirectoryEntry::scan(&state.room_manager, id), "{}", step);
+            }
+        };
+
+        let lobby = state.room_manager.create_room_for(alice, "Lobby".to_string(), 10, RoomLimits::default()).unwrap();
+        let side = state.room_manager.create_room("Side".to_string(), 10);
+        let join = |room: &Arc<Room>, name: &str| SignalingMessage::JoinRoom {
+            room_id: room.id.clone(),
+            username: name.to_string(),
+        };
+
+        send(alice, join(&lobby, "alice")).await;
+        send(bob, join(&lobby, "bob")).await;
+        send(carol, join(&side, "carol")).await;
+        check("joined");
+
+        send(alice, SignalingMessage::ToggleAudio { enabled: false }).await;
+        send(bob, SignalingMessage::ToggleVideo { enabled: true }).await;
+        check("toggled");
+
+        let move_bob = SignalingMessage::MoveParticipant {
+            participant_id: bob.clone(),
+            target_room_id: side.id.clone(),
+            force: false,
+        };
+        send(alice, move_bob).await;
+        check("moved");
+
+        send(carol, SignalingMessage::LeaveRoom).await;
+        send(carol, join(&lobby, "carol")).await;
+        send(alice, SignalingMessage::ClearRoom { room_id: lobby.id.clone() }).await;
+        check("cleared");
+
+        disconnect_client(&state, bob, LeaveReason::Disconnected).await;
+        check("disconnected");
+
+        // The listing reflects the directory
+        send(alice, join(&side, "alice")).await;
+        send(alice, SignalingMessage::ToggleAudio { enabled: false }).await;
+        match send(carol, SignalingMessage::ListServerUsers { offset: None, limit: None }).await {
+            SignalingMessage::ServerUserList { users, .. } => {
+                let alice_info = users.iter().find(|u| &u.id == alice).unwrap();
+                assert_eq!(alice_info.current_room.as_deref(), Some("Side"));
+                assert!(!alice_info.audio_enabled);
+                let carol_info = users.iter().find(|u| &u.id == carol).unwrap();
+                assert_eq!(carol_info.current_room, None);
+            }
+            other => panic!("expected ServerUserList, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idle_user_goes_away_and_returns() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+        for id in [&alice, &bob, &carol] {
+            log_in(&state, id);
+        }
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        client(&carol).write().status = UserStatus::Busy;
+
+        let idle = Duration::from_secs(60);
+        let later = Instant::now() + Duration::from_secs(120);
+        client(&bob).write().last_activity = later;
+        apply_idle_away(&state, later, idle).await;
+
+        // Only the idle, available user is switched; explicit Busy is left alone
+        assert_eq!(client(&alice).read().status, UserStatus::Away);
+        assert_eq!(client(&bob).read().status, UserStatus::Available);
+        assert_eq!(client(&carol).read().status, UserStatus::Busy);
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(SignalingMessage::UserStatusChanged { status: UserStatus::Away, .. })
+        ));
+
+        mark_active(&state, &alice).await;
+        assert_eq!(client(&alice).read().status, UserStatus::Available);
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(SignalingMessage::UserStatusChanged { status: UserStatus::Available, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_udp_endpoints_shared_with_room_members_only() {
+        use pqc_chat::media::{fragment_packet, UdpAudioPacket, UdpSessionKey, SAFE_UDP_PACKET_SIZE};
+        use pqc_chat::protocol::AudioCodec;
+
+        let config = ServerConfig {
+            udp_peer_assist: true,
+            ..ServerConfig::default()
+        };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Test".to_string(), 10);
+        let other_room = state.room_manager.create_room("Other".to_string(), 10);
+
+        let (alice, mut alice_rx) = register_client(&state);
+        let (bob, mut bob_rx) = register_client(&state);
+        let (carol, mut carol_rx) = register_client(&state);
+        for (id, room_id) in [(&alice, &room.id), (&bob, &room.id), (&carol, &other_room.id)] {
+            let participant = Participant::new(id.clone(), id.clone());
+            state.room_manager.join_room(room_id, participant).unwrap();
+        }
+
+        let keys: HashMap<String, UdpSessionKey> = [&alice, &bob]
+            .into_iter()
+            .map(|id| (id.clone(), UdpSessionKey::from_token(&state.media_forwarder.write().issue_session_token(id)).unwrap()))
+            .collect();
+        let counter = std::cell::Cell::new(0);
+        let datagram = |id: &str| {
+            counter.set(counter.get() + 1);
+            let payload = fragment_packet(counter.get() as u16, &[0], SAFE_UDP_PACKET_SIZE).unwrap().remove(0);
+            let packet = UdpAudioPacket { participant_id: id.to_string(), counter: counter.get(), codec: AudioCodec::Opus, epoch: 0, captured_at: None, payload };
+            packet.seal(&keys[id])
+        };
+        let alice_addr: SocketAddr = "198.51.100.1:5000".parse().unwrap();
+        let bob_addr: SocketAddr = "198.51.100.2:6000".parse().unwrap();
+
+        on_udp_datagram(&state, &datagram(&alice), alice_addr).await.unwrap();
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::PeerUdpEndpoint { participant_id, addr }) => {
+                assert_eq!(participant_id, alice);
+                assert_eq!(addr, alice_addr.to_string());
+            }
+            other => panic!("expected PeerUdpEndpoint, got {:?}", other),
+        }
+        assert!(carol_rx.try_recv().is_err());
+
+        // Bob's first packet tells Alice about Bob and Bob about Alice
+        on_udp_datagram(&state, &datagram(&bob), bob_addr).await.unwrap();
+        match alice_rx.try_recv() {
+            Ok(SignalingMessage::PeerUdpEndpoint { participant_id, addr }) => {
+                assert_eq!(participant_id, bob);
+                assert_eq!(addr, bob_addr.to_string());
+            }
+            other => panic!("expected PeerUdpEndpoint, got {:?}", other),
+        }
+        match bob_rx.try_recv() {
+            Ok(SignalingMessage::PeerUdpEndpoint { participant_id, .. }) => assert_eq!(participant_id, alice),
+            other => panic!("expected PeerUdpEndpoint, got {:?}", other),
+        }
+        assert!(carol_rx.try_recv().is_err());
+
+        // Repeat packets from the same endpoint aren't re-announced
+        on_udp_datagram(&state, &datagram(&alice), alice_addr).await.unwrap();
+        assert!(bob_rx.try_recv().is_err());
+
+        // Forged datagrams don't teach the server anything
+        let forged = UdpAudioPacket { participant_id: carol.clone(), counter: 0, codec: AudioCodec::Opus, epoch: 0, captured_at: None, payload: vec![] };
+        assert!(on_udp_datagram(&state, &forged.seal(&keys[&alice]), alice_addr).await.is_err());
+        assert!(state.media_forwarder.read().observed_endpoint(&carol).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_room_limits_return_limit_exceeded() {
+        let config = ServerConfig {
+            max_rooms: 2,
+            max_rooms_per_user: 1,
+            ..ServerConfig::default()
+        };
+        let state = Arc::new(ServerState::new(config));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let (carol, _carol_rx) = register_client(&state);
+
+        let create = |name: &str| SignalingMessage::CreateRoom {
+            name: name.to_string(),
+            max_participants: None,
+            slug: None,
+            required_codec: None,
+        };
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+
+        let login = SignalingMessage::Login { username: "alice".to_string(), resume_token: None };
+        handle_message(login.clone(), &alice, &client(&alice), &state).await;
+        let response = handle_message(create("a1"), &alice, &client(&alice), &state).await;
+        assert!(matches!(response, SignalingMessage::RoomCreated { success: true, .. }));
+
+        // Per-user cap
+        let response = handle_message(create("a2"), &alice, &client(&alice), &state).await;
+        assert!(matches!(
+            response,
+            SignalingMessage::Error { code: Some(ErrorCode::LimitExceeded), .. }
+        ));
+
+        // Reconnecting under the same name doesn't reset it
+        disconnect_client(&state, &alice, LeaveReason::Disconnected).await;
+        let (alice, _alice_rx) = register_client(&state);
+        handle_message(login, &alice, &client(&alice), &state).await;
+        let response = handle_message(create("a2"), &alice, &client(&alice), &state).await;
+        assert!(matches!(
+            response,
+            SignalingMessage::Error { code: Some(ErrorCode::LimitExceeded), .. }
+        ));
+
+        let response = handle_message(create("b1"), &bob, &client(&bob), &state).await;
+        assert!(matches!(response, SignalingMessage::RoomCreated { success: true, .. }));
+
+        // Global cap
+        let response = handle_message(create("c1"), &carol, &client(&carol), &state).await;
+        assert!(matches!(
+            response,
+            SignalingMessage::Error { code: Some(ErrorCode::LimitExceeded), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_frame_mac_agreed_only_after_key_exchange() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+        let hello = || SignalingMessage::Hello { features: vec![FEATURE_FRAME_MAC.to_string()] };
+
+        // No session key yet, so the feature is refused
+        let bob_client = state.clients.read().get(&bob).unwrap().clone();
+        let response = handle_message(hello(), &bob, &bob_client, &state).await;
+        assert!(matches!(response, SignalingMessage::Hello { ref features } if features.is_empty()));
+        assert!(bob_client.read().frame_auth.is_none());
+
+        let kyber = KyberKeyExchange::new();
+        let init = SignalingMessage::KeyExchangeInit { public_key: kyber.public_key_bytes() };
+        let SignalingMessage::KeyExchangeResponse { ciphertext } = handle_message(init, &alice, &client, &state).await else {
+            panic!("key exchange failed");
+        };
+        let secret = kyber.decapsulate(&ciphertext).unwrap();
+
+        let response = handle_message(hello(), &alice, &client, &state).await;
+        assert!(matches!(response, SignalingMessage::Hello { ref features } if features == &[FEATURE_FRAME_MAC]));
+
+        // A second hello can't turn the feature back off
+        let response = handle_message(SignalingMessage::Hello { features: vec![] }, &alice, &client, &state).await;
+        assert!(matches!(response, SignalingMessage::Error { code: None, .. }));
+        assert!(client.read().frame_auth.is_some());
+
+        // Frames sealed with the client's direction key verify on the server
+        let mut sealer = FrameAuthenticator::new(&KyberSession::new(secret).send_key(SessionRole::Client));
+        let frame = sealer.seal(SignalingMessage::ListRooms { offset: None, limit: None, joinable_only: false }.to_framed().unwrap());
+        let (len, compressed) = pqc_chat::protocol::parse_frame_header(frame[..4].try_into().unwrap());
+        let mut client_state = client.write();
+        let auth = client_state.frame_auth.as_mut().unwrap();
+        assert!(auth.open(&frame[4..4 + len], compressed).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_room_commands_rate_limited_per_client() {
+        let config = ServerConfig { room_ops_burst: 3, max_rooms_per_user: 0, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let (alice, _alice_rx) = register_client(&state);
+        let (bob, _bob_rx) = register_client(&state);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let create = || SignalingMessage::CreateRoom { name: "Spam".to_string(), max_participants: None, slug: None, required_codec: None };
+
+        for _ in 0..3 {
+            let response = handle_message(create(), &alice, &client(&alice), &state).await;
+            assert!(matches!(response, SignalingMessage::RoomCreated { .. }));
+        }
+        let response = handle_message(create(), &alice, &client(&alice), &state).await;
+        assert!(matches!(response, SignalingMessage::Error { code: Some(ErrorCode::RateLimited), .. }));
+
+        // Leaving always goes through
+        let room = state.room_manager.get_room_by_name("Spam").unwrap();
+        state.room_manager.join_room(&room.id, Participant::new(alice.clone(), "alice".to_string())).unwrap();
+        handle_message(SignalingMessage::LeaveRoom, &alice, &client(&alice), &state).await;
+        assert!(state.room_manager.get_participant_room(&alice).is_none());
+
+        // Other commands and other clients are unaffected
+        let response = handle_message(SignalingMessage::Whoami, &alice, &client(&alice), &state).await;
+        assert!(matches!(response, SignalingMessage::WhoamiResponse { .. }));
+        let response = handle_message(create(), &bob, &client(&bob), &state).await;
+        assert!(matches!(response, SignalingMessage::RoomCreated { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_signaling_log_keeps_login_secrets_and_chat_out() {
+        let path = std::env::temp_dir().join(format!("pqc-signaling-{}.ndjson", Uuid::new_v4()));
+        let log = SignalingLog::open(&path).unwrap();
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let room = state.room_manager.create_room("Lobby".to_string(), 10);
+        let (alice, _alice_rx) = register_client(&state);
+        let client = state.clients.read().get(&alice).unwrap().clone();
+
+        let login = SignalingMessage::Login { username: "alice".to_string(), resume_token: None };
+        log.record(&alice, Direction::Inbound, &login);
+        let response = handle_message(login, &alice, &client, &state).await;
+        let tokens = match &response {
+            SignalingMessage::LoginResponse { udp_session_token: Some(udp), resume_token: Some(resume), .. } => {
+                [udp.clone(), resume.clone()]
+            }
+            other => panic!("expected LoginResponse with tokens, got {:?}", other),
+        };
+        log.record(&alice, Direction::Outbound, &response);
+
+        let join = SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "alice".to_string() };
+        handle_message(join, &alice, &client, &state).await;
+        let chat = SignalingMessage::SendMessage { content: "the door code is 4711".to_string() };
+        log.record(&alice, Direction::Inbound, &chat);
+        let response = handle_message(chat, &alice, &client, &state).await;
+        log.record(&alice, Direction::Outbound, &response);
+        drop(log);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+        for token in tokens {
+            assert!(!contents.contains(&token));
+        }
+        assert!(!contents.contains("4711"));
+    }
+
+    #[tokio::test]
+    async fn test_joins_deliver_motd_as_system_message() {
+        let config = ServerConfig { motd: Some("Be nice".to_string()), ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let (owner, _owner_rx) = register_client(&state);
+        let room = state.room_manager.create_room_for(&owner, "Rules".to_string(), 10, RoomLimits::default()).unwrap();
+        room.set_topic(Some("No spoilers".to_string())).unwrap();
+        let other = state.room_manager.create_room_for(&owner, "Other".to_string(), 10, RoomLimits::default()).unwrap();
+        let (alice, mut alice_rx) = register_client(&state);
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let send = |from: &String, message| {
+            let (state, from, client) = (state.clone(), from.clone(), client(from));
+            async move {
+                let response = handle_message(message, &from, &client, &state).await;
+                send_response(&state, &from, response);
+            }
+        };
+        let system_lines = |rx: &mut outbound::OutboundReceiver| {
+            std::iter::from_fn(|| rx.try_recv().ok())
+                .filter_map(|message| match message {
+                    SignalingMessage::MessageReceived { sender_id, room_id, content, .. } if sender_id == SYSTEM_SENDER_ID => {
+                        Some((room_id, content))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The banner already carried the MOTD; no roomless chat line after login
+        send(&alice, SignalingMessage::Login { username: "alice".to_string(), resume_token: None }).await;
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::LoginResponse { success: true, .. })));
+        assert!(system_lines(&mut alice_rx).is_empty());
+
+        send(&alice, SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "alice".to_string() }).await;
+        // The reply comes first, so clients waiting on it aren't confused
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::RoomJoined { success: true, .. })));
+        assert_eq!(
+            system_lines(&mut alice_rx),
+            vec![(room.id.clone(), "Be nice".to_string()), (room.id.clone(), "Topic: No spoilers".to_string())]
+        );
+        // Welcome lines aren't replayed to later joiners as history
+        assert!(room.chat_history().iter().all(|m| m.content != "Be nice"));
+
+        // Being moved is a join too
+        let move_alice = SignalingMessage::MoveParticipant { participant_id: alice.clone(), target_room_id: other.id.clone(), force: false };
+        send(&owner, move_alice).await;
+        assert!(matches!(alice_rx.try_recv(), Ok(SignalingMessage::RoomJoined { success: true, .. })));
+        assert_eq!(system_lines(&mut alice_rx), vec![(other.id.clone(), "Be nice".to_string())]);
+    }
+
+    #[test]
+    fn test_half_a_certificate_pair_is_refused() {
+        let dir = std::env::temp_dir().join(format!("pqc-cert-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = ServerConfig { certfile: dir.join("cert.pem"), keyfile: dir.join("key.pem"), ..ServerConfig::default() };
+        std::fs::write(&config.certfile, "cert").unwrap();
+
+        assert!(ensure_self_signed_cert(&config, "localhost").is_err());
+        assert!(!config.keyfile.exists());
+        assert_eq!(std::fs::read_to_string(&config.certfile).unwrap(), "cert");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_private_key_is_owner_only() {
+        let path = std::env::temp_dir().join(format!("pqc-key-{}.pem", uuid::Uuid::new_v4()));
+        write_private_key(&path, b"key").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        // Never overwrites an existing key
+        assert!(write_private_key(&path, b"other").is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"key");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_info_is_first_frame_after_tls() {
+        use tokio_rustls::rustls::pki_types::ServerName;
+        use tokio_rustls::TlsConnector;
+
+        let (cert_pem, key_pem) = generate_self_signed(&["localhost".to_string()]).unwrap();
+        let certs = rustls_pemfile::certs(&mut cert_pem.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+        let key = rustls_pemfile::private_key(&mut key_pem.as_bytes()).unwrap().unwrap();
+        let server_tls = rustls::ServerConfig::builder().with_no_client_auth().with_single_cert(certs, key).unwrap();
+        let client_tls = rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(pqc_chat::crypto::tls::NoVerifier))
+            .with_no_client_auth();
+
+        let config = ServerConfig { motd: Some("Be nice".to_string()), ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(async move {
+            let stream = TlsAcceptor::from(Arc::new(server_tls)).accept(server_io).await.unwrap();
+            handle_client(stream, "127.0.0.1:9".parse().unwrap(), state, None).await
+        });
+
+        let mut stream = TlsConnector::from(Arc::new(client_tls))
+            .connect(ServerName::try_from("localhost").unwrap(), client_io)
+            .await
+            .unwrap();
+        match read_frame(&mut stream).await.unwrap().message().unwrap() {
+            SignalingMessage::ServerInfo { name, version, protocol_version, motd } => {
+                assert_eq!(name, "PQC Chat");
+                assert_eq!(version, env!("CARGO_PKG_VERSION"));
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert_eq!(motd.as_deref(), Some("Be nice"));
+            }
+            other => panic!("expected ServerInfo, got {:?}", other),
+        }
+        drop(stream);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_room_survives_drop_within_reconnect_grace() {
+        let config = ServerConfig { reap_empty_rooms: true, reconnect_grace_secs: 30, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let (alice, _alice_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&alice, "Solo".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        state.room_manager.join_room(&room.id, Participant::new(alice.clone(), "alice".to_string())).unwrap();
+
+        disconnect_client(&state, &alice, LeaveReason::Disconnected).await;
+        let now = Instant::now();
+        assert!(state.room_manager.reap_empty_rooms(now).is_empty());
+        assert!(state.room_manager.get_room(&room.id).is_some());
+
+        let reaped = state.room_manager.reap_empty_rooms(now + Duration::from_secs(31));
+        assert_eq!(reaped.len(), 1);
+        assert!(state.room_manager.get_room(&room.id).is_none());
+
+        // A clean leave gets no grace
+        let (bob, _bob_rx) = register_client(&state);
+        let room = state
+            .room_manager
+            .create_room_for(&bob, "Brief".to_string(), 10, RoomLimits::default())
+            .unwrap();
+        state.room_manager.join_room(&room.id, Participant::new(bob.clone(), "bob".to_string())).unwrap();
+        disconnect_client(&state, &bob, LeaveReason::Left).await;
+        assert_eq!(state.room_manager.reap_empty_rooms(Instant::now()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resume_token_restores_participant_id() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        let login = |token: Option<String>| SignalingMessage::Login { username: "alice".to_string(), resume_token: token };
+        let resume_token = |response| match response {
+            SignalingMessage::LoginResponse { success: true, participant_id, resume_token, .. } => {
+                (participant_id.unwrap(), resume_token.unwrap())
+            }
+            other => panic!("expected LoginResponse, got {:?}", other),
+        };
+
+        let (alice, _alice_rx) = register_client(&state);
+        let (_, token) = resume_token(handle_message(login(None), &alice, &client(&alice), &state).await);
+        disconnect_client(&state, &alice, LeaveReason::Disconnected).await;
+
+        // The new connection takes back the dropped one's ID
+        let (second, _second_rx) = register_client(&state);
+        let second_state = client(&second);
+        let (resumed, token) = resume_token(handle_message(login(Some(token.clone())), &second, &second_state, &state).await);
+        assert_eq!(resumed, alice);
+        assert!(Arc::ptr_eq(&client(&alice), &second_state));
+        assert!(!state.clients.read().contains_key(&second));
+
+        // A clean logout retires the token
+        disconnect_client(&state, &alice, LeaveReason::Left).await;
+        let (third, _third_rx) = register_client(&state);
+        let (id, _) = resume_token(handle_message(login(Some(token)), &third, &client(&third), &state).await);
+        assert_eq!(id, third);
+
+        // And each token only works once
+        let (_, token) = resume_token(handle_message(login(None), &third, &client(&third), &state).await);
+        disconnect_client(&state, &third, LeaveReason::Disconnected).await;
+        let (fourth, _fourth_rx) = register_client(&state);
+        let (id, _) = resume_token(handle_message(login(Some(token.clone())), &fourth, &client(&fourth), &state).await);
+        assert_eq!(id, third);
+        let (fifth, _fifth_rx) = register_client(&state);
+        let (id, _) = resume_token(handle_message(login(Some(token)), &fifth, &client(&fifth), &state).await);
+        assert_eq!(id, fifth);
+    }
+
+    #[tokio::test]
+    async fn test_rejoining_replays_chat_history() {
+        let state = Arc::new(ServerState::new(ServerConfig::default()));
+        let (owner, _owner_rx) = register_client(&state);
+        let room = state.room_manager.create_room_for(&owner, "Lobby".to_string(), 10, RoomLimits::default()).unwrap();
+        let client = |id: &String| state.clients.read().get(id).unwrap().clone();
+        let send = |from: &String, message| {
+            let (state, from, client) = (state.clone(), from.clone(), client(from));
+            async move {
+                let response = handle_message(message, &from, &client, &state).await;
+                send_response(&state, &from, response);
+            }
+        };
+        let login = |token| SignalingMessage::Login { username: "alice".to_string(), resume_token: token };
+        let join = || SignalingMessage::JoinRoom { room_id: room.id.clone(), username: "alice".to_string() };
+
+        let (alice, mut alice_rx) = register_client(&state);
+        send(&alice, login(None)).await;
+        let token = match alice_rx.try_recv() {
+            Ok(SignalingMessage::LoginResponse { resume_token: Some(token), .. }) => token,
+            other => panic!("expected LoginResponse, got {:?}", other),
+        };
+        send(&alice, join()).await;
+        send(&alice, SignalingMessage::SendMessage { content: "brb".to_string() }).await;
+        disconnect_client(&state, &alice, LeaveReason::Disconnected).await;
+
+        // Back on the same ID, rejoining brings the conversation back
+        let (second, mut second_rx) = register_client(&state);
+        send(&second, login(Some(token))).await;
+        send(&alice, join()).await;
+        let replayed: Vec<_> = std::iter::from_fn(|| second_rx.try_recv().ok())
+            .skip_while(|message| !matches!(message, SignalingMessage::RoomJoined { success: true, .. }))
+            .filter_map(|message| match message {
+                SignalingMessage::MessageReceived { sender_id, content, .. } => Some((sender_id, content)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(replayed, vec![(alice.clone(), "brb".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_mode_sends_one_stream_without_own_voice() {
+        let config = ServerConfig { mix_audio: true, ..ServerConfig::default() };
+        let state = Arc::new(ServerState::new(config));
+        let room = state.room_manager.create_room("Mixed".to_string(), 10);
+        let client = |id: &str| state.clients.read().get(id).unwrap().clone();
+        let mut members = Vec::new();
+        for name in ["alice", "bob", "carol"] {
+            let (id, rx) = register_client(&state);
+            let hello = SignalingMessage::Hello { features: vec!["codec_pcm".to_string()] };
+            handle_message(hello, &id, &client(&id), &state).await;
+            state.room_manager.join_room(&room.id, Participant::new(id.clone(), name.to_string())).unwrap();
+            members.push((id, rx));
+        }
+
+        let mut mix = RoomMix::default();
+        for ((id, _), level) in members.iter().zip([0.25f32, 0.5]) {
+            let data = pqc_chat::audio::samples_to_bytes(&[level; 960]);
+            mix.push(id, &data, false, AudioCodec::Pcm).unwrap();
+        }
+        assert!(send_room_mix(&state, &room.id, &mut mix));
+
+        // Alice hears only Bob, Bob only Alice, Carol both, one stream each
+        for ((_, rx), expected) in members.iter_mut().zip([0.5f32, 0.25, 0.75]) {
+            match rx.try_recv() {
+                Ok(SignalingMessage::AudioDataReceived { sender_id, data, codec, .. }) => {
+                    assert_eq!(sender_id, MIXED_AUDIO_SENDER_ID);
+                    assert_eq!(codec, AudioCodec::Pcm);
+                    let samples = pqc_chat::audio::bytes_to_samples(&data);
+                    assert_eq!(samples.len(), 960);
+                    assert!(samples.iter().all(|s| (s - expected).abs() < 1e-6));
+                }
+                other => panic!("expected mixed audio, got {:?}", other),
+            }
+            assert!(rx.try_recv().is_err());
+        }
+
+        // Audio sent to the server reaches the room's mixing thread, and
+        // comes back only as the mix
+        let data = pqc_chat::audio::samples_to_bytes(&[0.25f32; 960]);
+        let audio = SignalingMessage::AudioData { data, batched: false, codec: AudioCodec::Pcm, captured_at: None };
+        handle_message(audio, &members[0].0, &client(&members[0].0), &state).await;
+        let carol_rx = &mut members[2].1;
+        match tokio::time::timeout(Duration::from_secs(2), carol_rx.recv()).await {
+            Ok(Some(SignalingMessage::AudioDataReceived { sender_id, .. })) => assert_eq!(sender_id, MIXED_AUDIO_SENDER_ID),
+            other => panic!("expected mixed audio, got {:?}", other),
+        }
+
+        // Deleting the room stops its thread
+        assert!(state.room_manager.delete_room(&room.id));
+        assert!(!send_room_mix(&state, &room.id, &mut mix));
     }
 }
@@ -0,0 +1,168 @@
+//! Server-side audio mixing (`mix_audio`)
+//!
+//! Instead of relaying every participant's stream, the server decodes what
+//! each member sends into a per-room [`AudioMixer`] and, every frame
+//! period, sends each member one stream: the room's mix minus their own
+//! voice. Clients then decode a single stream however many people talk,
+//! at the cost of server CPU and an extra encode per recipient.
+//!
+//! Each room mixes on its own thread ([`RoomMixHandle`]), so decoding and
+//! encoding stay off the async runtime and no lock is held across them.
+
+use pqc_chat::audio::SampleFormat;
+use pqc_chat::audio_codec::{unpack_frames, CodecError, FrameDecoder, OpusEncoder};
+use pqc_chat::audio_mixer::{AudioMixer, MixedFrame};
+use pqc_chat::protocol::AudioCodec;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Samples per mixed frame (20ms at 48kHz, one Opus frame)
+pub const MIX_FRAME_LEN: usize = 960;
+
+/// How often a mixed frame is sent
+pub const MIX_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Decoded samples kept per sender (200ms); a sender running ahead of the
+/// mix loses its oldest audio
+pub const MAX_MIX_QUEUE: usize = MIX_FRAME_LEN * 10;
+
+/// Audio payloads waiting for a room's mixing thread; more are dropped
+pub const MIX_INPUT_QUEUE: usize = 64;
+
+/// Mixing state for one room
+pub struct RoomMix {
+    mixer: AudioMixer,
+    /// Per-sender decoders; Opus is stateful
+    decoders: HashMap<String, FrameDecoder>,
+    /// Per-recipient encoders, since every recipient hears a different mix
+    encoders: HashMap<String, OpusEncoder>,
+}
+
+impl Default for RoomMix {
+    fn default() -> Self {
+        let mut mixer = AudioMixer::new();
+        mixer.set_max_queued(MAX_MIX_QUEUE);
+        Self { mixer, decoders: HashMap::new(), encoders: HashMap::new() }
+    }
+}
+
+impl RoomMix {
+    /// Decode a sender's payload and queue it for the next mixes
+    pub fn push(&mut self, sender_id: &str, payload: &[u8], batched: bool, codec: AudioCodec) -> Result<(), CodecError> {
+        let frames = if batched { unpack_frames(payload)? } else { vec![payload.to_vec()] };
+        let decoder = match self.decoders.entry(sender_id.to_string()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert(FrameDecoder::new()?),
+        };
+        for frame in &frames {
+            let samples = decoder.decode(codec, frame)?;
+            self.mixer.push(sender_id, &samples);
+        }
+        Ok(())
+    }
+
+    /// Mix the next frame, if anyone has audio queued
+    pub fn next_frame(&mut self) -> Option<MixedFrame> {
+        if self.mixer.is_idle() {
+            None
+        } else {
+            Some(self.mixer.mix_parts(MIX_FRAME_LEN))
+        }
+    }
+
+    /// Encode a recipient's mix in the codec they asked for
+    pub fn encode_for(&mut self, recipient_id: &str, samples: &[f32], codec: AudioCodec) -> Result<Vec<u8>, CodecError> {
+        match codec {
+            AudioCodec::Opus => {
+                let encoder = match self.encoders.entry(recipient_id.to_string()) {
+                    std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                    std::collections::hash_map::Entry::Vacant(entry) => entry.insert(OpusEncoder::new()?),
+                };
+                encoder.encode(samples)
+            }
+            AudioCodec::Pcm => Ok(SampleFormat::F32.encode(samples)),
+            AudioCodec::Pcm16 => Ok(SampleFormat::I16.encode(samples)),
+        }
+    }
+
+    /// Drop a participant's codec state and queued audio
+    pub fn forget(&mut self, participant_id: &str) {
+        self.mixer.remove_sender(participant_id);
+        self.decoders.remove(participant_id);
+        self.encoders.remove(participant_id);
+    }
+}
+
+/// Work for a room's mixing thread
+enum MixInput {
+    Audio { sender_id: String, payload: Vec<u8>, batched: bool, codec: AudioCodec },
+    Forget(String),
+}
+
+/// A room's mixing thread. Every [`MIX_INTERVAL`] it calls `send_mix` with
+/// the room's [`RoomMix`]; it stops when `send_mix` returns `false` or the
+/// handle is dropped.
+pub struct RoomMixHandle {
+    tx: mpsc::Sender<MixInput>,
+    /// Audio inputs sent but not yet taken by the thread
+    pending: Arc<AtomicUsize>,
+}
+
+impl RoomMixHandle {
+    pub fn spawn<F>(room_id: &str, mut send_mix: F) -> std::io::Result<Self>
+    where
+        F: FnMut(&mut RoomMix) -> bool + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let thread_pending = pending.clone();
+        std::thread::Builder::new().name(format!("mix-{}", room_id)).spawn(move || {
+            let mut mix = RoomMix::default();
+            let mut next_tick = Instant::now() + MIX_INTERVAL;
+            loop {
+                let now = Instant::now();
+                if now >= next_tick {
+                    if !send_mix(&mut mix) {
+                        break;
+                    }
+                    // Skip missed ticks rather than bursting to catch up
+                    next_tick = (next_tick + MIX_INTERVAL).max(now);
+                    continue;
+                }
+                match rx.recv_timeout(next_tick - now) {
+                    Ok(MixInput::Audio { sender_id, payload, batched, codec }) => {
+                        thread_pending.fetch_sub(1, Ordering::Relaxed);
+                        if let Err(e) = mix.push(&sender_id, &payload, batched, codec) {
+                            log::warn!("Dropping {:?} audio from {} for the room mix: {}", codec, sender_id, e);
+                        }
+                    }
+                    Ok(MixInput::Forget(participant_id)) => mix.forget(&participant_id),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        })?;
+        Ok(Self { tx, pending })
+    }
+
+    /// Queue a sender's payload for the mix. Returns `false` when the
+    /// thread is [`MIX_INPUT_QUEUE`] payloads behind (or gone) and the
+    /// payload was dropped.
+    pub fn push(&self, sender_id: &str, payload: Vec<u8>, batched: bool, codec: AudioCodec) -> bool {
+        if self.pending.fetch_add(1, Ordering::Relaxed) >= MIX_INPUT_QUEUE {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+        let input = MixInput::Audio { sender_id: sender_id.to_string(), payload, batched, codec };
+        self.tx.send(input).is_ok()
+    }
+
+    /// Drop a participant's codec state and queued audio. Not subject to
+    /// the input limit, so it is never lost.
+    pub fn forget(&self, participant_id: &str) {
+        let _ = self.tx.send(MixInput::Forget(participant_id.to_string()));
+    }
+}
@@ -0,0 +1,94 @@
+//! Message of the day (`motd` / `motd_file`)
+//!
+//! The MOTD goes out on every connection and every room join, so
+//! `motd_file` isn't read each time. Its contents are cached and only read
+//! again once the file's modification time or size changes, which leaves a
+//! `stat` as the per-send cost while still picking up edits without a
+//! restart.
+
+use parking_lot::Mutex;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Modification time and length, to notice edits without reading the file
+type Stamp = (Option<SystemTime>, u64);
+
+/// The configured message of the day
+pub struct Motd {
+    fallback: Option<String>,
+    file: Option<PathBuf>,
+    /// Last stamp seen (`None` while the file can't be read) and contents
+    cached: Mutex<Option<(Option<Stamp>, Option<String>)>>,
+}
+
+impl Motd {
+    pub fn new(motd: Option<String>, motd_file: Option<PathBuf>) -> Self {
+        Self {
+            fallback: motd,
+            file: motd_file,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The message as of now: `motd_file`'s contents if set and readable,
+    /// otherwise `motd`. Blank messages count as none.
+    pub fn current(&self) -> Option<String> {
+        let from_file = self.file.as_deref().and_then(|path| self.read(path));
+        from_file
+            .or_else(|| self.fallback.clone())
+            .map(|motd| motd.trim().to_string())
+            .filter(|motd| !motd.is_empty())
+    }
+
+    /// The file's contents, from the cache unless it changed. Failures are
+    /// logged once, not on every send.
+    fn read(&self, path: &Path) -> Option<String> {
+        let stamp = std::fs::metadata(path).map(|meta| (meta.modified().ok(), meta.len()));
+        let mut cached = self.cached.lock();
+        let stamp = match stamp {
+            Ok(stamp) => Some(stamp),
+            Err(e) => {
+                if !matches!(*cached, Some((None, _))) {
+                    log::warn!("Could not read motd_file {}: {}", path.display(), e);
+                }
+                *cached = Some((None, None));
+                return None;
+            }
+        };
+        if let Some((seen, content)) = cached.as_ref() {
+            if *seen == stamp {
+                return content.clone();
+            }
+        }
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                log::warn!("Could not read motd_file {}: {}", path.display(), e);
+                None
+            }
+        };
+        *cached = Some((stamp, content.clone()));
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motd_file_overrides_and_is_reread_on_change() {
+        let path = std::env::temp_dir().join(format!("pqc-motd-{}.txt", uuid::Uuid::new_v4()));
+        let motd = Motd::new(Some("Fallback".to_string()), Some(path.clone()));
+        assert_eq!(motd.current().as_deref(), Some("Fallback"));
+
+        std::fs::write(&path, "Rules: be nice\n").unwrap();
+        assert_eq!(motd.current().as_deref(), Some("Rules: be nice"));
+        assert_eq!(motd.current().as_deref(), Some("Rules: be nice"));
+        std::fs::write(&path, "Maintenance at 6pm").unwrap();
+        assert_eq!(motd.current().as_deref(), Some("Maintenance at 6pm"));
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(motd.current().as_deref(), Some("Fallback"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Per-client outbound message queue
+//!
+//! Bounded for media, lossless for signaling: once the queue holds
+//! `capacity` messages, a new audio frame evicts the oldest queued audio
+//! frame (or is itself dropped if none is queued), while control messages
+//! are always enqueued. A slow reader can no longer grow server memory
+//! without limit just by falling behind on audio.
+//!
+//! [`write_frame`] drains the queue onto the socket, riding out transient
+//! write errors so backpressure doesn't tear down a healthy connection.
+
+use parking_lot::Mutex;
+use pqc_chat::protocol::SignalingMessage;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Notify;
+
+/// Attempts at a retryable write error before giving up on the connection
+const MAX_WRITE_RETRIES: u32 = 5;
+
+/// Pause before the first retry; doubles with each further attempt
+const WRITE_RETRY_BACKOFF: Duration = Duration::from_millis(5);
+
+/// The receiving side has gone away
+#[derive(Error, Debug)]
+#[error("Outbound queue closed")]
+pub struct SendError;
+
+/// Why `try_recv` returned nothing
+#[cfg(test)]
+#[derive(Error, Debug)]
+pub enum TryRecvError {
+    #[error("Outbound queue empty")]
+    Empty,
+    #[error("Outbound queue disconnected")]
+    Disconnected,
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<SignalingMessage>>,
+    capacity: usize,
+    notify: Notify,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+}
+
+/// Create a queue that holds at most `capacity` messages before shedding audio
+pub fn channel(capacity: usize) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::new()),
+        capacity: capacity.max(1),
+        notify: Notify::new(),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+    });
+    (
+        OutboundSender { shared: shared.clone() },
+        OutboundReceiver { shared },
+    )
+}
+
+pub struct OutboundSender {
+    shared: Arc<Shared>,
+}
+
+impl OutboundSender {
+    /// Queue a message, shedding audio if the queue is full
+    pub fn send(&self, message: SignalingMessage) -> Result<(), SendError> {
+        if !self.shared.receiver_alive.load(Ordering::Acquire) {
+            return Err(SendError);
+        }
+
+        {
+            let mut queue = self.shared.queue.lock();
+            if queue.len() >= self.shared.capacity && message.is_audio() {
+                log::debug!("Outbound queue full, shedding an audio frame");
+                match queue.iter().position(|m| m.is_audio()) {
+                    Some(oldest) => {
+                        queue.remove(oldest);
+                    }
+                    // Only signaling queued: drop the new frame instead
+                    None => return Ok(()),
+                }
+            }
+            queue.push_back(message);
+        }
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for OutboundSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for OutboundSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Wake the receiver so it sees the queue is finished
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+pub struct OutboundReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutboundReceiver {
+    /// Next message, or `None` once every sender is gone and the queue is drained
+    pub async fn recv(&mut self) -> Option<SignalingMessage> {
+        loop {
+            if let Some(message) = self.shared.queue.lock().pop_front() {
+                return Some(message);
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+
+    /// Non-blocking receive, for tests inspecting what a client was sent
+    #[cfg(test)]
+    pub fn try_recv(&mut self) -> Result<SignalingMessage, TryRecvError> {
+        if let Some(message) = self.shared.queue.lock().pop_front() {
+            return Ok(message);
+        }
+        if self.shared.senders.load(Ordering::Acquire) == 0 {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+/// Errors worth retrying: the peer is slow, not gone
+fn is_retryable(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::WouldBlock | ErrorKind::Interrupted)
+}
+
+/// Write a whole frame, resuming after partial writes and retrying
+/// transient errors with a bounded backoff. Any other error (reset, broken
+/// pipe, ...) is fatal and returned so the caller can drop the connection.
+pub async fn write_frame<W>(writer: &mut W, data: &[u8]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut written = 0;
+    let mut retries = 0;
+    while written < data.len() {
+        match writer.write(&data[written..]).await {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                written += n;
+                retries = 0;
+            }
+            Err(e) if is_retryable(e.kind()) && retries < MAX_WRITE_RETRIES => {
+                tokio::time::sleep(WRITE_RETRY_BACKOFF * 2u32.pow(retries)).await;
+                retries += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pqc_chat::protocol::AudioCodec;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Writer that fails with each queued error in turn, then accepts at
+    /// most `chunk` bytes per write
+    struct FlakyWriter {
+        errors: VecDeque<ErrorKind>,
+        chunk: usize,
+        written: Vec<u8>,
+    }
+
+    impl AsyncWrite for FlakyWriter {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            if let Some(kind) = self.errors.pop_front() {
+                return Poll::Ready(Err(kind.into()));
+            }
+            let n = buf.len().min(self.chunk);
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn audio(n: u8) -> SignalingMessage {
+        SignalingMessage::AudioDataReceived {
+            sender_id: "p-1".to_string(),
+            data: vec![n],
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: None,
+        }
+    }
+
+    fn audio_payload(message: SignalingMessage) -> u8 {
+        match message {
+            SignalingMessage::AudioDataReceived { data, .. } => data[0],
+            other => panic!("expected audio, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overflow_sheds_oldest_audio_but_keeps_signaling() {
+        let (tx, mut rx) = channel(3);
+        for n in 0..5 {
+            tx.send(audio(n)).unwrap();
+        }
+        tx.send(SignalingMessage::LeaveRoom).unwrap();
+
+        // Oldest frames went first; the control message still arrives
+        assert_eq!(audio_payload(rx.try_recv().unwrap()), 2);
+        assert_eq!(audio_payload(rx.try_recv().unwrap()), 3);
+        assert_eq!(audio_payload(rx.try_recv().unwrap()), 4);
+        assert!(matches!(rx.try_recv(), Ok(SignalingMessage::LeaveRoom)));
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[test]
+    fn test_full_of_signaling_drops_new_audio() {
+        let (tx, mut rx) = channel(2);
+        for _ in 0..3 {
+            tx.send(SignalingMessage::LeaveRoom).unwrap();
+        }
+        tx.send(audio(0)).unwrap();
+
+        for _ in 0..3 {
+            assert!(matches!(rx.try_recv(), Ok(SignalingMessage::LeaveRoom)));
+        }
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_retryable_write_error_keeps_connection() {
+        let frame = SignalingMessage::LeaveRoom.to_framed().unwrap();
+        let mut writer = FlakyWriter {
+            errors: VecDeque::from([ErrorKind::WouldBlock]),
+            chunk: 3,
+            written: Vec::new(),
+        };
+        write_frame(&mut writer, &frame).await.unwrap();
+        assert_eq!(writer.written, frame);
+
+        // A peer that stays stuck is given up on once the retries run out
+        let mut stuck = FlakyWriter {
+            errors: VecDeque::from(vec![ErrorKind::WouldBlock; MAX_WRITE_RETRIES as usize + 1]),
+            chunk: usize::MAX,
+            written: Vec::new(),
+        };
+        let err = write_frame(&mut stuck, &frame).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let mut reset = FlakyWriter {
+            errors: VecDeque::from([ErrorKind::ConnectionReset]),
+            chunk: usize::MAX,
+            written: Vec::new(),
+        };
+        let err = write_frame(&mut reset, &frame).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        assert!(reset.written.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_closing_either_side() {
+        let (tx, mut rx) = channel(4);
+        tx.send(SignalingMessage::LeaveRoom).unwrap();
+        drop(tx);
+        assert!(rx.recv().await.is_some());
+        assert!(rx.recv().await.is_none());
+
+        let (tx, rx) = channel(4);
+        drop(rx);
+        assert!(tx.send(SignalingMessage::LeaveRoom).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Per-client token bucket for room-mutating commands
+//!
+//! Each client starts with `burst` tokens; every `CreateRoom`, `JoinRoom`
+//! and similar command spends one, and tokens refill at `per_sec` up to the
+//! burst size. A client hammering those commands gets `RateLimited` errors
+//! instead of contending for the `RoomManager` locks.
+
+use std::time::Instant;
+
+pub struct TokenBucket {
+    capacity: f64,
+    per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket of `burst` tokens refilling at `per_sec`. A `burst` of
+    /// 0 disables limiting.
+    pub fn new(burst: u32, per_sec: f64) -> Self {
+        Self {
+            capacity: burst as f64,
+            per_sec: per_sec.max(0.0),
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Spend a token if one is available
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        if self.capacity == 0.0 {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_throttle_then_refill() {
+        let mut bucket = TokenBucket::new(3, 2.0);
+        let start = bucket.last_refill;
+
+        assert!((0..3).all(|_| bucket.try_acquire_at(start)));
+        assert!(!bucket.try_acquire_at(start));
+
+        // Half a second at 2/s buys exactly one more
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.try_acquire_at(later));
+        assert!(!bucket.try_acquire_at(later));
+
+        // A long pause refills only up to the burst size
+        let much_later = later + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.try_acquire_at(much_later)));
+        assert!(!bucket.try_acquire_at(much_later));
+    }
+
+    #[test]
+    fn test_zero_burst_is_unlimited() {
+        let mut bucket = TokenBucket::new(0, 0.0);
+        assert!((0..1000).all(|_| bucket.try_acquire()));
+    }
+}
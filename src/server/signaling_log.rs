@@ -0,0 +1,213 @@
+//! Replayable record of signaling traffic
+//!
+//! When `signaling_log` is set, every message read from or written to a
+//! client is appended to a newline-delimited JSON file, one entry per line:
+//!
+//! ```text
+//! {"ts":1700000000000,"participant_id":"…","direction":"in","message":{"type":"login",…}}
+//! ```
+//!
+//! Messages are redacted before they're serialized. Media payloads (`data`,
+//! `rgba`) are replaced by their length, e.g. `"data_len":960`, so a busy
+//! call doesn't fill the disk. Session and resume tokens are masked and chat
+//! text is reduced to its length, so the file holds nothing that would let
+//! a reader take over a session or read the conversation. Lines are written
+//! by a background thread; the connection tasks only queue them.
+
+use pqc_chat::protocol::{unix_millis, SignalingMessage};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+/// Lines queued for the writer thread before new ones are dropped
+const QUEUE_LINES: usize = 4096;
+
+/// Stands in for a token in the log
+const REDACTED: &str = "[redacted]";
+
+/// Which way a logged message travelled
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum Direction {
+    #[serde(rename = "in")]
+    Inbound,
+    #[serde(rename = "out")]
+    Outbound,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    ts: u64,
+    participant_id: &'a str,
+    direction: Direction,
+    message: Value,
+}
+
+pub struct SignalingLog {
+    lines: Option<SyncSender<String>>,
+    writer: Option<JoinHandle<()>>,
+    /// Lines lost because the writer fell behind
+    dropped: AtomicU64,
+}
+
+impl SignalingLog {
+    /// Open `path` for appending, creating it if needed, and start the
+    /// thread that writes to it
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (lines, queue) = mpsc::sync_channel::<String>(QUEUE_LINES);
+        let writer = std::thread::Builder::new()
+            .name("signaling-log".to_string())
+            .spawn(move || write_lines(LineWriter::new(file), queue))?;
+        Ok(Self { lines: Some(lines), writer: Some(writer), dropped: AtomicU64::new(0) })
+    }
+
+    /// Queue one entry. Failures are logged, never fatal to the session.
+    pub fn record(&self, participant_id: &str, direction: Direction, message: &SignalingMessage) {
+        let (message, payload) = redact(message);
+        let mut message = match serde_json::to_value(message) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Could not serialize message for the signaling log: {}", e);
+                return;
+            }
+        };
+        if let (Some((field, len)), Value::Object(fields)) = (payload, &mut message) {
+            fields.remove(field);
+            fields.insert(format!("{}_len", field), len.into());
+        }
+        let entry = Entry { ts: unix_millis(), participant_id, direction, message };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Could not serialize signaling log entry: {}", e);
+                return;
+            }
+        };
+        let Some(lines) = &self.lines else { return };
+        if let Err(TrySendError::Full(_)) = lines.try_send(line) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                log::warn!("Signaling log writer is behind; {} entries dropped so far", dropped);
+            }
+        }
+    }
+}
+
+impl Drop for SignalingLog {
+    /// Let the writer drain what's queued before the file is closed
+    fn drop(&mut self) {
+        drop(self.lines.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_lines(mut writer: LineWriter<File>, queue: mpsc::Receiver<String>) {
+    for line in queue {
+        if let Err(e) = writeln!(writer, "{}", line) {
+            log::warn!("Failed to write signaling log: {}", e);
+        }
+    }
+}
+
+/// A copy of `message` that is safe to log, plus the media field it had
+/// and that field's length. Media bytes aren't copied at all.
+fn redact(message: &SignalingMessage) -> (SignalingMessage, Option<(&'static str, usize)>) {
+    use SignalingMessage as M;
+
+    let (mut copy, payload) = match message {
+        M::AudioData { data, batched, codec, captured_at } => (
+            M::AudioData { data: Vec::new(), batched: *batched, codec: *codec, captured_at: *captured_at },
+            Some(("data", data.len())),
+        ),
+        M::AudioDataReceived { sender_id, data, batched, codec, captured_at } => (
+            M::AudioDataReceived {
+                sender_id: sender_id.clone(),
+                data: Vec::new(),
+                batched: *batched,
+                codec: *codec,
+                captured_at: *captured_at,
+            },
+            Some(("data", data.len())),
+        ),
+        M::VideoFrameReceived { sender_id, width, height, rgba } => (
+            M::VideoFrameReceived { sender_id: sender_id.clone(), width: *width, height: *height, rgba: Vec::new() },
+            Some(("rgba", rgba.len())),
+        ),
+        other => (other.clone(), None),
+    };
+
+    let mask = |token: &mut Option<String>| {
+        if let Some(token) = token {
+            *token = REDACTED.to_string();
+        }
+    };
+    let withhold = |text: &mut String| *text = format!("[{} chars]", text.chars().count());
+    match &mut copy {
+        M::Login { resume_token, .. } => mask(resume_token),
+        M::LoginResponse { udp_session_token, resume_token, .. } => {
+            mask(udp_session_token);
+            mask(resume_token);
+        }
+        M::SendMessage { content }
+        | M::GroupMessage { content, .. }
+        | M::MessageReceived { content, .. }
+        | M::GroupMessageReceived { content, .. } => withhold(content),
+        M::EditMessage { new_content, .. } | M::MessageEdited { new_content, .. } => withhold(new_content),
+        _ => {}
+    }
+    (copy, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pqc_chat::protocol::AudioCodec;
+
+    #[test]
+    fn test_entries_are_ndjson_with_payload_lengths() {
+        let path = std::env::temp_dir().join(format!("pqc-signaling-{}.ndjson", uuid::Uuid::new_v4()));
+        let log = SignalingLog::open(&path).unwrap();
+        log.record("p-1", Direction::Inbound, &SignalingMessage::Login { username: "alice".to_string(), resume_token: None });
+        log.record("p-1", Direction::Outbound, &SignalingMessage::AudioDataReceived {
+            sender_id: "p-2".to_string(),
+            data: vec![0; 960],
+            batched: false,
+            codec: AudioCodec::Opus,
+            captured_at: None,
+        });
+        drop(log);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let entries: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0]["participant_id"], "p-1");
+        assert_eq!(entries[0]["direction"], "in");
+        assert!(entries[0]["ts"].as_u64().unwrap() > 0);
+        let login: SignalingMessage = serde_json::from_value(entries[0]["message"].clone()).unwrap();
+        assert!(matches!(login, SignalingMessage::Login { username, .. } if username == "alice"));
+
+        assert_eq!(entries[1]["direction"], "out");
+        assert_eq!(entries[1]["message"]["type"], "audio_data_received");
+        assert_eq!(entries[1]["message"]["data_len"], 960);
+        assert!(entries[1]["message"].get("data").is_none());
+    }
+
+    #[test]
+    fn test_tokens_and_chat_text_are_redacted() {
+        let (login, _) = redact(&SignalingMessage::Login { username: "alice".to_string(), resume_token: Some("secret".to_string()) });
+        assert!(matches!(login, SignalingMessage::Login { resume_token: Some(t), .. } if t == REDACTED));
+
+        let (chat, payload) = redact(&SignalingMessage::SendMessage { content: "meet at 6".to_string() });
+        assert!(matches!(chat, SignalingMessage::SendMessage { content } if content == "[9 chars]"));
+        assert!(payload.is_none());
+    }
+}
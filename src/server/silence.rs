@@ -0,0 +1,47 @@
+//! Server-side silence suppression (`relay_silence_threshold`)
+//!
+//! Clients without voice activity detection send a frame every period even
+//! when nobody talks, and each one fans out to the whole room. With a
+//! threshold set, the server drops packets whose decoded frames are all
+//! quieter than it. The decoding is done by the caller, with the sender's
+//! transcoder, so it can be reused for fan-out. A short hangover keeps the
+//! trailing edge of speech from being clipped.
+
+use pqc_chat::audio::frame_rms;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long packets keep being relayed after the last audible one
+pub const SILENCE_HANGOVER: Duration = Duration::from_millis(300);
+
+/// Per-sender last-audible times
+pub struct SilenceGate {
+    threshold: f32,
+    last_audible: HashMap<String, Instant>,
+}
+
+impl SilenceGate {
+    /// Gate packets whose frames all have an RMS below `threshold`
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            last_audible: HashMap::new(),
+        }
+    }
+
+    /// Whether a sender's packet, given its decoded frames, should be relayed
+    pub fn should_relay(&mut self, sender_id: &str, frames: &[Vec<f32>], now: Instant) -> bool {
+        if frames.iter().any(|samples| frame_rms(samples) >= self.threshold) {
+            self.last_audible.insert(sender_id.to_string(), now);
+            return true;
+        }
+        self.last_audible
+            .get(sender_id)
+            .is_some_and(|&last| now.saturating_duration_since(last) < SILENCE_HANGOVER)
+    }
+
+    /// Drop a sender's state
+    pub fn forget(&mut self, sender_id: &str) {
+        self.last_audible.remove(sender_id);
+    }
+}
@@ -0,0 +1,224 @@
+//! Signaling Transports
+//!
+//! Signaling is a sequence of length-prefixed frames (see
+//! [`SignalingMessage::to_framed`]). [`SignalingTransport`] moves those
+//! frames without caring what carries them, so the rustls TCP connection
+//! used today can be swapped for a QUIC stream, or an in-memory pipe in
+//! tests, without touching the framing or the message handling above it.
+//!
+//! [`StreamTransport`] is the implementation for any byte stream, including
+//! the TLS streams the client and server use. Connections that split their
+//! stream into halves read with [`read_frame`] directly.
+
+use crate::protocol::{parse_frame_header, FrameOptions, ProtocolError, SignalingMessage, MAX_FRAME_LEN};
+use std::future::Future;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Errors from moving frames over a transport
+#[derive(Error, Debug)]
+pub enum TransportError {
+    #[error("Transport I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] ProtocolError),
+    #[error("Frame too large ({0} bytes)")]
+    FrameTooLarge(usize),
+    #[error("Malformed frame")]
+    MalformedFrame,
+    #[error("Connection closed")]
+    Closed,
+}
+
+/// A received frame, with its length prefix already taken apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub body: Vec<u8>,
+    pub compressed: bool,
+}
+
+impl Frame {
+    /// Split a complete frame (length prefix included) into its parts
+    pub fn parse(frame: &[u8]) -> Result<Self, TransportError> {
+        let (header, body) = frame.split_first_chunk::<4>().ok_or(TransportError::MalformedFrame)?;
+        let (len, compressed) = parse_frame_header(*header);
+        if len > MAX_FRAME_LEN {
+            return Err(TransportError::FrameTooLarge(len));
+        }
+        if body.len() != len {
+            return Err(TransportError::MalformedFrame);
+        }
+        Ok(Self { body: body.to_vec(), compressed })
+    }
+
+    /// Decode the message this frame carries
+    pub fn message(&self) -> Result<SignalingMessage, ProtocolError> {
+        SignalingMessage::from_frame_body(&self.body, self.compressed)
+    }
+}
+
+/// Something signaling frames can be sent and received over.
+///
+/// Frames handed to `send_frame` are complete, length prefix included, as
+/// produced by [`SignalingMessage::to_framed_with`] or
+/// [`FrameAuthenticator::seal`](crate::protocol::FrameAuthenticator::seal).
+/// Frame tagging stays above the transport, so every backend gets it.
+pub trait SignalingTransport: Send {
+    /// Send one complete frame
+    fn send_frame(&mut self, frame: &[u8]) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Wait for the next frame; [`TransportError::Closed`] once the peer
+    /// has gone away
+    fn recv_frame(&mut self) -> impl Future<Output = Result<Frame, TransportError>> + Send;
+
+    /// Frame and send a message using the negotiated `options`
+    fn send(
+        &mut self,
+        message: &SignalingMessage,
+        options: FrameOptions,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send
+    where
+        Self: Sized,
+    {
+        let frame = message.to_framed_with(options);
+        async move { self.send_frame(&frame?).await }
+    }
+
+    /// Receive and decode the next message (for untagged frames)
+    fn recv(&mut self) -> impl Future<Output = Result<SignalingMessage, TransportError>> + Send
+    where
+        Self: Sized,
+    {
+        async move { Ok(self.recv_frame().await?.message()?) }
+    }
+}
+
+/// Length-prefixed frames over a byte stream
+pub struct StreamTransport<S> {
+    stream: S,
+}
+
+impl<S> StreamTransport<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// The underlying stream, e.g. to split it once the handshake is done
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> SignalingTransport for StreamTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    async fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.stream.write_all(frame).await?;
+        Ok(())
+    }
+
+    async fn recv_frame(&mut self) -> Result<Frame, TransportError> {
+        read_frame(&mut self.stream).await
+    }
+}
+
+/// Read one length-prefixed frame from a byte stream, e.g. a connection's
+/// read half. [`TransportError::Closed`] on a clean EOF before the header.
+pub async fn read_frame<R>(reader: &mut R) -> Result<Frame, TransportError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 4];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(TransportError::Closed),
+        Err(e) => return Err(e.into()),
+    }
+    let (len, compressed) = parse_frame_header(header);
+    // Refuse before allocating, so a bogus prefix can't reserve 2 GiB
+    if len > MAX_FRAME_LEN {
+        return Err(TransportError::FrameTooLarge(len));
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+    Ok(Frame { body, compressed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    /// Whole frames passed over channels, as a datagram-ish backend would
+    struct MemoryTransport {
+        tx: mpsc::UnboundedSender<Vec<u8>>,
+        rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    }
+
+    impl MemoryTransport {
+        fn pair() -> (Self, Self) {
+            let (a_tx, a_rx) = mpsc::unbounded_channel();
+            let (b_tx, b_rx) = mpsc::unbounded_channel();
+            (Self { tx: a_tx, rx: b_rx }, Self { tx: b_tx, rx: a_rx })
+        }
+    }
+
+    impl SignalingTransport for MemoryTransport {
+        async fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+            self.tx.send(frame.to_vec()).map_err(|_| TransportError::Closed)
+        }
+
+        async fn recv_frame(&mut self) -> Result<Frame, TransportError> {
+            Frame::parse(&self.rx.recv().await.ok_or(TransportError::Closed)?)
+        }
+    }
+
+    fn chat(content: &str) -> SignalingMessage {
+        SignalingMessage::SendMessage { content: content.repeat(200) }
+    }
+
+    #[tokio::test]
+    async fn test_framing_over_memory_transport() {
+        let (mut client, mut server) = MemoryTransport::pair();
+        let options = FrameOptions { compress: true, binary_audio: true };
+
+        client.send(&chat("hello "), options).await.unwrap();
+        let audio = SignalingMessage::AudioData { data: vec![1, 2, 3], batched: false, codec: Default::default(), captured_at: None };
+        client.send(&audio, options).await.unwrap();
+
+        // Compression and binary audio survive the trip unchanged
+        let frame = server.recv_frame().await.unwrap();
+        assert!(frame.compressed);
+        match frame.message().unwrap() {
+            SignalingMessage::SendMessage { content, .. } => assert_eq!(content, "hello ".repeat(200)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(server.recv().await.unwrap(), SignalingMessage::AudioData { data, .. } if data == [1, 2, 3]));
+
+        drop(client);
+        assert!(matches!(server.recv().await, Err(TransportError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_transport_frames_and_limits() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (mut client, mut server) = (StreamTransport::new(a), StreamTransport::new(b));
+
+        // Larger than the pipe's buffer, so the frame arrives in pieces
+        let message = chat("split ");
+        let writer = tokio::spawn(async move {
+            client.send(&message, FrameOptions::default()).await.unwrap();
+            client
+        });
+        assert!(matches!(server.recv().await.unwrap(), SignalingMessage::SendMessage { content, .. } if content.len() == 1200));
+
+        let mut client = writer.await.unwrap();
+        let oversized = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        client.send_frame(&oversized).await.unwrap();
+        assert!(matches!(server.recv_frame().await, Err(TransportError::FrameTooLarge(_))));
+
+        drop(client);
+        assert!(matches!(server.recv_frame().await, Err(TransportError::Closed)));
+    }
+}
@@ -0,0 +1,114 @@
+//! Video Frames
+//!
+//! Decoded video frame type shared by the capture and rendering paths.
+
+use thiserror::Error;
+
+/// Video-related errors
+#[derive(Error, Debug)]
+pub enum VideoError {
+    #[error("Frame data is {actual} bytes, expected {expected} for {width}x{height} RGBA")]
+    InvalidFrameSize {
+        width: u32,
+        height: u32,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Camera error: {0}")]
+    Capture(String),
+    #[error("Video codec error: {0}")]
+    Codec(String),
+}
+
+/// A decoded RGBA8 video frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA pixels, `width * height * 4` bytes
+    pub rgba: Vec<u8>,
+}
+
+impl VideoFrame {
+    /// Wrap RGBA data, checking it matches the dimensions
+    pub fn new(width: u32, height: u32, rgba: Vec<u8>) -> Result<Self, VideoError> {
+        let expected = width as usize * height as usize * 4;
+        if rgba.len() != expected {
+            return Err(VideoError::InvalidFrameSize {
+                width,
+                height,
+                expected,
+                actual: rgba.len(),
+            });
+        }
+        Ok(Self { width, height, rgba })
+    }
+
+    /// Moving colour-bar test pattern, for exercising the render path without a camera
+    pub fn test_pattern(width: u32, height: u32, frame_number: u32) -> Self {
+        const BARS: [[u8; 3]; 7] = [
+            [192, 192, 192],
+            [192, 192, 0],
+            [0, 192, 192],
+            [0, 192, 0],
+            [192, 0, 192],
+            [192, 0, 0],
+            [0, 0, 192],
+        ];
+
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for _y in 0..height {
+            for x in 0..width {
+                let shifted = (x + frame_number) % width.max(1);
+                let bar = BARS[(shifted as usize * BARS.len()) / width.max(1) as usize];
+                rgba.extend_from_slice(&[bar[0], bar[1], bar[2], 255]);
+            }
+        }
+        Self { width, height, rgba }
+    }
+
+    /// Convert to an egui image for uploading as a texture
+    #[cfg(feature = "gui")]
+    pub fn to_color_image(&self) -> egui::ColorImage {
+        egui::ColorImage::from_rgba_unmultiplied(
+            [self.width as usize, self.height as usize],
+            &self.rgba,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_size_validation() {
+        assert!(VideoFrame::new(2, 2, vec![0; 16]).is_ok());
+        assert!(matches!(
+            VideoFrame::new(2, 2, vec![0; 15]),
+            Err(VideoError::InvalidFrameSize { expected: 16, actual: 15, .. })
+        ));
+    }
+
+    #[test]
+    fn test_pattern_dimensions() {
+        let frame = VideoFrame::test_pattern(64, 48, 3);
+        assert_eq!(frame.rgba.len(), 64 * 48 * 4);
+        assert!(frame.rgba.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn test_to_color_image_dimensions() {
+        let frame = VideoFrame::test_pattern(32, 24, 0);
+        let image = frame.to_color_image();
+        assert_eq!(image.size, [32, 24]);
+        assert_eq!(image.pixels.len(), 32 * 24);
+
+        let first = &frame.rgba[..4];
+        assert_eq!(
+            image.pixels[0],
+            egui::Color32::from_rgba_unmultiplied(first[0], first[1], first[2], first[3])
+        );
+    }
+}
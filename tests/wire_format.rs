@@ -0,0 +1,229 @@
+//! Wire format golden tests
+//!
+//! Deployed clients and servers exchange `SignalingMessage` as JSON, so a
+//! renamed field or variant silently breaks interop. Each golden string here
+//! must deserialize into the expected message and the message must serialize
+//! back to the same JSON. Update these only for a deliberate protocol change.
+
+use pqc_chat::protocol::{AudioCodec, LeaveReason, ParticipantInfo, SignalingMessage};
+use serde_json::Value;
+
+/// Parse `golden`, and check the re-serialized message is the same JSON
+fn roundtrip(golden: &str) -> SignalingMessage {
+    let message: SignalingMessage = serde_json::from_str(golden).expect("golden JSON must parse");
+    let expected: Value = serde_json::from_str(golden).unwrap();
+    assert_eq!(serde_json::to_value(&message).unwrap(), expected);
+    message
+}
+
+#[test]
+fn login() {
+    match roundtrip(r#"{"type":"login","username":"alice"}"#) {
+        SignalingMessage::Login { username, .. } => assert_eq!(username, "alice"),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn login_response() {
+    let golden = r#"{"type":"login_response","success":true,"participant_id":"p-1","error":null,"udp_session_token":"p-1.abcd","resume_token":"r-1"}"#;
+    match roundtrip(golden) {
+        SignalingMessage::LoginResponse { success, participant_id, error, udp_session_token, resume_token } => {
+            assert!(success);
+            assert_eq!(participant_id.as_deref(), Some("p-1"));
+            assert_eq!(error, None);
+            assert_eq!(udp_session_token.as_deref(), Some("p-1.abcd"));
+            assert_eq!(resume_token.as_deref(), Some("r-1"));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn join_room() {
+    match roundtrip(r#"{"type":"join_room","room_id":"r-1","username":"alice"}"#) {
+        SignalingMessage::JoinRoom { room_id, username } => {
+            assert_eq!(room_id, "r-1");
+            assert_eq!(username, "alice");
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn leave_room() {
+    assert!(matches!(roundtrip(r#"{"type":"leave_room"}"#), SignalingMessage::LeaveRoom));
+}
+
+#[test]
+fn room_joined() {
+    let golden = r#"{"type":"room_joined","success":true,"room_id":"r-1","room_name":"Lobby","participants":[{"id":"p-1","username":"alice","audio_enabled":true,"video_enabled":false}],"error":null,"roster_version":3}"#;
+    match roundtrip(golden) {
+        SignalingMessage::RoomJoined { success, room_id, room_name, participants, error, roster_version } => {
+            assert!(success);
+            assert_eq!(roster_version, 3);
+            assert_eq!(room_id.as_deref(), Some("r-1"));
+            assert_eq!(room_name.as_deref(), Some("Lobby"));
+            assert_eq!(error, None);
+
+            let participants: Vec<ParticipantInfo> = participants.unwrap();
+            assert_eq!(participants.len(), 1);
+            assert_eq!(participants[0].id, "p-1");
+            assert_eq!(participants[0].username, "alice");
+            assert!(participants[0].audio_enabled);
+            assert!(!participants[0].video_enabled);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn room_joined_from_older_server() {
+    // Servers predating roster deltas don't send roster_version
+    let golden = r#"{"type":"room_joined","success":true,"room_id":"r-1","room_name":"Lobby","participants":[],"error":null}"#;
+    match serde_json::from_str::<SignalingMessage>(golden).unwrap() {
+        SignalingMessage::RoomJoined { roster_version, .. } => assert_eq!(roster_version, 0),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn room_roster_delta() {
+    let golden = r#"{"type":"room_roster_delta","room_id":"r-1","version":4,"added":[{"id":"p-2","username":"bob","audio_enabled":true,"video_enabled":false}],"removed":["p-1"],"media_changes":[["p-3",false,true]]}"#;
+    match roundtrip(golden) {
+        SignalingMessage::RoomRosterDelta { room_id, version, added, removed, media_changes } => {
+            assert_eq!(room_id, "r-1");
+            assert_eq!(version, 4);
+            assert_eq!(added[0].username, "bob");
+            assert_eq!(removed, vec!["p-1".to_string()]);
+            assert_eq!(media_changes, vec![("p-3".to_string(), false, true)]);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn participant_left() {
+    match roundtrip(r#"{"type":"participant_left","participant_id":"p-1","reason":"timed_out"}"#) {
+        SignalingMessage::ParticipantLeft { participant_id, reason } => {
+            assert_eq!(participant_id, "p-1");
+            assert_eq!(reason, LeaveReason::TimedOut);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+    // Servers predating leave reasons only sent the id
+    match serde_json::from_str::<SignalingMessage>(r#"{"type":"participant_left","participant_id":"p-1"}"#).unwrap() {
+        SignalingMessage::ParticipantLeft { reason, .. } => assert_eq!(reason, LeaveReason::Left),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn send_message() {
+    match roundtrip(r#"{"type":"send_message","content":"hello"}"#) {
+        SignalingMessage::SendMessage { content } => assert_eq!(content, "hello"),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn message_received() {
+    let golden = r#"{"type":"message_received","message_id":"m-1","room_id":"r-1","sender_id":"p-1","sender_username":"alice","content":"hello","timestamp":1700000000,"edited":false}"#;
+    match roundtrip(golden) {
+        SignalingMessage::MessageReceived { message_id, room_id, sender_id, sender_username, content, timestamp, edited } => {
+            assert_eq!(message_id, "m-1");
+            assert_eq!(room_id, "r-1");
+            assert_eq!(sender_id, "p-1");
+            assert_eq!(sender_username, "alice");
+            assert_eq!(content, "hello");
+            assert_eq!(timestamp, 1_700_000_000);
+            assert!(!edited);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn message_received_from_older_server() {
+    // Servers predating chat history don't send message_id/edited
+    let golden = r#"{"type":"message_received","sender_id":"p-1","sender_username":"alice","content":"hi","timestamp":1}"#;
+    match serde_json::from_str::<SignalingMessage>(golden).unwrap() {
+        SignalingMessage::MessageReceived { message_id, room_id, edited, .. } => {
+            assert!(message_id.is_empty());
+            assert!(room_id.is_empty());
+            assert!(!edited);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn audio_data() {
+    match roundtrip(r#"{"type":"audio_data","data":[1,2,3],"batched":false,"codec":"opus"}"#) {
+        SignalingMessage::AudioData { data, batched, codec, captured_at } => {
+            assert_eq!(data, vec![1, 2, 3]);
+            assert!(!batched);
+            assert_eq!(codec, AudioCodec::Opus);
+            assert_eq!(captured_at, None);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn audio_data_without_codec_is_opus() {
+    // Senders predating the codec tag only ever sent Opus
+    match serde_json::from_str::<SignalingMessage>(r#"{"type":"audio_data","data":[1]}"#).unwrap() {
+        SignalingMessage::AudioData { codec, .. } => assert_eq!(codec, AudioCodec::Opus),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn audio_data_received() {
+    let golden = r#"{"type":"audio_data_received","sender_id":"p-2","data":[0,255,16],"batched":true,"codec":"pcm"}"#;
+    match roundtrip(golden) {
+        SignalingMessage::AudioDataReceived { sender_id, data, batched, codec, captured_at } => {
+            assert_eq!(sender_id, "p-2");
+            assert_eq!(data, vec![0, 255, 16]);
+            assert!(batched);
+            assert_eq!(codec, AudioCodec::Pcm);
+            assert_eq!(captured_at, None);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+    let golden = r#"{"type":"audio_data_received","sender_id":"p-2","data":[1],"batched":false,"codec":"opus","captured_at":1700000000000}"#;
+    match roundtrip(golden) {
+        SignalingMessage::AudioDataReceived { captured_at, .. } => assert_eq!(captured_at, Some(1_700_000_000_000)),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn key_exchange() {
+    match roundtrip(r#"{"type":"key_exchange_init","public_key":[9,8,7]}"#) {
+        SignalingMessage::KeyExchangeInit { public_key } => assert_eq!(public_key, vec![9, 8, 7]),
+        other => panic!("unexpected message: {:?}", other),
+    }
+    match roundtrip(r#"{"type":"key_exchange_response","ciphertext":[6,5]}"#) {
+        SignalingMessage::KeyExchangeResponse { ciphertext } => assert_eq!(ciphertext, vec![6, 5]),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn error_without_code() {
+    // `code` is omitted entirely when absent
+    match roundtrip(r#"{"type":"error","message":"Room not found"}"#) {
+        SignalingMessage::Error { message, code } => {
+            assert_eq!(message, "Room not found");
+            assert!(code.is_none());
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_type_is_rejected() {
+    assert!(serde_json::from_str::<SignalingMessage>(r#"{"type":"join","room_id":"r-1"}"#).is_err());
+}